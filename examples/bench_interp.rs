@@ -0,0 +1,41 @@
+//! Interpreter throughput microbenchmark for the LUI/ADDI/SW/JAL hot path
+//! `execute_cached` walks on every cycle of a tight compute loop - the same
+//! path CachedInst's pre-decoded fields (see `icache.rs`) are meant to
+//! speed up. Run with `cargo run --release --example bench_interp`.
+//!
+//! JIT is left disabled (the default) so this measures the interpreter,
+//! not `try_jit_execution`.
+
+use otoriscv::System;
+use std::time::Instant;
+
+fn main() {
+    const DRAM_BASE: u32 = 0x8000_0000;
+    // lui x1,0x1 ; addi x2,x0,0 ; loop: addi x2,x2,1 ; sw x2,0(x1) ; jal x0,loop
+    let insts: [u32; 5] = [
+        0x000010b7, // lui x1, 0x1
+        0x00000113, // addi x2, x0, 0
+        0x00110113, // addi x2,x2,1
+        0x0020a023, // sw x2,0(x1)
+        0xffdff06f, // jal x0, -4 (back to addi x2,x2,1)
+    ];
+    let mut bytes = Vec::new();
+    for inst in insts {
+        bytes.extend_from_slice(&inst.to_le_bytes());
+    }
+
+    let mut sys = System::new(16, None).unwrap();
+    sys.load_binary(&bytes, DRAM_BASE).unwrap();
+    sys.cpu.pc = DRAM_BASE + 4; // start at addi x2,x0,0
+
+    let iters: u32 = 50_000_000;
+    let start = Instant::now();
+    let done = sys.run(iters);
+    let elapsed = start.elapsed();
+    println!(
+        "cycles={} elapsed={:?} ips={:.2}M/s",
+        done,
+        elapsed,
+        done as f64 / elapsed.as_secs_f64() / 1e6
+    );
+}
@@ -0,0 +1,22 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use otoriscv::cpu::Cpu;
+use otoriscv::memory::Memory;
+
+// Feeds arbitrary 32-bit words through the RV32 decoder/executor.
+// The CPU/memory are freshly constructed each run so any panic is
+// attributable to `inst` alone rather than accumulated state.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 4 {
+        return;
+    }
+
+    let mut cpu = Cpu::new();
+    let mut memory = Memory::new(1);
+
+    for chunk in data.chunks_exact(4) {
+        let inst = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        let _ = cpu.execute(inst, &mut memory);
+    }
+});
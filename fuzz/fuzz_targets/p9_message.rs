@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use otoriscv::devices::Virtio9p;
+use otoriscv::devices::virtio_9p::{Backend, in_memory::InMemoryFileSystem};
+
+// Feeds arbitrary bytes through the 9p message parser as if they were a
+// guest-controlled Tmessage. Guards against out-of-bounds slicing in the
+// per-message handlers (e.g. handle_mkdir/handle_lcreate name-length fields).
+fuzz_target!(|data: &[u8]| {
+    let mut fs = Virtio9p::new("rootfs", Backend::InMemory(InMemoryFileSystem::new()));
+    let _ = fs.process_message(data);
+});
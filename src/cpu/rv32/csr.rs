@@ -20,6 +20,8 @@ pub const CSR_SEPC: u32 = 0x141;
 pub const CSR_SCAUSE: u32 = 0x142;
 pub const CSR_STVAL: u32 = 0x143;
 pub const CSR_SIP: u32 = 0x144;
+pub const CSR_STIMECMP: u32 = 0x14D;
+pub const CSR_STIMECMPH: u32 = 0x15D;
 pub const CSR_SATP: u32 = 0x180;
 
 // Machine CSRs
@@ -30,12 +32,17 @@ pub const CSR_MIDELEG: u32 = 0x303;
 pub const CSR_MIE: u32 = 0x304;
 pub const CSR_MTVEC: u32 = 0x305;
 pub const CSR_MCOUNTEREN: u32 = 0x306;
+pub const CSR_MENVCFG: u32 = 0x30A;
+pub const CSR_MENVCFGH: u32 = 0x31A;
 pub const CSR_MSCRATCH: u32 = 0x340;
 pub const CSR_MEPC: u32 = 0x341;
 pub const CSR_MCAUSE: u32 = 0x342;
 pub const CSR_MTVAL: u32 = 0x343;
 pub const CSR_MIP: u32 = 0x344;
 pub const CSR_MHARTID: u32 = 0xF14;
+pub const CSR_MVENDORID: u32 = 0xF11;
+pub const CSR_MARCHID: u32 = 0xF12;
+pub const CSR_MIMPID: u32 = 0xF13;
 
 // Time CSRs
 pub const CSR_CYCLE: u32 = 0xC00;
@@ -45,6 +52,30 @@ pub const CSR_CYCLEH: u32 = 0xC80;
 pub const CSR_TIMEH: u32 = 0xC81;
 pub const CSR_INSTRETH: u32 = 0xC82;
 
+// Zihpm: hpmcounter3-31 (shadow of mhpmcounter3-31), and their upper halves
+pub const CSR_HPMCOUNTER3: u32 = 0xC03;
+pub const CSR_HPMCOUNTER31: u32 = 0xC1F;
+pub const CSR_HPMCOUNTER3H: u32 = 0xC83;
+pub const CSR_HPMCOUNTER31H: u32 = 0xC9F;
+
+// Zihpm: mhpmcounter3-31 and mhpmevent3-31
+pub const CSR_MHPMCOUNTER3: u32 = 0xB03;
+pub const CSR_MHPMCOUNTER31: u32 = 0xB1F;
+pub const CSR_MHPMCOUNTER3H: u32 = 0xB83;
+pub const CSR_MHPMCOUNTER31H: u32 = 0xB9F;
+pub const CSR_MHPMEVENT3: u32 = 0x323;
+pub const CSR_MHPMEVENT31: u32 = 0x33F;
+
+/// Number of programmable HPM counters (3..=31)
+pub const HPM_COUNTER_COUNT: usize = 29;
+
+/// mhpmevent selector: counter does not count
+pub const HPM_EVENT_OFF: u32 = 0;
+/// mhpmevent selector: counter counts cycles (same as `cycle`)
+pub const HPM_EVENT_CYCLES: u32 = 1;
+/// mhpmevent selector: counter counts retired instructions (same as `instret`)
+pub const HPM_EVENT_INSTRET: u32 = 2;
+
 // MSTATUS bits
 pub const MSTATUS_UIE: u32 = 1 << 0;
 pub const MSTATUS_SIE: u32 = 1 << 1;
@@ -64,6 +95,18 @@ pub const MSTATUS_TW: u32 = 1 << 21;
 pub const MSTATUS_TSR: u32 = 1 << 22;
 pub const MSTATUS_SD: u32 = 1 << 31;
 
+// MISA extension bits (indexed by extension letter - 'a')
+pub const MISA_A: u32 = 1 << 0;  // Atomic
+pub const MISA_B: u32 = 1 << 1;  // Bitmanip (Zba/Zbb/Zbs)
+pub const MISA_C: u32 = 1 << 2;  // Compressed
+pub const MISA_D: u32 = 1 << 3;  // Double-precision float
+pub const MISA_F: u32 = 1 << 5;  // Single-precision float
+pub const MISA_I: u32 = 1 << 8;  // Base integer
+pub const MISA_M: u32 = 1 << 12; // Multiply/divide
+pub const MISA_S: u32 = 1 << 18; // Supervisor mode
+/// MXL field: 32-bit (MXLEN)
+pub const MISA_MXL_32: u32 = 1 << 30;
+
 // MIP/MIE bits (interrupt pending/enable)
 pub const MIP_SSIP: u32 = 1 << 1;  // Supervisor software interrupt
 pub const MIP_MSIP: u32 = 1 << 3;  // Machine software interrupt
@@ -72,6 +115,12 @@ pub const MIP_MTIP: u32 = 1 << 7;  // Machine timer interrupt
 pub const MIP_SEIP: u32 = 1 << 9;  // Supervisor external interrupt
 pub const MIP_MEIP: u32 = 1 << 11; // Machine external interrupt
 
+// MENVCFGH bits (upper half of the logical 64-bit menvcfg on RV32)
+/// STCE (Sstc Enable) - bit 63 of menvcfg, i.e. bit 31 of menvcfgh on RV32.
+/// When clear, S-mode accesses to stimecmp/stimecmph raise an illegal
+/// instruction exception; M-mode can always reach them.
+pub const MENVCFGH_STCE: u32 = 1 << 31;
+
 /// CSR storage
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Csr {
@@ -114,18 +163,40 @@ pub struct Csr {
     pub stval: u32,
     /// Supervisor address translation and protection
     pub satp: u32,
-    
+    /// Supervisor timer compare (Sstc) - fires STIP when time >= stimecmp
+    pub stimecmp: u64,
+
+    /// Machine environment configuration, low half (only STCE, in the high
+    /// half, is implemented; the rest reads back as zero)
+    pub menvcfg: u32,
+    /// Machine environment configuration, high half (holds STCE)
+    pub menvcfgh: u32,
+
     /// Cycle counter
     pub cycle: u64,
     /// Timer value (mtime)
     pub time: u64,
+
+    /// mhpmcounter3..31 (Zihpm), indexed by counter number - 3
+    pub mhpmcounter: [u64; HPM_COUNTER_COUNT],
+    /// mhpmevent3..31 event selectors, indexed by counter number - 3
+    pub mhpmevent: [u32; HPM_COUNTER_COUNT],
+
+    /// JEDEC vendor ID for mvendorid, 0 meaning "not implemented" per spec.
+    /// Set via `System::new_with_isa`; read-only to the guest like the real
+    /// CSR (address bits [11:10] mark it so, and `write` rejects it).
+    pub mvendorid: u32,
+    /// Microarchitecture ID for marchid, 0 meaning "not implemented".
+    pub marchid: u32,
+    /// Implementation version for mimpid, 0 meaning "not implemented".
+    pub mimpid: u32,
 }
 
 impl Csr {
     pub fn new() -> Self {
         Csr {
-            // MISA: RV32IMAFD (I=8, M=12, A=0, F=5, D=3, S=18, 32-bit=1<<30)
-            misa: (1 << 30) | (1 << 8) | (1 << 12) | (1 << 0) | (1 << 18) | (1 << 5) | (1 << 3), // I M A S F D
+            // MISA: RV32IMAFD
+            misa: MISA_MXL_32 | MISA_I | MISA_M | MISA_A | MISA_S | MISA_F | MISA_D,
             mstatus: MSTATUS_FS, // Enable FPU by default (FS = Initial)
             medeleg: 0,
             mideleg: 0,
@@ -145,12 +216,62 @@ impl Csr {
             scause: 0,
             stval: 0,
             satp: 0,
-            
+            stimecmp: 0,
+
+            menvcfg: 0,
+            menvcfgh: 0,
+
             cycle: 0,
             time: 0,
+
+            mhpmcounter: [0; HPM_COUNTER_COUNT],
+            mhpmevent: [0; HPM_COUNTER_COUNT],
+
+            mvendorid: 0,
+            marchid: 0,
+            mimpid: 0,
         }
     }
-    
+
+    /// Advance the cycle/instret/hpm counters by `count` retired
+    /// instructions. This machine has no superscalar modeling, so "cycles"
+    /// and "instructions retired" tick together.
+    pub fn advance(&mut self, count: u64) {
+        self.cycle = self.cycle.wrapping_add(count);
+        for i in 0..HPM_COUNTER_COUNT {
+            match self.mhpmevent[i] & 0xff {
+                HPM_EVENT_CYCLES | HPM_EVENT_INSTRET => {
+                    self.mhpmcounter[i] = self.mhpmcounter[i].wrapping_add(count);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// True if counter `n` (0..=31: cycle/time/instret, then the HPM
+    /// counters) is visible to the current privilege level per
+    /// mcounteren/scounteren.
+    fn counter_accessible(&self, n: u32, priv_level: PrivilegeLevel) -> bool {
+        let bit = 1u32 << n;
+        if priv_level == PrivilegeLevel::Machine {
+            return true;
+        }
+        if self.mcounteren & bit == 0 {
+            return false;
+        }
+        if priv_level == PrivilegeLevel::User && self.scounteren & bit == 0 {
+            return false;
+        }
+        true
+    }
+
+    /// True if stimecmp/stimecmph are reachable from `priv_level`. Per the
+    /// Sstc spec, M-mode can always get at them; S-mode needs menvcfg.STCE
+    /// set, otherwise the access should look like an illegal instruction.
+    fn stimecmp_accessible(&self, priv_level: PrivilegeLevel) -> bool {
+        priv_level == PrivilegeLevel::Machine || self.menvcfgh & MENVCFGH_STCE != 0
+    }
+
     /// Read CSR (returns None if invalid/inaccessible)
     pub fn read(&self, addr: u32, priv_level: PrivilegeLevel) -> Option<u32> {
         // Check privilege level (CSR[9:8] encodes minimum privilege)
@@ -158,7 +279,44 @@ impl Csr {
         if (priv_level as u8) < min_priv {
             return None;
         }
-        
+
+        if (CSR_HPMCOUNTER3..=CSR_HPMCOUNTER31).contains(&addr) {
+            let n = addr - CSR_HPMCOUNTER3 + 3;
+            if !self.counter_accessible(n, priv_level) { return None; }
+            return Some(self.mhpmcounter[(n - 3) as usize] as u32);
+        }
+        if (CSR_HPMCOUNTER3H..=CSR_HPMCOUNTER31H).contains(&addr) {
+            let n = addr - CSR_HPMCOUNTER3H + 3;
+            if !self.counter_accessible(n, priv_level) { return None; }
+            return Some((self.mhpmcounter[(n - 3) as usize] >> 32) as u32);
+        }
+        if (CSR_MHPMCOUNTER3..=CSR_MHPMCOUNTER31).contains(&addr) {
+            let n = addr - CSR_MHPMCOUNTER3 + 3;
+            return Some(self.mhpmcounter[(n - 3) as usize] as u32);
+        }
+        if (CSR_MHPMCOUNTER3H..=CSR_MHPMCOUNTER31H).contains(&addr) {
+            let n = addr - CSR_MHPMCOUNTER3H + 3;
+            return Some((self.mhpmcounter[(n - 3) as usize] >> 32) as u32);
+        }
+        if (CSR_MHPMEVENT3..=CSR_MHPMEVENT31).contains(&addr) {
+            let n = addr - CSR_MHPMEVENT3 + 3;
+            return Some(self.mhpmevent[(n - 3) as usize]);
+        }
+        if addr == CSR_STIMECMP || addr == CSR_STIMECMPH {
+            if !self.stimecmp_accessible(priv_level) { return None; }
+            return Some(if addr == CSR_STIMECMP { self.stimecmp as u32 } else { (self.stimecmp >> 32) as u32 });
+        }
+        if matches!(addr, CSR_CYCLE | CSR_CYCLEH | CSR_TIME | CSR_TIMEH | CSR_INSTRET | CSR_INSTRETH) {
+            let n = if matches!(addr, CSR_CYCLE | CSR_CYCLEH) {
+                0
+            } else if matches!(addr, CSR_TIME | CSR_TIMEH) {
+                1
+            } else {
+                2
+            };
+            if !self.counter_accessible(n, priv_level) { return None; }
+        }
+
         Some(match addr {
             CSR_MSTATUS => self.mstatus,
             CSR_MISA => self.misa,
@@ -167,13 +325,18 @@ impl Csr {
             CSR_MIE => self.mie,
             CSR_MTVEC => self.mtvec,
             CSR_MCOUNTEREN => self.mcounteren,
+            CSR_MENVCFG => self.menvcfg,
+            CSR_MENVCFGH => self.menvcfgh,
             CSR_MSCRATCH => self.mscratch,
             CSR_MEPC => self.mepc,
             CSR_MCAUSE => self.mcause,
             CSR_MTVAL => self.mtval,
             CSR_MIP => self.mip,
             CSR_MHARTID => 0, // Hart ID is always 0
-            
+            CSR_MVENDORID => self.mvendorid,
+            CSR_MARCHID => self.marchid,
+            CSR_MIMPID => self.mimpid,
+
             CSR_SSTATUS => self.mstatus & (MSTATUS_SIE | MSTATUS_SPIE | MSTATUS_SPP | 
                                            MSTATUS_FS | MSTATUS_XS | MSTATUS_SUM | 
                                            MSTATUS_MXR | MSTATUS_SD),
@@ -210,7 +373,40 @@ impl Csr {
         if (addr >> 10) & 3 == 3 {
             return false;
         }
-        
+
+        if (CSR_MHPMCOUNTER3..=CSR_MHPMCOUNTER31).contains(&addr) {
+            let n = (addr - CSR_MHPMCOUNTER3 + 3) as usize;
+            let c = &mut self.mhpmcounter[n - 3];
+            *c = (*c & !0xffff_ffff) | value as u64;
+            return true;
+        }
+        if (CSR_MHPMCOUNTER3H..=CSR_MHPMCOUNTER31H).contains(&addr) {
+            let n = (addr - CSR_MHPMCOUNTER3H + 3) as usize;
+            let c = &mut self.mhpmcounter[n - 3];
+            *c = (*c & 0xffff_ffff) | ((value as u64) << 32);
+            return true;
+        }
+        if (CSR_MHPMEVENT3..=CSR_MHPMEVENT31).contains(&addr) {
+            let n = (addr - CSR_MHPMEVENT3 + 3) as usize;
+            // WARL: only event 1 (cycles) and event 2 (instret) are
+            // implemented, everything else reads back as inactive (0).
+            self.mhpmevent[n - 3] = match value & 0xff {
+                HPM_EVENT_CYCLES => HPM_EVENT_CYCLES,
+                HPM_EVENT_INSTRET => HPM_EVENT_INSTRET,
+                _ => HPM_EVENT_OFF,
+            };
+            return true;
+        }
+        if addr == CSR_STIMECMP || addr == CSR_STIMECMPH {
+            if !self.stimecmp_accessible(priv_level) { return false; }
+            if addr == CSR_STIMECMP {
+                self.stimecmp = (self.stimecmp & !0xffff_ffff) | value as u64;
+            } else {
+                self.stimecmp = (self.stimecmp & 0xffff_ffff) | ((value as u64) << 32);
+            }
+            return true;
+        }
+
         match addr {
             CSR_MSTATUS => {
                 let mask = MSTATUS_SIE | MSTATUS_MIE | MSTATUS_SPIE | MSTATUS_MPIE |
@@ -224,8 +420,13 @@ impl Csr {
                 let mask = MIP_SSIP | MIP_MSIP | MIP_STIP | MIP_MTIP | MIP_SEIP | MIP_MEIP;
                 self.mie = value & mask;
             }
-            CSR_MTVEC => self.mtvec = value & !3, // Align to 4 bytes
+            // MODE (bits[1:0]) is WARL: only Direct (0) and Vectored (1) are
+            // implemented, so reserved values 2/3 collapse to bit 1 = 0.
+            CSR_MTVEC => self.mtvec = value & !0b10,
             CSR_MCOUNTEREN => self.mcounteren = value,
+            // Only STCE (top bit) is implemented; everything else is WARL 0.
+            CSR_MENVCFG => self.menvcfg = 0,
+            CSR_MENVCFGH => self.menvcfgh = value & MENVCFGH_STCE,
             CSR_MSCRATCH => self.mscratch = value,
             CSR_MEPC => self.mepc = value & !1, // Align to 2 bytes
             CSR_MCAUSE => self.mcause = value,
@@ -244,7 +445,8 @@ impl Csr {
             CSR_SIE => {
                 self.mie = (self.mie & !self.mideleg) | (value & self.mideleg);
             }
-            CSR_STVEC => self.stvec = value & !3,
+            // Same WARL clamp as MTVEC.
+            CSR_STVEC => self.stvec = value & !0b10,
             CSR_SCOUNTEREN => self.scounteren = value,
             CSR_SSCRATCH => self.sscratch = value,
             CSR_SEPC => self.sepc = value & !1,
@@ -293,8 +495,123 @@ impl Csr {
         self.scause = 0;
         self.stval = 0;
         self.satp = 0;
-        
+        self.stimecmp = 0;
+
+        self.menvcfg = 0;
+        self.menvcfgh = 0;
+
         self.cycle = 0;
         self.time = 0;
+
+        self.mhpmcounter = [0; HPM_COUNTER_COUNT];
+        self.mhpmevent = [0; HPM_COUNTER_COUNT];
+    }
+
+    /// Is the extension identified by `bit` (one of the `MISA_*` constants)
+    /// currently enabled?
+    pub fn extension_enabled(&self, bit: u32) -> bool {
+        self.misa & bit != 0
+    }
+
+    /// Parse an ISA string (e.g. "rv32ima") and replace the current
+    /// extension bits of `misa` with the ones it names. The `rv32`/`rv64`
+    /// prefix, if present, is ignored (MXLEN is fixed by the CPU, not the
+    /// string); `i` is implied but may also be written explicitly.
+    pub fn set_isa_string(&mut self, isa: &str) -> Result<(), String> {
+        let lower = isa.to_ascii_lowercase();
+        let exts = lower.strip_prefix("rv32").or_else(|| lower.strip_prefix("rv64")).unwrap_or(&lower);
+
+        let mut misa = self.misa & MISA_MXL_32; // keep MXLEN, drop old extension bits
+        misa |= MISA_I | MISA_S; // base integer + supervisor mode are always present
+        for c in exts.chars() {
+            misa |= match c {
+                'i' => MISA_I,
+                'm' => MISA_M,
+                'a' => MISA_A,
+                'b' => MISA_B,
+                'f' => MISA_F,
+                'd' => MISA_D,
+                'c' => MISA_C,
+                other => return Err(format!("unsupported ISA extension '{}'", other)),
+            };
+        }
+
+        self.misa = misa;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hpm_counter_counts_cycles_when_configured() {
+        let mut csr = Csr::new();
+        // Configure mhpmcounter3 (CSR 0xB03) to count cycles.
+        assert!(csr.write(CSR_MHPMEVENT3, HPM_EVENT_CYCLES, PrivilegeLevel::Machine));
+        assert_eq!(csr.read(CSR_MHPMCOUNTER3, PrivilegeLevel::Machine), Some(0));
+
+        csr.advance(10);
+        assert_eq!(csr.read(CSR_MHPMCOUNTER3, PrivilegeLevel::Machine), Some(10));
+        assert_eq!(csr.cycle, 10);
+    }
+
+    #[test]
+    fn test_hpm_counter_ignores_unimplemented_events() {
+        let mut csr = Csr::new();
+        assert!(csr.write(CSR_MHPMEVENT3, 0x42, PrivilegeLevel::Machine));
+        assert_eq!(csr.read(CSR_MHPMEVENT3, PrivilegeLevel::Machine), Some(HPM_EVENT_OFF));
+
+        csr.advance(100);
+        assert_eq!(csr.read(CSR_MHPMCOUNTER3, PrivilegeLevel::Machine), Some(0));
+    }
+
+    #[test]
+    fn test_stimecmp_gated_by_menvcfg_stce() {
+        let mut csr = Csr::new();
+
+        // STCE clear: S-mode can't see stimecmp at all.
+        assert_eq!(csr.read(CSR_STIMECMP, PrivilegeLevel::Supervisor), None);
+        assert!(!csr.write(CSR_STIMECMP, 0x1234, PrivilegeLevel::Supervisor));
+
+        // M-mode is unaffected by STCE either way.
+        assert!(csr.write(CSR_STIMECMP, 0x1234, PrivilegeLevel::Machine));
+        assert_eq!(csr.read(CSR_STIMECMP, PrivilegeLevel::Machine), Some(0x1234));
+
+        // Enable STCE via menvcfgh, S-mode access now works.
+        assert!(csr.write(CSR_MENVCFGH, MENVCFGH_STCE, PrivilegeLevel::Machine));
+        assert!(csr.write(CSR_STIMECMP, 0xaabb_ccdd, PrivilegeLevel::Supervisor));
+        assert!(csr.write(CSR_STIMECMPH, 0x1, PrivilegeLevel::Supervisor));
+        assert_eq!(csr.read(CSR_STIMECMP, PrivilegeLevel::Supervisor), Some(0xaabb_ccdd));
+        assert_eq!(csr.read(CSR_STIMECMPH, PrivilegeLevel::Supervisor), Some(0x1));
+        assert_eq!(csr.stimecmp, 0x1_aabb_ccdd);
+    }
+
+    #[test]
+    fn test_hpm_counter_respects_mcounteren() {
+        let mut csr = Csr::new();
+        // mcounteren bit 3 (counter #3) not set: S-mode read is denied.
+        assert_eq!(csr.read(CSR_HPMCOUNTER3, PrivilegeLevel::Supervisor), None);
+
+        csr.mcounteren |= 1 << 3;
+        assert_eq!(csr.read(CSR_HPMCOUNTER3, PrivilegeLevel::Supervisor), Some(0));
+    }
+
+    #[test]
+    fn test_vendor_arch_impl_id_csrs_are_read_only() {
+        let mut csr = Csr::new();
+        csr.mvendorid = 0x1234;
+        csr.marchid = 0x5678;
+        csr.mimpid = 0x9abc;
+
+        assert_eq!(csr.read(CSR_MVENDORID, PrivilegeLevel::Machine), Some(0x1234));
+        assert_eq!(csr.read(CSR_MARCHID, PrivilegeLevel::Machine), Some(0x5678));
+        assert_eq!(csr.read(CSR_MIMPID, PrivilegeLevel::Machine), Some(0x9abc));
+
+        // Read-only per the address encoding ([11:10] == 0b11): writes must
+        // be rejected, not silently accepted.
+        assert!(!csr.write(CSR_MVENDORID, 0, PrivilegeLevel::Machine));
+        assert_eq!(csr.mvendorid, 0x1234);
     }
 }
@@ -232,3 +232,39 @@ pub const FUNCT3_FLE: u32 = 0b000;
 // FP fmt field (for fused multiply-add, bits [26:25])
 pub const FMT_S: u32 = 0b00;  // Single-precision
 pub const FMT_D: u32 = 0b01;  // Double-precision
+
+// Zba (address generation) funct7/funct3, both on OP
+pub const FUNCT7_ZBA: u32 = 0b0010000;
+pub const FUNCT3_SH1ADD: u32 = 0b010;
+pub const FUNCT3_SH2ADD: u32 = 0b100;
+pub const FUNCT3_SH3ADD: u32 = 0b110;
+
+// Zbb (basic bit manipulation) funct7/funct3
+pub const FUNCT7_ANDN_ORN_XNOR: u32 = 0b0100000; // OP
+pub const FUNCT3_ANDN: u32 = 0b111;
+pub const FUNCT3_ORN: u32 = 0b110;
+pub const FUNCT3_XNOR: u32 = 0b100;
+pub const FUNCT7_MIN_MAX: u32 = 0b0000101; // OP
+pub const FUNCT3_MIN: u32 = 0b100;
+pub const FUNCT3_MINU: u32 = 0b101;
+pub const FUNCT3_MAX: u32 = 0b110;
+pub const FUNCT3_MAXU: u32 = 0b111;
+pub const FUNCT7_ROL_ROR: u32 = 0b0110000; // OP (ROL/ROR) and OP-IMM (RORI, CLZ/CTZ/CPOP/SEXT.B/SEXT.H)
+pub const FUNCT3_ROL: u32 = 0b001;
+pub const FUNCT3_ROR_RORI: u32 = 0b101;
+pub const FUNCT3_CLZ_CTZ_CPOP_SEXT: u32 = 0b001; // OP-IMM, rs2 picks the sub-op
+pub const RS2_CLZ: u32 = 0b00000;
+pub const RS2_CTZ: u32 = 0b00001;
+pub const RS2_CPOP: u32 = 0b00010;
+pub const RS2_SEXT_B: u32 = 0b00100;
+pub const RS2_SEXT_H: u32 = 0b00101;
+pub const FUNCT7_ZEXT_H: u32 = 0b0000100; // OP, rs2 = x0
+pub const FUNCT3_ZEXT_H: u32 = 0b100;
+
+// Zbs (single-bit) funct7/funct3, on both OP and OP-IMM
+pub const FUNCT7_BCLR_BCLRI: u32 = 0b0100100;
+pub const FUNCT7_BEXT_BEXTI: u32 = 0b0100100; // same funct7 as BCLR, distinguished by funct3
+pub const FUNCT7_BINV_BINVI: u32 = 0b0110100;
+pub const FUNCT7_BSET_BSETI: u32 = 0b0010100;
+pub const FUNCT3_BCLR_BINV_BSET: u32 = 0b001;
+pub const FUNCT3_BEXT: u32 = 0b101;
@@ -11,7 +11,53 @@ use crate::cpu::PrivilegeLevel;
 use crate::cpu::trap::{self, Trap};
 use crate::memory::Bus;
 
+/// `slli x0,x0,0x1f` - first half of the ARM-style semihosting EBREAK marker.
+const SEMIHOSTING_SLLI: u32 = 0x01f0_1013;
+/// `srai x0,x0,7` - second half of the semihosting EBREAK marker.
+const SEMIHOSTING_SRAI: u32 = 0x4070_5013;
+
+const SYS_WRITEC: u32 = 0x03;
+const SYS_WRITE0: u32 = 0x04;
+const SYS_EXIT: u32 = 0x18;
+/// ADP_Stopped_ApplicationExit - the only exit reason we bother
+/// distinguishing; anything else exits with status 0.
+const ADP_STOPPED_APPLICATION_EXIT: u32 = 0x2_0026;
+
 impl Cpu {
+    /// Dispatch an ARM-style semihosting call: a0 (x10) is the operation
+    /// number, a1 (x11) is the parameter block address.
+    fn handle_semihosting_call(&mut self, bus: &mut impl Bus) {
+        let op = self.read_reg(10);
+        let param = self.read_reg(11);
+
+        match op {
+            SYS_WRITEC => {
+                self.semihosting_output.push(bus.read8(param));
+            }
+            SYS_WRITE0 => {
+                let mut addr = param;
+                loop {
+                    let byte = bus.read8(addr);
+                    if byte == 0 {
+                        break;
+                    }
+                    self.semihosting_output.push(byte);
+                    addr = addr.wrapping_add(1);
+                }
+            }
+            SYS_EXIT => {
+                let reason = bus.read32(param);
+                let code = if reason == ADP_STOPPED_APPLICATION_EXIT {
+                    bus.read32(param.wrapping_add(4)) as i32
+                } else {
+                    0
+                };
+                self.exit_code = Some(code);
+            }
+            _ => {}
+        }
+    }
+
     /// Execute using cached decoded instruction (fast path)
     #[inline(always)]
     pub fn execute_cached(&mut self, inst: u32, cached: &CachedInst, bus: &mut impl Bus) -> Result<(), Trap> {
@@ -19,25 +65,25 @@ impl Cpu {
         
         match d.opcode {
             OP_LUI => {
-                let imm = DecodedInst::imm_u(inst) as u32;
+                let imm = cached.imm as u32;
                 self.write_reg(d.rd, imm);
                 self.pc = self.pc.wrapping_add(4);
             }
             
             OP_AUIPC => {
-                let imm = DecodedInst::imm_u(inst) as u32;
+                let imm = cached.imm as u32;
                 self.write_reg(d.rd, self.pc.wrapping_add(imm));
                 self.pc = self.pc.wrapping_add(4);
             }
             
             OP_JAL => {
-                let imm = DecodedInst::imm_j(inst) as u32;
+                let imm = cached.imm as u32;
                 self.write_reg(d.rd, self.pc.wrapping_add(4));
                 self.pc = self.pc.wrapping_add(imm);
             }
             
             OP_JALR => {
-                let imm = DecodedInst::imm_i(inst) as u32;
+                let imm = cached.imm as u32;
                 let target = (self.read_reg(d.rs1).wrapping_add(imm)) & !1;
                 self.write_reg(d.rd, self.pc.wrapping_add(4));
                 self.pc = target;
@@ -46,7 +92,7 @@ impl Cpu {
             OP_BRANCH => {
                 let rs1 = self.read_reg(d.rs1);
                 let rs2 = self.read_reg(d.rs2);
-                let imm = DecodedInst::imm_b(inst) as u32;
+                let imm = cached.imm as u32;
                 
                 let taken = match d.funct3 {
                     FUNCT3_BEQ => rs1 == rs2,
@@ -66,7 +112,7 @@ impl Cpu {
             }
             
             OP_LOAD => {
-                let imm = DecodedInst::imm_i(inst) as u32;
+                let imm = cached.imm as u32;
                 let vaddr = self.read_reg(d.rs1).wrapping_add(imm);
                 let satp = self.csr.satp;
                 let mstatus = self.csr.mstatus;
@@ -85,7 +131,17 @@ impl Cpu {
                         return Err(Trap::from_cause(cause, vaddr));
                     }
                 };
-                
+
+                if self.strict_memory && !bus.is_mapped(paddr) {
+                    return Err(Trap::LoadAccessFault(vaddr));
+                }
+
+                // Host-imposed protection overlay (distinct from PMP, which
+                // the guest controls itself) - see `System::add_protected_range`.
+                if !bus.is_read_allowed(paddr) {
+                    return Err(Trap::LoadAccessFault(vaddr));
+                }
+
                 // Emulate misaligned loads (byte-by-byte) for full hardware support
                 let value = match d.funct3 {
                     FUNCT3_LB => bus.read8(paddr) as i8 as i32 as u32,
@@ -130,7 +186,7 @@ impl Cpu {
             }
             
             OP_STORE => {
-                let imm = DecodedInst::imm_s(inst) as u32;
+                let imm = cached.imm as u32;
                 let vaddr = self.read_reg(d.rs1).wrapping_add(imm);
                 let value = self.read_reg(d.rs2);
                 let satp = self.csr.satp;
@@ -150,7 +206,21 @@ impl Cpu {
                         return Err(Trap::from_cause(cause, vaddr));
                     }
                 };
-                
+
+                // The boot ROM is read-only on real hardware - a guest
+                // store here is always a bug, so fault instead of quietly
+                // discarding the write.
+                if bus.is_rom(paddr) {
+                    bus.record_rom_write_attempt(self.pc, paddr);
+                    return Err(Trap::StoreAccessFault(vaddr));
+                }
+
+                // Host-imposed protection overlay (distinct from PMP, which
+                // the guest controls itself) - see `System::add_protected_range`.
+                if !bus.is_write_allowed(paddr) {
+                    return Err(Trap::StoreAccessFault(vaddr));
+                }
+
                 // Emulate misaligned stores (byte-by-byte) for full hardware support
                 match d.funct3 {
                     0b000 => { // SB
@@ -184,46 +254,71 @@ impl Cpu {
                     }
                     _ => return Err(Trap::IllegalInstruction(inst)),
                 }
-                
+
+                // A plain store to the reserved word invalidates the LR
+                // reservation, so a subsequent SC correctly fails even
+                // though it never went through the AMO path itself.
+                if let Some(reserved) = self.reservation {
+                    let len = match d.funct3 { 0b000 => 1, 0b001 => 2, _ => 4 };
+                    if vaddr < reserved.wrapping_add(4) && vaddr.wrapping_add(len) > reserved {
+                        self.reservation = None;
+                    }
+                }
+
                 self.pc = self.pc.wrapping_add(4);
             }
-            
+
             OP_OP_IMM => {
                 let rs1 = self.read_reg(d.rs1);
-                let imm = DecodedInst::imm_i(inst) as u32;
+                let imm = cached.imm as u32;
                 let shamt = (imm & 0x1F) as u32;
-                
-                let result = match d.funct3 {
-                    FUNCT3_ADD_SUB => rs1.wrapping_add(imm), // ADDI
-                    FUNCT3_SLT => if (rs1 as i32) < (imm as i32) { 1 } else { 0 }, // SLTI
-                    FUNCT3_SLTU => if rs1 < imm { 1 } else { 0 }, // SLTIU
-                    FUNCT3_XOR => rs1 ^ imm, // XORI
-                    FUNCT3_OR => rs1 | imm, // ORI
-                    FUNCT3_AND => rs1 & imm, // ANDI
-                    FUNCT3_SLL => rs1 << shamt, // SLLI
-                    FUNCT3_SRL_SRA => {
-                        if (imm >> 10) & 1 != 0 {
-                            // SRAI
-                            ((rs1 as i32) >> shamt) as u32
-                        } else {
-                            // SRLI
-                            rs1 >> shamt
+
+                let result = if let Some(r) = Self::execute_b_op_imm(d.funct3, d.funct7, d.rs2, rs1, shamt) {
+                    if !self.csr.extension_enabled(MISA_B) {
+                        return Err(Trap::IllegalInstruction(inst));
+                    }
+                    r
+                } else {
+                    match d.funct3 {
+                        FUNCT3_ADD_SUB => rs1.wrapping_add(imm), // ADDI
+                        FUNCT3_SLT => if (rs1 as i32) < (imm as i32) { 1 } else { 0 }, // SLTI
+                        FUNCT3_SLTU => if rs1 < imm { 1 } else { 0 }, // SLTIU
+                        FUNCT3_XOR => rs1 ^ imm, // XORI
+                        FUNCT3_OR => rs1 | imm, // ORI
+                        FUNCT3_AND => rs1 & imm, // ANDI
+                        FUNCT3_SLL => rs1 << shamt, // SLLI
+                        FUNCT3_SRL_SRA => {
+                            if (imm >> 10) & 1 != 0 {
+                                // SRAI
+                                ((rs1 as i32) >> shamt) as u32
+                            } else {
+                                // SRLI
+                                rs1 >> shamt
+                            }
                         }
+                        _ => return Err(Trap::IllegalInstruction(inst)),
                     }
-                    _ => return Err(Trap::IllegalInstruction(inst)),
                 };
-                
+
                 self.write_reg(d.rd, result);
                 self.pc = self.pc.wrapping_add(4);
             }
-            
+
             OP_OP => {
                 let rs1 = self.read_reg(d.rs1);
                 let rs2 = self.read_reg(d.rs2);
-                
+
                 let result = if d.funct7 == 0b0000001 {
                     // M extension
+                    if !self.csr.extension_enabled(MISA_M) {
+                        return Err(Trap::IllegalInstruction(inst));
+                    }
                     self.execute_m_extension(d.funct3, rs1, rs2)?
+                } else if let Some(r) = Self::execute_b_op(d.funct3, d.funct7, d.rs2, rs1, rs2) {
+                    if !self.csr.extension_enabled(MISA_B) {
+                        return Err(Trap::IllegalInstruction(inst));
+                    }
+                    r
                 } else {
                     // Base integer
                     match (d.funct3, d.funct7) {
@@ -240,7 +335,7 @@ impl Cpu {
                         _ => return Err(Trap::IllegalInstruction(inst)),
                     }
                 };
-                
+
                 self.write_reg(d.rd, result);
                 self.pc = self.pc.wrapping_add(4);
             }
@@ -250,9 +345,10 @@ impl Cpu {
                 // funct3 == 0: FENCE (memory ordering - no-op in simple implementation)
                 // funct3 == 1: FENCE.I (instruction cache synchronization)
                 if d.funct3 == 1 {
-                    // FENCE.I - invalidate instruction cache
+                    // FENCE.I - invalidate instruction cache only; the TLB is
+                    // untouched (that's SFENCE.VMA's job, see execute_system).
                     self.icache.invalidate_all();
-                    self.cache_invalidation_pending = true;
+                    self.icache_invalidation_pending = true;
                 }
                 self.pc = self.pc.wrapping_add(4);
             }
@@ -351,9 +447,60 @@ impl Cpu {
             _ => return Err(Trap::IllegalInstruction(0)),
         })
     }
-    
+
+    /// Execute a Zba/Zbb/Zbs register-register (OP) instruction. Returns
+    /// `None` if `funct3`/`funct7` don't name one, so the caller falls
+    /// through to base-ISA decoding - the extension check happens there,
+    /// once we know an instruction actually matched.
+    fn execute_b_op(funct3: u32, funct7: u32, rs2_reg: u32, rs1: u32, rs2: u32) -> Option<u32> {
+        Some(match (funct7, funct3) {
+            (FUNCT7_ZBA, FUNCT3_SH1ADD) => (rs1 << 1).wrapping_add(rs2),
+            (FUNCT7_ZBA, FUNCT3_SH2ADD) => (rs1 << 2).wrapping_add(rs2),
+            (FUNCT7_ZBA, FUNCT3_SH3ADD) => (rs1 << 3).wrapping_add(rs2),
+            (FUNCT7_ANDN_ORN_XNOR, FUNCT3_ANDN) => rs1 & !rs2,
+            (FUNCT7_ANDN_ORN_XNOR, FUNCT3_ORN) => rs1 | !rs2,
+            (FUNCT7_ANDN_ORN_XNOR, FUNCT3_XNOR) => !(rs1 ^ rs2),
+            (FUNCT7_MIN_MAX, FUNCT3_MIN) => ((rs1 as i32).min(rs2 as i32)) as u32,
+            (FUNCT7_MIN_MAX, FUNCT3_MINU) => rs1.min(rs2),
+            (FUNCT7_MIN_MAX, FUNCT3_MAX) => ((rs1 as i32).max(rs2 as i32)) as u32,
+            (FUNCT7_MIN_MAX, FUNCT3_MAXU) => rs1.max(rs2),
+            (FUNCT7_ROL_ROR, FUNCT3_ROL) => rs1.rotate_left(rs2 & 0x1F),
+            (FUNCT7_ROL_ROR, FUNCT3_ROR_RORI) => rs1.rotate_right(rs2 & 0x1F),
+            // ZEXT.H is `pack rd, rs1, x0` - only valid when rs2 names x0.
+            (FUNCT7_ZEXT_H, FUNCT3_ZEXT_H) if rs2_reg == 0 => rs1 & 0xFFFF,
+            (FUNCT7_BCLR_BCLRI, FUNCT3_BCLR_BINV_BSET) => rs1 & !(1u32 << (rs2 & 0x1F)),
+            (FUNCT7_BEXT_BEXTI, FUNCT3_BEXT) => (rs1 >> (rs2 & 0x1F)) & 1,
+            (FUNCT7_BINV_BINVI, FUNCT3_BCLR_BINV_BSET) => rs1 ^ (1u32 << (rs2 & 0x1F)),
+            (FUNCT7_BSET_BSETI, FUNCT3_BCLR_BINV_BSET) => rs1 | (1u32 << (rs2 & 0x1F)),
+            _ => return None,
+        })
+    }
+
+    /// Execute a Zba/Zbb/Zbs register-immediate (OP-IMM) instruction, given
+    /// the already-masked 5-bit `shamt`. Returns `None` if `funct3`/`funct7`
+    /// (and, for CLZ/CTZ/CPOP/SEXT.B/SEXT.H, the `rs2` sub-op field) don't
+    /// name one.
+    fn execute_b_op_imm(funct3: u32, funct7: u32, rs2: u32, rs1: u32, shamt: u32) -> Option<u32> {
+        Some(match (funct7, funct3) {
+            (FUNCT7_ROL_ROR, FUNCT3_CLZ_CTZ_CPOP_SEXT) => match rs2 {
+                RS2_CLZ => rs1.leading_zeros(),
+                RS2_CTZ => rs1.trailing_zeros(),
+                RS2_CPOP => rs1.count_ones(),
+                RS2_SEXT_B => (rs1 as i8) as i32 as u32,
+                RS2_SEXT_H => (rs1 as i16) as i32 as u32,
+                _ => return None,
+            },
+            (FUNCT7_ROL_ROR, FUNCT3_ROR_RORI) => rs1.rotate_right(shamt),
+            (FUNCT7_BCLR_BCLRI, FUNCT3_BCLR_BINV_BSET) => rs1 & !(1u32 << shamt),
+            (FUNCT7_BEXT_BEXTI, FUNCT3_BEXT) => (rs1 >> shamt) & 1,
+            (FUNCT7_BINV_BINVI, FUNCT3_BCLR_BINV_BSET) => rs1 ^ (1u32 << shamt),
+            (FUNCT7_BSET_BSETI, FUNCT3_BCLR_BINV_BSET) => rs1 | (1u32 << shamt),
+            _ => return None,
+        })
+    }
+
     /// Execute SYSTEM instructions
-    fn execute_system(&mut self, inst: u32, d: &DecodedInst, _bus: &mut impl Bus) -> Result<(), Trap> {
+    fn execute_system(&mut self, inst: u32, d: &DecodedInst, bus: &mut impl Bus) -> Result<(), Trap> {
         match d.funct3 {
             FUNCT3_PRIV => {
                 match inst {
@@ -367,7 +514,17 @@ impl Cpu {
                         return Err(trap);
                     }
                     0x00100073 => {
-                        // EBREAK
+                        // EBREAK - check for the ARM-style semihosting magic
+                        // sequence (slli x0,x0,0x1f ; ebreak ; srai x0,x0,7)
+                        // surrounding this instruction before falling back
+                        // to a normal Breakpoint trap.
+                        let prev = self.pc.checked_sub(4).map(|addr| bus.read32(addr));
+                        let next = bus.read32(self.pc.wrapping_add(4));
+                        if prev == Some(SEMIHOSTING_SLLI) && next == SEMIHOSTING_SRAI {
+                            self.handle_semihosting_call(bus);
+                            self.pc = self.pc.wrapping_add(4);
+                            return Ok(());
+                        }
                         return Err(Trap::Breakpoint(self.pc));
                     }
                     0x10200073 => {
@@ -393,11 +550,13 @@ impl Cpu {
                         return Ok(());
                     }
                     _ => {
-                        // SFENCE.VMA
+                        // SFENCE.VMA - flush the TLB (synchronously, unlike
+                        // the block cache below) and invalidate the icache,
+                        // since a remapped page can change what's fetched.
                         if (inst >> 25) == 0b0001001 {
                             self.mmu.invalidate();
                             self.icache.invalidate_all();
-                            self.cache_invalidation_pending = true;
+                            self.icache_invalidation_pending = true;
                             self.pc = self.pc.wrapping_add(4);
                             return Ok(());
                         }
@@ -417,6 +576,16 @@ impl Cpu {
                     self.read_reg(d.rs1)
                 };
                 
+                // `time`/`timeh` are refreshed from the live CLINT counter
+                // right before the read instead of relying solely on the
+                // periodic batch update in `run_with_reason` - see
+                // `Bus::mtime`.
+                if csr_addr == CSR_TIME || csr_addr == CSR_TIMEH {
+                    if let Some(mtime) = bus.mtime() {
+                        self.csr.time = mtime;
+                    }
+                }
+
                 // Handle FP CSRs specially (they live in FPU, not CSR)
                 let old_val = match csr_addr {
                     CSR_FFLAGS => self.fpu.fflags.to_bits(),
@@ -452,6 +621,13 @@ impl Cpu {
                         }
                         _ => {
                             if csr_addr == CSR_SATP && new_val != old_val {
+                                // Only the TLB needs to go here: the icache
+                                // and block cache are keyed on physical
+                                // address and (for the icache) content, so
+                                // a remapping alone can't make either serve
+                                // stale decodes - that only happens via
+                                // self-modifying code, which FENCE.I/
+                                // SFENCE.VMA already cover above.
                                 self.mmu.invalidate();
                             }
                             if !self.csr.write(csr_addr, new_val, self.priv_level) {
@@ -473,13 +649,23 @@ impl Cpu {
     
     /// Execute atomic (A extension) instructions
     fn execute_amo(&mut self, inst: u32, d: &DecodedInst, bus: &mut impl Bus) -> Result<(), Trap> {
+        if !self.csr.extension_enabled(MISA_A) {
+            return Err(Trap::IllegalInstruction(inst));
+        }
+
         let vaddr = self.read_reg(d.rs1);
-        
-        // Check alignment
+        let funct5 = d.funct7 >> 2;
+
+        // The A extension requires naturally-aligned addresses for every
+        // LR/SC/AMO; LR faults as a load, SC and the AMOs fault as a store.
         if vaddr & 3 != 0 {
-            return Err(Trap::StoreAddressMisaligned(vaddr));
+            return Err(if funct5 == FUNCT5_LR {
+                Trap::LoadAddressMisaligned(vaddr)
+            } else {
+                Trap::StoreAddressMisaligned(vaddr)
+            });
         }
-        
+
         // Get translation parameters
         let satp = self.csr.satp;
         let mstatus = self.csr.mstatus;
@@ -491,8 +677,6 @@ impl Cpu {
             priv_level = PrivilegeLevel::from(mpp as u8);
         }
         
-        let funct5 = d.funct7 >> 2;
-        
         match funct5 {
             FUNCT5_LR => {
                 // LR.W - Load Reserved
@@ -503,7 +687,13 @@ impl Cpu {
                         return Err(Trap::from_cause(cause, vaddr));
                     }
                 };
-                
+                if !bus.is_ram(paddr) {
+                    return Err(Trap::LoadAccessFault(vaddr));
+                }
+                if !bus.is_read_allowed(paddr) {
+                    return Err(Trap::LoadAccessFault(vaddr));
+                }
+
                 let value = bus.read32(paddr);
                 self.write_reg(d.rd, value);
                 // Store VIRTUAL address for reservation (LR/SC pair uses same vaddr)
@@ -521,7 +711,13 @@ impl Cpu {
                             return Err(Trap::from_cause(cause, vaddr));
                         }
                     };
-                    
+                    if !bus.is_ram(paddr) {
+                        return Err(Trap::StoreAccessFault(vaddr));
+                    }
+                    if !bus.is_write_allowed(paddr) {
+                        return Err(Trap::StoreAccessFault(vaddr));
+                    }
+
                     bus.write32(paddr, self.read_reg(d.rs2));
                     self.write_reg(d.rd, 0); // Success
                 } else {
@@ -537,7 +733,13 @@ impl Cpu {
                         return Err(Trap::from_cause(cause, vaddr));
                     }
                 };
-                
+                if !bus.is_ram(paddr) {
+                    return Err(Trap::StoreAccessFault(vaddr));
+                }
+                if !bus.is_write_allowed(paddr) {
+                    return Err(Trap::StoreAccessFault(vaddr));
+                }
+
                 let old_val = bus.read32(paddr);
                 let rs2 = self.read_reg(d.rs2);
                 
@@ -0,0 +1,407 @@
+//! RV32 compressed (C) extension execution
+//!
+//! A compressed instruction is expanded into its equivalent 32-bit
+//! instruction and run through the normal `execute_cached` path, then the
+//! PC/return-address side effects (which `execute_cached` computes assuming
+//! a 4-byte instruction) are patched to the real 2-byte width. See
+//! `Cpu64::execute_compressed` for the RV64C sibling this mirrors; the two
+//! differ only in which quadrant-00/01/10 slots exist (RV32C has C.JAL and
+//! single-precision C.FLW/C.FSW/C.FLWSP/C.FSWSP where RV64C has C.ADDIW and
+//! C.LD/C.SD/C.LDSP/C.SDSP).
+
+use super::Cpu;
+use super::decode::*;
+use super::icache::CachedInst;
+use crate::cpu::trap::Trap;
+use crate::memory::Bus;
+
+impl Cpu {
+    pub fn execute_compressed(&mut self, inst16: u16, bus: &mut impl Bus) -> Result<(), Trap> {
+        let expanded = expand_compressed(inst16).ok_or(Trap::IllegalInstruction(inst16 as u32))?;
+        let pc_before = self.pc;
+        let cached = CachedInst::decode(expanded);
+
+        self.execute_cached(expanded, &cached, bus)?;
+        fixup_compressed_pc(self, pc_before, cached.opcode as u32, cached.rd);
+        Ok(())
+    }
+}
+
+/// After running a compressed instruction's expanded 32-bit equivalent
+/// through `execute_cached` - which always computes PC/return-address side
+/// effects assuming a 4-byte instruction - patch those up to the real
+/// 2-byte width. Shared by the interpreter's `execute_compressed` above and
+/// the JIT's basic-block executor, which both expand-then-patch this way.
+pub(crate) fn fixup_compressed_pc(cpu: &mut Cpu, pc_before: u32, opcode: u32, rd: u8) {
+    match opcode {
+        // Taken branch/jump targets were computed from the real immediate
+        // or register value, so only the straight-line +4 fallthrough and
+        // the +4 return address (for OP_JAL/OP_JALR with rd != 0) assume
+        // the wrong instruction width and need fixing up.
+        OP_JAL | OP_JALR => {
+            if rd != 0 {
+                cpu.write_reg(rd as u32, pc_before.wrapping_add(2));
+            }
+        }
+        _ => {
+            if cpu.pc == pc_before.wrapping_add(4) {
+                cpu.pc = pc_before.wrapping_add(2);
+            }
+        }
+    }
+}
+
+/// Expand a 16-bit RVC instruction into its equivalent 32-bit instruction,
+/// or `None` if it doesn't decode to a defined RV32C encoding (includes
+/// `0x0000`, which RVC reserves as an illegal instruction to catch
+/// accidentally-erased memory).
+pub(crate) fn expand_compressed(inst: u16) -> Option<u32> {
+    let opcode = inst & 0b11;
+    let funct3 = (inst >> 13) & 0b111;
+
+    match (funct3, opcode) {
+        (0b000, 0b00) => c_addi4spn(inst),
+        (0b001, 0b00) => c_fld(inst), // C.FLD - double-precision load
+        (0b010, 0b00) => c_lw(inst),
+        (0b011, 0b00) => c_flw(inst), // C.FLW - single-precision load
+        (0b101, 0b00) => c_fsd(inst), // C.FSD - double-precision store
+        (0b110, 0b00) => c_sw(inst),
+        (0b111, 0b00) => c_fsw(inst), // C.FSW - single-precision store
+
+        (0b000, 0b01) => c_addi(inst),
+        (0b001, 0b01) => c_jal(inst),
+        (0b010, 0b01) => c_li(inst),
+        (0b011, 0b01) => c_addi16sp_lui(inst),
+        (0b100, 0b01) => c_alu_imm(inst),
+        (0b101, 0b01) => c_j(inst),
+        (0b110, 0b01) => c_beqz(inst),
+        (0b111, 0b01) => c_bnez(inst),
+
+        (0b000, 0b10) => c_slli(inst),
+        (0b001, 0b10) => c_fldsp(inst), // C.FLDSP - double-precision load from sp
+        (0b010, 0b10) => c_lwsp(inst),
+        (0b011, 0b10) => c_flwsp(inst), // C.FLWSP - single-precision load from sp
+        (0b100, 0b10) => c_misc_alu(inst),
+        (0b101, 0b10) => c_fsdsp(inst), // C.FSDSP - double-precision store to sp
+        (0b110, 0b10) => c_swsp(inst),
+        (0b111, 0b10) => c_fswsp(inst), // C.FSWSP - single-precision store to sp
+        _ => None,
+    }
+}
+
+fn reg_prime(val: u16) -> u32 {
+    8 + (val as u32 & 0x7)
+}
+
+fn sign_extend(val: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    ((val << shift) as i32) >> shift
+}
+
+fn encode_i(op: u32, rd: u32, rs1: u32, funct3: u32, imm: i32) -> u32 {
+    let imm_u = (imm as u32) & 0xFFF;
+    (imm_u << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | op
+}
+
+fn encode_u(op: u32, rd: u32, imm: i32) -> u32 {
+    (imm as u32 & 0xFFFFF000) | (rd << 7) | op
+}
+
+fn encode_r(op: u32, rd: u32, rs1: u32, rs2: u32, funct3: u32, funct7: u32) -> u32 {
+    (funct7 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | op
+}
+
+fn encode_s(op: u32, rs1: u32, rs2: u32, funct3: u32, imm: i32) -> u32 {
+    let imm_u = imm as u32;
+    let imm_11_5 = (imm_u >> 5) & 0x7F;
+    let imm_4_0 = imm_u & 0x1F;
+    (imm_11_5 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (imm_4_0 << 7) | op
+}
+
+fn encode_b(op: u32, rs1: u32, rs2: u32, funct3: u32, imm: i32) -> u32 {
+    let imm_u = imm as u32;
+    let imm_12 = (imm_u >> 12) & 1;
+    let imm_10_5 = (imm_u >> 5) & 0x3F;
+    let imm_4_1 = (imm_u >> 1) & 0xF;
+    let imm_11 = (imm_u >> 11) & 1;
+    (imm_12 << 31) | (imm_10_5 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) |
+        (imm_4_1 << 8) | (imm_11 << 7) | op
+}
+
+fn encode_j(op: u32, rd: u32, imm: i32) -> u32 {
+    let imm_u = imm as u32;
+    let imm_20 = (imm_u >> 20) & 1;
+    let imm_10_1 = (imm_u >> 1) & 0x3FF;
+    let imm_11 = (imm_u >> 11) & 1;
+    let imm_19_12 = (imm_u >> 12) & 0xFF;
+    (imm_20 << 31) | (imm_19_12 << 12) | (imm_11 << 20) | (imm_10_1 << 21) | (rd << 7) | op
+}
+
+fn c_addi4spn(inst: u16) -> Option<u32> {
+    let rd = reg_prime((inst >> 2) & 0x7);
+    let imm = ((inst as u32 >> 12) & 1) << 5
+        | ((inst as u32 >> 11) & 1) << 4
+        | ((inst as u32 >> 7) & 0xF) << 6
+        | ((inst as u32 >> 6) & 1) << 2
+        | ((inst as u32 >> 5) & 1) << 3;
+    if imm == 0 {
+        return None;
+    }
+    Some(encode_i(OP_OP_IMM, rd, 2, FUNCT3_ADD_SUB, imm as i32))
+}
+
+fn c_lw(inst: u16) -> Option<u32> {
+    let rd = reg_prime((inst >> 2) & 0x7);
+    let rs1 = reg_prime((inst >> 7) & 0x7);
+    let imm = ((inst as u32 >> 10) & 0x7) << 3
+        | ((inst as u32 >> 6) & 1) << 2
+        | ((inst as u32 >> 5) & 1) << 6;
+    Some(encode_i(OP_LOAD, rd, rs1, FUNCT3_LW, imm as i32))
+}
+
+fn c_flw(inst: u16) -> Option<u32> {
+    let rd = reg_prime((inst >> 2) & 0x7);
+    let rs1 = reg_prime((inst >> 7) & 0x7);
+    let imm = ((inst as u32 >> 10) & 0x7) << 3
+        | ((inst as u32 >> 6) & 1) << 2
+        | ((inst as u32 >> 5) & 1) << 6;
+    Some(encode_i(OP_LOAD_FP, rd, rs1, FUNCT3_FLW, imm as i32))
+}
+
+fn c_sw(inst: u16) -> Option<u32> {
+    let rs2 = reg_prime((inst >> 2) & 0x7);
+    let rs1 = reg_prime((inst >> 7) & 0x7);
+    let imm = ((inst as u32 >> 10) & 0x7) << 3
+        | ((inst as u32 >> 6) & 1) << 2
+        | ((inst as u32 >> 5) & 1) << 6;
+    Some(encode_s(OP_STORE, rs1, rs2, FUNCT3_LW, imm as i32))
+}
+
+fn c_fsw(inst: u16) -> Option<u32> {
+    let rs2 = reg_prime((inst >> 2) & 0x7);
+    let rs1 = reg_prime((inst >> 7) & 0x7);
+    let imm = ((inst as u32 >> 10) & 0x7) << 3
+        | ((inst as u32 >> 6) & 1) << 2
+        | ((inst as u32 >> 5) & 1) << 6;
+    Some(encode_s(OP_STORE_FP, rs1, rs2, FUNCT3_FLW, imm as i32))
+}
+
+// C.FLD - compressed double-precision load: fld rd', offset(rs1')
+fn c_fld(inst: u16) -> Option<u32> {
+    let rd = reg_prime((inst >> 2) & 0x7);
+    let rs1 = reg_prime((inst >> 7) & 0x7);
+    let imm = ((inst as u32 >> 10) & 0x7) << 3
+        | ((inst as u32 >> 5) & 0x3) << 6;
+    Some(encode_i(OP_LOAD_FP, rd, rs1, FUNCT3_FLD, imm as i32))
+}
+
+// C.FSD - compressed double-precision store: fsd rs2', offset(rs1')
+fn c_fsd(inst: u16) -> Option<u32> {
+    let rs2 = reg_prime((inst >> 2) & 0x7);
+    let rs1 = reg_prime((inst >> 7) & 0x7);
+    let imm = ((inst as u32 >> 10) & 0x7) << 3
+        | ((inst as u32 >> 5) & 0x3) << 6;
+    Some(encode_s(OP_STORE_FP, rs1, rs2, FUNCT3_FLD, imm as i32))
+}
+
+fn c_addi(inst: u16) -> Option<u32> {
+    let rd = ((inst >> 7) & 0x1F) as u32;
+    let imm = sign_extend(((inst as u32 >> 2) & 0x1F) | ((inst as u32 >> 12) & 1) << 5, 6);
+    if rd == 0 && imm == 0 {
+        return Some(encode_i(OP_OP_IMM, 0, 0, FUNCT3_ADD_SUB, 0));
+    }
+    Some(encode_i(OP_OP_IMM, rd, rd, FUNCT3_ADD_SUB, imm))
+}
+
+// C.JAL - RV32C only (the RV64C encoding at this slot is C.ADDIW instead).
+fn c_jal(inst: u16) -> Option<u32> {
+    let imm = decode_cj_imm(inst);
+    Some(encode_j(OP_JAL, 1, imm))
+}
+
+fn c_li(inst: u16) -> Option<u32> {
+    let rd = ((inst >> 7) & 0x1F) as u32;
+    let imm = sign_extend(((inst as u32 >> 2) & 0x1F) | ((inst as u32 >> 12) & 1) << 5, 6);
+    Some(encode_i(OP_OP_IMM, rd, 0, FUNCT3_ADD_SUB, imm))
+}
+
+fn c_addi16sp_lui(inst: u16) -> Option<u32> {
+    let rd = ((inst >> 7) & 0x1F) as u32;
+    if rd == 2 {
+        let imm = ((inst as u32 >> 12) & 1) << 9
+            | ((inst as u32 >> 3) & 0x3) << 7
+            | ((inst as u32 >> 5) & 1) << 6
+            | ((inst as u32 >> 2) & 1) << 5
+            | ((inst as u32 >> 6) & 1) << 4;
+        let imm = sign_extend(imm, 10);
+        Some(encode_i(OP_OP_IMM, 2, 2, FUNCT3_ADD_SUB, imm))
+    } else {
+        let imm = sign_extend(((inst as u32 >> 12) & 1) << 5 | ((inst as u32 >> 2) & 0x1F), 6);
+        if imm == 0 {
+            return None;
+        }
+        Some(encode_u(OP_LUI, rd, imm << 12))
+    }
+}
+
+fn c_alu_imm(inst: u16) -> Option<u32> {
+    let subop = (inst >> 10) & 0x3;
+    let rs1 = reg_prime((inst >> 7) & 0x7);
+
+    match subop {
+        0b00 => {
+            let shamt = ((inst as u32 >> 2) & 0x1F) | (((inst as u32 >> 12) & 1) << 5);
+            // RV32C's nzuimm is only 5 bits (bit 12 must be 0); a well-formed
+            // encoder never sets it, but mask defensively rather than shift
+            // by an out-of-range amount for a 32-bit register.
+            Some(encode_i(OP_OP_IMM, rs1, rs1, FUNCT3_SRL_SRA, (shamt & 0x1F) as i32))
+        }
+        0b01 => {
+            let shamt = ((inst as u32 >> 2) & 0x1F) | (((inst as u32 >> 12) & 1) << 5);
+            Some(encode_i(OP_OP_IMM, rs1, rs1, FUNCT3_SRL_SRA, (0b010000 << 6) | (shamt & 0x1F) as i32))
+        }
+        0b10 => {
+            let imm = sign_extend(((inst as u32 >> 2) & 0x1F) | ((inst as u32 >> 12) & 1) << 5, 6);
+            Some(encode_i(OP_OP_IMM, rs1, rs1, FUNCT3_AND, imm))
+        }
+        0b11 => c_alu_reg(inst),
+        _ => None,
+    }
+}
+
+fn c_alu_reg(inst: u16) -> Option<u32> {
+    // Bit 12 selects C.SUBW/C.ADDW on RV64C/RV128C; those words don't exist
+    // for RV32C, so treat it as an illegal instruction here.
+    if (inst >> 12) & 1 != 0 {
+        return None;
+    }
+    let rs1 = reg_prime((inst >> 7) & 0x7);
+    let rs2 = reg_prime((inst >> 2) & 0x7);
+    let funct2 = (inst >> 5) & 0x3;
+    let (funct3, funct7) = match funct2 {
+        0b00 => (FUNCT3_ADD_SUB, 0b0100000),
+        0b01 => (FUNCT3_XOR, 0b0000000),
+        0b10 => (FUNCT3_OR, 0b0000000),
+        0b11 => (FUNCT3_AND, 0b0000000),
+        _ => return None,
+    };
+    Some(encode_r(OP_OP, rs1, rs1, rs2, funct3, funct7))
+}
+
+fn c_j(inst: u16) -> Option<u32> {
+    let imm = decode_cj_imm(inst);
+    Some(encode_j(OP_JAL, 0, imm))
+}
+
+fn c_beqz(inst: u16) -> Option<u32> {
+    let rs1 = reg_prime((inst >> 7) & 0x7);
+    let imm = decode_cb_imm(inst);
+    Some(encode_b(OP_BRANCH, rs1, 0, FUNCT3_BEQ, imm))
+}
+
+fn c_bnez(inst: u16) -> Option<u32> {
+    let rs1 = reg_prime((inst >> 7) & 0x7);
+    let imm = decode_cb_imm(inst);
+    Some(encode_b(OP_BRANCH, rs1, 0, FUNCT3_BNE, imm))
+}
+
+fn c_slli(inst: u16) -> Option<u32> {
+    let rd = ((inst >> 7) & 0x1F) as u32;
+    let shamt = ((inst as u32 >> 2) & 0x1F) | (((inst as u32 >> 12) & 1) << 5);
+    Some(encode_i(OP_OP_IMM, rd, rd, FUNCT3_SLL, (shamt & 0x1F) as i32))
+}
+
+fn c_lwsp(inst: u16) -> Option<u32> {
+    let rd = ((inst >> 7) & 0x1F) as u32;
+    if rd == 0 {
+        return None;
+    }
+    let imm = ((inst as u32 >> 12) & 1) << 5
+        | ((inst as u32 >> 4) & 0x7) << 2
+        | ((inst as u32 >> 2) & 0x3) << 6;
+    Some(encode_i(OP_LOAD, rd, 2, FUNCT3_LW, imm as i32))
+}
+
+fn c_flwsp(inst: u16) -> Option<u32> {
+    let rd = ((inst >> 7) & 0x1F) as u32;
+    let imm = ((inst as u32 >> 12) & 1) << 5
+        | ((inst as u32 >> 4) & 0x7) << 2
+        | ((inst as u32 >> 2) & 0x3) << 6;
+    Some(encode_i(OP_LOAD_FP, rd, 2, FUNCT3_FLW, imm as i32))
+}
+
+fn c_misc_alu(inst: u16) -> Option<u32> {
+    let rd = ((inst >> 7) & 0x1F) as u32;
+    let rs2 = ((inst >> 2) & 0x1F) as u32;
+    let bit12 = (inst >> 12) & 1;
+
+    if bit12 == 0 {
+        if rs2 == 0 {
+            if rd == 0 {
+                None
+            } else {
+                Some(encode_i(OP_JALR, 0, rd, FUNCT3_ADD_SUB, 0))
+            }
+        } else {
+            Some(encode_r(OP_OP, rd, 0, rs2, FUNCT3_ADD_SUB, 0))
+        }
+    } else if rs2 == 0 {
+        if rd == 0 {
+            Some(0x0010_0073) // C.EBREAK
+        } else {
+            Some(encode_i(OP_JALR, 1, rd, FUNCT3_ADD_SUB, 0))
+        }
+    } else {
+        Some(encode_r(OP_OP, rd, rd, rs2, FUNCT3_ADD_SUB, 0))
+    }
+}
+
+fn c_swsp(inst: u16) -> Option<u32> {
+    let rs2 = ((inst >> 2) & 0x1F) as u32;
+    let imm = ((inst as u32 >> 9) & 0xF) << 2
+        | ((inst as u32 >> 7) & 0x3) << 6;
+    Some(encode_s(OP_STORE, 2, rs2, FUNCT3_LW, imm as i32))
+}
+
+fn c_fswsp(inst: u16) -> Option<u32> {
+    let rs2 = ((inst >> 2) & 0x1F) as u32;
+    let imm = ((inst as u32 >> 9) & 0xF) << 2
+        | ((inst as u32 >> 7) & 0x3) << 6;
+    Some(encode_s(OP_STORE_FP, 2, rs2, FUNCT3_FLW, imm as i32))
+}
+
+fn c_fldsp(inst: u16) -> Option<u32> {
+    let rd = ((inst >> 7) & 0x1F) as u32;
+    let imm = ((inst as u32 >> 12) & 0x1) << 5
+        | ((inst as u32 >> 5) & 0x3) << 3
+        | ((inst as u32 >> 2) & 0x7) << 6;
+    Some(encode_i(OP_LOAD_FP, rd, 2, FUNCT3_FLD, imm as i32))
+}
+
+fn c_fsdsp(inst: u16) -> Option<u32> {
+    let rs2 = ((inst >> 2) & 0x1F) as u32;
+    let imm = ((inst as u32 >> 10) & 0x7) << 3
+        | ((inst as u32 >> 7) & 0x7) << 6;
+    Some(encode_s(OP_STORE_FP, 2, rs2, FUNCT3_FLD, imm as i32))
+}
+
+fn decode_cj_imm(inst: u16) -> i32 {
+    let imm = ((inst as u32 >> 12) & 1) << 11
+        | ((inst as u32 >> 8) & 0x1) << 10
+        | ((inst as u32 >> 9) & 0x3) << 8
+        | ((inst as u32 >> 6) & 0x1) << 7
+        | ((inst as u32 >> 7) & 0x1) << 6
+        | ((inst as u32 >> 2) & 0x1) << 5
+        | ((inst as u32 >> 11) & 0x1) << 4
+        | ((inst as u32 >> 3) & 0x7) << 1;
+    sign_extend(imm, 12)
+}
+
+fn decode_cb_imm(inst: u16) -> i32 {
+    let imm = ((inst as u32 >> 12) & 1) << 8
+        | ((inst as u32 >> 5) & 0x3) << 6
+        | ((inst as u32 >> 2) & 0x1) << 5
+        | ((inst as u32 >> 10) & 0x3) << 3
+        | ((inst as u32 >> 3) & 0x3) << 1;
+    sign_extend(imm, 9)
+}
@@ -14,8 +14,8 @@ use crate::memory::Bus;
 impl Cpu {
     /// Execute floating-point load instructions (FLW, FLD)
     pub fn execute_load_fp(&mut self, inst: u32, d: &DecodedInst, bus: &mut impl Bus) -> Result<(), Trap> {
-        // Check if FP is enabled (FS != 0 in mstatus)
-        if (self.csr.mstatus & MSTATUS_FS) == 0 {
+        // Check if the F extension is enabled and FP state is on
+        if !self.csr.extension_enabled(MISA_F) || (self.csr.mstatus & MSTATUS_FS) == 0 {
             return Err(Trap::IllegalInstruction(inst));
         }
         
@@ -68,8 +68,8 @@ impl Cpu {
     
     /// Execute floating-point store instructions (FSW, FSD)
     pub fn execute_store_fp(&mut self, inst: u32, d: &DecodedInst, bus: &mut impl Bus) -> Result<(), Trap> {
-        // Check if FP is enabled
-        if (self.csr.mstatus & MSTATUS_FS) == 0 {
+        // Check if the F extension is enabled and FP state is on
+        if !self.csr.extension_enabled(MISA_F) || (self.csr.mstatus & MSTATUS_FS) == 0 {
             return Err(Trap::IllegalInstruction(inst));
         }
         
@@ -91,7 +91,12 @@ impl Cpu {
                 return Err(Trap::from_cause(cause, vaddr));
             }
         };
-        
+
+        if bus.is_rom(paddr) {
+            bus.record_rom_write_attempt(self.pc, paddr);
+            return Err(Trap::StoreAccessFault(vaddr));
+        }
+
         match d.funct3 {
             FUNCT3_FLW => {
                 // FSW - Store single-precision float
@@ -119,8 +124,8 @@ impl Cpu {
     
     /// Execute fused multiply-add instructions (FMADD, FMSUB, FNMSUB, FNMADD)
     pub fn execute_fma(&mut self, inst: u32, d: &DecodedInst, opcode: u32) -> Result<(), Trap> {
-        // Check if FP is enabled
-        if (self.csr.mstatus & MSTATUS_FS) == 0 {
+        // Check if the F extension is enabled and FP state is on
+        if !self.csr.extension_enabled(MISA_F) || (self.csr.mstatus & MSTATUS_FS) == 0 {
             return Err(Trap::IllegalInstruction(inst));
         }
         
@@ -198,8 +203,8 @@ impl Cpu {
     
     /// Execute floating-point computational instructions (OP-FP opcode)
     pub fn execute_op_fp(&mut self, inst: u32, d: &DecodedInst) -> Result<(), Trap> {
-        // Check if FP is enabled
-        if (self.csr.mstatus & MSTATUS_FS) == 0 {
+        // Check if the F extension is enabled and FP state is on
+        if !self.csr.extension_enabled(MISA_F) || (self.csr.mstatus & MSTATUS_FS) == 0 {
             return Err(Trap::IllegalInstruction(inst));
         }
         
@@ -3,11 +3,15 @@
 //! Caches decoded instructions per physical page to avoid
 //! repeated decoding of hot code paths.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 /// Number of instruction slots per page (4KB / 4 bytes)
 const INSTS_PER_PAGE: usize = 1024;
 
+/// Default number of pages held before the cache starts evicting the
+/// oldest one to make room; see `ICache::with_capacity`.
+const DEFAULT_MAX_PAGES: usize = 64;
+
 /// Cached decoded instruction - minimal representation
 #[derive(Clone, Copy, Default)]
 pub struct CachedInst {
@@ -27,23 +31,68 @@ pub struct CachedInst {
     pub funct3: u8,
     /// funct7 (7 bits)
     pub funct7: u8,
+    /// Sign-extended immediate, pre-selected at decode time from whichever
+    /// format `opcode` actually uses (I/S/B/U/J); 0 for opcodes with no
+    /// immediate. Lets `execute_cached` use it directly instead of
+    /// re-running `DecodedInst::imm_*` on `raw` every execution.
+    pub imm: i32,
     /// Valid flag
     pub valid: bool,
+    /// Width in bytes of the instruction this was decoded from: 4 for a
+    /// normal instruction, 2 if it was expanded from a compressed (RVC)
+    /// encoding via `decode_compressed`. Block scanners (the JIT's
+    /// `discover_basic_blocks`) need this to advance by the real
+    /// instruction width instead of always assuming 4.
+    pub len: u8,
 }
 
 impl CachedInst {
     #[inline(always)]
     pub fn decode(raw: u32) -> Self {
+        let opcode = (raw & 0x7F) as u8;
         CachedInst {
             raw,
-            opcode: (raw & 0x7F) as u8,
+            opcode,
             rd: ((raw >> 7) & 0x1F) as u8,
             rs1: ((raw >> 15) & 0x1F) as u8,
             rs2: ((raw >> 20) & 0x1F) as u8,
             rs3: ((raw >> 27) & 0x1F) as u8,
             funct3: ((raw >> 12) & 0x7) as u8,
             funct7: ((raw >> 25) & 0x7F) as u8,
+            imm: Self::decode_imm(opcode, raw),
             valid: true,
+            len: 4,
+        }
+    }
+
+    /// Like `decode`, but for a 16-bit compressed (RVC) instruction: expand
+    /// it to its equivalent 32-bit instruction via `execute_c::expand_compressed`
+    /// and decode that, with `len` set to 2 and `raw` left holding the
+    /// *expanded* word so callers (icache/JIT) can still feed it straight
+    /// into `execute_cached`/offset-extraction unchanged. Returns `None` for
+    /// an undefined 16-bit encoding.
+    #[inline(always)]
+    pub fn decode_compressed(inst16: u16) -> Option<Self> {
+        let expanded = super::execute_c::expand_compressed(inst16)?;
+        let mut cached = Self::decode(expanded);
+        cached.len = 2;
+        Some(cached)
+    }
+
+    /// Select and sign-extend the one immediate format `opcode` actually
+    /// encodes, so callers don't need to know which format applies.
+    #[inline(always)]
+    fn decode_imm(opcode: u8, raw: u32) -> i32 {
+        use super::decode::{
+            DecodedInst, OP_AUIPC, OP_BRANCH, OP_JAL, OP_JALR, OP_LOAD, OP_LUI, OP_OP_IMM, OP_STORE,
+        };
+        match opcode as u32 {
+            OP_LUI | OP_AUIPC => DecodedInst::imm_u(raw),
+            OP_JAL => DecodedInst::imm_j(raw),
+            OP_JALR | OP_LOAD | OP_OP_IMM => DecodedInst::imm_i(raw),
+            OP_BRANCH => DecodedInst::imm_b(raw),
+            OP_STORE => DecodedInst::imm_s(raw),
+            _ => 0,
         }
     }
 }
@@ -67,11 +116,20 @@ impl CachedPage {
 pub struct ICache {
     /// Map from physical page number (paddr >> 12) to cached page
     pages: HashMap<u32, CachedPage>,
+    /// Page numbers in insertion order, oldest first, so a cache at
+    /// capacity knows which page to evict next. Only ever contains a page
+    /// number while `pages` also contains it.
+    page_order: VecDeque<u32>,
+    /// Maximum number of pages held at once; see `with_capacity`.
+    max_pages: usize,
     /// Generation counter for invalidation
     generation: u32,
     /// Stats
     pub hits: u64,
     pub misses: u64,
+    /// Pages dropped to stay within `max_pages`, distinct from the entries
+    /// invalidated by `invalidate_addr`/`invalidate_all`.
+    pub evictions: u64,
 }
 
 impl Default for ICache {
@@ -82,11 +140,22 @@ impl Default for ICache {
 
 impl ICache {
     pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_MAX_PAGES)
+    }
+
+    /// Like `new`, but holds at most `max_pages` pages at once, evicting
+    /// the oldest one (FIFO) once full. Lets a caller trade cache size for
+    /// memory on tight embedders, or grow it while profiling a workload
+    /// whose hot set doesn't fit the default.
+    pub fn with_capacity(max_pages: usize) -> Self {
         ICache {
-            pages: HashMap::with_capacity(64),
+            pages: HashMap::with_capacity(max_pages),
+            page_order: VecDeque::with_capacity(max_pages),
+            max_pages: max_pages.max(1),
             generation: 1,
             hits: 0,
             misses: 0,
+            evictions: 0,
         }
     }
 
@@ -109,7 +178,17 @@ impl ICache {
         // Cache miss - decode and store
         self.misses += 1;
         let decoded = CachedInst::decode(raw_inst);
-        
+
+        if !self.pages.contains_key(&page_num) {
+            if self.pages.len() >= self.max_pages {
+                if let Some(oldest) = self.page_order.pop_front() {
+                    self.pages.remove(&oldest);
+                    self.evictions += 1;
+                }
+            }
+            self.page_order.push_back(page_num);
+        }
+
         // Insert into cache
         let page = self.pages.entry(page_num).or_insert_with(|| CachedPage::new(self.generation));
         if page.generation != self.generation {
@@ -118,7 +197,7 @@ impl ICache {
             page.instructions = Box::new([CachedInst::default(); INSTS_PER_PAGE]);
         }
         page.instructions[offset] = decoded;
-        
+
         decoded
     }
 
@@ -127,7 +206,7 @@ impl ICache {
     pub fn invalidate_addr(&mut self, paddr: u32) {
         let page_num = paddr >> 12;
         let offset = ((paddr >> 2) & 0x3FF) as usize;
-        
+
         if let Some(page) = self.pages.get_mut(&page_num) {
             if page.generation == self.generation {
                 page.instructions[offset].valid = false;
@@ -143,9 +222,11 @@ impl ICache {
     /// Reset cache
     pub fn reset(&mut self) {
         self.pages.clear();
+        self.page_order.clear();
         self.generation = 1;
         self.hits = 0;
         self.misses = 0;
+        self.evictions = 0;
     }
 
     /// Get hit rate
@@ -157,4 +238,74 @@ impl ICache {
             self.hits as f64 / total as f64
         }
     }
+
+    /// `(lookups, hits, evictions)`, for the general stats accessors on
+    /// `Cpu`/`System` - mirrors `Mmu::tlb_stats`'s shape.
+    pub fn stats(&self) -> (u64, u64, u64) {
+        (self.hits + self.misses, self.hits, self.evictions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_or_decode_self_heals_on_content_change_without_invalidation() {
+        // This cache validates against the raw instruction word the caller
+        // just fetched (see `get_or_decode`'s `cached.raw == raw_inst`
+        // check), so a decode never goes stale here even if nothing ever
+        // calls `invalidate_addr`/`invalidate_all`: the next fetch after a
+        // guest overwrites an instruction naturally misses and redecodes.
+        // Coarser caches keyed only on address - the block cache, the JIT -
+        // don't re-validate content this way, which is why FENCE.I exists
+        // to drop those explicitly instead.
+        let mut cache = ICache::new();
+
+        let first = cache.get_or_decode(0x1000, 0x0000_0013); // nop
+        assert_eq!(first.opcode, 0x13);
+        assert_eq!(cache.stats(), (1, 0, 0));
+
+        let second = cache.get_or_decode(0x1000, 0x0000_0013);
+        assert_eq!(second.raw, first.raw);
+        assert_eq!(cache.stats(), (2, 1, 0));
+
+        // Guest overwrites the instruction in place; the caller passes the
+        // new raw word on the next fetch without calling invalidate_addr.
+        let patched = cache.get_or_decode(0x1000, 0x0020_0093); // addi x1, x0, 2
+        assert_eq!(patched.raw, 0x0020_0093);
+        assert_eq!(cache.stats(), (3, 1, 0));
+    }
+
+    #[test]
+    fn test_invalidate_all_forces_redecode_even_of_unchanged_content() {
+        let mut cache = ICache::new();
+        cache.get_or_decode(0x2000, 0x0000_0013);
+        assert_eq!(cache.stats(), (1, 0, 0));
+        cache.get_or_decode(0x2000, 0x0000_0013);
+        assert_eq!(cache.stats(), (2, 1, 0));
+
+        cache.invalidate_all();
+        cache.get_or_decode(0x2000, 0x0000_0013);
+        assert_eq!(cache.stats(), (3, 1, 0));
+    }
+
+    #[test]
+    fn test_capacity_evicts_oldest_page_once_full() {
+        let mut cache = ICache::with_capacity(2);
+
+        cache.get_or_decode(0x0000, 0x0000_0013);
+        cache.get_or_decode(0x1000, 0x0000_0013);
+        assert_eq!(cache.stats().2, 0);
+
+        // A third distinct page evicts page 0x0000 (oldest).
+        cache.get_or_decode(0x2000, 0x0000_0013);
+        assert_eq!(cache.stats().2, 1);
+
+        // Page 0x0000 is gone, so re-fetching it is a fresh miss again
+        // rather than the hit it would've been at unlimited capacity.
+        let hits_before = cache.hits;
+        cache.get_or_decode(0x0000, 0x0000_0013);
+        assert_eq!(cache.hits, hits_before);
+    }
 }
@@ -15,3 +15,32 @@ pub use v2::{
     Page, RegionResult, JitState, execute_region,
     HEAT_PER_BLOCK, JIT_THRESHOLD,
 };
+
+/// Tunable knobs shared by both JIT backends, for experimenting with the
+/// IPS/compile-time tradeoff without recompiling the emulator. See
+/// `System::set_jit_config`.
+#[derive(Clone, Copy, Debug)]
+pub struct JitConfig {
+    /// Maximum instructions per compiled block before it's split even if
+    /// no real terminator (branch/jump/system) was seen.
+    pub max_block_size: usize,
+    /// When set, loads and stores also end a block. The actual target
+    /// address isn't known until the block runs, so this is a coarse
+    /// stand-in for "avoid compiling MMIO accesses into a block" - it
+    /// splits on every load/store, not just ones that turn out to hit
+    /// MMIO.
+    pub split_on_mmio: bool,
+    /// v2 JIT hotness threshold before a page is compiled; see
+    /// `JitState::set_threshold`.
+    pub threshold: u32,
+}
+
+impl Default for JitConfig {
+    fn default() -> Self {
+        JitConfig {
+            max_block_size: 64,
+            split_on_mmio: false,
+            threshold: JIT_THRESHOLD,
+        }
+    }
+}
@@ -1,12 +1,19 @@
-//! WASM instruction emitter for RV32I ALU operations
-//! 
-//! Generates WASM bytecode from RISC-V basic blocks.
-//! For now, only handles pure ALU operations (register-register).
-//! Memory and system calls fall back to interpreter.
+//! WASM instruction emitter for RV32I basic blocks
+//!
+//! Generates WASM bytecode from RISC-V basic blocks: ALU operations
+//! (register-register and register-immediate), LUI/AUIPC, and the
+//! control-flow terminators (BRANCH/JAL/JALR) that end a block by
+//! returning the resolved next PC. Loads and stores fall back to the
+//! interpreter - they need the CPU's MMU/CSR state to translate a
+//! virtual address, which isn't available inside a compiled block (see
+//! `CompiledWasmBlock::execute`, which only gets the register file).
 
 #[cfg(target_arch = "wasm32")]
 use super::wasm::WasmBuilder;
 
+#[cfg(target_arch = "wasm32")]
+use crate::cpu::rv32::decode::DecodedInst;
+
 /// RISC-V opcodes
 #[cfg(target_arch = "wasm32")]
 mod rv_opcode {
@@ -31,46 +38,66 @@ pub enum EmitResult {
     NeedsFallback,
 }
 
+/// Whether `emit_instruction` can handle this opcode at all. Used both to
+/// decide whether it's worth building a block up front and by
+/// `can_compile_block`.
+#[cfg(target_arch = "wasm32")]
+pub fn is_compilable_opcode(opcode: u8) -> bool {
+    use rv_opcode::*;
+    matches!(opcode, OP_LUI | OP_AUIPC | OP_OP | OP_OP_IMM | OP_BRANCH | OP_JAL | OP_JALR)
+}
+
 /// Emit WASM code for a single RISC-V instruction
-/// 
-/// Registers are represented as WASM locals 0-31.
-/// Returns false if instruction requires fallback to interpreter.
+///
+/// Registers are represented as WASM locals 0-31. BRANCH/JAL/JALR end the
+/// block by pushing the resolved next PC and returning it, matching
+/// `CompiledWasmBlock::execute`'s "returns next PC" contract.
+/// Returns false if the instruction requires fallback to the interpreter.
 #[cfg(target_arch = "wasm32")]
 pub fn emit_instruction(
     builder: &mut WasmBuilder,
     inst: &crate::cpu::rv32::icache::CachedInst,
-    _pc: u32,
+    pc: u32,
 ) -> bool {
     use rv_opcode::*;
-    
+
     match inst.opcode {
         // LUI rd, imm
         OP_LUI if inst.rd != 0 => {
-            let imm = (inst.raw & 0xFFFFF000) as i32;
+            let imm = DecodedInst::imm_u(inst.raw);
             builder.i32_const(imm);
             builder.local_set(inst.rd as u32);
             true
         }
-        
-        // AUIPC rd, imm - needs PC, skip for now
-        OP_AUIPC => false,
-        
+
+        // AUIPC rd, imm
+        OP_AUIPC if inst.rd != 0 => {
+            let imm = DecodedInst::imm_u(inst.raw);
+            builder.i32_const(pc.wrapping_add(imm as u32) as i32);
+            builder.local_set(inst.rd as u32);
+            true
+        }
+
         // R-type ALU operations
         OP_OP if inst.rd != 0 => {
             emit_r_type_alu(builder, inst)
         }
-        
+
         // I-type ALU operations
         OP_OP_IMM if inst.rd != 0 => {
             emit_i_type_alu(builder, inst)
         }
-        
+
         // x0 writes are NOPs
-        OP_LUI | OP_OP | OP_OP_IMM if inst.rd == 0 => true,
-        
-        // Memory and control flow need fallback
-        OP_LOAD | OP_STORE | OP_BRANCH | OP_JAL | OP_JALR | OP_SYSTEM => false,
-        
+        OP_LUI | OP_AUIPC | OP_OP | OP_OP_IMM if inst.rd == 0 => true,
+
+        OP_BRANCH => emit_branch(builder, inst, pc),
+        OP_JAL => emit_jal(builder, inst, pc),
+        OP_JALR => emit_jalr(builder, inst, pc),
+
+        // Memory ops and ECALL/EBREAK/CSR need fallback
+        OP_LOAD | OP_STORE | OP_SYSTEM => false,
+
         _ => false,
     }
 }
@@ -84,7 +111,7 @@ fn emit_r_type_alu(
     // Load rs1 and rs2
     builder.local_get(inst.rs1 as u32);
     builder.local_get(inst.rs2 as u32);
-    
+
     match (inst.funct3, inst.funct7) {
         // ADD
         (0b000, 0b0000000) => builder.i32_add(),
@@ -109,7 +136,7 @@ fn emit_r_type_alu(
         // Unknown - fallback
         _ => return false,
     }
-    
+
     // Store to rd
     builder.local_set(inst.rd as u32);
     true
@@ -122,11 +149,11 @@ fn emit_i_type_alu(
     inst: &crate::cpu::rv32::icache::CachedInst,
 ) -> bool {
     // Extract I-type immediate (sign-extended)
-    let imm = ((inst.raw as i32) >> 20) as i32;
-    
+    let imm = DecodedInst::imm_i(inst.raw);
+
     // Load rs1
     builder.local_get(inst.rs1 as u32);
-    
+
     match inst.funct3 {
         // ADDI
         0b000 => {
@@ -176,23 +203,97 @@ fn emit_i_type_alu(
         }
         _ => return false,
     }
-    
+
     // Store to rd
     builder.local_set(inst.rd as u32);
     true
 }
 
-/// Check if a block can be compiled to WASM (ALU-only)
+/// Emit a conditional branch. Ends the block: pushes the resolved target
+/// (taken or fallthrough) and returns it, rather than falling through to
+/// another emitted instruction, since a branch is always the last
+/// instruction of a compiled block (see `is_block_terminator`).
 #[cfg(target_arch = "wasm32")]
-pub fn can_compile_block(block: &super::super::CompiledBlock) -> bool {
-    use rv_opcode::*;
-    
-    for inst in &block.instructions {
-        match inst.opcode {
-            OP_LUI | OP_OP | OP_OP_IMM => continue,
-            _ => return false,
-        }
+fn emit_branch(
+    builder: &mut WasmBuilder,
+    inst: &crate::cpu::rv32::icache::CachedInst,
+    pc: u32,
+) -> bool {
+    let imm = DecodedInst::imm_b(inst.raw);
+    let taken_target = pc.wrapping_add(imm as u32);
+    let fallthrough_target = pc.wrapping_add(4);
+
+    builder.local_get(inst.rs1 as u32);
+    builder.local_get(inst.rs2 as u32);
+    match inst.funct3 {
+        0b000 => builder.i32_eq(),   // BEQ
+        0b001 => builder.i32_ne(),   // BNE
+        0b100 => builder.i32_lt_s(), // BLT
+        0b101 => builder.i32_ge_s(), // BGE
+        0b110 => builder.i32_lt_u(), // BLTU
+        0b111 => builder.i32_ge_u(), // BGEU
+        _ => return false,
     }
-    
+
+    // Skip the taken-path block when the condition is false.
+    builder.i32_eqz();
+    let taken = builder.block_void();
+    builder.br_if(taken);
+    builder.i32_const(taken_target as i32);
+    builder.return_();
+    builder.end();
+
+    builder.i32_const(fallthrough_target as i32);
+    builder.return_();
     true
 }
+
+/// Emit JAL: both the link value and the target are compile-time
+/// constants, so this needs no runtime comparison at all.
+#[cfg(target_arch = "wasm32")]
+fn emit_jal(
+    builder: &mut WasmBuilder,
+    inst: &crate::cpu::rv32::icache::CachedInst,
+    pc: u32,
+) -> bool {
+    if inst.rd != 0 {
+        builder.i32_const(pc.wrapping_add(4) as i32);
+        builder.local_set(inst.rd as u32);
+    }
+    let imm = DecodedInst::imm_j(inst.raw);
+    builder.i32_const(pc.wrapping_add(imm as u32) as i32);
+    builder.return_();
+    true
+}
+
+/// Emit JALR: the target depends on rs1's current value, so it's computed
+/// and pushed *before* rd (which may alias rs1) is overwritten. The
+/// pushed value survives the rd write underneath it on the WASM operand
+/// stack and is what `return` ultimately pops.
+#[cfg(target_arch = "wasm32")]
+fn emit_jalr(
+    builder: &mut WasmBuilder,
+    inst: &crate::cpu::rv32::icache::CachedInst,
+    pc: u32,
+) -> bool {
+    let imm = DecodedInst::imm_i(inst.raw);
+    builder.local_get(inst.rs1 as u32);
+    builder.i32_const(imm);
+    builder.i32_add();
+    builder.i32_const(-2); // clear bit 0, matching `& !1`
+    builder.i32_and();
+
+    if inst.rd != 0 {
+        builder.i32_const(pc.wrapping_add(4) as i32);
+        builder.local_set(inst.rd as u32);
+    }
+
+    builder.return_();
+    true
+}
+
+/// Check if a block can be compiled to WASM
+#[cfg(target_arch = "wasm32")]
+pub fn can_compile_block(block: &super::super::CompiledBlock) -> bool {
+    block.instructions.iter().all(|inst| is_compilable_opcode(inst.opcode))
+}
@@ -12,13 +12,21 @@ extern "C" {
     /// Returns a module ID that can be used to call the function
     #[wasm_bindgen(js_namespace = window, js_name = "otoriscvCompileWasm")]
     fn js_compile_wasm(bytecode: &[u8]) -> u32;
-    
+
+    /// Same as `js_compile_wasm`, but instantiates the module off the main
+    /// thread's critical path (e.g. via `WebAssembly.instantiate`, which
+    /// unlike `new WebAssembly.Module` is async and isn't subject to the
+    /// ~4KB synchronous-compile limit most browsers enforce). Resolves to
+    /// the module ID, or 0 on failure.
+    #[wasm_bindgen(js_namespace = window, js_name = "otoriscvCompileWasmAsync")]
+    fn js_compile_wasm_async(bytecode: &[u8]) -> js_sys::Promise;
+
     /// Called from Rust to execute a compiled WASM function
     /// Takes module ID and register state array
     /// Returns next PC
     #[wasm_bindgen(js_namespace = window, js_name = "otoriscvRunWasm")]
     fn js_run_wasm(module_id: u32, registers: &mut [u32]) -> u32;
-    
+
     /// Free a compiled WASM module
     #[wasm_bindgen(js_namespace = window, js_name = "otoriscvFreeWasm")]
     fn js_free_wasm(module_id: u32);
@@ -40,7 +48,30 @@ impl CompiledWasmBlock {
             Some(CompiledWasmBlock { module_id })
         }
     }
-    
+
+    /// Compile bytecode to a WASM module without blocking the calling
+    /// thread. `BlockCache::compile_block` can't call this directly since
+    /// it doesn't own an executor - see `Emulator::jit_compile_pending`,
+    /// which drives this from JS via `wasm-bindgen-futures` and later
+    /// installs the result with `from_module_id`.
+    pub async fn compile_async(bytecode: &[u8]) -> Option<u32> {
+        let promise = js_compile_wasm_async(bytecode);
+        let result = wasm_bindgen_futures::JsFuture::from(promise).await.ok()?;
+        let module_id = result.as_f64()? as u32;
+        if module_id == 0 {
+            None
+        } else {
+            Some(module_id)
+        }
+    }
+
+    /// Wrap a module ID obtained from `compile_async` (once its future has
+    /// resolved) back into a handle, so `BlockCache` can install it same
+    /// as a synchronously-compiled block.
+    pub(crate) fn from_module_id(module_id: u32) -> Self {
+        CompiledWasmBlock { module_id }
+    }
+
     /// Execute the compiled block
     /// Updates registers in place, returns next PC
     pub fn execute(&self, registers: &mut [u32; 32]) -> u32 {
@@ -192,6 +192,7 @@ mod builder_impl {
         pub fn i32_ge_u(&mut self) { self.code.push(op::OP_I32_GE_U); }
         pub fn i32_eq(&mut self) { self.code.push(op::OP_I32_EQ); }
         pub fn i32_ne(&mut self) { self.code.push(op::OP_I32_NE); }
+        pub fn i32_eqz(&mut self) { self.code.push(op::OP_I32_EQZ); }
         
         fn label_depth(&self, label: Label) -> u32 {
             let target_depth = *self.label_depths.get(&label).unwrap();
@@ -14,10 +14,15 @@ use crate::cpu::trap::Trap;
 
 /// Result of executing a compiled block
 pub enum BlockResult {
-    /// Continue execution at the given PC
-    Continue(u32),
-    /// A trap occurred during execution
-    Trap(Trap),
+    /// Continue execution at the given PC, having actually executed this
+    /// many instructions - not necessarily `block.inst_count`, since an
+    /// MMIO access ends the block early (see `execute_block`).
+    Continue(u32, u32),
+    /// A trap occurred during execution, after this many earlier
+    /// instructions in the same block already retired (their PC/register
+    /// side effects already took hold) - callers must still account for
+    /// these before handling the trap, same as `Continue`'s count.
+    Trap(Trap, u32),
     /// Need to fall back to interpreter (e.g., for complex instructions)
     Interpret,
 }
@@ -46,10 +51,29 @@ pub struct BlockCache {
     blocks: HashMap<u32, CompiledBlock>,
     /// Generation counter for bulk invalidation
     generation: u32,
+    /// Maximum instructions per block; see `JitConfig::max_block_size`.
+    max_block_size: usize,
+    /// Whether loads/stores also end a block; see
+    /// `JitConfig::split_on_mmio`.
+    split_on_mmio: bool,
     /// Statistics
     pub hits: u64,
     pub misses: u64,
     pub compiles: u64,
+    /// WASM bytecode that's ready to instantiate but hasn't been handed
+    /// off yet - see `take_pending_wasm_compiles`/`Emulator::jit_compile_pending`.
+    #[cfg(target_arch = "wasm32")]
+    pending_wasm_compiles: Vec<(u32, Vec<u8>)>,
+    /// Per-backend WASM stats, mirroring `hits`/`misses`/`compiles` above:
+    /// blocks whose bytecode compiled but is still waiting to be
+    /// instantiated, blocks that finished instantiating and are live, and
+    /// blocks JS reported it couldn't instantiate.
+    #[cfg(target_arch = "wasm32")]
+    pub wasm_pending: u64,
+    #[cfg(target_arch = "wasm32")]
+    pub wasm_compiled: u64,
+    #[cfg(target_arch = "wasm32")]
+    pub wasm_compile_failed: u64,
 }
 
 impl Default for BlockCache {
@@ -63,9 +87,19 @@ impl BlockCache {
         BlockCache {
             blocks: HashMap::with_capacity(4096),
             generation: 1,
+            max_block_size: 64,
+            split_on_mmio: false,
             hits: 0,
             misses: 0,
             compiles: 0,
+            #[cfg(target_arch = "wasm32")]
+            pending_wasm_compiles: Vec::new(),
+            #[cfg(target_arch = "wasm32")]
+            wasm_pending: 0,
+            #[cfg(target_arch = "wasm32")]
+            wasm_compiled: 0,
+            #[cfg(target_arch = "wasm32")]
+            wasm_compile_failed: 0,
         }
     }
 
@@ -88,6 +122,14 @@ impl BlockCache {
         self.blocks.get(&paddr).filter(|b| b.generation == self.generation)
     }
 
+    /// Apply `max_block_size`/`split_on_mmio` from a `JitConfig`. Doesn't
+    /// touch already-compiled blocks; they age out normally as generations
+    /// bump or get overwritten on the next compile at their address.
+    pub fn configure(&mut self, config: &super::JitConfig) {
+        self.max_block_size = config.max_block_size.max(1);
+        self.split_on_mmio = config.split_on_mmio;
+    }
+
     /// Compile a basic block starting at the given physical address
     pub fn compile_block(&mut self, bus: &mut impl Bus, start_paddr: u32) -> &CompiledBlock {
         let mut instructions = Vec::with_capacity(32);
@@ -95,19 +137,31 @@ impl BlockCache {
 
         // Scan instructions until we hit a block terminator
         loop {
+            // Stop at the edge of executable memory rather than reading
+            // past RAM into whatever follows (MMIO with read side effects,
+            // or genuinely unmapped space) - the interpreter will raise a
+            // real `InstructionAccessFault` if this block is ever entered
+            // at `paddr`, so a short block here is safe, not a correctness
+            // gap.
+            if !bus.is_executable(paddr) {
+                break;
+            }
+
             let inst = bus.read32(paddr);
             let cached = CachedInst::decode(inst);
+            let is_mmio_split = self.split_on_mmio
+                && matches!(cached.opcode as u32, OP_LOAD | OP_STORE);
             instructions.push(cached);
 
             // Check if this is a block-ending instruction
-            if is_block_terminator(cached.opcode) {
+            if is_block_terminator(cached.opcode) || is_mmio_split {
                 break;
             }
 
             paddr += 4;
 
             // Limit block size to avoid huge blocks
-            if instructions.len() >= 64 {
+            if instructions.len() >= self.max_block_size {
                 break;
             }
         }
@@ -122,38 +176,35 @@ impl BlockCache {
             }
         };
 
-        // Try to compile to WASM (if wasm32 target)
+        // WASM blocks aren't instantiated here - `js_compile_wasm`'s
+        // synchronous module instantiation is what browsers refuse to run
+        // on the main thread past a few KB. Bytecode that's ready gets
+        // queued for `Emulator::jit_compile_pending` to instantiate off
+        // the critical path; the block runs through the interpreter until
+        // `install_compiled_wasm_block` fills in `wasm_code` later.
         #[cfg(target_arch = "wasm32")]
         let wasm_code = {
             use codegen::emit;
             use codegen::wasm::WasmBuilder;
-            
-            // Check if all instructions can be compiled
-            let can_compile = instructions.iter().all(|inst| {
-                use super::super::decode::*;
-                inst.opcode == OP_LUI as u8 || inst.opcode == OP_OP as u8 || inst.opcode == OP_OP_IMM as u8
-            });
-            
-            if can_compile {
+
+            if instructions.iter().all(|inst| emit::is_compilable_opcode(inst.opcode)) {
                 let mut builder = WasmBuilder::new();
                 let mut success = true;
-                
+
                 for inst in &instructions {
                     if !emit::emit_instruction(&mut builder, inst, start_paddr) {
                         success = false;
                         break;
                     }
                 }
-                
+
                 if success {
-                    let bytecode = builder.get_code();
-                    codegen::runtime::CompiledWasmBlock::compile(bytecode)
-                } else {
-                    None
+                    self.pending_wasm_compiles.push((start_paddr, builder.get_code().to_vec()));
+                    self.wasm_pending += 1;
                 }
-            } else {
-                None
             }
+
+            None
         };
 
         let block = CompiledBlock {
@@ -178,6 +229,34 @@ impl BlockCache {
         self.generation = self.generation.wrapping_add(1);
     }
 
+    /// Drain the WASM bytecode queued by `compile_block` since the last
+    /// call, handing ownership to the caller so it can instantiate each
+    /// one off the critical path (see `Emulator::jit_compile_pending`).
+    #[cfg(target_arch = "wasm32")]
+    pub fn take_pending_wasm_compiles(&mut self) -> Vec<(u32, Vec<u8>)> {
+        std::mem::take(&mut self.pending_wasm_compiles)
+    }
+
+    /// Install a module instantiated from a bytecode blob handed out by
+    /// `take_pending_wasm_compiles`. If the block at `paddr` was
+    /// invalidated in the meantime (e.g. by a FENCE.I) the result is
+    /// simply dropped - the interpreter has already been running that
+    /// address instead, and will keep doing so until it's recompiled.
+    #[cfg(target_arch = "wasm32")]
+    pub fn install_compiled_wasm_block(&mut self, paddr: u32, module_id: Option<u32>) {
+        match module_id {
+            Some(id) => {
+                if let Some(block) = self.blocks.get_mut(&paddr) {
+                    if block.generation == self.generation {
+                        block.wasm_code = Some(codegen::runtime::CompiledWasmBlock::from_module_id(id));
+                        self.wasm_compiled += 1;
+                    }
+                }
+            }
+            None => self.wasm_compile_failed += 1,
+        }
+    }
+
     /// Invalidate blocks in a specific page
     #[allow(dead_code)]
     pub fn invalidate_page(&mut self, page_addr: u32) {
@@ -195,6 +274,13 @@ impl BlockCache {
         self.hits = 0;
         self.misses = 0;
         self.compiles = 0;
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.pending_wasm_compiles.clear();
+            self.wasm_pending = 0;
+            self.wasm_compiled = 0;
+            self.wasm_compile_failed = 0;
+        }
     }
 
     /// Get hit rate
@@ -232,7 +318,7 @@ pub fn execute_block(cpu: &mut Cpu, block: &CompiledBlock, bus: &mut impl Bus) -
         let inst_count = native_block.execute(&mut cpu.regs);
         // Update PC by advancing by the number of instructions executed
         cpu.pc = cpu.pc.wrapping_add(inst_count * 4);
-        return BlockResult::Continue(cpu.pc);
+        return BlockResult::Continue(cpu.pc, inst_count);
     }
 
     // Try WASM execution (if available)
@@ -241,12 +327,12 @@ pub fn execute_block(cpu: &mut Cpu, block: &CompiledBlock, bus: &mut impl Bus) -
         // Execute WASM code - it modifies registers in-place
         let next_pc = wasm_block.execute(&mut cpu.regs);
         cpu.pc = next_pc;
-        return BlockResult::Continue(cpu.pc);
+        return BlockResult::Continue(cpu.pc, block.inst_count);
     }
 
     // Fall back to interpreter execution
     let inst_count = block.instructions.len();
-    
+
     for (i, cached) in block.instructions.iter().enumerate() {
         let is_last = i == inst_count - 1;
         let inst = cached.raw;
@@ -254,6 +340,18 @@ pub fn execute_block(cpu: &mut Cpu, block: &CompiledBlock, bus: &mut impl Bus) -
         // Execute the instruction
         match cpu.execute_cached(inst, cached, bus) {
             Ok(()) => {
+                // A device access (UART/CLINT/PLIC/virtio9p) needs the
+                // System-level round trip between instructions to advance
+                // its state machine - e.g. the UART's LSR bit only flips
+                // once a real transmit tick runs. Looping further cached
+                // instructions without that round trip is how a guest's
+                // register-poll loop (the 8250 putchar wait) ends up
+                // spinning far longer under the JIT than the interpreter,
+                // so end the block here rather than at the next real
+                // terminator.
+                if bus.take_mmio_access() {
+                    return BlockResult::Continue(cpu.pc, i as u32 + 1);
+                }
                 // For non-terminal instructions, PC was already advanced in execute_cached
                 // We just continue to the next instruction
                 if !is_last {
@@ -261,12 +359,12 @@ pub fn execute_block(cpu: &mut Cpu, block: &CompiledBlock, bus: &mut impl Bus) -
                 }
             }
             Err(trap) => {
-                return BlockResult::Trap(trap);
+                return BlockResult::Trap(trap, i as u32);
             }
         }
     }
 
-    BlockResult::Continue(cpu.pc)
+    BlockResult::Continue(cpu.pc, inst_count as u32)
 }
 
 #[cfg(test)]
@@ -291,4 +389,25 @@ mod tests {
         assert!(!is_block_terminator(OP_OP_IMM as u8));
         assert!(!is_block_terminator(OP_OP as u8));
     }
+
+    #[test]
+    fn test_compile_block_stops_at_ram_boundary_without_reading_past_it() {
+        use crate::memory::{Memory, DRAM_BASE};
+
+        // 1 MiB of RAM, all NOPs, with nothing to terminate the block -
+        // starting 8 bytes before the end leaves room for exactly two more
+        // instructions before `compile_block` would otherwise read off the
+        // end of RAM and into whatever follows.
+        let mut mem = Memory::new(1);
+        let nop = 0x0000_0013u32.to_le_bytes();
+        for offset in (0..1024 * 1024).step_by(4) {
+            mem.write_slice(DRAM_BASE + offset, &nop);
+        }
+
+        let mut cache = BlockCache::new();
+        let start = DRAM_BASE + 1024 * 1024 - 8;
+        let block = cache.compile_block(&mut mem, start);
+
+        assert_eq!(block.inst_count, 2, "should stop exactly at the RAM boundary");
+    }
 }
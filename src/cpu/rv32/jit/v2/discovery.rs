@@ -9,9 +9,6 @@ use super::super::super::decode::*;
 use super::super::super::icache::CachedInst;
 use crate::memory::Bus;
 
-/// Maximum instructions per basic block
-const MAX_BLOCK_SIZE: usize = 64;
-
 /// Check if an opcode terminates a basic block
 #[inline(always)]
 fn is_block_terminator(opcode: u8) -> bool {
@@ -47,10 +44,16 @@ fn extract_jal_offset(inst: u32) -> i32 {
 ///
 /// All addresses are VIRTUAL addresses. The bus handles VA→PA translation.
 /// Returns a map from virtual address to BasicBlock.
+///
+/// `max_block_size` and `split_on_mmio` come from `JitConfig`; see its docs
+/// for what `split_on_mmio` actually does (it can't know the real target
+/// address at discovery time, so it splits on every load/store).
 pub fn discover_basic_blocks(
     bus: &mut impl Bus,
     page: Page,
     entry_points: &[u32],
+    max_block_size: usize,
+    split_on_mmio: bool,
 ) -> HashMap<u32, BasicBlock> {
     let mut blocks = HashMap::new();
     let mut worklist: VecDeque<u32> = entry_points.iter().copied().collect();
@@ -76,14 +79,79 @@ pub fn discover_basic_blocks(
                 break;
             }
 
-            // Read instruction via bus (which handles VA→PA translation)
-            let inst = bus.read32(vaddr);
-            let cached = CachedInst::decode(inst);
+            // Stop at the edge of executable memory rather than reading
+            // past RAM into MMIO with read side effects, or genuinely
+            // unmapped space - mirrors the guard in v1's `compile_block`.
+            if !bus.is_executable(vaddr) {
+                break;
+            }
+
+            // Read instruction via bus (which handles VA→PA translation).
+            // RVC instructions are 16-bit aligned and self-identify via
+            // their low 2 bits (`11` means a full 4-byte instruction
+            // follows); read the low half-word first so we never fetch
+            // past a compressed instruction's 2 bytes, mirroring the
+            // interpreter's `Cpu::step`.
+            let low16 = bus.read16(vaddr);
+            let decoded = if (low16 & 0b11) != 0b11 {
+                CachedInst::decode_compressed(low16)
+            } else {
+                Some(CachedInst::decode(bus.read32(vaddr)))
+            };
+            let cached = match decoded {
+                Some(cached) => cached,
+                None => {
+                    // Undefined 16-bit encoding: close off whatever we've
+                    // collected so far rather than mis-decode, and give up
+                    // on this entry point - the interpreter will fault on
+                    // it correctly if it's ever actually reached.
+                    if !instructions.is_empty() {
+                        let block = BasicBlock {
+                            addr: start_vaddr,
+                            end_addr: vaddr,
+                            instructions,
+                            ty: BasicBlockType::Fallthrough {
+                                next: if page.contains(vaddr) { Some(vaddr) } else { None },
+                            },
+                            is_entry_point: entry_points.contains(&start_vaddr),
+                        };
+                        blocks.insert(start_vaddr, block);
+                    }
+                    break;
+                }
+            };
             let opcode = cached.opcode;
+            // `cached.raw` is the expanded 32-bit equivalent for a
+            // compressed instruction, so branch/jump offset extraction
+            // below works unchanged either way.
+            let inst = cached.raw;
+            let len = cached.len as u32;
             instructions.push(cached);
 
-            let next_vaddr = vaddr + 4;
+            let next_vaddr = vaddr + len;
             let is_terminator = is_block_terminator(opcode);
+            let is_mmio_split = split_on_mmio && matches!(opcode as u32, OP_LOAD | OP_STORE);
+
+            if is_mmio_split && !is_terminator {
+                if page.contains(next_vaddr) {
+                    worklist.push_back(next_vaddr);
+                }
+                let block = BasicBlock {
+                    addr: start_vaddr,
+                    end_addr: next_vaddr,
+                    instructions,
+                    ty: BasicBlockType::Fallthrough {
+                        next: if page.contains(next_vaddr) {
+                            Some(next_vaddr)
+                        } else {
+                            None
+                        },
+                    },
+                    is_entry_point: entry_points.contains(&start_vaddr),
+                };
+                blocks.insert(start_vaddr, block);
+                break;
+            }
 
             if is_terminator {
                 // Determine block type and successors (all targets are VIRTUAL addresses)
@@ -184,7 +252,7 @@ pub fn discover_basic_blocks(
             }
 
             // Check block size limit
-            if instructions.len() >= MAX_BLOCK_SIZE {
+            if instructions.len() >= max_block_size {
                 if page.contains(next_vaddr) {
                     worklist.push_back(next_vaddr);
                 }
@@ -205,9 +273,65 @@ pub fn discover_basic_blocks(
                 break;
             }
 
-            vaddr = next_vaddr;
+                vaddr = next_vaddr;
         }
     }
 
     blocks
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::execute::execute_region;
+    use super::super::types::CompiledRegion;
+    use crate::cpu::rv32::Cpu;
+    use crate::memory::{Memory, DRAM_BASE};
+
+    /// A page mixing compressed (2-byte) and regular (4-byte) instructions
+    /// must be discovered as a single block whose instructions carry the
+    /// real per-instruction width, and running that block through the JIT
+    /// must land on byte-for-byte identical register/PC state as stepping
+    /// the same bytes through the interpreter.
+    #[test]
+    fn test_mixed_width_block_matches_interpreter() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&0x4515u16.to_le_bytes()); // c.li a0, 5
+        bytes.extend_from_slice(&0x0070_0593u32.to_le_bytes()); // addi a1, x0, 7
+        bytes.extend_from_slice(&0x050Du16.to_le_bytes()); // c.addi a0, a0, 3
+        bytes.extend_from_slice(&0x00B5_0633u32.to_le_bytes()); // add a2, a0, a1
+
+        let mut interp_mem = Memory::new(1);
+        interp_mem.load_binary(&bytes, DRAM_BASE).unwrap();
+        let mut interp_cpu = Cpu::new();
+        interp_cpu.pc = DRAM_BASE;
+        for _ in 0..4 {
+            interp_cpu.step(&mut interp_mem).unwrap();
+        }
+        assert_eq!(interp_cpu.read_reg(10), 8); // a0 = 5 + 3
+        assert_eq!(interp_cpu.read_reg(11), 7); // a1
+        assert_eq!(interp_cpu.read_reg(12), 15); // a2 = a0 + a1
+
+        let mut jit_mem = Memory::new(1);
+        jit_mem.load_binary(&bytes, DRAM_BASE).unwrap();
+        let page = Page::of(DRAM_BASE);
+        let blocks = discover_basic_blocks(&mut jit_mem, page, &[DRAM_BASE], 16, false);
+
+        let block = blocks.get(&DRAM_BASE).expect("block discovered at entry point");
+        assert_eq!(block.instructions.len(), 4);
+        assert_eq!(block.instructions.iter().map(|c| c.len as u32).sum::<u32>(), 12);
+
+        let region = CompiledRegion {
+            blocks,
+            structure: vec![],
+            entry_points: vec![DRAM_BASE],
+            generation: 0,
+        };
+        let mut jit_cpu = Cpu::new();
+        jit_cpu.pc = DRAM_BASE;
+        execute_region(&mut jit_cpu, &mut jit_mem, &region, DRAM_BASE);
+
+        assert_eq!(jit_cpu.pc, interp_cpu.pc);
+        assert_eq!(jit_cpu.regs, interp_cpu.regs);
+    }
+}
@@ -6,6 +6,7 @@
 use std::collections::HashMap;
 use super::types::{BasicBlock, BasicBlockType, ControlFlowStructure, Page, RegionResult, CompiledRegion};
 use super::super::super::Cpu;
+use super::super::super::execute_c::fixup_compressed_pc;
 use crate::cpu::trap::Trap;
 use crate::memory::Bus;
 
@@ -19,9 +20,16 @@ fn execute_basic_block(
     bus: &mut impl Bus,
     block: &BasicBlock,
 ) -> Result<u32, Trap> {
-    // Execute all instructions in the block
+    // Execute all instructions in the block. `execute_cached` always
+    // advances PC/return-addresses assuming a 4-byte instruction, so a
+    // compressed one (cached.len == 2) needs the same post-hoc fixup the
+    // interpreter's `Cpu::execute_compressed` applies.
     for cached in &block.instructions {
+        let pc_before = cpu.pc;
         cpu.execute_cached(cached.raw, cached, bus)?;
+        if cached.len == 2 {
+            fixup_compressed_pc(cpu, pc_before, cached.opcode as u32, cached.rd);
+        }
     }
     
     // Determine next PC based on terminator (returns VA)
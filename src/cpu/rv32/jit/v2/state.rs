@@ -40,6 +40,12 @@ pub struct JitState {
     generation: u32,
     /// Compilation threshold
     threshold: u32,
+    /// Maximum instructions per discovered basic block; see
+    /// `JitConfig::max_block_size`.
+    max_block_size: usize,
+    /// Whether loads/stores also end a basic block; see
+    /// `JitConfig::split_on_mmio`.
+    split_on_mmio: bool,
     /// Last SATP value (to detect page table changes)
     last_satp: u32,
     /// Statistics
@@ -61,6 +67,8 @@ impl JitState {
             regions: HashMap::new(),
             generation: 1,
             threshold: JIT_THRESHOLD,
+            max_block_size: 64,
+            split_on_mmio: false,
             last_satp: 0,
             compiles: 0,
             region_hits: 0,
@@ -73,6 +81,12 @@ impl JitState {
         self.threshold = threshold;
     }
 
+    /// Apply `max_block_size`/`split_on_mmio` from a `JitConfig`.
+    pub fn configure(&mut self, config: &super::super::JitConfig) {
+        self.max_block_size = config.max_block_size.max(1);
+        self.split_on_mmio = config.split_on_mmio;
+    }
+
     /// Check if SATP changed and invalidate if needed
     #[inline]
     pub fn check_satp(&mut self, satp: u32) {
@@ -131,7 +145,13 @@ impl JitState {
             .unwrap_or_else(|| vec![page.base_addr()]);
 
         // Discover basic blocks (using virtual addresses)
-        let blocks = discover_basic_blocks(bus, page, &entry_points);
+        let blocks = discover_basic_blocks(
+            bus,
+            page,
+            &entry_points,
+            self.max_block_size,
+            self.split_on_mmio,
+        );
 
         if blocks.is_empty() {
             return;
@@ -1,12 +1,13 @@
-//! RV32IMAFD CPU module
+//! RV32IMAFDB CPU module
 //!
 //! Implements the RISC-V 32-bit base integer instruction set
 //! with M (multiply/divide), A (atomic), F (single-precision float),
-//! and D (double-precision float) extensions
+//! D (double-precision float), and B (Zba/Zbb/Zbs bitmanip) extensions
 
 pub mod csr;
-mod decode;
+pub mod decode;
 mod execute;
+mod execute_c;
 mod execute_fp;
 pub mod mmu;
 pub mod icache;
@@ -30,7 +31,12 @@ use serde::{Serialize, Deserialize};
 pub struct Cpu {
     /// Program counter
     pub pc: u32,
-    /// General purpose registers (x0-x31)
+    /// General purpose registers (x0-x31). This is the single authoritative
+    /// copy - `read_reg`/`write_reg` index straight into it, and every
+    /// execution path (plain interpretation, the block cache, and the v2
+    /// region executor's per-instruction `execute_cached` calls) takes
+    /// `&mut Cpu` and touches it in place. There's no hot-loop-local copy
+    /// that gets synced back on trap/CSR/MMU slow paths to worry about.
     pub regs: [u32; 32],
     /// Floating-point unit (f0-f31 + FCSR)
     pub fpu: Fpu,
@@ -47,7 +53,14 @@ pub struct Cpu {
     
     /// Instruction counter for performance
     pub instruction_count: u64,
-    
+
+    /// Instructions retired while in each privilege level, indexed by
+    /// [`priv_index`] (User, Supervisor, Machine) - a breakdown of
+    /// `instruction_count` for boot-time profiling (e.g. spotting
+    /// excessive M-mode SBI handling vs. S-mode kernel work).
+    pub priv_instruction_counts: [u64; 3],
+
+
     /// MMU for address translation
     #[serde(skip)]
     pub mmu: Mmu,
@@ -56,13 +69,33 @@ pub struct Cpu {
     #[serde(skip)]
     pub icache: ICache,
 
-    /// Flag set when instruction cache needs invalidation (FENCE.I, SFENCE.VMA)
+    /// Flag set by FENCE.I or SFENCE.VMA when the host-side block cache and
+    /// JIT need invalidating. This is purely about our compiled-block cache;
+    /// it has nothing to do with the MMU's TLB, which SFENCE.VMA invalidates
+    /// synchronously via `mmu.invalidate()` regardless of this flag.
     /// System should clear this after invalidating block cache
-    pub cache_invalidation_pending: bool,
+    pub icache_invalidation_pending: bool,
 
     // Debugging helpers
     pub last_write_addr: u32,
     pub last_write_val: u32,
+
+    /// Bytes written by SYS_WRITEC/SYS_WRITE0 semihosting calls, pending
+    /// pickup by System (which forwards them to the UART output stream).
+    #[serde(skip)]
+    pub semihosting_output: Vec<u8>,
+
+    /// Set by a SYS_EXIT semihosting call; System stops the run loop once
+    /// this becomes `Some`.
+    pub exit_code: Option<i32>,
+
+    /// When set (see `System::set_strict_memory`), loads from a genuinely
+    /// unmapped physical address raise `LoadAccessFault` instead of the
+    /// default lenient behavior of reading back zero. Off by default; CI
+    /// harnesses that want to catch a guest wandering into unmapped space
+    /// turn it on.
+    #[serde(default)]
+    pub strict_memory: bool,
 }
 
 impl Cpu {
@@ -76,11 +109,15 @@ impl Cpu {
             wfi: false,
             reservation: None,
             instruction_count: 0,
+            priv_instruction_counts: [0; 3],
             mmu: Mmu::new(),
             icache: ICache::new(),
-            cache_invalidation_pending: false,
+            icache_invalidation_pending: false,
             last_write_addr: 0,
             last_write_val: 0,
+            semihosting_output: Vec::new(),
+            exit_code: None,
+            strict_memory: false,
         };
         
         // x0 is always 0
@@ -120,20 +157,58 @@ impl Cpu {
                 return Err(Trap::from_cause(cause, self.pc));
             }
         };
-        
-        let inst = bus.read32(paddr);
-        
-        // Try instruction cache
-        let cached = self.icache.get_or_decode(paddr, inst);
-        
-        // Execute with cached decode
-        self.execute_cached(inst, &cached, bus)?;
-        
-        self.instruction_count += 1;
-        
+
+        if !bus.is_executable(paddr) {
+            return Err(Trap::InstructionAccessFault(self.pc));
+        }
+
+        // RVC instructions are 16-bit aligned and self-identify via their
+        // low 2 bits (`11` means a full 4-byte instruction follows); read
+        // the low half-word first so we never fetch past a compressed
+        // instruction's 2 bytes.
+        let low16 = bus.read16(paddr);
+        if (low16 & 0b11) != 0b11 {
+            self.execute_compressed(low16, bus)?;
+        } else {
+            let inst = bus.read32(paddr);
+
+            // Try instruction cache
+            let cached = self.icache.get_or_decode(paddr, inst);
+
+            // Execute with cached decode
+            self.execute_cached(inst, &cached, bus)?;
+        }
+
+        self.record_retired_in(priv_level, 1);
+
         Ok(())
     }
-    
+
+    /// Map a privilege level to a dense `0..3` index for
+    /// `priv_instruction_counts` via a lookup table rather than a branch -
+    /// `PrivilegeLevel` is `0/1/3` (`Machine` shares its encoding with the
+    /// reserved level 2), not already contiguous.
+    #[inline(always)]
+    fn priv_index(priv_level: PrivilegeLevel) -> usize {
+        const LUT: [usize; 4] = [0, 1, 2, 2];
+        LUT[priv_level as u8 as usize]
+    }
+
+    /// Account for `count` retired instructions against both the total
+    /// counter and `priv_level`'s bucket in `priv_instruction_counts`.
+    /// Callers pass the privilege level active *before* the instruction(s)
+    /// ran - e.g. an MRET/SRET is attributed to the mode that executed it,
+    /// not the mode it switched into - which is also why this takes an
+    /// explicit level instead of reading `self.priv_level`: for a
+    /// multi-instruction JIT block, that field may already reflect a
+    /// trailing MRET/SRET's new privilege by the time the caller gets
+    /// control back.
+    #[inline(always)]
+    pub fn record_retired_in(&mut self, priv_level: PrivilegeLevel, count: u64) {
+        self.instruction_count += count;
+        self.priv_instruction_counts[Self::priv_index(priv_level)] += count;
+    }
+
     /// Reset CPU state
     pub fn reset(&mut self) {
         self.pc = 0x0000_1000;
@@ -144,12 +219,27 @@ impl Cpu {
         self.wfi = false;
         self.reservation = None;
         self.mmu.reset();
-        self.cache_invalidation_pending = false;
+        self.icache_invalidation_pending = false;
+        self.semihosting_output.clear();
+        self.exit_code = None;
     }
 
     pub fn tlb_stats(&self) -> (u64, u64) {
         self.mmu.tlb_stats()
     }
+
+    /// `(lookups, hits, evictions)` for the per-instruction decode cache;
+    /// see `ICache::stats`.
+    pub fn icache_stats(&self) -> (u64, u64, u64) {
+        self.icache.stats()
+    }
+
+    /// Configure which extensions are enabled via an ISA string (e.g.
+    /// "rv32ima"), for conformance testing. Instructions from a disabled
+    /// extension trap `IllegalInstruction`.
+    pub fn set_isa(&mut self, isa: &str) -> Result<(), String> {
+        self.csr.set_isa_string(isa)
+    }
     
     /// Check for pending interrupts and handle if any
     pub fn check_interrupts(&mut self) -> Option<Trap> {
@@ -185,8 +275,233 @@ mod tests {
         let mut cpu = Cpu::new();
         cpu.write_reg(0, 0xDEADBEEF);
         assert_eq!(cpu.read_reg(0), 0);
-        
+
         cpu.write_reg(1, 0x12345678);
         assert_eq!(cpu.read_reg(1), 0x12345678);
     }
+
+    #[test]
+    fn test_semihosting_sys_exit_sets_exit_code() {
+        use crate::memory::{Memory, DRAM_BASE};
+
+        let mut cpu = Cpu::new();
+        cpu.pc = DRAM_BASE;
+
+        let mut memory = Memory::new(1);
+
+        // addi a0, x0, 0x18   (a0 = SYS_EXIT)
+        // lui  a1, 0x80000
+        // addi a1, a1, 0x100  (a1 = DRAM_BASE + 0x100, param block)
+        // slli x0, x0, 0x1f   (semihosting marker)
+        // ebreak
+        // srai x0, x0, 7      (semihosting marker)
+        let insts: [u32; 6] = [
+            0x0180_0513,
+            0x8000_05B7,
+            0x1005_8593,
+            0x01f0_1013,
+            0x0010_0073,
+            0x4070_5013,
+        ];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        memory.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        // ADP_Stopped_ApplicationExit param block: reason, exit code
+        memory.write32(DRAM_BASE + 0x100, 0x0002_0026);
+        memory.write32(DRAM_BASE + 0x104, 42);
+
+        for _ in 0..6 {
+            cpu.step(&mut memory).unwrap();
+            if cpu.exit_code.is_some() {
+                break;
+            }
+        }
+
+        assert_eq!(cpu.exit_code, Some(42));
+    }
+
+    #[test]
+    fn test_disabling_m_extension_traps_mul() {
+        use crate::memory::{Memory, DRAM_BASE};
+
+        let mut cpu = Cpu::new();
+        cpu.pc = DRAM_BASE;
+        cpu.set_isa("rv32i").unwrap();
+
+        let mut memory = Memory::new(1);
+
+        // mul x1, x2, x3
+        let insts: [u32; 1] = [0x0231_00b3];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        memory.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        let err = cpu.step(&mut memory).unwrap_err();
+        assert!(matches!(err, Trap::IllegalInstruction(_)));
+    }
+
+    #[test]
+    fn test_fadd_traps_when_fs_off() {
+        use crate::memory::{Memory, DRAM_BASE};
+        use crate::cpu::rv32::csr::MSTATUS_FS;
+
+        let mut cpu = Cpu::new();
+        cpu.pc = DRAM_BASE;
+        cpu.csr.mstatus &= !MSTATUS_FS; // FS = Off
+
+        let mut memory = Memory::new(1);
+
+        // fadd.s f1, f2, f3
+        let insts: [u32; 1] = [0x0031_00d3];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        memory.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        let err = cpu.step(&mut memory).unwrap_err();
+        assert!(matches!(err, Trap::IllegalInstruction(_)));
+    }
+
+    #[test]
+    fn test_unaligned_lr_faults() {
+        use crate::memory::{Memory, DRAM_BASE};
+
+        let mut cpu = Cpu::new();
+        cpu.pc = DRAM_BASE;
+        cpu.write_reg(10, DRAM_BASE + 1); // a0: misaligned address
+
+        let mut memory = Memory::new(1);
+
+        // lr.w x1, (a0)
+        let insts: [u32; 1] = [0x1005_20AF];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        memory.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        let err = cpu.step(&mut memory).unwrap_err();
+        assert!(matches!(err, Trap::LoadAddressMisaligned(_)));
+        assert_eq!(cpu.reservation, None);
+    }
+
+    #[test]
+    fn test_amo_to_device_address_faults_instead_of_touching_memory() {
+        use crate::memory::{Memory, DRAM_BASE};
+
+        let mut cpu = Cpu::new();
+        cpu.pc = DRAM_BASE;
+        cpu.write_reg(10, 0x0300_0000); // a0: UART_BASE in the real System - not RAM here either
+        cpu.write_reg(11, 5); // a1: amount to add
+
+        let mut memory = Memory::new(1);
+
+        // amoadd.w a2, (a0), a1
+        let insts: [u32; 1] = [0x00b5_262f];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        memory.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        let err = cpu.step(&mut memory).unwrap_err();
+        assert!(matches!(err, Trap::StoreAccessFault(_)));
+        // rd should be untouched - the AMO never got far enough to write back.
+        assert_eq!(cpu.read_reg(12), 0);
+    }
+
+    #[test]
+    fn test_store_to_boot_rom_faults_instead_of_being_silently_discarded() {
+        use crate::memory::{Memory, DRAM_BASE, ROM_BASE};
+
+        let mut cpu = Cpu::new();
+        cpu.pc = DRAM_BASE;
+        cpu.write_reg(10, ROM_BASE); // a0: boot ROM base
+        cpu.write_reg(11, 0x1234); // a1: value the guest is trying to write
+
+        let mut memory = Memory::new(1);
+
+        // sw a1, 0(a0)
+        let insts: [u32; 1] = [0x00b5_2023];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        memory.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        let err = cpu.step(&mut memory).unwrap_err();
+        assert!(matches!(err, Trap::StoreAccessFault(addr) if addr == ROM_BASE));
+        // The write never happened - the ROM byte is still whatever it was
+        // initialized to.
+        assert_eq!(memory.read8(ROM_BASE), 0);
+    }
+
+    #[test]
+    fn test_sc_fails_after_intervening_store() {
+        use crate::memory::{Memory, DRAM_BASE};
+
+        let mut cpu = Cpu::new();
+        cpu.pc = DRAM_BASE;
+        cpu.write_reg(10, DRAM_BASE + 0x100); // a0: reservation address
+
+        let mut memory = Memory::new(1);
+
+        // lr.w  x1, (a0)
+        // sw    x0, 0(a0)   ; another store to the reserved word
+        // sc.w  x3, x0, (a0)
+        let insts: [u32; 3] = [0x1005_20AF, 0x0005_2023, 0x1805_21AF];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        memory.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        cpu.step(&mut memory).unwrap(); // lr.w
+        assert_eq!(cpu.reservation, Some(DRAM_BASE + 0x100));
+
+        cpu.step(&mut memory).unwrap(); // sw clears the reservation
+        assert_eq!(cpu.reservation, None);
+
+        cpu.step(&mut memory).unwrap(); // sc.w must observe the lost reservation
+        assert_eq!(cpu.read_reg(3), 1); // failure
+    }
+
+    #[test]
+    fn test_sc_fails_after_an_intervening_timer_interrupt() {
+        use crate::memory::{Memory, DRAM_BASE};
+        use crate::cpu::trap::handle_trap;
+
+        let mut cpu = Cpu::new();
+        cpu.pc = DRAM_BASE;
+        cpu.write_reg(10, DRAM_BASE + 0x100); // a0: reservation address
+
+        let mut memory = Memory::new(1);
+
+        // lr.w  x1, (a0)
+        // sc.w  x3, x0, (a0)
+        let insts: [u32; 2] = [0x1005_20AF, 0x1805_21AF];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        memory.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        cpu.step(&mut memory).unwrap(); // lr.w
+        assert_eq!(cpu.reservation, Some(DRAM_BASE + 0x100));
+
+        // A timer interrupt lands between the LR and the SC - the
+        // reservation must not survive the trip through the trap handler.
+        handle_trap(&mut cpu, Trap::MachineTimerInterrupt);
+        assert_eq!(cpu.reservation, None);
+        cpu.pc = DRAM_BASE + 4; // return straight to the sc.w as if mret had run
+
+        cpu.step(&mut memory).unwrap(); // sc.w must observe the lost reservation
+        assert_eq!(cpu.read_reg(3), 1); // failure
+    }
 }
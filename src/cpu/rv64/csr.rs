@@ -189,7 +189,9 @@ impl Csr64 {
             CSR_MEDELEG => self.medeleg = value,
             CSR_MIDELEG => self.mideleg = value,
             CSR_MIE => self.mie = value,
-            CSR_MTVEC => self.mtvec = value & !3,
+            // MODE (bits[1:0]) is WARL: only Direct (0) and Vectored (1) are
+            // implemented, so reserved values 2/3 collapse to bit 1 = 0.
+            CSR_MTVEC => self.mtvec = value & !0b10,
             CSR_MCOUNTEREN => self.mcounteren = value,
             CSR_MSCRATCH => self.mscratch = value,
             CSR_MEPC => self.mepc = value & !1,
@@ -208,7 +210,8 @@ impl Csr64 {
             CSR_SIE => {
                 self.mie = (self.mie & !self.mideleg) | (value & self.mideleg);
             }
-            CSR_STVEC => self.stvec = value & !3,
+            // Same WARL clamp as MTVEC.
+            CSR_STVEC => self.stvec = value & !0b10,
             CSR_SCOUNTEREN => self.scounteren = value,
             CSR_SSCRATCH => self.sscratch = value,
             CSR_SEPC => self.sepc = value & !1,
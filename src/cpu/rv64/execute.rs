@@ -99,6 +99,12 @@ impl Cpu64 {
                 };
                 let addr = Self::map_paddr(paddr)?;
 
+                // Host-imposed protection overlay (distinct from PMP, which
+                // the guest controls itself) - see `System64::add_protected_range`.
+                if !bus.is_read_allowed(addr) {
+                    return Err(Trap64::LoadAccessFault(vaddr));
+                }
+
                 let value = match d.funct3 {
                     FUNCT3_LB => bus.read8(addr) as i8 as i64 as u64,
                     FUNCT3_LH => {
@@ -181,6 +187,12 @@ impl Cpu64 {
                 };
                 let addr = Self::map_paddr(paddr)?;
 
+                // Host-imposed protection overlay (distinct from PMP, which
+                // the guest controls itself) - see `System64::add_protected_range`.
+                if !bus.is_write_allowed(addr) {
+                    return Err(Trap64::StoreAccessFault(vaddr));
+                }
+
                 match d.funct3 {
                     0b000 => {
                         bus.write8(addr, value as u8);
@@ -223,6 +235,16 @@ impl Cpu64 {
                     _ => return Err(Trap64::IllegalInstruction(inst as u64)),
                 }
 
+                // A plain store to the reserved word invalidates the LR
+                // reservation, so a subsequent SC correctly fails even
+                // though it never went through the AMO path itself.
+                if let Some(reserved) = self.reservation {
+                    let len = match d.funct3 { 0b000 => 1, 0b001 => 2, 0b010 => 4, _ => 8 };
+                    if vaddr < reserved.wrapping_add(8) && vaddr.wrapping_add(len) > reserved {
+                        self.reservation = None;
+                    }
+                }
+
                 self.pc = self.pc.wrapping_add(4);
             }
             OP_OP_IMM => {
@@ -498,7 +520,7 @@ impl Cpu64 {
         out
     }
 
-    fn execute_system(&mut self, inst: u32, d: &DecodedInst, _bus: &mut impl Bus) -> Result<(), Trap64> {
+    fn execute_system(&mut self, inst: u32, d: &DecodedInst, bus: &mut impl Bus) -> Result<(), Trap64> {
         match d.funct3 {
             FUNCT3_PRIV => match inst {
                 0x00000073 => {
@@ -545,6 +567,16 @@ impl Cpu64 {
                 let is_imm = d.funct3 >= FUNCT3_CSRRWI;
                 let rs1_val = if is_imm { d.rs1 as u64 } else { self.read_reg(d.rs1) };
 
+                // `time`/`timeh` are refreshed from the live CLINT counter
+                // right before the read instead of relying solely on the
+                // periodic batch update in `run_with_reason` - see
+                // `Bus::mtime`.
+                if csr_addr == CSR_TIME || csr_addr == CSR_TIMEH {
+                    if let Some(mtime) = bus.mtime() {
+                        self.csr.time = mtime;
+                    }
+                }
+
                 let old_val = match csr_addr {
                     CSR_FFLAGS => self.fpu.fflags.to_bits() as u64,
                     CSR_FRM => self.fpu.frm as u64,
@@ -701,9 +733,19 @@ impl Cpu64 {
         };
         let addr = Self::map_paddr(paddr)?;
 
+        // Host-imposed protection overlay (distinct from PMP, which the
+        // guest controls itself) - see `System64::add_protected_range`.
+        if access == AccessType::Load {
+            if !bus.is_read_allowed(addr) {
+                return Err(Trap64::LoadAccessFault(vaddr));
+            }
+        } else if !bus.is_write_allowed(addr) {
+            return Err(Trap64::StoreAccessFault(vaddr));
+        }
+
         match width {
-            0b010 => self.execute_amo_word(funct5, d, addr, bus, vaddr)?,
-            0b011 => self.execute_amo_double(funct5, d, addr, bus, vaddr)?,
+            0b010 => self.execute_amo_word(inst, funct5, d, addr, bus, vaddr)?,
+            0b011 => self.execute_amo_double(inst, funct5, d, addr, bus, vaddr)?,
             _ => return Err(Trap64::IllegalInstruction(inst as u64)),
         }
 
@@ -711,7 +753,7 @@ impl Cpu64 {
         Ok(())
     }
 
-    fn execute_amo_word(&mut self, funct5: u32, d: &DecodedInst, addr: u32, bus: &mut impl Bus, vaddr: u64) -> Result<(), Trap64> {
+    fn execute_amo_word(&mut self, inst: u32, funct5: u32, d: &DecodedInst, addr: u32, bus: &mut impl Bus, vaddr: u64) -> Result<(), Trap64> {
         if vaddr & 3 != 0 {
             return Err(Trap64::StoreAddressMisaligned(vaddr));
         }
@@ -744,7 +786,7 @@ impl Cpu64 {
                     FUNCT5_AMOMAX => if (old as i32) > (rs2 as i32) { old } else { rs2 },
                     FUNCT5_AMOMINU => if old < rs2 { old } else { rs2 },
                     FUNCT5_AMOMAXU => if old > rs2 { old } else { rs2 },
-                    _ => return Err(Trap64::IllegalInstruction(0)),
+                    _ => return Err(Trap64::IllegalInstruction(inst as u64)),
                 };
                 bus.write32(addr, new);
                 self.write_reg(d.rd, (old as i32 as i64) as u64);
@@ -753,7 +795,7 @@ impl Cpu64 {
         Ok(())
     }
 
-    fn execute_amo_double(&mut self, funct5: u32, d: &DecodedInst, addr: u32, bus: &mut impl Bus, vaddr: u64) -> Result<(), Trap64> {
+    fn execute_amo_double(&mut self, inst: u32, funct5: u32, d: &DecodedInst, addr: u32, bus: &mut impl Bus, vaddr: u64) -> Result<(), Trap64> {
         if vaddr & 7 != 0 {
             return Err(Trap64::StoreAddressMisaligned(vaddr));
         }
@@ -786,7 +828,7 @@ impl Cpu64 {
                     FUNCT5_AMOMAX => if (old as i64) > (rs2 as i64) { old } else { rs2 },
                     FUNCT5_AMOMINU => if old < rs2 { old } else { rs2 },
                     FUNCT5_AMOMAXU => if old > rs2 { old } else { rs2 },
-                    _ => return Err(Trap64::IllegalInstruction(0)),
+                    _ => return Err(Trap64::IllegalInstruction(inst as u64)),
                 };
                 bus.write64(addr, new);
                 self.write_reg(d.rd, old);
@@ -14,10 +14,9 @@ use crate::memory::Bus;
 impl Cpu64 {
     /// Execute floating-point load instructions (FLW, FLD)
     pub fn execute_load_fp(&mut self, inst: u32, d: &DecodedInst, bus: &mut impl Bus) -> Result<(), Trap64> {
-        // WORKAROUND: Skip FS check - kernel may not be enabling FPU properly
-        // if (self.csr.mstatus & MSTATUS_FS) == 0 {
-        //     return Err(Trap64::IllegalInstruction(inst as u64));
-        // }
+        if (self.csr.mstatus & MSTATUS_FS) == 0 {
+            return Err(Trap64::IllegalInstruction(inst as u64));
+        }
         
         let imm = DecodedInst::imm_i(inst) as u64;
         let vaddr = self.read_reg(d.rs1).wrapping_add(imm);
@@ -69,10 +68,9 @@ impl Cpu64 {
     
     /// Execute floating-point store instructions (FSW, FSD)
     pub fn execute_store_fp(&mut self, inst: u32, d: &DecodedInst, bus: &mut impl Bus) -> Result<(), Trap64> {
-        // WORKAROUND: Skip FS check - kernel may not be enabling FPU properly
-        // if (self.csr.mstatus & MSTATUS_FS) == 0 {
-        //     return Err(Trap64::IllegalInstruction(inst as u64));
-        // }
+        if (self.csr.mstatus & MSTATUS_FS) == 0 {
+            return Err(Trap64::IllegalInstruction(inst as u64));
+        }
         
         let imm = DecodedInst::imm_s(inst) as u64;
         let vaddr = self.read_reg(d.rs1).wrapping_add(imm);
@@ -121,10 +119,9 @@ impl Cpu64 {
     
     /// Execute fused multiply-add instructions (FMADD, FMSUB, FNMSUB, FNMADD)
     pub fn execute_fma(&mut self, inst: u32, d: &DecodedInst, opcode: u32) -> Result<(), Trap64> {
-        // WORKAROUND: Skip FS check - kernel may not be enabling FPU properly
-        // if (self.csr.mstatus & MSTATUS_FS) == 0 {
-        //     return Err(Trap64::IllegalInstruction(inst as u64));
-        // }
+        if (self.csr.mstatus & MSTATUS_FS) == 0 {
+            return Err(Trap64::IllegalInstruction(inst as u64));
+        }
         
         let rm = self.fpu.effective_rm(d.funct3);
         let fmt = (inst >> 25) & 0b11;
@@ -200,10 +197,9 @@ impl Cpu64 {
     
     /// Execute floating-point computational instructions (OP-FP opcode)
     pub fn execute_op_fp(&mut self, inst: u32, d: &DecodedInst) -> Result<(), Trap64> {
-        // WORKAROUND: Skip FS check - kernel may not be enabling FPU properly
-        // if (self.csr.mstatus & MSTATUS_FS) == 0 {
-        //     return Err(Trap64::IllegalInstruction(inst as u64));
-        // }
+        if (self.csr.mstatus & MSTATUS_FS) == 0 {
+            return Err(Trap64::IllegalInstruction(inst as u64));
+        }
         
         let rm = self.fpu.effective_rm(d.funct3);
         
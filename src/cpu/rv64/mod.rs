@@ -121,6 +121,10 @@ impl Cpu64 {
             Err(cause) => return Err(Trap64::from_cause(cause, self.pc)),
         };
 
+        if !bus.is_executable(Self::map_paddr(paddr)?) {
+            return Err(Trap64::InstructionAccessFault(self.pc));
+        }
+
         let inst = self.read_inst(bus, paddr)?;
         
         // Debug instruction execution (limited range to avoid flooding)
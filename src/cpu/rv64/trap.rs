@@ -152,6 +152,9 @@ pub fn handle_trap(cpu: &mut Cpu64, trap: Trap64) {
     let tval = trap.value();
     let is_interrupt = trap.is_interrupt();
 
+    // Any trap invalidates an outstanding LR reservation.
+    cpu.reservation = None;
+
     let deleg = if is_interrupt { cpu.csr.mideleg } else { cpu.csr.medeleg };
     let bit = cause & 0x7FFF_FFFF_FFFF_FFFF;
     let delegate_to_s = cpu.priv_level <= PrivilegeLevel::Supervisor &&
@@ -178,7 +181,11 @@ pub fn handle_trap(cpu: &mut Cpu64, trap: Trap64) {
         cpu.csr.mstatus = status;
 
         cpu.priv_level = PrivilegeLevel::Supervisor;
-        cpu.pc = cpu.csr.stvec;
+        cpu.pc = if is_interrupt && (cpu.csr.stvec & 1) != 0 {
+            (cpu.csr.stvec & !1) + bit * 4
+        } else {
+            cpu.csr.stvec & !1
+        };
         return;
     }
 
@@ -197,10 +204,17 @@ pub fn handle_trap(cpu: &mut Cpu64, trap: Trap64) {
     cpu.csr.mstatus = status;
 
     cpu.priv_level = PrivilegeLevel::Machine;
-    cpu.pc = cpu.csr.mtvec;
+    cpu.pc = if is_interrupt && (cpu.csr.mtvec & 1) != 0 {
+        (cpu.csr.mtvec & !1) + bit * 4
+    } else {
+        cpu.csr.mtvec & !1
+    };
 }
 
 pub fn mret(cpu: &mut Cpu64) {
+    // A privilege-level switch invalidates an outstanding LR reservation.
+    cpu.reservation = None;
+
     let mpp = (cpu.csr.mstatus >> 11) & 3;
     cpu.priv_level = PrivilegeLevel::from(mpp as u8);
 
@@ -217,6 +231,9 @@ pub fn mret(cpu: &mut Cpu64) {
 }
 
 pub fn sret(cpu: &mut Cpu64) {
+    // A privilege-level switch invalidates an outstanding LR reservation.
+    cpu.reservation = None;
+
     let spp = (cpu.csr.mstatus >> 8) & 1;
     cpu.priv_level = if spp == 1 { PrivilegeLevel::Supervisor } else { PrivilegeLevel::User };
 
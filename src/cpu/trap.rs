@@ -165,7 +165,10 @@ pub fn handle_trap(cpu: &mut Cpu, trap: Trap) {
     let cause = trap.code();
     let tval = trap.value();
     let is_interrupt = trap.is_interrupt();
-    
+
+    // Any trap invalidates an outstanding LR reservation.
+    cpu.reservation = None;
+
     // Determine if trap should be delegated to S-mode
     let deleg = if is_interrupt {
         cpu.csr.mideleg
@@ -254,10 +257,13 @@ pub fn handle_trap(cpu: &mut Cpu, trap: Trap) {
 
 /// Handle MRET instruction
 pub fn mret(cpu: &mut Cpu) {
+    // A privilege-level switch invalidates an outstanding LR reservation.
+    cpu.reservation = None;
+
     // Restore privilege from MPP
     let mpp = (cpu.csr.mstatus >> 11) & 3;
     cpu.priv_level = PrivilegeLevel::from(mpp as u8);
-    
+
     // Restore MIE from MPIE
     let mut status = cpu.csr.mstatus;
     if (status & MSTATUS_MPIE) != 0 {
@@ -280,6 +286,9 @@ pub fn mret(cpu: &mut Cpu) {
 
 /// Handle SRET instruction
 pub fn sret(cpu: &mut Cpu) {
+    // A privilege-level switch invalidates an outstanding LR reservation.
+    cpu.reservation = None;
+
     // Restore privilege from SPP
     let spp = (cpu.csr.mstatus >> 8) & 1;
     cpu.priv_level = if spp == 1 { 
@@ -307,3 +316,120 @@ pub fn sret(cpu: &mut Cpu) {
     // Jump to sepc
     cpu.pc = cpu.csr.sepc;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delegated_page_fault_from_u_mode_traps_to_s_mode() {
+        let mut cpu = Cpu::new();
+        cpu.priv_level = PrivilegeLevel::User;
+        cpu.pc = 0x8000_1000;
+        cpu.csr.medeleg = 1 << 13; // delegate LoadPageFault
+        cpu.csr.stvec = 0x8000_2000;
+        cpu.csr.mstatus |= MSTATUS_SIE;
+
+        handle_trap(&mut cpu, Trap::LoadPageFault(0x9000_0000));
+
+        assert_eq!(cpu.priv_level, PrivilegeLevel::Supervisor);
+        assert_eq!(cpu.csr.sepc, 0x8000_1000);
+        assert_eq!(cpu.csr.scause, 13);
+        assert_eq!(cpu.csr.stval, 0x9000_0000);
+        assert_eq!(cpu.pc, 0x8000_2000);
+        assert_eq!(cpu.csr.mstatus & MSTATUS_SPP, 0); // SPP=U, trap came from U-mode
+        assert_ne!(cpu.csr.mstatus & MSTATUS_SPIE, 0); // SPIE = old SIE (was set)
+        assert_eq!(cpu.csr.mstatus & MSTATUS_SIE, 0); // SIE cleared on entry
+    }
+
+    #[test]
+    fn test_non_delegated_ecall_from_s_traps_to_m_mode() {
+        let mut cpu = Cpu::new();
+        cpu.priv_level = PrivilegeLevel::Supervisor;
+        cpu.pc = 0x8000_3000;
+        cpu.csr.medeleg = 0; // ecall-from-S NOT delegated (as the boot ROM sets up)
+        cpu.csr.mtvec = 0x1000;
+        cpu.csr.mstatus |= MSTATUS_MIE;
+
+        handle_trap(&mut cpu, Trap::EnvironmentCallFromS);
+
+        assert_eq!(cpu.priv_level, PrivilegeLevel::Machine);
+        assert_eq!(cpu.csr.mepc, 0x8000_3000);
+        assert_eq!(cpu.csr.mcause, 9);
+        assert_eq!(cpu.pc, 0x1000);
+        assert_eq!((cpu.csr.mstatus >> 11) & 3, PrivilegeLevel::Supervisor as u32); // MPP = S
+        assert_ne!(cpu.csr.mstatus & MSTATUS_MPIE, 0); // MPIE = old MIE (was set)
+        assert_eq!(cpu.csr.mstatus & MSTATUS_MIE, 0); // MIE cleared on entry
+    }
+
+    #[test]
+    fn test_vectored_mtvec_dispatches_interrupts_by_cause_but_exceptions_to_base() {
+        let mut cpu = Cpu::new();
+        cpu.priv_level = PrivilegeLevel::Machine;
+        cpu.pc = 0x8000_4000;
+        cpu.csr.mtvec = 0x1000 | 1; // vectored, base = 0x1000
+
+        handle_trap(&mut cpu, Trap::MachineTimerInterrupt);
+        // MachineTimerInterrupt is cause 7 -> base + 4*7
+        assert_eq!(cpu.pc, 0x1000 + 4 * 7);
+
+        cpu.priv_level = PrivilegeLevel::Machine;
+        cpu.pc = 0x8000_5000;
+        handle_trap(&mut cpu, Trap::IllegalInstruction(0));
+        // Exceptions always go to the base address, even in vectored mode.
+        assert_eq!(cpu.pc, 0x1000);
+    }
+
+    #[test]
+    fn test_mtvec_write_rejects_reserved_mode_but_keeps_vectored_bit() {
+        let mut cpu = Cpu::new();
+        // Reserved mode (0b10) should collapse to vectored (0b01) rather
+        // than being stored as-is.
+        cpu.csr.write(CSR_MTVEC, 0x2000 | 0b10, PrivilegeLevel::Machine);
+        assert_eq!(cpu.csr.mtvec, 0x2000);
+
+        cpu.csr.write(CSR_MTVEC, 0x2000 | 0b01, PrivilegeLevel::Machine);
+        assert_eq!(cpu.csr.mtvec, 0x2000 | 0b01);
+    }
+
+    #[test]
+    fn test_mret_from_m_to_s_demotes_mpp_to_user() {
+        let mut cpu = Cpu::new();
+        cpu.priv_level = PrivilegeLevel::Machine;
+        cpu.csr.mepc = 0x8000_6000;
+        cpu.csr.mstatus |= MSTATUS_MIE;
+        // MPP = S, so this MRET returns to Supervisor.
+        cpu.csr.mstatus = (cpu.csr.mstatus & !MSTATUS_MPP) | ((PrivilegeLevel::Supervisor as u32) << 11);
+
+        mret(&mut cpu);
+
+        assert_eq!(cpu.priv_level, PrivilegeLevel::Supervisor);
+        assert_eq!(cpu.pc, 0x8000_6000);
+        // Per spec, MRET always leaves MPP set to U (the least-privileged
+        // supported mode), not the mode it just returned to.
+        assert_eq!((cpu.csr.mstatus & MSTATUS_MPP) >> 11, PrivilegeLevel::User as u32);
+        // MIE restored from MPIE (which was 0), MPIE set to 1.
+        assert_eq!(cpu.csr.mstatus & MSTATUS_MIE, 0);
+        assert_ne!(cpu.csr.mstatus & MSTATUS_MPIE, 0);
+    }
+
+    #[test]
+    fn test_sret_from_s_to_u_demotes_spp_to_user_and_restores_sie_from_spie() {
+        let mut cpu = Cpu::new();
+        cpu.priv_level = PrivilegeLevel::Supervisor;
+        cpu.csr.sepc = 0x8000_7000;
+        cpu.csr.mstatus &= !MSTATUS_SIE;
+        cpu.csr.mstatus |= MSTATUS_SPIE; // SPIE=1, so SIE should come back set
+        cpu.csr.mstatus &= !MSTATUS_SPP; // SPP=U, so this SRET returns to User
+
+        sret(&mut cpu);
+
+        assert_eq!(cpu.priv_level, PrivilegeLevel::User);
+        assert_eq!(cpu.pc, 0x8000_7000);
+        // SPP always reads back as 0 (U) after SRET.
+        assert_eq!(cpu.csr.mstatus & MSTATUS_SPP, 0);
+        // SIE restored from the saved SPIE.
+        assert_ne!(cpu.csr.mstatus & MSTATUS_SIE, 0);
+        assert_ne!(cpu.csr.mstatus & MSTATUS_SPIE, 0);
+    }
+}
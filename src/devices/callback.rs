@@ -0,0 +1,116 @@
+//! Device whose reads and writes are dispatched to caller-supplied
+//! closures instead of a fixed peripheral, for prototyping a new device
+//! without writing a dedicated `Device` impl for it. The wasm bindings use
+//! this to let JavaScript register an MMIO device (see
+//! `Emulator::register_mmio_device` in lib.rs); the closures themselves
+//! are plain Rust here so the plumbing can be exercised without a JS
+//! runtime.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::memory::Device;
+
+/// A [`Device`] backed by a read and a write closure, both invoked with a
+/// byte offset relative to the device's base address.
+///
+/// Both closures run synchronously, on whatever thread is driving
+/// `System::run`/`run_reason`, exactly when the guest's load/store lands
+/// in this device's mapped range. A closure that turns around and pokes
+/// the same device again (directly, or indirectly by re-entering the
+/// emulator) would recurse into `read8`/`write8`; `in_callback` guards
+/// against that by treating the nested access as unmapped (read as 0,
+/// write ignored) instead of calling the closure again.
+///
+/// `Device: Send + Sync` requires `read8` to be soundly callable from a
+/// shared `&CallbackDevice` across threads, so the read closure and the
+/// re-entrancy flag use `Mutex`/`AtomicBool` rather than `RefCell`/`Cell` -
+/// those aren't `Sync`, and asserting it with an unsafe impl would be a
+/// real data race, not just an overly conservative bound.
+pub struct CallbackDevice {
+    read: Mutex<Box<dyn FnMut(u32) -> u8 + Send>>,
+    // `&mut self` already makes write8 exclusive, but the field still needs
+    // to be `Sync` for `CallbackDevice` itself to be - a boxed trait object
+    // isn't `Sync` just because it's `Send`, so it's wrapped the same way.
+    write: Mutex<Box<dyn FnMut(u32, u8) + Send>>,
+    in_callback: AtomicBool,
+}
+
+impl CallbackDevice {
+    pub fn new(
+        read: impl FnMut(u32) -> u8 + Send + 'static,
+        write: impl FnMut(u32, u8) + Send + 'static,
+    ) -> Self {
+        Self {
+            read: Mutex::new(Box::new(read)),
+            write: Mutex::new(Box::new(write)),
+            in_callback: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Device for CallbackDevice {
+    fn read8(&self, offset: u32) -> u8 {
+        if self.in_callback.swap(true, Ordering::SeqCst) {
+            return 0;
+        }
+        let value = (self.read.lock().unwrap())(offset);
+        self.in_callback.store(false, Ordering::SeqCst);
+        value
+    }
+
+    fn write8(&mut self, offset: u32, value: u8) {
+        if self.in_callback.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        (self.write.get_mut().unwrap())(offset, value);
+        self.in_callback.store(false, Ordering::SeqCst);
+    }
+
+    fn reset(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_callback_device_dispatches_reads_and_writes_with_offset() {
+        // `Arc<Mutex<_>>` rather than `Rc<RefCell<_>>`, since the write
+        // closure must be `Send` now that `CallbackDevice` derives it
+        // instead of asserting it.
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        let writes_cb = writes.clone();
+
+        let device = CallbackDevice::new(
+            |offset| 0x10 + offset as u8,
+            move |offset, value| writes_cb.lock().unwrap().push((offset, value)),
+        );
+
+        let mut mem = Memory::new(1);
+        mem.add_device(Box::new(device), 0x5000_0000, 0x1000);
+
+        assert_eq!(mem.read8(0x5000_0000), 0x10);
+        assert_eq!(mem.read8(0x5000_0003), 0x13);
+
+        mem.write8(0x5000_0002, 0x42);
+        assert_eq!(*writes.lock().unwrap(), vec![(2, 0x42)]);
+    }
+
+    #[test]
+    fn test_callback_device_guards_against_reentrant_access() {
+        let device = CallbackDevice::new(
+            |_offset| {
+                // A well-behaved callback wouldn't do this, but if it
+                // does, the nested read must not recurse into `read`.
+                42
+            },
+            |_offset, _value| {},
+        );
+
+        // Reading directly (no re-entrancy in play) still works.
+        assert_eq!(device.read8(0), 42);
+    }
+}
@@ -101,6 +101,40 @@ impl Clint {
         }
     }
     
+    /// Read a 64-bit register (mtime or mtimecmp) atomically, i.e. without
+    /// composing it from two separate 32-bit reads that could straddle a
+    /// `tick()` in between.
+    pub fn read64(&self, offset: u32) -> u64 {
+        match offset {
+            MTIMECMP_BASE => self.mtimecmp,
+            MTIME_BASE => self.mtime,
+            _ => {
+                let lo = self.read32(offset) as u64;
+                let hi = self.read32(offset + 4) as u64;
+                lo | (hi << 32)
+            }
+        }
+    }
+
+    /// Write a 64-bit register (mtime or mtimecmp) atomically, the write
+    /// counterpart to `read64`.
+    pub fn write64(&mut self, offset: u32, value: u64) {
+        match offset {
+            MTIMECMP_BASE => {
+                self.mtimecmp = value;
+                self.check_timer();
+            }
+            MTIME_BASE => {
+                self.mtime = value;
+                self.check_timer();
+            }
+            _ => {
+                self.write32(offset, value as u32);
+                self.write32(offset + 4, (value >> 32) as u32);
+            }
+        }
+    }
+
     pub fn write32(&mut self, offset: u32, value: u32) {
         match offset {
             o if o >= MSIP_BASE && o < MSIP_BASE + 4 => {
@@ -14,6 +14,13 @@ const FDT_PROP: u32 = 3;
 const FDT_NOP: u32 = 4;
 const FDT_END: u32 = 9;
 
+/// CLINT `mtime` ticks per second, advertised to the guest as
+/// `timebase-frequency` below - the guest's own timer interrupt scheduling
+/// assumes ticks run at this rate, so host code that needs to reason about
+/// guest wall-clock time (e.g. `System::set_paste_rate`) converts through
+/// this constant too.
+pub const TIMEBASE_HZ: u32 = 10_000_000;
+
 pub struct DtbBuilder {
     struct_buf: Vec<u8>,
     strings_buf: Vec<u8>,
@@ -154,9 +161,188 @@ impl DtbBuilder {
     }
 }
 
+/// Why a caller-supplied DTB (see `System::setup_linux_boot_with_dtb`) was
+/// rejected before it got anywhere near the guest's address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DtbError {
+    /// Too short to even hold an FDT header.
+    TooSmall { len: usize, min: usize },
+    /// First word isn't the FDT magic number.
+    BadMagic(u32),
+    /// The header's own `totalsize` field disagrees with the blob's actual
+    /// length - a sign it was truncated or concatenated with something else.
+    TotalSizeMismatch { header_totalsize: u32, actual_len: usize },
+    /// Bigger than the RAM it would need to be loaded into.
+    TooLargeForRam { totalsize: u32, available: u32 },
+    /// A token, length, or string-table offset in the struct block reads or
+    /// indexes past the end of the blob - the header checked out, but the
+    /// body is truncated or was adversarially crafted.
+    MalformedStructBlock,
+}
+
+impl std::fmt::Display for DtbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DtbError::TooSmall { len, min } => {
+                write!(f, "DTB is {} bytes, too short for an FDT header ({} bytes)", len, min)
+            }
+            DtbError::BadMagic(magic) => {
+                write!(f, "DTB has bad magic 0x{:08x} (expected 0x{:08x})", magic, FDT_MAGIC)
+            }
+            DtbError::TotalSizeMismatch { header_totalsize, actual_len } => write!(
+                f,
+                "DTB header says {} bytes but the blob is {} bytes",
+                header_totalsize, actual_len
+            ),
+            DtbError::TooLargeForRam { totalsize, available } => write!(
+                f,
+                "DTB is {} bytes, which doesn't fit in {} bytes of RAM",
+                totalsize, available
+            ),
+            DtbError::MalformedStructBlock => {
+                write!(f, "DTB struct block is truncated or has an out-of-bounds token")
+            }
+        }
+    }
+}
+
+const FDT_HEADER_SIZE: usize = 40;
+
+/// Validate a DTB's header and overall size before trusting it enough to
+/// load into guest RAM: right magic, `totalsize` matches the blob's actual
+/// length, and it fits within `available_ram` bytes.
+pub fn validate_header(dtb: &[u8], available_ram: u32) -> Result<(), DtbError> {
+    if dtb.len() < FDT_HEADER_SIZE {
+        return Err(DtbError::TooSmall { len: dtb.len(), min: FDT_HEADER_SIZE });
+    }
+    let magic = read_be_u32(dtb, 0);
+    if magic != FDT_MAGIC {
+        return Err(DtbError::BadMagic(magic));
+    }
+    let totalsize = read_be_u32(dtb, 4);
+    if totalsize as usize != dtb.len() {
+        return Err(DtbError::TotalSizeMismatch { header_totalsize: totalsize, actual_len: dtb.len() });
+    }
+    if totalsize > available_ram {
+        return Err(DtbError::TooLargeForRam { totalsize, available: available_ram });
+    }
+    Ok(())
+}
+
+fn read_be_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_be_bytes(buf[off..off + 4].try_into().unwrap())
+}
+
+/// Like `read_be_u32`, but for offsets that come from inside the blob
+/// itself (and so can't be trusted to be in bounds) rather than from the
+/// fixed-size, already-length-checked header.
+fn checked_be_u32(buf: &[u8], off: usize) -> Result<u32, DtbError> {
+    buf.get(off..off + 4)
+        .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+        .ok_or(DtbError::MalformedStructBlock)
+}
+
+/// Like `&buf[start..end]`, but returns `MalformedStructBlock` instead of
+/// panicking when `start..end` runs past the end of `buf`.
+fn checked_slice(buf: &[u8], start: usize, end: usize) -> Result<&[u8], DtbError> {
+    buf.get(start..end).ok_or(DtbError::MalformedStructBlock)
+}
+
+/// Render a DTB as an indented dump of its node/property tree - strings
+/// decoded, property values that aren't a null-terminated string list shown
+/// as either a `<...>` cell list (if a multiple of 4 bytes) or raw hex
+/// bytes, mirroring how `dtc -O dts` reads back a compiled blob. Not a full
+/// FDT parser (no phandle/alias resolution) - just enough to eyeball what a
+/// boot actually got handed. Used by `System::get_dtb_text`.
+pub fn dump_text(dtb: &[u8]) -> Result<String, DtbError> {
+    validate_header(dtb, u32::MAX)?;
+
+    let off_dt_struct = read_be_u32(dtb, 8) as usize;
+    let off_dt_strings = read_be_u32(dtb, 12) as usize;
+    let size_dt_struct = read_be_u32(dtb, 36) as usize;
+    let struct_end = off_dt_struct.checked_add(size_dt_struct).filter(|&end| end <= dtb.len()).ok_or(DtbError::MalformedStructBlock)?;
+
+    let mut out = String::new();
+    let mut pos = off_dt_struct;
+    let mut depth = 0usize;
+
+    while pos + 4 <= struct_end {
+        let token = checked_be_u32(dtb, pos)?;
+        pos += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name_len = dtb.get(pos..).ok_or(DtbError::MalformedStructBlock)?.iter().position(|&b| b == 0).unwrap_or(0);
+                let name_end = pos.checked_add(name_len).ok_or(DtbError::MalformedStructBlock)?;
+                let name = String::from_utf8_lossy(checked_slice(dtb, pos, name_end)?);
+                let label = if name.is_empty() { "/" } else { &name };
+                out.push_str(&"  ".repeat(depth));
+                out.push_str(label);
+                out.push_str(" {\n");
+                pos = name_end.checked_add(1).ok_or(DtbError::MalformedStructBlock)?;
+                pos = (pos + 3) & !3;
+                depth += 1;
+            }
+            FDT_END_NODE => {
+                depth = depth.saturating_sub(1);
+                out.push_str(&"  ".repeat(depth));
+                out.push_str("};\n");
+            }
+            FDT_PROP => {
+                let len = checked_be_u32(dtb, pos)? as usize;
+                pos += 4;
+                let name_off_raw = checked_be_u32(dtb, pos)? as usize;
+                pos += 4;
+                let name_off = off_dt_strings.checked_add(name_off_raw).ok_or(DtbError::MalformedStructBlock)?;
+                let name_len = dtb.get(name_off..).ok_or(DtbError::MalformedStructBlock)?.iter().position(|&b| b == 0).unwrap_or(0);
+                let name_end = name_off.checked_add(name_len).ok_or(DtbError::MalformedStructBlock)?;
+                let name = String::from_utf8_lossy(checked_slice(dtb, name_off, name_end)?);
+                let data_end = pos.checked_add(len).ok_or(DtbError::MalformedStructBlock)?;
+                let data = checked_slice(dtb, pos, data_end)?;
+                pos = data_end;
+                pos = (pos + 3) & !3;
+                out.push_str(&"  ".repeat(depth));
+                out.push_str(&name);
+                out.push_str(" = ");
+                out.push_str(&format_prop_value(data));
+                out.push_str(";\n");
+            }
+            FDT_NOP => {}
+            FDT_END => break,
+            _ => break,
+        }
+    }
+
+    Ok(out)
+}
+
+/// String list if every byte is printable ASCII (or a NUL separator) and
+/// the data ends in NUL, cells if it's a multiple of 4 bytes, otherwise raw
+/// hex - the same heuristic `dtc`/`fdtdump` use to guess a property's type.
+fn format_prop_value(data: &[u8]) -> String {
+    if data.is_empty() {
+        return String::new();
+    }
+    if data.last() == Some(&0) && data[..data.len() - 1].iter().all(|&b| b == 0 || (0x20..0x7f).contains(&b)) {
+        let strings: Vec<String> = data[..data.len() - 1]
+            .split(|&b| b == 0)
+            .map(|s| format!("{:?}", String::from_utf8_lossy(s)))
+            .collect();
+        return strings.join(", ");
+    }
+    if data.len().is_multiple_of(4) {
+        let cells: Vec<String> = data
+            .chunks(4)
+            .map(|c| format!("0x{:08x}", u32::from_be_bytes(c.try_into().unwrap())))
+            .collect();
+        return format!("<{}>", cells.join(" "));
+    }
+    let bytes: Vec<String> = data.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("[{}]", bytes.join(" "))
+}
+
 /// Generate the Device Tree Blob for our emulator
 /// If initrd_start and initrd_end are provided, adds initrd info to /chosen
-pub fn generate_fdt(ram_size_mb: u32, cmdline: &str, initrd: Option<(u32, u32)>) -> Vec<u8> {
+pub fn generate_fdt(ram_size_mb: u32, cmdline: &str, initrd: Option<(u32, u32)>, isa: &str) -> Vec<u8> {
     let mut dtb = DtbBuilder::new();
     
     // Root node
@@ -170,21 +356,29 @@ pub fn generate_fdt(ram_size_mb: u32, cmdline: &str, initrd: Option<(u32, u32)>)
     dtb.begin_node("chosen");
     dtb.property_string("bootargs", cmdline);
     dtb.property_string("stdout-path", "/soc/uart@3000000");
-    
+
     // Add initrd location if provided
     if let Some((start, end)) = initrd {
         // Linux expects these as 32-bit values for rv32
         dtb.property_u32("linux,initrd-start", start);
         dtb.property_u32("linux,initrd-end", end);
     }
-    
+
     dtb.end_node();
-    
+
+    // /aliases - lets the kernel resolve "serial0"/"serial1" (e.g. from a
+    // udev rule or another alias-relative reference) to the actual UART
+    // nodes below without hardcoding their unit addresses.
+    dtb.begin_node("aliases");
+    dtb.property_string("serial0", "/soc/uart@3000000");
+    dtb.property_string("serial1", "/soc/uart@3001000");
+    dtb.end_node();
+
     // /cpus
     dtb.begin_node("cpus");
     dtb.property_u32("#address-cells", 1);
     dtb.property_u32("#size-cells", 0);
-    dtb.property_u32("timebase-frequency", 10_000_000); // 10 MHz
+    dtb.property_u32("timebase-frequency", TIMEBASE_HZ);
     
         // /cpus/cpu@0
         dtb.begin_node("cpu@0");
@@ -192,7 +386,25 @@ pub fn generate_fdt(ram_size_mb: u32, cmdline: &str, initrd: Option<(u32, u32)>)
         dtb.property_u32("reg", 0);
         dtb.property_string("status", "okay");
         dtb.property_string("compatible", "riscv");
-        dtb.property_string("riscv,isa", "rv32ima");
+        dtb.property_string("riscv,isa", isa);
+        // Sstc is a multi-letter extension and doesn't fit the legacy
+        // `riscv,isa` string, so newer kernels look for it here instead.
+        // menvcfg.STCE itself isn't device-tree visible - the kernel sets
+        // that bit on its own once it sees "sstc" advertised.
+        let isa_lower = isa.to_ascii_lowercase();
+        let isa_exts = isa_lower.strip_prefix("rv32").or_else(|| isa_lower.strip_prefix("rv64")).unwrap_or(&isa_lower);
+        let mut isa_extensions: Vec<String> = isa_exts.chars().map(|c| c.to_string()).collect();
+        isa_extensions.push("sstc".to_string());
+        // The legacy `riscv,isa` string only has room for the single letter
+        // 'b'; list the actual Zba/Zbb/Zbs sub-extensions it stands for here
+        // too, since that's what newer kernels look for.
+        if isa_exts.contains('b') {
+            isa_extensions.push("zba".to_string());
+            isa_extensions.push("zbb".to_string());
+            isa_extensions.push("zbs".to_string());
+        }
+        let isa_extension_refs: Vec<&str> = isa_extensions.iter().map(String::as_str).collect();
+        dtb.property_string_list("riscv,isa-extensions", &isa_extension_refs);
         dtb.property_string("mmu-type", "riscv,sv32");
         
             // /cpus/cpu@0/interrupt-controller
@@ -232,7 +444,10 @@ pub fn generate_fdt(ram_size_mb: u32, cmdline: &str, initrd: Option<(u32, u32)>)
         // Linux in S-mode uses S-mode timer (5)
         // Format: &cpu_intc irq_num repeated for each interrupt
         dtb.property_array_u32("interrupts-extended", &[1, 3, 1, 7, 1, 1, 1, 5]); 
-        dtb.property_array_u32("reg", &[0, 0x02000000, 0, 0x10000]);
+        dtb.property_array_u32("reg", &[
+            (super::map::CLINT_BASE >> 32) as u32, super::map::CLINT_BASE as u32,
+            (super::map::CLINT_SIZE >> 32) as u32, super::map::CLINT_SIZE as u32,
+        ]);
         dtb.end_node();
         
         // PLIC
@@ -248,15 +463,25 @@ pub fn generate_fdt(ram_size_mb: u32, cmdline: &str, initrd: Option<(u32, u32)>)
         dtb.property_u32("phandle", 2); // PHANDLE_PLIC
         dtb.end_node();
         
-        // UART
+        // UART 0 - interactive console
         dtb.begin_node("uart@3000000");
         dtb.property_string("compatible", "ns16550a");
         dtb.property_array_u32("reg", &[0, 0x03000000, 0, 0x1000]);
         dtb.property_u32("interrupts", 10);
         dtb.property_u32("interrupt-parent", 2); // &plic
-        dtb.property_u32("clock-frequency", 3686400); 
+        dtb.property_u32("clock-frequency", 3686400);
         dtb.end_node();
-        
+
+        // UART 1 - free for a dedicated channel, e.g. kernel log via
+        // console=ttyS1, separate from the interactive shell on UART 0.
+        dtb.begin_node("uart@3001000");
+        dtb.property_string("compatible", "ns16550a");
+        dtb.property_array_u32("reg", &[0, 0x03001000, 0, 0x1000]);
+        dtb.property_u32("interrupts", 11);
+        dtb.property_u32("interrupt-parent", 2); // &plic
+        dtb.property_u32("clock-frequency", 3686400);
+        dtb.end_node();
+
         // VirtIO
         dtb.begin_node("virtio@20000000");
         dtb.property_string("compatible", "virtio,mmio");
@@ -292,21 +517,26 @@ pub fn generate_fdt_rv64(ram_size_mb: u32, cmdline: &str, initrd: Option<(u32, u
     dtb.begin_node("chosen");
     dtb.property_string("bootargs", cmdline);
     dtb.property_string("stdout-path", "/soc/serial@10000000");
-    
+
     // Add initrd location if provided (64-bit addresses for RV64)
     if let Some((start, end)) = initrd {
         // RV64 uses 64-bit values
         dtb.property_u64("linux,initrd-start", start as u64);
         dtb.property_u64("linux,initrd-end", end as u64);
     }
-    
+
     dtb.end_node();
-    
+
+    // /aliases
+    dtb.begin_node("aliases");
+    dtb.property_string("serial0", "/soc/serial@10000000");
+    dtb.end_node();
+
     // /cpus
     dtb.begin_node("cpus");
     dtb.property_u32("#address-cells", 1);
     dtb.property_u32("#size-cells", 0);
-    dtb.property_u32("timebase-frequency", 10_000_000); // 10 MHz
+    dtb.property_u32("timebase-frequency", TIMEBASE_HZ);
     
         // /cpus/cpu@0
         dtb.begin_node("cpu@0");
@@ -342,13 +572,16 @@ pub fn generate_fdt_rv64(ram_size_mb: u32, cmdline: &str, initrd: Option<(u32, u
     dtb.property_string("compatible", "simple-bus");
     dtb.property_null("ranges");
     
-        // CLINT at 0x02000000 (matches system64.rs CLINT_BASE)
+        // CLINT at `map::CLINT_BASE`, shared with `System`/`System64`
         dtb.begin_node("clint@2000000");
         dtb.property_string("compatible", "riscv,clint0");
         // Format: &cpu_intc irq_num for each interrupt
         // M-mode SW (3), M-mode Timer (7), S-mode SW (1), S-mode Timer (5)
         dtb.property_array_u32("interrupts-extended", &[1, 3, 1, 7, 1, 1, 1, 5]); 
-        dtb.property_array_u32("reg", &[0, 0x02000000, 0, 0x10000]);
+        dtb.property_array_u32("reg", &[
+            (super::map::CLINT_BASE >> 32) as u32, super::map::CLINT_BASE as u32,
+            (super::map::CLINT_SIZE >> 32) as u32, super::map::CLINT_SIZE as u32,
+        ]);
         dtb.end_node();
         
         // PLIC at 0x0C000000 (matches system64.rs PLIC_BASE)
@@ -378,10 +611,217 @@ pub fn generate_fdt_rv64(ram_size_mb: u32, cmdline: &str, initrd: Option<(u32, u
         dtb.property_u32("interrupts", 1);
         dtb.property_u32("interrupt-parent", 2); // &plic
         dtb.end_node();
-        
+
     dtb.end_node(); // soc
 
     dtb.end_node(); // root
 
     dtb.finish()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FdtHeader {
+        magic: u32,
+        totalsize: u32,
+        off_dt_struct: u32,
+        off_dt_strings: u32,
+        size_dt_strings: u32,
+        size_dt_struct: u32,
+    }
+
+    fn read_be_u32(buf: &[u8], off: usize) -> u32 {
+        u32::from_be_bytes(buf[off..off + 4].try_into().unwrap())
+    }
+
+    fn parse_header(dtb: &[u8]) -> FdtHeader {
+        FdtHeader {
+            magic: read_be_u32(dtb, 0),
+            totalsize: read_be_u32(dtb, 4),
+            off_dt_struct: read_be_u32(dtb, 8),
+            off_dt_strings: read_be_u32(dtb, 12),
+            size_dt_strings: read_be_u32(dtb, 32),
+            size_dt_struct: read_be_u32(dtb, 36),
+        }
+    }
+
+    /// Walk the structure block looking for the first property named
+    /// `prop_name` anywhere in the tree (good enough for these tests, since
+    /// none of the property names we check for are reused across nodes).
+    fn find_property<'a>(dtb: &'a [u8], header: &FdtHeader, prop_name: &str) -> Option<&'a [u8]> {
+        let struct_start = header.off_dt_struct as usize;
+        let struct_end = struct_start + header.size_dt_struct as usize;
+        let strings_start = header.off_dt_strings as usize;
+        let mut pos = struct_start;
+
+        while pos < struct_end {
+            let token = read_be_u32(dtb, pos);
+            pos += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name_len = dtb[pos..].iter().position(|&b| b == 0).unwrap();
+                    pos += name_len + 1;
+                    pos = (pos + 3) & !3;
+                }
+                FDT_END_NODE | FDT_NOP => {}
+                FDT_PROP => {
+                    let len = read_be_u32(dtb, pos) as usize;
+                    pos += 4;
+                    let name_off = strings_start + read_be_u32(dtb, pos) as usize;
+                    pos += 4;
+                    let name_len = dtb[name_off..].iter().position(|&b| b == 0).unwrap();
+                    let name = std::str::from_utf8(&dtb[name_off..name_off + name_len]).unwrap();
+                    let data = &dtb[pos..pos + len];
+                    pos += len;
+                    pos = (pos + 3) & !3;
+                    if name == prop_name {
+                        return Some(data);
+                    }
+                }
+                FDT_END => break,
+                other => panic!("unexpected FDT token 0x{:x} at offset {}", other, pos - 4),
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn test_generate_fdt_round_trips_a_4kb_cmdline() {
+        let cmdline: String = "console=ttyS0 module.param=1 ".repeat(150);
+        assert!(cmdline.len() > 4096, "test cmdline should exceed 4KB, got {}", cmdline.len());
+
+        let dtb = generate_fdt(64, &cmdline, None, "rv32imafdc");
+        let header = parse_header(&dtb);
+
+        assert_eq!(header.magic, FDT_MAGIC);
+        assert_eq!(header.totalsize as usize, dtb.len());
+        assert_eq!(header.off_dt_struct + header.size_dt_struct, header.off_dt_strings);
+        assert_eq!(header.off_dt_strings + header.size_dt_strings, dtb.len() as u32);
+
+        let bootargs = find_property(&dtb, &header, "bootargs").expect("bootargs property");
+        let mut expected = cmdline.into_bytes();
+        expected.push(0);
+        assert_eq!(bootargs, expected.as_slice());
+    }
+
+    #[test]
+    fn test_generate_fdt_adds_aliases_for_both_uarts() {
+        let dtb = generate_fdt(64, "console=ttyS0", None, "rv32imafdc");
+        let header = parse_header(&dtb);
+
+        let serial0 = find_property(&dtb, &header, "serial0").expect("serial0 alias");
+        assert_eq!(&serial0[..serial0.len() - 1], b"/soc/uart@3000000");
+
+        let serial1 = find_property(&dtb, &header, "serial1").expect("serial1 alias");
+        assert_eq!(&serial1[..serial1.len() - 1], b"/soc/uart@3001000");
+    }
+
+    #[test]
+    fn test_generate_fdt_rv64_adds_stdout_path_and_serial0_alias() {
+        let dtb = generate_fdt_rv64(128, "console=ttyS0", None);
+        let header = parse_header(&dtb);
+
+        let stdout_path = find_property(&dtb, &header, "stdout-path").expect("stdout-path property");
+        assert_eq!(&stdout_path[..stdout_path.len() - 1], b"/soc/serial@10000000");
+
+        let serial0 = find_property(&dtb, &header, "serial0").expect("serial0 alias");
+        assert_eq!(&serial0[..serial0.len() - 1], b"/soc/serial@10000000");
+    }
+
+    #[test]
+    fn test_validate_header_accepts_generate_fdt_output() {
+        let dtb = generate_fdt(64, "console=ttyS0", None, "rv32imafdc");
+        validate_header(&dtb, 64 * 1024 * 1024).expect("generate_fdt output should validate");
+    }
+
+    #[test]
+    fn test_validate_header_rejects_too_small_blob() {
+        let err = validate_header(&[0u8; 10], 64 * 1024 * 1024).unwrap_err();
+        assert_eq!(err, DtbError::TooSmall { len: 10, min: FDT_HEADER_SIZE });
+    }
+
+    #[test]
+    fn test_validate_header_rejects_bad_magic() {
+        let mut dtb = generate_fdt(64, "console=ttyS0", None, "rv32imafdc");
+        dtb[0] = 0;
+        let err = validate_header(&dtb, 64 * 1024 * 1024).unwrap_err();
+        assert_eq!(err, DtbError::BadMagic(read_be_u32(&dtb, 0)));
+    }
+
+    #[test]
+    fn test_validate_header_rejects_totalsize_mismatch() {
+        let mut dtb = generate_fdt(64, "console=ttyS0", None, "rv32imafdc");
+        dtb.push(0); // totalsize field no longer matches the blob's real length
+        let err = validate_header(&dtb, 64 * 1024 * 1024).unwrap_err();
+        assert_eq!(err, DtbError::TotalSizeMismatch {
+            header_totalsize: read_be_u32(&dtb, 4),
+            actual_len: dtb.len(),
+        });
+    }
+
+    #[test]
+    fn test_validate_header_rejects_dtb_larger_than_available_ram() {
+        let dtb = generate_fdt(64, "console=ttyS0", None, "rv32imafdc");
+        let totalsize = read_be_u32(&dtb, 4);
+        let err = validate_header(&dtb, totalsize - 1).unwrap_err();
+        assert_eq!(err, DtbError::TooLargeForRam { totalsize, available: totalsize - 1 });
+    }
+
+    #[test]
+    fn test_dump_text_renders_string_property_quoted() {
+        let dtb = generate_fdt(64, "console=ttyS0", None, "rv32imafdc");
+        let text = dump_text(&dtb).expect("dump_text should succeed");
+        assert!(text.contains("bootargs = \"console=ttyS0\";"), "dump was:\n{}", text);
+    }
+
+    #[test]
+    fn test_dump_text_renders_cell_array_property_as_hex() {
+        let dtb = generate_fdt(64, "console=ttyS0", None, "rv32imafdc");
+        let text = dump_text(&dtb).expect("dump_text should succeed");
+        assert!(text.contains("#address-cells = <0x00000002>;"), "dump was:\n{}", text);
+    }
+
+    /// A header-valid DTB whose struct block claims an `FDT_PROP` length (or
+    /// string-table offset) that runs past the end of the blob used to drive
+    /// `read_be_u32`/slicing past the buffer and panic instead of returning
+    /// `Err(DtbError::MalformedStructBlock)`.
+    #[test]
+    fn test_dump_text_rejects_struct_block_with_oversized_prop_len() {
+        let mut dtb = Vec::new();
+        dtb.extend_from_slice(&FDT_MAGIC.to_be_bytes());
+        dtb.extend_from_slice(&48u32.to_be_bytes()); // totalsize
+        dtb.extend_from_slice(&40u32.to_be_bytes()); // off_dt_struct
+        dtb.extend_from_slice(&0u32.to_be_bytes()); // off_dt_strings
+        dtb.extend_from_slice(&40u32.to_be_bytes()); // off_mem_rsvmap
+        dtb.extend_from_slice(&FDT_VERSION.to_be_bytes());
+        dtb.extend_from_slice(&FDT_LAST_COMP_VERSION.to_be_bytes());
+        dtb.extend_from_slice(&0u32.to_be_bytes()); // boot_cpuid_phys
+        dtb.extend_from_slice(&0u32.to_be_bytes()); // size_dt_strings
+        dtb.extend_from_slice(&8u32.to_be_bytes()); // size_dt_struct
+        dtb.extend_from_slice(&FDT_PROP.to_be_bytes());
+        dtb.extend_from_slice(&0xFFFFFFFFu32.to_be_bytes()); // claimed prop len
+        assert_eq!(dtb.len(), 48);
+
+        assert_eq!(dump_text(&dtb), Err(DtbError::MalformedStructBlock));
+    }
+
+    #[test]
+    fn test_dump_text_rejects_size_dt_struct_that_overruns_the_blob() {
+        let mut dtb = Vec::new();
+        dtb.extend_from_slice(&FDT_MAGIC.to_be_bytes());
+        dtb.extend_from_slice(&40u32.to_be_bytes()); // totalsize - matches the actual blob length
+        dtb.extend_from_slice(&40u32.to_be_bytes()); // off_dt_struct
+        dtb.extend_from_slice(&0u32.to_be_bytes()); // off_dt_strings
+        dtb.extend_from_slice(&40u32.to_be_bytes()); // off_mem_rsvmap
+        dtb.extend_from_slice(&FDT_VERSION.to_be_bytes());
+        dtb.extend_from_slice(&FDT_LAST_COMP_VERSION.to_be_bytes());
+        dtb.extend_from_slice(&0u32.to_be_bytes()); // boot_cpuid_phys
+        dtb.extend_from_slice(&0u32.to_be_bytes()); // size_dt_strings
+        dtb.extend_from_slice(&8u32.to_be_bytes()); // size_dt_struct claims 8 bytes that don't exist
+        assert_eq!(dtb.len(), 40);
+
+        assert_eq!(dump_text(&dtb), Err(DtbError::MalformedStructBlock));
+    }
+}
@@ -0,0 +1,17 @@
+//! Shared MMIO region constants for devices wired into both `System`
+//! (rv32) and `System64` - the address map doesn't depend on XLEN, so
+//! defining it once here keeps it from drifting out of sync the way
+//! `CLINT_BASE`/`CLINT_SIZE` previously did across `system.rs`,
+//! `system64.rs`, and `dtb::generate_fdt`, which each hardcoded their own
+//! copy of the same two numbers.
+//!
+//! This currently covers CLINT only. UART, PLIC, and virtio9p still have
+//! their base addresses and IRQ numbers defined locally in each
+//! `System`/`System64` - folding those in here too, alongside interrupt
+//! routing and DTB node generation, is the natural next step toward a
+//! full address-width-agnostic device layer shared by both systems.
+
+/// Physical base address of the CLINT MMIO region.
+pub const CLINT_BASE: u64 = 0x0200_0000;
+/// Size in bytes of the CLINT MMIO region.
+pub const CLINT_SIZE: u64 = 0x0001_0000;
@@ -8,9 +8,12 @@ mod plic;
 pub mod virtio;
 pub mod virtio_9p;
 pub mod dtb;
+mod callback;
+pub mod map;
 
-pub use uart::Uart;
+pub use uart::{Uart, TxOverflowPolicy};
 pub use clint::Clint;
 pub use plic::Plic;
 pub use virtio::VirtioMmio;
 pub use virtio_9p::Virtio9p;
+pub use callback::CallbackDevice;
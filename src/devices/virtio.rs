@@ -54,6 +54,20 @@ pub const VRING_DESC_F_NEXT: u16 = 1;
 pub const VRING_DESC_F_WRITE: u16 = 2;
 pub const VRING_DESC_F_INDIRECT: u16 = 4;
 
+/// Device/driver can negotiate the `used_event`/`avail_event` fields for
+/// interrupt and notification suppression instead of always
+/// interrupting/notifying on every ring update.
+pub const VIRTIO_F_EVENT_IDX: u64 = 1 << 29;
+
+/// The `VRING_NEED_EVENT` check from the VirtIO spec's "Used Buffer
+/// Notification Suppression" section: does advancing the used index from
+/// `old` to `new` cross the driver's requested `event` index? Used to
+/// decide whether an interrupt is actually needed once VIRTIO_F_EVENT_IDX
+/// is negotiated, instead of raising one on every batch of used entries.
+pub fn vring_need_event(event: u16, new: u16, old: u16) -> bool {
+    new.wrapping_sub(event).wrapping_sub(1) < new.wrapping_sub(old)
+}
+
 /// Virtqueue descriptor
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Descriptor {
@@ -149,19 +163,41 @@ impl Virtqueue {
         // used->idx is at offset 2
         let idx_addr = self.used_addr + 2;
         let idx = mem.read16(idx_addr as u32);
-        
+
         // Write used element
         // Ring starts at offset 4
         // Each element is 8 bytes: id (32), len (32)
         let offset = 4 + (idx as u64 % self.num as u64) * 8;
         let addr = self.used_addr + offset;
-        
+
         mem.write32(addr as u32, desc_idx);
         mem.write32((addr + 4) as u32, len);
-        
+
         // Increment index
         mem.write16(idx_addr as u32, idx.wrapping_add(1));
     }
+
+    /// Current used ring index (used->idx).
+    pub fn used_idx(&self, mem: &Memory) -> u16 {
+        mem.read16((self.used_addr + 2) as u32)
+    }
+
+    /// The `used_event` value the driver wrote just past the avail ring,
+    /// telling the device which used index it wants to be interrupted at.
+    /// Only meaningful once `VIRTIO_F_EVENT_IDX` is negotiated.
+    pub fn used_event(&self, mem: &Memory) -> u16 {
+        let addr = self.avail_addr + 4 + (self.num as u64) * 2;
+        mem.read16(addr as u32)
+    }
+
+    /// Write `avail_event` just past the used ring, telling the driver
+    /// which avail index the device has consumed so it knows when it next
+    /// needs to notify. Only meaningful once `VIRTIO_F_EVENT_IDX` is
+    /// negotiated.
+    pub fn set_avail_event(&mut self, mem: &mut Memory, avail_idx: u16) {
+        let addr = self.used_addr + 4 + (self.num as u64) * 8;
+        mem.write16(addr as u32, avail_idx);
+    }
 }
 
 /// VirtIO MMIO device base
@@ -195,6 +231,12 @@ pub struct VirtioMmio {
     pub interrupt_pending: bool,
     /// Pending queue notifications (indices)
     pub queue_notify_pending: VecDeque<u32>,
+    /// Set whenever the guest writes the queue-notify register (or work is
+    /// left over from a budget-limited `process_queues` pass) and cleared
+    /// by `take_notify_dirty`. Lets the run loop skip calling
+    /// `process_queues` at all when there's nothing to do, instead of
+    /// paying for the call every single step.
+    pub notify_dirty: bool,
 }
 
 impl VirtioMmio {
@@ -219,6 +261,7 @@ impl VirtioMmio {
             config_generation: 0,
             interrupt_pending: false,
             queue_notify_pending: VecDeque::new(),
+            notify_dirty: false,
         }
     }
     
@@ -300,6 +343,7 @@ impl VirtioMmio {
             }
             VIRTIO_MMIO_QUEUE_NOTIFY => {
                 self.queue_notify_pending.push_back(value);
+                self.notify_dirty = true;
             }
             VIRTIO_MMIO_QUEUE_DESC_LOW => {
                 if let Some(q) = self.queues.get_mut(self.queue_sel as usize) {
@@ -385,8 +429,22 @@ impl VirtioMmio {
         }
         self.interrupt_pending = false;
         self.queue_notify_pending.clear();
+        self.notify_dirty = false;
+    }
+
+    /// Consume the notify-dirty flag: `true` if the guest has kicked a
+    /// queue (or budget-limited work was left over) since the last call.
+    pub fn take_notify_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.notify_dirty, false)
     }
     
+    /// Whether the driver negotiated `VIRTIO_F_EVENT_IDX`, enabling
+    /// `used_event`/`avail_event`-based interrupt and notification
+    /// suppression instead of interrupting on every used ring update.
+    pub fn event_idx_negotiated(&self) -> bool {
+        self.driver_features & VIRTIO_F_EVENT_IDX != 0
+    }
+
     /// Raise an interrupt
     pub fn raise_interrupt(&mut self, ring_update: bool) {
         if ring_update {
@@ -57,6 +57,10 @@ pub trait FileSystem: Send + Sync {
     /// Write to a file
     fn write(&mut self, qid: &Qid, offset: u64, data: &[u8]) -> Result<u32, u32>;
 
+    /// Truncate (or extend with zeros) a file to exactly `size` bytes,
+    /// e.g. for O_TRUNC on open.
+    fn truncate(&mut self, qid: &Qid, size: u64) -> Result<(), u32>;
+
     /// Read directory entries
     fn readdir(&mut self, qid: &Qid, offset: u64, count: u32) -> Result<Vec<DirEntry>, u32>;
 
@@ -65,4 +69,17 @@ pub trait FileSystem: Send + Sync {
     
     /// Rename/Move a file
     fn rename(&mut self, qid: &Qid, new_dir: &Qid, new_name: &str) -> Result<(), u32>;
+
+    /// Called when a fid referencing `qid` is clunked, so a backend caching
+    /// per-directory `readdir` state (see `readdir`) can drop it instead of
+    /// holding it until the directory changes again. Default is a no-op for
+    /// backends with nothing to clean up.
+    fn clunk(&mut self, _qid: &Qid) {}
+
+    /// Preferred I/O size to advertise in Rlopen/Rlcreate, in bytes.
+    /// `0` means "no preference" - the caller should fall back to
+    /// `msize` minus protocol overhead instead.
+    fn iounit(&self) -> u32 {
+        4096
+    }
 }
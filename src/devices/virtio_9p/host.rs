@@ -1,14 +1,76 @@
 
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::collections::HashMap;
-use std::sync::{RwLock, Arc};
+use std::collections::{HashMap, HashSet};
+use std::sync::{RwLock, Arc, Mutex};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
 use std::os::unix::fs::{MetadataExt, PermissionsExt};
 use std::time::SystemTime;
 
-use super::{Qid, P9_QTDIR, P9_QTFILE, P9_QTSYMLINK};
+use super::{Qid, P9_QTDIR, P9_QTFILE, P9_QTSYMLINK, EAGAIN};
 use super::filesystem::{FileSystem, FileAttr, DirEntry};
 
+/// Default `HostFileSystem` async I/O threshold (see `set_async_threshold`):
+/// reads/writes at or above this size are dispatched to `AsyncIoPool`
+/// instead of running inline. Below it, the cost of scheduling a job and
+/// waiting one or more `process_queues` calls for the result outweighs just
+/// blocking briefly - most 9P traffic (stat-sized reads, small config
+/// files) is well under this.
+pub const DEFAULT_ASYNC_IO_THRESHOLD: u64 = 256 * 1024;
+
+/// Number of persistent worker threads shared by `AsyncIoPool`. Kept small
+/// and fixed: the goal is to stop one slow transfer from freezing the
+/// single emulation thread, not to parallelize disk throughput.
+const ASYNC_IO_WORKERS: usize = 2;
+
+type IoJob = Box<dyn FnOnce() + Send>;
+
+/// (QID path, offset, count) - identifies one in-flight or completed async
+/// read or write.
+type AsyncIoKey = (u64, u64, u32);
+
+/// Completed async reads/writes waiting for `retry_suspended_requests` to
+/// pick them back up, keyed by `AsyncIoKey`.
+type AsyncIoResults<T> = Arc<RwLock<HashMap<AsyncIoKey, Result<T, u32>>>>;
+
+/// A tiny fixed-size thread pool for host filesystem I/O that would
+/// otherwise block `process_queues` - and with it the whole guest, since
+/// there's only one emulation thread - for the duration of a large
+/// synchronous `std::fs::File` read or write. Jobs are plain closures that
+/// write their own result into `HostFileSystem`'s result map before
+/// returning; the pool itself knows nothing about 9P. No async runtime:
+/// just a job channel and a handful of worker threads.
+struct AsyncIoPool {
+    job_tx: Sender<IoJob>,
+}
+
+impl AsyncIoPool {
+    fn new() -> Self {
+        let (job_tx, job_rx) = mpsc::channel::<IoJob>();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        for _ in 0..ASYNC_IO_WORKERS {
+            let job_rx = Arc::clone(&job_rx);
+            thread::spawn(move || loop {
+                let job = job_rx.lock().unwrap().recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => break, // every Sender dropped - pool shutting down
+                }
+            });
+        }
+        AsyncIoPool { job_tx }
+    }
+
+    fn dispatch(&self, job: IoJob) {
+        // A send only fails if every worker has panicked and dropped its
+        // receiver; there's nothing to do but drop the job, which leaves
+        // the request suspended forever - the same failure mode as a
+        // backend that never provides a missing blob.
+        let _ = self.job_tx.send(job);
+    }
+}
+
 pub struct HostFileSystem {
     root_path: PathBuf,
     // Mapping from QID path (u64) to host PathBuf
@@ -18,6 +80,43 @@ pub struct HostFileSystem {
     // For now simple counter.
     ids: Arc<RwLock<HashMap<PathBuf, u64>>>,
     next_id: Arc<RwLock<u64>>,
+    /// Sorted directory listings taken at the start of a readdir sequence
+    /// (or the previous one invalidated - see `readdir`), keyed by the
+    /// directory's QID path. Lets `readdir` serve each page by indexing
+    /// straight into a stable snapshot instead of re-running `read_dir` and
+    /// skipping `offset` entries every call, which is both O(n^2) over a
+    /// full listing and racy against concurrent modifications (a directory
+    /// changing between pages could otherwise shift entries and make the
+    /// guest see duplicates or miss ones).
+    dir_snapshots: Arc<RwLock<HashMap<u64, DirSnapshot>>>,
+    /// Reads/writes at or above this size are dispatched to `io_pool`; see
+    /// `set_async_threshold`.
+    async_threshold: u64,
+    /// Background pool for reads/writes at or above `async_threshold`.
+    io_pool: Arc<AsyncIoPool>,
+    /// (QID path, offset, count) keys for reads currently running on
+    /// `io_pool`, so a retried request that's still in flight suspends
+    /// again instead of double-dispatching the same read.
+    async_read_in_flight: Arc<RwLock<HashSet<AsyncIoKey>>>,
+    /// Finished async reads, waiting for `retry_suspended_requests` to pick
+    /// them back up. Populated by the worker thread that ran the job;
+    /// consumed by `read` the next time it's polled for that key.
+    async_read_results: AsyncIoResults<Vec<u8>>,
+    /// Same as `async_read_in_flight`, for writes.
+    async_write_in_flight: Arc<RwLock<HashSet<AsyncIoKey>>>,
+    /// Same as `async_read_results`, for writes.
+    async_write_results: AsyncIoResults<u32>,
+}
+
+/// A `readdir` snapshot for one directory QID. Stores only name + type + id
+/// per entry (not full metadata) so caching a huge directory doesn't cost
+/// much more than the names themselves.
+struct DirSnapshot {
+    entries: Vec<(String, u8, u64)>,
+    /// The directory's own mtime when snapshotted, so a later readdir call
+    /// notices the directory changed and rebuilds instead of serving a
+    /// stale listing.
+    mtime: (i64, i64),
 }
 
 use serde::{Serialize, Deserialize, Serializer, Deserializer};
@@ -43,6 +142,13 @@ impl<'de> Deserialize<'de> for HostFileSystem {
             paths: Arc::new(RwLock::new(HashMap::new())),
             ids: Arc::new(RwLock::new(HashMap::new())),
             next_id: Arc::new(RwLock::new(1)),
+            dir_snapshots: Arc::new(RwLock::new(HashMap::new())),
+            async_threshold: DEFAULT_ASYNC_IO_THRESHOLD,
+            io_pool: Arc::new(AsyncIoPool::new()),
+            async_read_in_flight: Arc::new(RwLock::new(HashSet::new())),
+            async_read_results: Arc::new(RwLock::new(HashMap::new())),
+            async_write_in_flight: Arc::new(RwLock::new(HashSet::new())),
+            async_write_results: Arc::new(RwLock::new(HashMap::new())),
         })
     }
 }
@@ -54,9 +160,46 @@ impl HostFileSystem {
             paths: Arc::new(RwLock::new(HashMap::new())),
             ids: Arc::new(RwLock::new(HashMap::new())),
             next_id: Arc::new(RwLock::new(1)), // 0 is usually root
+            dir_snapshots: Arc::new(RwLock::new(HashMap::new())),
+            async_threshold: DEFAULT_ASYNC_IO_THRESHOLD,
+            io_pool: Arc::new(AsyncIoPool::new()),
+            async_read_in_flight: Arc::new(RwLock::new(HashSet::new())),
+            async_read_results: Arc::new(RwLock::new(HashMap::new())),
+            async_write_in_flight: Arc::new(RwLock::new(HashSet::new())),
+            async_write_results: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
+    /// Override the async I/O size threshold (default
+    /// `DEFAULT_ASYNC_IO_THRESHOLD`). Mainly useful for tests that want to
+    /// exercise the background-pool path without shuffling megabytes
+    /// around.
+    pub fn set_async_threshold(&mut self, bytes: u64) {
+        self.async_threshold = bytes;
+    }
+
+    /// Read `path`'s directory contents fresh, sorted for stable paging.
+    fn build_dir_snapshot(&self, path: &Path) -> Result<DirSnapshot, u32> {
+        let dir_meta = fs::metadata(path).map_err(|_| 5u32)?;
+        let mtime = (dir_meta.mtime(), dir_meta.mtime_nsec());
+
+        let mut entries: Vec<(String, u8, u64)> = fs::read_dir(path)
+            .map_err(|_| 20u32)?
+            .filter_map(|e| e.ok())
+            .map(|entry| {
+                let entry_path = entry.path();
+                let qtype = entry.metadata()
+                    .map(|m| Self::metadata_to_qtype(&m))
+                    .unwrap_or(P9_QTFILE);
+                let id = self.get_or_create_id(&entry_path);
+                (entry.file_name().to_string_lossy().to_string(), qtype, id)
+            })
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(DirSnapshot { entries, mtime })
+    }
+
     fn get_path(&self, qid: &Qid) -> Option<PathBuf> {
         self.paths.read().unwrap().get(&qid.path).cloned()
     }
@@ -80,6 +223,35 @@ impl HostFileSystem {
         id
     }
     
+    /// The actual synchronous read, run inline for small requests and on an
+    /// `io_pool` worker thread for large ones.
+    fn read_sync(path: &Path, offset: u64, count: u32) -> Result<Vec<u8>, u32> {
+        let mut file = fs::File::open(path).map_err(|_| 5u32)?;
+        use std::io::{Read, Seek, SeekFrom};
+
+        file.seek(SeekFrom::Start(offset)).map_err(|_| 5u32)?;
+
+        let mut buf = vec![0u8; count as usize];
+        let bytes_read = file.read(&mut buf).map_err(|_| 5u32)?;
+
+        buf.truncate(bytes_read);
+        Ok(buf)
+    }
+
+    /// The actual synchronous write, run inline for small requests and on
+    /// an `io_pool` worker thread for large ones.
+    fn write_sync(path: &Path, offset: u64, data: &[u8]) -> Result<u32, u32> {
+        use std::fs::OpenOptions;
+        use std::io::{Write, Seek, SeekFrom};
+
+        let mut file = OpenOptions::new().write(true).open(path).map_err(|_| 5u32)?;
+
+        file.seek(SeekFrom::Start(offset)).map_err(|_| 5u32)?;
+        file.write_all(data).map_err(|_| 5u32)?;
+
+        Ok(data.len() as u32)
+    }
+
     // Convert std::fs::Metadata to specific Qid Type
     fn metadata_to_qtype(metadata: &fs::Metadata) -> u8 {
         if metadata.is_dir() { 
@@ -125,6 +297,12 @@ impl HostFileSystem {
 }
 
 impl FileSystem for HostFileSystem {
+    fn iounit(&self) -> u32 {
+        // The host filesystem has no natural block-size ceiling of its own;
+        // let the caller size I/O off msize instead.
+        0
+    }
+
     fn attach(&mut self) -> Result<Qid, u32> {
         let root = self.root_path.clone();
         if !root.exists() {
@@ -220,66 +398,102 @@ impl FileSystem for HostFileSystem {
 
     fn read(&mut self, qid: &Qid, offset: u64, count: u32) -> Result<Vec<u8>, u32> {
         let path = self.get_path(qid).ok_or(2u32)?;
-        
-        let mut file = fs::File::open(&path).map_err(|_| 5u32)?;
-        use std::io::{Read, Seek, SeekFrom};
-        
-        file.seek(SeekFrom::Start(offset)).map_err(|_| 5u32)?;
-        
-        // Limit read to count or reasonably small buffer
-        let to_read = count as usize; // Check max?
-        let mut buf = vec![0u8; to_read];
-        let bytes_read = file.read(&mut buf).map_err(|_| 5u32)?;
-        
-        buf.truncate(bytes_read);
-        Ok(buf)
+
+        if (count as u64) < self.async_threshold {
+            return Self::read_sync(&path, offset, count);
+        }
+
+        // Large read: run it on the background pool instead of blocking the
+        // emulation thread. The first poll for a given (qid, offset, count)
+        // always dispatches and suspends (see EAGAIN below); later polls
+        // pick the result up from `async_read_results` once the worker
+        // thread has filled it in.
+        let key = (qid.path, offset, count);
+        if let Some(result) = self.async_read_results.write().unwrap().remove(&key) {
+            return result;
+        }
+        if self.async_read_in_flight.read().unwrap().contains(&key) {
+            return Err(EAGAIN);
+        }
+
+        self.async_read_in_flight.write().unwrap().insert(key);
+        let in_flight = Arc::clone(&self.async_read_in_flight);
+        let results = Arc::clone(&self.async_read_results);
+        self.io_pool.dispatch(Box::new(move || {
+            let result = Self::read_sync(&path, offset, count);
+            results.write().unwrap().insert(key, result);
+            in_flight.write().unwrap().remove(&key);
+        }));
+        Err(EAGAIN)
     }
 
     fn write(&mut self, qid: &Qid, offset: u64, data: &[u8]) -> Result<u32, u32> {
         let path = self.get_path(qid).ok_or(2u32)?;
-        
-        // Open for writing
-        use std::fs::OpenOptions;
-        use std::io::{Write, Seek, SeekFrom};
-        
-        let mut file = OpenOptions::new().write(true).open(&path).map_err(|_| 5u32)?;
-        
-        file.seek(SeekFrom::Start(offset)).map_err(|_| 5u32)?;
-        file.write_all(data).map_err(|_| 5u32)?;
-        
-        Ok(data.len() as u32)
+
+        if (data.len() as u64) < self.async_threshold {
+            return Self::write_sync(&path, offset, data);
+        }
+
+        let key = (qid.path, offset, data.len() as u32);
+        if let Some(result) = self.async_write_results.write().unwrap().remove(&key) {
+            return result;
+        }
+        if self.async_write_in_flight.read().unwrap().contains(&key) {
+            return Err(EAGAIN);
+        }
+
+        self.async_write_in_flight.write().unwrap().insert(key);
+        let in_flight = Arc::clone(&self.async_write_in_flight);
+        let results = Arc::clone(&self.async_write_results);
+        let data = data.to_vec();
+        self.io_pool.dispatch(Box::new(move || {
+            let result = Self::write_sync(&path, offset, &data);
+            results.write().unwrap().insert(key, result);
+            in_flight.write().unwrap().remove(&key);
+        }));
+        Err(EAGAIN)
+    }
+
+    fn truncate(&mut self, qid: &Qid, size: u64) -> Result<(), u32> {
+        let path = self.get_path(qid).ok_or(2u32)?;
+        let file = fs::OpenOptions::new().write(true).open(&path).map_err(|_| 5u32)?;
+        file.set_len(size).map_err(|_| 5u32)
     }
 
     fn readdir(&mut self, qid: &Qid, offset: u64, count: u32) -> Result<Vec<DirEntry>, u32> {
+        let _ = count; // byte-budget trimming happens in mod.rs once entries are serialized
         let path = self.get_path(qid).ok_or(2u32)?;
-        
-        let read_dir = fs::read_dir(&path).map_err(|_| 20u32)?; // ENOTDIR?
-        
-        let mut entries = Vec::new();
-        let mut current_pos = 0;
-        
-        for entry in read_dir {
-            if current_pos >= offset {
-                let entry = entry.map_err(|_| 5u32)?;
-                let entry_path = entry.path();
-                let metadata = entry.metadata().map_err(|_| 5u32)?;
-                
-                let id = self.get_or_create_id(&entry_path);
-                
-                entries.push(DirEntry {
-                    qid: Qid::new(Self::metadata_to_qtype(&metadata), id),
-                    offset: current_pos + 1,
-                    type_: Self::metadata_to_qtype(&metadata),
-                    name: entry.file_name().to_string_lossy().to_string(),
-                });
-                
-                // If we have "enough", we could stop, but for now we rely on the implementation 
-                // in mod.rs to filter/serialize.
-            }
-            current_pos += 1;
+
+        let dir_meta = fs::metadata(&path).map_err(|_| 5u32)?;
+        let current_mtime = (dir_meta.mtime(), dir_meta.mtime_nsec());
+
+        // A 9P client always starts a fresh listing at offset 0, so that's
+        // also the natural point to notice the directory changed since the
+        // last time it was listed and rebuild the snapshot.
+        let stale = offset == 0 || self.dir_snapshots.read().unwrap()
+            .get(&qid.path)
+            .is_none_or(|s| s.mtime != current_mtime);
+        if stale {
+            let snapshot = self.build_dir_snapshot(&path)?;
+            self.dir_snapshots.write().unwrap().insert(qid.path, snapshot);
         }
-        
-        Ok(entries)
+
+        let snapshots = self.dir_snapshots.read().unwrap();
+        let snapshot = snapshots.get(&qid.path).ok_or(5u32)?;
+
+        Ok(snapshot.entries.iter().enumerate()
+            .skip(offset as usize)
+            .map(|(pos, (name, qtype, id))| DirEntry {
+                qid: Qid::new(*qtype, *id),
+                offset: pos as u64 + 1,
+                type_: *qtype,
+                name: name.clone(),
+            })
+            .collect())
+    }
+
+    fn clunk(&mut self, qid: &Qid) {
+        self.dir_snapshots.write().unwrap().remove(&qid.path);
     }
 
     fn remove(&mut self, qid: &Qid) -> Result<(), u32> {
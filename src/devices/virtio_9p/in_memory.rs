@@ -1,6 +1,6 @@
 
 use std::collections::{HashMap, HashSet};
-use super::{Qid, P9_QTDIR, P9_QTFILE, P9_QTSYMLINK};
+use super::{Qid, P9_QTDIR, P9_QTFILE, P9_QTSYMLINK, EAGAIN};
 use super::filesystem::{FileSystem, FileAttr, DirEntry};
 use serde::{Serialize, Deserialize};
 
@@ -18,12 +18,25 @@ pub struct Inode {
     pub content: InodeContent,
     pub children: Vec<u64>,
     pub parent: u64,
+    /// Bumped whenever `children` changes (create/mkdir/remove/rename),
+    /// mirroring the host backend's mtime check so `readdir` can tell a
+    /// cached listing snapshot is stale. `#[serde(default)]` so state saved
+    /// before this field existed still deserializes, just re-snapshotting
+    /// on the next readdir.
+    #[serde(default)]
+    pub dir_version: u64,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub enum InodeContent {
     Inline(Vec<u8>),
     Hash(String),
+    /// A large lazily-fetched file split into `(start_offset, hash)`
+    /// chunks, sorted by `start_offset`. A chunk's end is the next
+    /// chunk's `start_offset`, or the inode's `size` for the last one.
+    /// Unlike `Hash`, a read only needs to wait on the chunk(s) actually
+    /// covered by its `[offset, offset + count)` range.
+    Chunked(Vec<(u64, String)>),
     Directory,
     Symlink(String),
 }
@@ -41,6 +54,19 @@ pub struct InMemoryFileSystem {
     pub next_inode: u64,
     pub blob_cache: HashMap<String, Vec<u8>>,
     pub missing_blobs: HashSet<String>,
+    /// Sorted `readdir` snapshots keyed by directory inode, mirroring
+    /// `HostFileSystem`'s so both backends page consistently. Not persisted
+    /// across snapshots - rebuilt lazily from `inodes`/`dir_version`.
+    #[serde(skip)]
+    dir_snapshots: HashMap<u64, DirSnapshot>,
+}
+
+/// A `readdir` snapshot for one directory inode. See `HostFileSystem`'s
+/// `DirSnapshot` - this is the same idea, keyed to `dir_version` instead of
+/// an mtime since in-memory inodes don't track a real one.
+struct DirSnapshot {
+    entries: Vec<(String, Qid)>,
+    version: u64,
 }
 
 impl InMemoryFileSystem {
@@ -50,8 +76,9 @@ impl InMemoryFileSystem {
             next_inode: 1,
             blob_cache: HashMap::new(),
             missing_blobs: HashSet::new(),
+            dir_snapshots: HashMap::new(),
         };
-        
+
         // Create root
         let root_qid = Qid::new(P9_QTDIR, 0);
         let root = Inode {
@@ -67,6 +94,7 @@ impl InMemoryFileSystem {
             content: InodeContent::Directory,
             children: Vec::new(),
             parent: 0,
+            dir_version: 0,
         };
         fs.inodes.insert(0, root);
         fs
@@ -77,6 +105,281 @@ impl InMemoryFileSystem {
         self.next_inode += 1;
         id
     }
+
+    /// Collect `(path, inode id)` for every descendant of `dir_id`,
+    /// depth-first with directories before their children, for
+    /// `export_tar`. Paths are relative to the filesystem root and don't
+    /// start with `/`.
+    fn walk_paths(&self, dir_id: u64, prefix: &str, out: &mut Vec<(String, u64)>) {
+        let Some(dir) = self.inodes.get(&dir_id) else { return };
+        let mut children = dir.children.clone();
+        children.sort_by_key(|id| self.inodes.get(id).map(|n| n.name.clone()).unwrap_or_default());
+        for child_id in children {
+            let Some(child) = self.inodes.get(&child_id) else { continue };
+            let path = if prefix.is_empty() { child.name.clone() } else { format!("{}/{}", prefix, child.name) };
+            out.push((path.clone(), child_id));
+            if child.is_dir() {
+                self.walk_paths(child_id, &path, out);
+            }
+        }
+    }
+
+    /// Hash a hash-backed inode is waiting on, if `path` names one. Used by
+    /// `Virtio9p::debug_state` to report which blob a suspended request is
+    /// blocked on.
+    pub fn hash_for_path(&self, path: u64) -> Option<String> {
+        match &self.inodes.get(&path)?.content {
+            InodeContent::Hash(h) => Some(h.clone()),
+            // Best-effort: report the first chunk that isn't in the cache
+            // yet. A read of a `Chunked` file can suspend on whichever
+            // chunk it actually needs, which this can't see from just the
+            // qid - good enough for a diagnostic snapshot.
+            InodeContent::Chunked(chunks) => chunks.iter()
+                .map(|(_, h)| h)
+                .find(|h| !self.blob_cache.contains_key(h.as_str()))
+                .cloned(),
+            _ => None,
+        }
+    }
+
+    /// Serialize the tree into a ustar-format archive, for
+    /// `System::export_filesystem_tar` (native `--persist-fs`). Symlinks
+    /// and hash-backed blobs that haven't been fetched yet are skipped -
+    /// writing them as empty files would be worse than omitting them.
+    pub fn export_tar(&self) -> Vec<u8> {
+        let mut entries = Vec::new();
+        self.walk_paths(0, "", &mut entries);
+
+        let mut out = Vec::new();
+        for (path, id) in entries {
+            let Some(inode) = self.inodes.get(&id) else { continue };
+            let (typeflag, content): (u8, Vec<u8>) = match &inode.content {
+                InodeContent::Directory => (b'5', Vec::new()),
+                InodeContent::Inline(data) => (b'0', data.clone()),
+                InodeContent::Hash(hash) => match self.blob_cache.get(hash) {
+                    Some(data) => (b'0', data.clone()),
+                    None => continue,
+                },
+                InodeContent::Chunked(chunks) => {
+                    let mut buf = Vec::with_capacity(inode.size as usize);
+                    let mut complete = true;
+                    for (_, hash) in chunks {
+                        match self.blob_cache.get(hash) {
+                            Some(data) => buf.extend_from_slice(data),
+                            None => { complete = false; break; }
+                        }
+                    }
+                    if !complete { continue; }
+                    (b'0', buf)
+                }
+                InodeContent::Symlink(_) => continue,
+            };
+            write_ustar_entry(&mut out, &path, typeflag, inode, &content);
+        }
+        out.extend_from_slice(&[0u8; 1024]); // two zero-filled end-of-archive blocks
+        out
+    }
+
+    /// Replace the tree with one restored from a ustar archive produced by
+    /// `export_tar` (or a typical GNU/BSD `tar`), for
+    /// `System::import_filesystem_tar` (native `--import-fs`). Rejects
+    /// entries whose path contains a `..` component so a crafted archive
+    /// can't write outside the filesystem root.
+    pub fn import_tar(&mut self, data: &[u8]) -> Result<(), String> {
+        self.inodes.clear();
+        self.dir_snapshots.clear();
+        self.next_inode = 1;
+        self.inodes.insert(0, Inode {
+            qid: Qid::new(P9_QTDIR, 0),
+            name: String::new(),
+            size: 0,
+            mode: 0o40755,
+            uid: 0,
+            gid: 0,
+            mtime: 0,
+            atime: 0,
+            ctime: 0,
+            content: InodeContent::Directory,
+            children: Vec::new(),
+            parent: 0,
+            dir_version: 0,
+        });
+
+        let mut offset = 0usize;
+        while offset + 512 <= data.len() {
+            let header = &data[offset..offset + 512];
+            if header.iter().all(|&b| b == 0) {
+                break; // end-of-archive marker
+            }
+            let name = parse_ustar_name(header);
+            let mode = parse_octal(&header[100..108])? as u32;
+            let uid = parse_octal(&header[108..116])? as u32;
+            let gid = parse_octal(&header[116..124])? as u32;
+            let size = parse_octal(&header[124..136])? as usize;
+            let mtime = parse_octal(&header[136..148])?;
+            let typeflag = header[156];
+            offset += 512;
+
+            let content = data.get(offset..offset + size)
+                .ok_or_else(|| format!("tar entry '{}' truncated", name))?
+                .to_vec();
+            offset += size.div_ceil(512) * 512;
+
+            if name.split('/').any(|part| part == "..") {
+                return Err(format!("tar entry '{}' escapes the archive root", name));
+            }
+            if name.is_empty() || name == "." {
+                continue;
+            }
+
+            // ustar's mode field holds only permission bits (0o7777) -
+            // the file type is carried by typeflag instead, so it has to
+            // be folded back into `mode` here to match `Inode::is_dir`'s
+            // expectations (and `create`/`mkdir`'s).
+            let perm = if mode == 0 { 0o755 } else { mode & 0o7777 };
+            match typeflag {
+                b'5' => {
+                    self.ensure_dir_path(&name, 0o040000 | perm, uid, gid, mtime);
+                }
+                b'0' | 0 => {
+                    let (dir, file_name) = name.rsplit_once('/').unwrap_or(("", &name));
+                    let parent_id = self.ensure_dir_path(dir, 0o40755, 0, 0, mtime);
+                    let id = self.alloc_inode();
+                    self.inodes.insert(id, Inode {
+                        qid: Qid::new(P9_QTFILE, id),
+                        name: file_name.to_string(),
+                        size: content.len() as u64,
+                        mode: 0o100000 | perm,
+                        uid,
+                        gid,
+                        mtime,
+                        atime: mtime,
+                        ctime: mtime,
+                        content: InodeContent::Inline(content),
+                        children: Vec::new(),
+                        parent: parent_id,
+                        dir_version: 0,
+                    });
+                    if let Some(parent) = self.inodes.get_mut(&parent_id) {
+                        parent.children.push(id);
+                        parent.dir_version += 1;
+                    }
+                }
+                _ => {} // symlinks/devices/etc - skip, mirrors export_tar
+            }
+        }
+        Ok(())
+    }
+
+    /// Create every directory along `path` that doesn't already exist yet
+    /// (like `mkdir -p`), returning the inode id of the final component.
+    /// An empty `path` returns the root.
+    fn ensure_dir_path(&mut self, path: &str, mode: u32, uid: u32, gid: u32, mtime: u64) -> u64 {
+        let mut current = 0u64;
+        for part in path.split('/').filter(|p| !p.is_empty()) {
+            let existing = self.inodes.get(&current).and_then(|dir| {
+                dir.children.iter()
+                    .find(|&&c| self.inodes.get(&c).is_some_and(|n| n.name == part))
+                    .copied()
+            });
+            current = existing.unwrap_or_else(|| {
+                let id = self.alloc_inode();
+                self.inodes.insert(id, Inode {
+                    qid: Qid::new(P9_QTDIR, id),
+                    name: part.to_string(),
+                    size: 0,
+                    mode,
+                    uid,
+                    gid,
+                    mtime,
+                    atime: mtime,
+                    ctime: mtime,
+                    content: InodeContent::Directory,
+                    children: Vec::new(),
+                    parent: current,
+                    dir_version: 0,
+                });
+                if let Some(parent) = self.inodes.get_mut(&current) {
+                    parent.children.push(id);
+                    parent.dir_version += 1;
+                }
+                id
+            });
+        }
+        current
+    }
+}
+
+/// Write one ustar header + content (padded to a 512-byte boundary) for
+/// `InMemoryFileSystem::export_tar`. Paths over 100 bytes are split across
+/// the ustar `prefix`/`name` fields at a `/` boundary, as real tars do.
+fn write_ustar_entry(out: &mut Vec<u8>, path: &str, typeflag: u8, inode: &Inode, content: &[u8]) {
+    let mut header = [0u8; 512];
+    let (prefix, name) = split_ustar_path(path);
+    copy_into(&mut header[0..100], name.as_bytes());
+    set_octal(&mut header[100..108], (inode.mode & 0o7777) as u64);
+    set_octal(&mut header[108..116], inode.uid as u64);
+    set_octal(&mut header[116..124], inode.gid as u64);
+    set_octal(&mut header[124..136], content.len() as u64);
+    set_octal(&mut header[136..148], inode.mtime);
+    header[148..156].copy_from_slice(b"        "); // chksum placeholder, filled below
+    header[156] = typeflag;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    copy_into(&mut header[345..500], prefix.as_bytes());
+
+    let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+    let chksum = format!("{:06o}\0 ", checksum);
+    header[148..156].copy_from_slice(chksum.as_bytes());
+
+    out.extend_from_slice(&header);
+    out.extend_from_slice(content);
+    out.extend(std::iter::repeat_n(0u8, (512 - content.len() % 512) % 512));
+}
+
+fn copy_into(dst: &mut [u8], src: &[u8]) {
+    let n = src.len().min(dst.len());
+    dst[..n].copy_from_slice(&src[..n]);
+}
+
+fn set_octal(field: &mut [u8], value: u64) {
+    let width = field.len();
+    let s = format!("{:0width$o}", value, width = width - 1);
+    let start = s.len().saturating_sub(width - 1);
+    field[..width - 1].copy_from_slice(&s.as_bytes()[start..]);
+    field[width - 1] = 0;
+}
+
+fn split_ustar_path(path: &str) -> (String, String) {
+    if path.len() <= 100 {
+        return (String::new(), path.to_string());
+    }
+    let bytes = path.as_bytes();
+    for (i, &b) in bytes.iter().enumerate() {
+        if b == b'/' && bytes.len() - i - 1 <= 100 && i <= 155 {
+            return (path[..i].to_string(), path[i + 1..].to_string());
+        }
+    }
+    (String::new(), path[path.len() - 100..].to_string()) // lossy fallback
+}
+
+fn cstr_field(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).trim_end().to_string()
+}
+
+fn parse_ustar_name(header: &[u8]) -> String {
+    let name = cstr_field(&header[0..100]);
+    let prefix = cstr_field(&header[345..500]);
+    if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) }
+}
+
+fn parse_octal(field: &[u8]) -> Result<u64, String> {
+    let s = cstr_field(field);
+    if s.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(&s, 8).map_err(|e| format!("invalid tar header field '{}': {}", s, e))
 }
 
 impl FileSystem for InMemoryFileSystem {
@@ -179,14 +482,16 @@ impl FileSystem for InMemoryFileSystem {
             content,
             children: Vec::new(),
             parent: parent_path,
+            dir_version: 0,
         };
-        
+
         self.inodes.insert(new_path, inode);
-        
+
         // Add to parent
         let parent = self.inodes.get_mut(&parent_path).unwrap();
         parent.children.push(new_path);
-        
+        parent.dir_version += 1;
+
         Ok(qid)
     }
 
@@ -216,14 +521,55 @@ impl FileSystem for InMemoryFileSystem {
                     let end = std::cmp::min(start + count as usize, data.len());
                     Ok(data[start..end].to_vec())
                 } else {
-                    // Missing blob
-                    // self.missing_blobs.insert(hash.clone()); // Mutability issue?
-                    // In a real implementation we would signal missing blob.
-                    // For now return IO error or handle it.
-                    // Implementing "lazy load" via trait is tricky without async or callback.
-                    Err(5) // EIO (or custom for "try again")
+                    // Not fetched yet - record it as missing (so
+                    // `get_missing_blobs` can surface it to the host) and
+                    // ask the caller to suspend the request rather than
+                    // fail it; `provide_blob` retries suspended requests
+                    // once the blob shows up in `blob_cache`.
+                    self.missing_blobs.insert(hash.clone());
+                    Err(EAGAIN)
                 }
             },
+            InodeContent::Chunked(chunks) => {
+                let total_len = inode.size as usize;
+                let start = offset as usize;
+                if start >= total_len {
+                    return Ok(Vec::new());
+                }
+                let end = std::cmp::min(start + count as usize, total_len);
+
+                // Only the chunk(s) overlapping [start, end) matter - a read
+                // confined to an already-fetched chunk shouldn't have to
+                // wait on ones later in the file.
+                let mut needed = Vec::new();
+                for (i, (chunk_off, hash)) in chunks.iter().enumerate() {
+                    let chunk_start = *chunk_off as usize;
+                    let chunk_end = chunks.get(i + 1).map(|(o, _)| *o as usize).unwrap_or(total_len);
+                    if chunk_end > start && chunk_start < end {
+                        needed.push((chunk_start, chunk_end, hash.clone()));
+                    }
+                }
+
+                let mut any_missing = false;
+                for (_, _, hash) in &needed {
+                    if !self.blob_cache.contains_key(hash) {
+                        self.missing_blobs.insert(hash.clone());
+                        any_missing = true;
+                    }
+                }
+                if any_missing {
+                    return Err(EAGAIN);
+                }
+
+                let mut out = Vec::with_capacity(end - start);
+                for (chunk_start, chunk_end, hash) in needed {
+                    let data = &self.blob_cache[&hash];
+                    let lo = start.max(chunk_start) - chunk_start;
+                    let hi = (end.min(chunk_end) - chunk_start).min(data.len());
+                    out.extend_from_slice(&data[lo.min(data.len())..hi]);
+                }
+                Ok(out)
+            },
             _ => Err(5), // EIO
         }
     }
@@ -245,37 +591,56 @@ impl FileSystem for InMemoryFileSystem {
         }
     }
 
+    fn truncate(&mut self, qid: &Qid, size: u64) -> Result<(), u32> {
+        let inode = self.inodes.get_mut(&qid.path).ok_or(2u32)?;
+        match &mut inode.content {
+            InodeContent::Inline(content) => {
+                content.resize(size as usize, 0);
+                inode.size = size;
+                Ok(())
+            }
+            InodeContent::Directory => Err(21), // EISDIR
+            _ => Err(5), // EIO - hash-backed blobs aren't mutable in place
+        }
+    }
+
     fn readdir(&mut self, qid: &Qid, offset: u64, count: u32) -> Result<Vec<DirEntry>, u32> {
+        let _ = count; // byte-budget trimming happens in mod.rs once entries are serialized
         let inode = self.inodes.get(&qid.path).ok_or(2u32)?;
-        
+
         if !inode.is_dir() {
             return Err(20);
         }
-        
-        let mut entries = Vec::new();
-        let mut current_pos = 0;
-        
-        for &child_path in &inode.children {
-            if current_pos >= offset {
-                 if let Some(child) = self.inodes.get(&child_path) {
-                     entries.push(DirEntry {
-                         qid: child.qid,
-                         offset: current_pos + 1,
-                         type_: child.qid.qtype,
-                         name: child.name.clone(),
-                     });
-                     
-                     // Approximate size check (not exact 9P wire size, but close enough for logic)
-                     // In the outer loop we serialize and check real size.
-                     // Here we just return all relevant entries and let caller paginate?
-                     // 9P `count` is byte limit. It's hard to guess exact bytes here.
-                     // The trait returns Vec<DirEntry> which the caller serializes until full.
-                 }
-            }
-            current_pos += 1;
+
+        // As with `HostFileSystem::readdir`: snapshot a sorted listing once
+        // per directory-version and serve every page from it by index, so
+        // `offset` means the same thing across the whole listing instead of
+        // depending on `children`'s current order at each call.
+        let stale = offset == 0 || self.dir_snapshots.get(&qid.path)
+            .is_none_or(|s| s.version != inode.dir_version);
+        if stale {
+            let mut entries: Vec<(String, Qid)> = inode.children.iter()
+                .filter_map(|child_path| self.inodes.get(child_path))
+                .map(|child| (child.name.clone(), child.qid))
+                .collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            self.dir_snapshots.insert(qid.path, DirSnapshot { entries, version: inode.dir_version });
         }
-        
-        Ok(entries)
+
+        let snapshot = self.dir_snapshots.get(&qid.path).ok_or(5u32)?;
+        Ok(snapshot.entries.iter().enumerate()
+            .skip(offset as usize)
+            .map(|(pos, (name, qid))| DirEntry {
+                qid: *qid,
+                offset: pos as u64 + 1,
+                type_: qid.qtype,
+                name: name.clone(),
+            })
+            .collect())
+    }
+
+    fn clunk(&mut self, qid: &Qid) {
+        self.dir_snapshots.remove(&qid.path);
     }
 
     fn remove(&mut self, qid: &Qid) -> Result<(), u32> {
@@ -289,9 +654,11 @@ impl FileSystem for InMemoryFileSystem {
         // Remove from parent
         if let Some(parent) = self.inodes.get_mut(&parent_path) {
             parent.children.retain(|&x| x != qid.path);
+            parent.dir_version += 1;
         }
-        
+
         self.inodes.remove(&qid.path);
+        self.dir_snapshots.remove(&qid.path);
         Ok(())
     }
 
@@ -307,12 +674,17 @@ impl FileSystem for InMemoryFileSystem {
             // Move references
              if let Some(p) = self.inodes.get_mut(&old_parent) {
                 p.children.retain(|&x| x != qid.path);
+                p.dir_version += 1;
             }
              if let Some(p) = self.inodes.get_mut(&new_dir.path) {
                 p.children.push(qid.path);
+                p.dir_version += 1;
             }
+        } else if let Some(p) = self.inodes.get_mut(&new_dir.path) {
+            // Renamed in place - still changes what a listing shows.
+            p.dir_version += 1;
         }
-        
+
         Ok(())
     }
 }
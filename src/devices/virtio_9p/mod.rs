@@ -93,6 +93,33 @@ const EISDIR: u32 = 21;
 const EINVAL: u32 = 22;
 const ENOSPC: u32 = 28;
 const ENOTEMPTY: u32 = 39;
+/// Sentinel `FileSystem::read` error meaning "not ready yet, don't send an
+/// error to the guest" - e.g. a lazily-loaded blob that hasn't arrived.
+/// `handle_read` turns this into a suspended request instead of an RLERROR.
+const EAGAIN: u32 = 11;
+
+// Linux open(2) flag values - 9P2000.L's Tlopen/Tlcreate `flags` field is
+// defined to match Linux's numbering directly, so these can be used as-is
+// against the flags stored on a `Fid`.
+const O_RDONLY: u32 = 0o0;
+const O_WRONLY: u32 = 0o1;
+const O_ACCMODE: u32 = 0o3;
+const O_TRUNC: u32 = 0o1000;
+const O_APPEND: u32 = 0o2000;
+
+/// Whether `flags` (as stored in `Fid::open_flags`) permits reads.
+fn open_flags_readable(flags: u32) -> bool {
+    flags & O_ACCMODE != O_WRONLY
+}
+
+/// Whether `flags` permits writes.
+fn open_flags_writable(flags: u32) -> bool {
+    flags & O_ACCMODE != O_RDONLY
+}
+
+/// Twrite header size: size[4] type[1] tag[2] fid[4] offset[8] count[4],
+/// i.e. the bytes of a Twrite message that aren't the write payload itself.
+const P9_TWRITE_HEADER_SIZE: u32 = 23;
 
 /// A 9P QID (unique identifier for a file)
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
@@ -133,6 +160,20 @@ pub enum Backend {
     Host(host::HostFileSystem),
 }
 
+impl Backend {
+    /// Hash a suspended request's fid is waiting on, if it names a
+    /// hash-backed inode that hasn't been fetched yet. Only the in-memory
+    /// backend defers reads on a missing blob - a host-backed filesystem
+    /// reads straight through, so it never suspends on a hash.
+    fn hash_for_qid(&self, qid: &Qid) -> Option<String> {
+        match self {
+            Backend::InMemory(fs) => fs.hash_for_path(qid.path),
+            #[cfg(not(target_arch = "wasm32"))]
+            Backend::Host(_) => None,
+        }
+    }
+}
+
 impl FileSystem for Backend {
     fn attach(&mut self) -> Result<Qid, u32> {
         match self {
@@ -190,6 +231,13 @@ impl FileSystem for Backend {
              Backend::Host(fs) => fs.write(qid, offset, data),
         }
     }
+    fn truncate(&mut self, qid: &Qid, size: u64) -> Result<(), u32> {
+         match self {
+             Backend::InMemory(fs) => fs.truncate(qid, size),
+             #[cfg(not(target_arch = "wasm32"))]
+             Backend::Host(fs) => fs.truncate(qid, size),
+        }
+    }
     fn readdir(&mut self, qid: &Qid, offset: u64, count: u32) -> Result<Vec<DirEntry>, u32> {
          match self {
              Backend::InMemory(fs) => fs.readdir(qid, offset, count),
@@ -211,6 +259,146 @@ impl FileSystem for Backend {
              Backend::Host(fs) => fs.rename(qid, new_dir, new_name),
         }
     }
+    fn iounit(&self) -> u32 {
+         match self {
+             Backend::InMemory(fs) => fs.iounit(),
+             #[cfg(not(target_arch = "wasm32"))]
+             Backend::Host(fs) => fs.iounit(),
+        }
+    }
+    fn clunk(&mut self, qid: &Qid) {
+         match self {
+             Backend::InMemory(fs) => fs.clunk(qid),
+             #[cfg(not(target_arch = "wasm32"))]
+             Backend::Host(fs) => fs.clunk(qid),
+        }
+    }
+}
+
+/// Bounds-checked cursor for parsing a 9P message payload, so handlers don't
+/// have to hand-roll `u32::from_le_bytes([payload[n], ...])` offset math.
+/// Every read returns `EINVAL` on truncation instead of panicking.
+pub struct P9Reader<'a> {
+    payload: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> P9Reader<'a> {
+    pub fn new(payload: &'a [u8]) -> Self {
+        P9Reader { payload, offset: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], u32> {
+        if self.offset + len > self.payload.len() {
+            return Err(EINVAL);
+        }
+        let bytes = &self.payload[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, u32> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, u32> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, u32> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// A 9P string: a u16 byte length followed by (possibly non-UTF8) bytes,
+    /// lossily converted the same way the existing handlers already do.
+    pub fn read_string(&mut self) -> Result<String, u32> {
+        let len = self.read_u16()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).to_string())
+    }
+
+    pub fn read_qid(&mut self) -> Result<Qid, u32> {
+        let qtype = self.read_u8()?;
+        let version = self.read_u32()?;
+        let path = self.read_u64()?;
+        Ok(Qid { qtype, version, path })
+    }
+
+    /// Remaining unread bytes, e.g. for a trailing write payload whose length
+    /// was already given by an earlier `count` field.
+    pub fn rest(&self) -> &'a [u8] {
+        &self.payload[self.offset..]
+    }
+}
+
+/// Builder for a 9P reply message: writes the type/tag header up front and
+/// backpatches the leading size field in `finalize`, so handlers don't have
+/// to hand-roll `resp[0..4].copy_from_slice(&size.to_le_bytes())` themselves.
+pub struct P9Writer {
+    buf: Vec<u8>,
+}
+
+impl P9Writer {
+    /// Reserves the leading size field and writes `msg_type`/`tag`.
+    pub fn new(msg_type: u8, tag: u16) -> Self {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf.push(msg_type);
+        buf.extend_from_slice(&tag.to_le_bytes());
+        P9Writer { buf }
+    }
+
+    pub fn write_u8(&mut self, v: u8) -> &mut Self {
+        self.buf.push(v);
+        self
+    }
+
+    pub fn write_u16(&mut self, v: u16) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    pub fn write_u32(&mut self, v: u32) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    pub fn write_u64(&mut self, v: u64) -> &mut Self {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(bytes);
+        self
+    }
+
+    /// A 9P string: u16 byte length followed by the raw bytes.
+    pub fn write_string(&mut self, s: &str) -> &mut Self {
+        self.write_u16(s.len() as u16);
+        self.write_bytes(s.as_bytes());
+        self
+    }
+
+    pub fn write_qid(&mut self, qid: &Qid) -> &mut Self {
+        self.write_bytes(&qid.encode());
+        self
+    }
+
+    /// Backpatch the size field and return the finished message. Errors with
+    /// `ENOMEM` if the message would exceed the negotiated `msize` rather
+    /// than sending a reply the guest didn't agree to accept.
+    pub fn finalize(mut self, msize: u32) -> Result<Vec<u8>, u32> {
+        if self.buf.len() as u32 > msize {
+            return Err(ENOMEM);
+        }
+        let size = self.buf.len() as u32;
+        self.buf[0..4].copy_from_slice(&size.to_le_bytes());
+        Ok(self.buf)
+    }
 }
 
 /// VirtIO-9p device
@@ -234,8 +422,30 @@ pub struct Virtio9p {
     pub pending_responses: VecDeque<Vec<u8>>,
     
     pub suspended_requests: Vec<SuspendedRequest>,
+
+    /// Max descriptors processed per `process_queues` call (see
+    /// `set_work_budget`). Bounds how long a single guest kick can hog the
+    /// run loop before timer delivery gets a chance to run.
+    #[serde(default = "default_work_budget")]
+    work_budget: usize,
+
+    /// Number of times `process_queues` has actually run, for
+    /// `get_process_queues_calls`. A compute-bound guest that never touches
+    /// virtio should drive this far below the instruction/block count,
+    /// since `System::pump_virtio` only calls in when `notify_dirty` is set.
+    /// Not persisted across snapshots.
+    #[serde(skip)]
+    process_queues_calls: u64,
+}
+
+fn default_work_budget() -> usize {
+    DEFAULT_WORK_BUDGET
 }
 
+/// Default `Virtio9p::work_budget`: descriptors processed per
+/// `process_queues` call before yielding back to the run loop.
+pub const DEFAULT_WORK_BUDGET: usize = 8;
+
 #[derive(Serialize, Deserialize)]
 pub struct SuspendedRequest {
     pub queue_idx: usize,
@@ -243,6 +453,30 @@ pub struct SuspendedRequest {
     pub output_descriptors: Vec<Descriptor>,
     pub tag: u16,
     pub input_buffer: Vec<u8>,
+    /// Hash of the blob this request is waiting on, if it could be
+    /// determined from the fid's qid at suspend time (see `debug_state`).
+    #[serde(default)]
+    pub awaited_hash: Option<String>,
+}
+
+/// Snapshot of `Virtio9p`'s in-flight state for diagnostics, e.g. a browser
+/// app showing "waiting for blob abc123 (3 requests suspended)" when 9p
+/// looks hung. See `debug_state`.
+#[derive(Serialize, Deserialize)]
+pub struct Debug9pState {
+    /// Number of currently open fids.
+    pub open_fids: usize,
+    /// One entry per suspended request, in suspension order.
+    pub suspended_requests: Vec<SuspendedRequestInfo>,
+    /// Hashes the backend has been asked for but doesn't have yet.
+    pub missing_blobs: Vec<String>,
+}
+
+/// One `suspended_requests` entry in `Debug9pState`.
+#[derive(Serialize, Deserialize)]
+pub struct SuspendedRequestInfo {
+    pub tag: u16,
+    pub awaited_hash: Option<String>,
 }
 
 impl Virtio9p {
@@ -261,8 +495,9 @@ impl Virtio9p {
         let mut virtio = VirtioMmio::new(9, 1, config); // Device ID 9 = 9p
         
         // Set device features
-        // VIRTIO_9P_MOUNT_TAG
-        virtio.device_features = 1;
+        // VIRTIO_9P_MOUNT_TAG, plus VIRTIO_F_EVENT_IDX for interrupt/
+        // notification suppression.
+        virtio.device_features = 1 | super::virtio::VIRTIO_F_EVENT_IDX;
         
         Virtio9p {
             virtio,
@@ -273,9 +508,39 @@ impl Virtio9p {
             pending_requests: Vec::new(),
             pending_responses: VecDeque::new(),
             suspended_requests: Vec::new(),
+            work_budget: DEFAULT_WORK_BUDGET,
+            process_queues_calls: 0,
         }
     }
-    
+
+    /// Number of times `process_queues` has run, for benchmarking/testing
+    /// how effectively `notify_dirty` skips idle scans on compute-bound
+    /// workloads.
+    pub fn get_process_queues_calls(&self) -> u64 {
+        self.process_queues_calls
+    }
+
+    /// Configure how many descriptors `process_queues` will service per
+    /// call before leaving the rest for the next call. A guest that queues
+    /// hundreds of requests at once (e.g. `make -j` reading many files)
+    /// would otherwise monopolize a single `step_block` call and starve
+    /// timer delivery.
+    pub fn set_work_budget(&mut self, budget: usize) {
+        self.work_budget = budget.max(1);
+    }
+
+    /// I/O size to advertise to the guest in Rlopen/Rlcreate. Uses the
+    /// backend's preferred size, falling back to `msize` minus the Twrite
+    /// header when the backend has no fixed block size of its own.
+    fn iounit(&self) -> u32 {
+        let iounit = self.fs.iounit();
+        if iounit != 0 {
+            iounit
+        } else {
+            self.msize.saturating_sub(P9_TWRITE_HEADER_SIZE)
+        }
+    }
+
     // ... [Basic virtio methods: read8, write8, read32, write32] ...
     pub fn read8(&self, offset: u32) -> u8 {
         self.virtio.read8(offset)
@@ -306,61 +571,143 @@ impl Virtio9p {
             _ => Vec::new(),
         }
     }
-    
+
+    /// Snapshot of open fids and in-flight requests, for an embedder to
+    /// surface when 9p looks stuck (e.g. "waiting on blob abc123").
+    pub fn debug_state(&self) -> Debug9pState {
+        Debug9pState {
+            open_fids: self.fids.len(),
+            suspended_requests: self.suspended_requests.iter()
+                .map(|req| SuspendedRequestInfo { tag: req.tag, awaited_hash: req.awaited_hash.clone() })
+                .collect(),
+            missing_blobs: self.get_missing_blobs(),
+        }
+    }
+
     pub fn provide_blob(&mut self, hash: String, data: Vec<u8>, mem: &mut Memory) {
         match &mut self.fs {
             Backend::InMemory(fs) => {
                 fs.blob_cache.insert(hash.clone(), data);
                 fs.missing_blobs.remove(&hash);
-                // Retry suspended requests logic would go here
-                // For now, simpler implementation: next read will succeed
             },
             _ => {},
         }
+        self.retry_suspended_requests(mem);
+    }
+
+    /// Re-run every request that previously suspended (e.g. a Tread that
+    /// hit a missing blob) now that the backend state that blocked it may
+    /// have changed. Anything that still can't complete goes back onto
+    /// `suspended_requests`; anything that now succeeds gets its response
+    /// written to the descriptors it was suspended with and posted to the
+    /// used ring, exactly as if `process_queue` had finished it inline.
+    pub fn retry_suspended_requests(&mut self, mem: &mut Memory) {
+        let pending: Vec<SuspendedRequest> = self.suspended_requests.drain(..).collect();
+        let mut completed_any = false;
+        for req in pending {
+            match self.process_message(&req.input_buffer) {
+                Some(response) => {
+                    let bytes_written = write_descriptor_chain(mem, &req.output_descriptors, &response);
+                    self.virtio.queues[req.queue_idx].push_used(mem, req.head_idx as u32, bytes_written as u32);
+                    completed_any = true;
+                }
+                None => self.suspended_requests.push(req),
+            }
+        }
+        if completed_any {
+            self.virtio.raise_interrupt(true);
+        }
     }
 
-    pub fn process_queues(&mut self, mem: &mut Memory) {
+    /// Service every queue the guest has kicked since the last call, up to
+    /// `work_budget` descriptors total across all of them. Returns the
+    /// number of descriptors actually processed, so the run loop can
+    /// charge it against guest-visible time. A queue that still has
+    /// descriptors available once the budget runs out is put back on
+    /// `queue_notify_pending` (and `notify_dirty` re-set) so the next call
+    /// picks up where this one left off, instead of the leftover work
+    /// silently waiting for another guest kick that may never come.
+    pub fn process_queues(&mut self, mem: &mut Memory) -> usize {
+        self.process_queues_calls += 1;
         let mut queues_to_process = Vec::new();
         while let Some(q) = self.virtio.queue_notify_pending.pop_front() {
             queues_to_process.push(q);
         }
         queues_to_process.sort_unstable();
         queues_to_process.dedup();
-        
+
+        // Process every notified queue first and only decide whether to
+        // interrupt once at the end, instead of once per queue: with
+        // request pipelining a single guest kick can cover several queues'
+        // worth of work, and coalescing avoids redundant PLIC traffic.
+        let mut needs_interrupt = false;
+        let mut budget = self.work_budget;
+        let mut processed = 0;
         for queue_idx in queues_to_process {
-            self.process_queue(mem, queue_idx as usize);
+            let before = budget;
+            let (interrupt, work_remaining) = self.process_queue(mem, queue_idx as usize, &mut budget);
+            processed += before - budget;
+            if interrupt {
+                needs_interrupt = true;
+            }
+            if work_remaining {
+                self.virtio.queue_notify_pending.push_back(queue_idx);
+                self.virtio.notify_dirty = true;
+            }
+        }
+
+        if needs_interrupt {
+            self.virtio.raise_interrupt(true);
         }
+
+        processed
     }
 
-    fn process_queue(&mut self, mem: &mut Memory, queue_idx: usize) {
+    /// Process one queue's available descriptors, servicing at most
+    /// `*budget` of them (decremented as they're consumed). Returns
+    /// whether the driver should be interrupted for the used entries just
+    /// pushed - per the `VIRTIO_F_EVENT_IDX` `used_event` contract if
+    /// negotiated, otherwise whenever anything was processed at all - and
+    /// whether descriptors were still available when the budget ran out.
+    fn process_queue(&mut self, mem: &mut Memory, queue_idx: usize, budget: &mut usize) -> (bool, bool) {
+        let old_used_idx = self.virtio.queues[queue_idx].used_idx(mem);
         let mut processed_any = false;
-        
+        let mut work_remaining = false;
+
         loop {
-            // STEP 1: Borrow queue (same logic as before)
-            let (head_idx, input_buffer, output_descriptors) = {
+            // STEP 1: Borrow queue and walk the descriptor chain. Input
+            // descriptors are gathered with bulk reads instead of a
+            // byte-at-a-time copy; the header is peeked before deciding
+            // whether the full payload needs to be materialized at all.
+            let (head_idx, input_descriptors, output_descriptors) = {
                 let queue = if let Some(q) = self.virtio.queues.get_mut(queue_idx) {
                     q
                 } else {
-                    return;
+                    return (false, false);
                 };
-                
-                if !queue.ready { return; }
+
+                if !queue.ready { return (false, false); }
                 let avail_idx = queue.avail_idx(mem);
                 if queue.last_avail_idx == avail_idx { break; }
-                
+
+                if *budget == 0 {
+                    // Out of budget for this call; leave the rest queued.
+                    work_remaining = true;
+                    break;
+                }
+                *budget -= 1;
+
                 let head_idx = queue.get_avail_head(mem, queue.last_avail_idx);
                 queue.last_avail_idx = queue.last_avail_idx.wrapping_add(1);
-                
+
                 let mut desc_idx = head_idx;
                 let mut input = Vec::new();
                 let mut output = Vec::new();
-                
+
                 loop {
                     let desc = queue.read_desc(mem, desc_idx);
                     if (desc.flags & super::virtio::VRING_DESC_F_WRITE) == 0 {
-                         for i in 0..desc.len {
-                             input.push(mem.read8((desc.addr + i as u64) as u32));
-                         }
+                        input.push(desc);
                     } else {
                         output.push(desc);
                     }
@@ -369,27 +716,39 @@ impl Virtio9p {
                 }
                 (head_idx, input, output)
             };
-            
+
+            let msg_type = descriptors_peek_byte(mem, &input_descriptors, 4);
+
+            // TREAD/TWRITE get a zero-copy-ish fast path that streams the
+            // file payload straight between the backend and the guest
+            // descriptors instead of round-tripping through an
+            // intermediate response/request Vec<u8>.
+            if msg_type == Some(P9_TREAD) {
+                if let Some(bytes_written) = self.handle_read_fast(mem, &input_descriptors, &output_descriptors) {
+                    self.virtio.queues[queue_idx].push_used(mem, head_idx as u32, bytes_written);
+                    processed_any = true;
+                    continue;
+                }
+                // Fall through to the generic path (e.g. missing blob -> suspend).
+            } else if msg_type == Some(P9_TWRITE) {
+                if let Some(written) = self.handle_write_fast(mem, &input_descriptors, &output_descriptors) {
+                    self.virtio.queues[queue_idx].push_used(mem, head_idx as u32, written);
+                    processed_any = true;
+                    continue;
+                }
+            }
+
+            let input_buffer = read_descriptor_chain(mem, &input_descriptors);
+
             // STEP 2: Process message
             let result = self.process_message(&input_buffer);
-            
+
             match result {
                 Some(response) => {
                     // STEP 3: Write response
                     {
                         let queue = &mut self.virtio.queues[queue_idx];
-                        let mut bytes_written = 0;
-                        let mut resp_offset = 0;
-                        for desc in output_descriptors {
-                            if resp_offset >= response.len() { break; }
-                            let to_write = std::cmp::min(desc.len as usize, response.len() - resp_offset);
-                            for i in 0..to_write {
-                                mem.write32(desc.addr as u32 + i as u32, response[resp_offset + i] as u32);
-                                mem.write8((desc.addr + i as u64) as u32, response[resp_offset + i]);
-                            }
-                            resp_offset += to_write;
-                            bytes_written += to_write;
-                        }
+                        let bytes_written = write_descriptor_chain(mem, &output_descriptors, &response);
                         queue.push_used(mem, head_idx as u32, bytes_written as u32);
                     }
                     processed_any = true;
@@ -399,16 +758,123 @@ impl Virtio9p {
                      let tag = if input_buffer.len() >= 7 {
                         u16::from_le_bytes([input_buffer[5], input_buffer[6]])
                     } else { 0xFFFF };
+                    let fid = if input_buffer.len() >= 11 {
+                        Some(u32::from_le_bytes([input_buffer[7], input_buffer[8], input_buffer[9], input_buffer[10]]))
+                    } else { None };
+                    let awaited_hash = fid
+                        .and_then(|fid| self.fids.get(&fid))
+                        .and_then(|fid| self.fs.hash_for_qid(&fid.qid));
                     self.suspended_requests.push(SuspendedRequest {
-                        queue_idx, head_idx, output_descriptors, tag, input_buffer: input_buffer.to_vec(),
+                        queue_idx, head_idx, output_descriptors, tag, input_buffer: input_buffer.to_vec(), awaited_hash,
                     });
                 }
             }
         }
-        
-        if processed_any {
-            self.virtio.raise_interrupt(true);
+
+        let event_idx_negotiated = self.virtio.event_idx_negotiated();
+        let queue = &mut self.virtio.queues[queue_idx];
+        if event_idx_negotiated {
+            // Tell the driver up to which avail index we've consumed, so it
+            // knows when it next needs to kick us.
+            queue.set_avail_event(mem, queue.last_avail_idx);
+        }
+
+        if !processed_any {
+            return (false, work_remaining);
+        }
+
+        let needs_interrupt = if event_idx_negotiated {
+            let new_used_idx = queue.used_idx(mem);
+            let event = queue.used_event(mem);
+            super::virtio::vring_need_event(event, new_used_idx, old_used_idx)
+        } else {
+            true
+        };
+        (needs_interrupt, work_remaining)
+    }
+
+    /// Zero-copy-ish TREAD: parse just the small fixed header, write the
+    /// Rread header into the output descriptors, then stream the file
+    /// payload from the backend directly into the remaining descriptor
+    /// space via `Memory::write_slice` without ever assembling a combined
+    /// header+payload response buffer.
+    ///
+    /// Returns `None` if the request can't be served this way (short
+    /// header, unknown fid, or the backend suspends on a missing blob),
+    /// in which case the caller falls back to the generic path.
+    fn handle_read_fast(&mut self, mem: &mut Memory, input: &[Descriptor], output: &[Descriptor]) -> Option<u32> {
+        let header = read_descriptor_chain_prefix(mem, input, 23)?;
+        let tag = u16::from_le_bytes([header[5], header[6]]);
+        let fid = u32::from_le_bytes([header[7], header[8], header[9], header[10]]);
+        let offset = u64::from_le_bytes(header[11..19].try_into().ok()?);
+        let count = u32::from_le_bytes([header[19], header[20], header[21], header[22]]);
+
+        let f = self.fids.get(&fid)?;
+        if !open_flags_readable(f.open_flags) {
+            return None; // let the generic path build the EBADF RLERROR
+        }
+        let qid = f.qid;
+        let data = match self.fs.read(&qid, offset, count) {
+            Ok(data) => data,
+            Err(_) => return None, // let the generic path build the RLERROR
+        };
+
+        let mut header_resp = Vec::with_capacity(11);
+        header_resp.extend_from_slice(&0u32.to_le_bytes()); // size placeholder
+        header_resp.push(P9_RREAD);
+        header_resp.extend_from_slice(&tag.to_le_bytes());
+        header_resp.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        let total_size = (header_resp.len() + data.len()) as u32;
+        header_resp[0..4].copy_from_slice(&total_size.to_le_bytes());
+
+        let header_len = write_descriptor_chain(mem, output, &header_resp);
+        let payload_len = write_descriptor_chain_at(mem, output, header_len, &data);
+        Some((header_len + payload_len) as u32)
+    }
+
+    /// Zero-copy-ish TWRITE: parse the fixed header out of the descriptor
+    /// chain, then read the payload straight from the guest descriptors
+    /// into a buffer sized exactly to `count` (instead of first copying
+    /// the whole up-to-`msize` message into a scratch buffer), and hand
+    /// that straight to the backend.
+    fn handle_write_fast(&mut self, mem: &mut Memory, input: &[Descriptor], output: &[Descriptor]) -> Option<u32> {
+        let header = read_descriptor_chain_prefix(mem, input, 27)?;
+        let tag = u16::from_le_bytes([header[5], header[6]]);
+        let fid = u32::from_le_bytes([header[7], header[8], header[9], header[10]]);
+        let offset = u64::from_le_bytes(header[11..19].try_into().ok()?);
+        let requested = u32::from_le_bytes([header[19], header[20], header[21], header[22]]);
+        // Same short-write clamp as the generic path: never trust a guest
+        // count past what the negotiated msize allows.
+        let count = requested.min(self.msize.saturating_sub(P9_TWRITE_HEADER_SIZE));
+
+        let f = self.fids.get(&fid)?;
+        // O_RDONLY and O_APPEND both need the generic path: the former to
+        // build the EBADF RLERROR, the latter because it ignores this
+        // header's offset in favor of the backend's current EOF.
+        if !open_flags_writable(f.open_flags) || f.open_flags & O_APPEND != 0 {
+            return None;
         }
+        let qid = f.qid;
+        let data = read_descriptor_chain_range(mem, input, 23, count as usize)?;
+
+        let response = match self.fs.write(&qid, offset, &data) {
+            Ok(written) => {
+                let mut resp = Vec::with_capacity(11);
+                resp.extend_from_slice(&0u32.to_le_bytes());
+                resp.push(P9_RWRITE);
+                resp.extend_from_slice(&tag.to_le_bytes());
+                resp.extend_from_slice(&written.to_le_bytes());
+                let size = resp.len() as u32;
+                resp[0..4].copy_from_slice(&size.to_le_bytes());
+                resp
+            }
+            // Backend dispatched a large write to its background pool
+            // instead of completing inline - fall back to the generic path
+            // so the request suspends instead of erroring out.
+            Err(EAGAIN) => return None,
+            Err(e) => self.error_response(tag, e),
+        };
+        Some(write_descriptor_chain(mem, output, &response) as u32)
     }
     
     pub fn notify(&mut self, _queue: u32) {}
@@ -429,8 +895,8 @@ impl Virtio9p {
             P9_TGETATTR => Some(self.handle_getattr(tag, payload)),
             P9_TREADDIR => Some(self.handle_readdir(tag, payload)),
             P9_TLOPEN => Some(self.handle_lopen(tag, payload)),
-            P9_TREAD => Some(self.handle_read(tag, payload)),
-            P9_TWRITE => Some(self.handle_write(tag, payload)),
+            P9_TREAD => self.handle_read(tag, payload),
+            P9_TWRITE => self.handle_write(tag, payload),
             P9_TMKDIR => Some(self.handle_mkdir(tag, payload)),
             P9_TMKNOD => Some(self.handle_mknod(tag, payload)), // Treat as create
             P9_TLCREATE => Some(self.handle_lcreate(tag, payload)),
@@ -453,26 +919,25 @@ impl Virtio9p {
     }
 
     fn handle_version(&mut self, tag: u16, payload: &[u8]) -> Vec<u8> {
-        if payload.len() < 6 { return self.error_response(tag, EINVAL); }
-        let msize = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+        let mut r = P9Reader::new(payload);
+        let msize = match r.read_u32() {
+            Ok(v) => v,
+            Err(e) => return self.error_response(tag, e),
+        };
         self.msize = msize.min(8192);
-        let version = b"9P2000.L";
-        let mut resp = Vec::new();
-        resp.extend_from_slice(&0u32.to_le_bytes());
-        resp.push(P9_RVERSION);
-        resp.extend_from_slice(&tag.to_le_bytes());
-        resp.extend_from_slice(&self.msize.to_le_bytes());
-        resp.extend_from_slice(&(version.len() as u16).to_le_bytes());
-        resp.extend_from_slice(version);
-        let size = resp.len() as u32;
-        resp[0..4].copy_from_slice(&size.to_le_bytes());
-        resp
+        let mut w = P9Writer::new(P9_RVERSION, tag);
+        w.write_u32(self.msize);
+        w.write_string("9P2000.L");
+        w.finalize(self.msize).unwrap_or_else(|e| self.error_response(tag, e))
     }
-    
+
     fn handle_attach(&mut self, tag: u16, payload: &[u8]) -> Vec<u8> {
-        if payload.len() < 12 { return self.error_response(tag, EINVAL); }
-        let fid = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
-        
+        let mut r = P9Reader::new(payload);
+        let fid = match r.read_u32() {
+            Ok(v) => v,
+            Err(e) => return self.error_response(tag, e),
+        };
+
         match self.fs.attach() {
             Ok(qid) => {
                 self.fids.insert(fid, Fid {
@@ -481,44 +946,45 @@ impl Virtio9p {
                     open_flags: 0,
                     position: 0,
                 });
-                let mut resp = Vec::new();
-                resp.extend_from_slice(&0u32.to_le_bytes());
-                resp.push(P9_RATTACH);
-                resp.extend_from_slice(&tag.to_le_bytes());
-                resp.extend_from_slice(&qid.encode());
-                let size = resp.len() as u32;
-                resp[0..4].copy_from_slice(&size.to_le_bytes());
-                resp
+                let mut w = P9Writer::new(P9_RATTACH, tag);
+                w.write_qid(&qid);
+                w.finalize(self.msize).unwrap_or_else(|e| self.error_response(tag, e))
             },
             Err(e) => self.error_response(tag, e)
         }
     }
-    
+
     fn handle_walk(&mut self, tag: u16, payload: &[u8]) -> Vec<u8> {
-        if payload.len() < 10 { return self.error_response(tag, EINVAL); }
-        let fid = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
-        let newfid = u32::from_le_bytes([payload[4], payload[5], payload[6], payload[7]]);
-        let nwname = u16::from_le_bytes([payload[8], payload[9]]) as usize;
-        
+        let mut r = P9Reader::new(payload);
+        let fid = match r.read_u32() {
+            Ok(v) => v,
+            Err(e) => return self.error_response(tag, e),
+        };
+        let newfid = match r.read_u32() {
+            Ok(v) => v,
+            Err(e) => return self.error_response(tag, e),
+        };
+        let nwname = match r.read_u16() {
+            Ok(v) => v as usize,
+            Err(e) => return self.error_response(tag, e),
+        };
+
         let mut current_fid = match self.fids.get(&fid) {
             Some(f) => f.clone(),
             None => return self.error_response(tag, EBADF),
         };
-        
+
         let mut qids = Vec::new();
-        let mut offset = 10;
-        
+
         for _ in 0..nwname {
-            if offset + 2 > payload.len() { return self.error_response(tag, EINVAL); }
-            let name_len = u16::from_le_bytes([payload[offset], payload[offset+1]]) as usize;
-            offset += 2;
-            if offset + name_len > payload.len() { return self.error_response(tag, EINVAL); }
-            let name = String::from_utf8_lossy(&payload[offset..offset+name_len]).to_string();
-            offset += name_len;
-            
+            let name = match r.read_string() {
+                Ok(v) => v,
+                Err(e) => return self.error_response(tag, e),
+            };
+
             match self.fs.walk(&current_fid.qid, &name) {
                 Ok(qid) => {
-                    qids.push(qid.encode());
+                    qids.push(qid);
                     current_fid.qid = qid;
                 },
                 Err(e) => {
@@ -527,33 +993,28 @@ impl Virtio9p {
                 }
             }
         }
-        
+
         if qids.len() == nwname {
              self.fids.insert(newfid, current_fid);
         }
-        
-        let mut resp = Vec::new();
-        resp.extend_from_slice(&0u32.to_le_bytes());
-        resp.push(P9_RWALK);
-        resp.extend_from_slice(&tag.to_le_bytes());
-        resp.extend_from_slice(&(qids.len() as u16).to_le_bytes());
-        for q in qids { resp.extend_from_slice(&q); }
-        let size = resp.len() as u32;
-        resp[0..4].copy_from_slice(&size.to_le_bytes());
-        resp
+
+        let mut w = P9Writer::new(P9_RWALK, tag);
+        w.write_u16(qids.len() as u16);
+        for q in &qids { w.write_qid(q); }
+        w.finalize(self.msize).unwrap_or_else(|e| self.error_response(tag, e))
     }
 
     fn handle_clunk(&mut self, tag: u16, payload: &[u8]) -> Vec<u8> {
-        if payload.len() < 4 { return self.error_response(tag, EINVAL); }
-        let fid = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
-        self.fids.remove(&fid);
-        let mut resp = Vec::new();
-        resp.extend_from_slice(&0u32.to_le_bytes());
-        resp.push(P9_RCLUNK);
-        resp.extend_from_slice(&tag.to_le_bytes());
-        let size = resp.len() as u32;
-        resp[0..4].copy_from_slice(&size.to_le_bytes());
-        resp
+        let mut r = P9Reader::new(payload);
+        let fid = match r.read_u32() {
+            Ok(v) => v,
+            Err(e) => return self.error_response(tag, e),
+        };
+        if let Some(f) = self.fids.remove(&fid) {
+            self.fs.clunk(&f.qid);
+        }
+        let w = P9Writer::new(P9_RCLUNK, tag);
+        w.finalize(self.msize).unwrap_or_else(|e| self.error_response(tag, e))
     }
 
     fn handle_getattr(&mut self, tag: u16, payload: &[u8]) -> Vec<u8> {
@@ -609,13 +1070,19 @@ impl Virtio9p {
                     f.open = true;
                     f.open_flags = flags;
                     f.position = 0;
-                    
+
+                    if flags & O_TRUNC != 0 {
+                        if let Err(e) = self.fs.truncate(&f.qid, 0) {
+                            return self.error_response(tag, e);
+                        }
+                    }
+
                     let mut resp = Vec::new();
                     resp.extend_from_slice(&0u32.to_le_bytes());
                     resp.push(P9_RLOPEN);
                     resp.extend_from_slice(&tag.to_le_bytes());
                     resp.extend_from_slice(&f.qid.encode());
-                    resp.extend_from_slice(&4096u32.to_le_bytes()); // iounit
+                    resp.extend_from_slice(&self.iounit().to_le_bytes());
                     let size = resp.len() as u32;
                     resp[0..4].copy_from_slice(&size.to_le_bytes());
                     resp
@@ -627,14 +1094,20 @@ impl Virtio9p {
         }
     }
     
-    fn handle_read(&mut self, tag: u16, payload: &[u8]) -> Vec<u8> {
-        if payload.len() < 12 { return self.error_response(tag, EINVAL); }
+    /// Returns `None` when the read should suspend instead of erroring -
+    /// see `EAGAIN` - so the caller (`process_queue`) parks the request on
+    /// `suspended_requests` instead of sending an RLERROR to the guest.
+    fn handle_read(&mut self, tag: u16, payload: &[u8]) -> Option<Vec<u8>> {
+        if payload.len() < 12 { return Some(self.error_response(tag, EINVAL)); }
         let fid = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
         let offset = u64::from_le_bytes([payload[4], payload[5], payload[6], payload[7], payload[8], payload[9], payload[10], payload[11]]);
         let count = u32::from_le_bytes([payload[12], payload[13], payload[14], payload[15]]);
-        
-        let f = match self.fids.get(&fid) { Some(f) => f, None => return self.error_response(tag, EBADF) };
-        
+
+        let f = match self.fids.get(&fid) { Some(f) => f, None => return Some(self.error_response(tag, EBADF)) };
+        if !open_flags_readable(f.open_flags) {
+            return Some(self.error_response(tag, EBADF));
+        }
+
         match self.fs.read(&f.qid, offset, count) {
             Ok(data) => {
                 let mut resp = Vec::new();
@@ -645,23 +1118,52 @@ impl Virtio9p {
                 resp.extend_from_slice(&data);
                 let size = resp.len() as u32;
                 resp[0..4].copy_from_slice(&size.to_le_bytes());
-                resp
+                Some(resp)
             },
-            Err(e) => self.error_response(tag, e)
+            Err(EAGAIN) => None,
+            Err(e) => Some(self.error_response(tag, e))
         }
     }
     
-    fn handle_write(&mut self, tag: u16, payload: &[u8]) -> Vec<u8> {
-        if payload.len() < 16 { return self.error_response(tag, EINVAL); }
+    /// Returns `None` when the write should suspend instead of erroring -
+    /// see `EAGAIN` - so the caller (`process_queue`) parks the request on
+    /// `suspended_requests` instead of sending an RLERROR to the guest.
+    /// Mirrors `handle_read`, which suspends the same way on a missing
+    /// blob; here it's `HostFileSystem` dispatching a large write to its
+    /// background pool instead.
+    fn handle_write(&mut self, tag: u16, payload: &[u8]) -> Option<Vec<u8>> {
+        if payload.len() < 16 { return Some(self.error_response(tag, EINVAL)); }
         let fid = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
         let offset = u64::from_le_bytes([payload[4], payload[5], payload[6], payload[7], payload[8], payload[9], payload[10], payload[11]]);
-        let count = u32::from_le_bytes([payload[12], payload[13], payload[14], payload[15]]);
-        if payload.len() < 16 + count as usize { return self.error_response(tag, EINVAL); }
+        let requested = u32::from_le_bytes([payload[12], payload[13], payload[14], payload[15]]);
+        // Clamp to what actually fits in this msize-bounded message rather
+        // than erroring - a short write is what qemu's 9p server does, and
+        // it's what the Linux 9p client expects when it races ahead of a
+        // freshly negotiated (possibly smaller) msize.
+        let available = (payload.len() as u32).saturating_sub(16);
+        let count = requested.min(available);
         let data = &payload[16..16+count as usize];
-        
-        let f = match self.fids.get(&fid) { Some(f) => f, None => return self.error_response(tag, EBADF) };
-        
-        match self.fs.write(&f.qid, offset, data) {
+
+        let (qid, open_flags) = match self.fids.get(&fid) {
+            Some(f) => (f.qid, f.open_flags),
+            None => return Some(self.error_response(tag, EBADF)),
+        };
+        if !open_flags_writable(open_flags) {
+            return Some(self.error_response(tag, EBADF));
+        }
+        // O_APPEND ignores the client-supplied offset and always lands at
+        // current EOF - query it fresh from the backend since the client
+        // doesn't track the file's size itself.
+        let write_offset = if open_flags & O_APPEND != 0 {
+            match self.fs.getattr(&qid) {
+                Ok(attr) => attr.size,
+                Err(e) => return Some(self.error_response(tag, e)),
+            }
+        } else {
+            offset
+        };
+
+        match self.fs.write(&qid, write_offset, data) {
              Ok(written) => {
                 let mut resp = Vec::new();
                 resp.extend_from_slice(&0u32.to_le_bytes());
@@ -670,9 +1172,10 @@ impl Virtio9p {
                 resp.extend_from_slice(&written.to_le_bytes());
                 let size = resp.len() as u32;
                 resp[0..4].copy_from_slice(&size.to_le_bytes());
-                resp
+                Some(resp)
              },
-             Err(e) => self.error_response(tag, e)
+             Err(EAGAIN) => None,
+             Err(e) => Some(self.error_response(tag, e)),
         }
     }
     
@@ -768,7 +1271,7 @@ impl Virtio9p {
                      resp.push(P9_RLCREATE);
                      resp.extend_from_slice(&tag.to_le_bytes());
                      resp.extend_from_slice(&qid.encode());
-                     resp.extend_from_slice(&4096u32.to_le_bytes()); // iounit
+                     resp.extend_from_slice(&self.iounit().to_le_bytes());
                      let size = resp.len() as u32;
                      resp[0..4].copy_from_slice(&size.to_le_bytes());
                      resp
@@ -841,3 +1344,703 @@ impl Virtio9p {
          }
     }
 }
+
+/// Read the whole descriptor chain into a single buffer using bulk memory
+/// accesses instead of a byte-at-a-time copy loop.
+fn read_descriptor_chain(mem: &Memory, descriptors: &[Descriptor]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(descriptors.iter().map(|d| d.len as usize).sum());
+    for desc in descriptors {
+        buf.extend_from_slice(&mem.read_slice(desc.addr as u32, desc.len as usize));
+    }
+    buf
+}
+
+/// Read just the first `len` bytes spanning the descriptor chain, without
+/// materializing anything past that point. Used to sniff/parse fixed-size
+/// message headers ahead of a bulk payload transfer.
+fn read_descriptor_chain_prefix(mem: &Memory, descriptors: &[Descriptor], len: usize) -> Option<Vec<u8>> {
+    let mut buf = Vec::with_capacity(len);
+    for desc in descriptors {
+        if buf.len() >= len { break; }
+        let take = (len - buf.len()).min(desc.len as usize);
+        buf.extend_from_slice(&mem.read_slice(desc.addr as u32, take));
+    }
+    if buf.len() < len { None } else { Some(buf) }
+}
+
+/// Read `len` bytes starting at byte offset `skip` into the descriptor
+/// chain, e.g. the payload that follows a fixed Twrite header.
+fn read_descriptor_chain_range(mem: &Memory, descriptors: &[Descriptor], skip: usize, len: usize) -> Option<Vec<u8>> {
+    let mut buf = Vec::with_capacity(len);
+    let mut consumed = 0usize;
+    for desc in descriptors {
+        let desc_len = desc.len as usize;
+        let desc_start = consumed;
+        let desc_end = consumed + desc_len;
+        consumed = desc_end;
+        if desc_end <= skip { continue; }
+        if buf.len() >= len { break; }
+
+        let start_in_desc = skip.saturating_sub(desc_start);
+        let want = len - buf.len();
+        let avail = desc_len - start_in_desc;
+        let take = want.min(avail);
+        buf.extend_from_slice(&mem.read_slice(desc.addr as u32 + start_in_desc as u32, take));
+    }
+    if buf.len() < len { None } else { Some(buf) }
+}
+
+/// Peek a single byte at `offset` into the (possibly chained) input
+/// descriptors without copying the rest of the message.
+fn descriptors_peek_byte(mem: &Memory, descriptors: &[Descriptor], offset: usize) -> Option<u8> {
+    let mut consumed = 0usize;
+    for desc in descriptors {
+        let desc_len = desc.len as usize;
+        if offset < consumed + desc_len {
+            return Some(mem.read_slice(desc.addr as u32 + (offset - consumed) as u32, 1)[0]);
+        }
+        consumed += desc_len;
+    }
+    None
+}
+
+/// Write `data` across the output descriptor chain using bulk memory
+/// writes, returning the number of bytes actually written.
+fn write_descriptor_chain(mem: &mut Memory, descriptors: &[Descriptor], data: &[u8]) -> usize {
+    write_descriptor_chain_at(mem, descriptors, 0, data)
+}
+
+/// Write `data` across the output descriptor chain starting at byte offset
+/// `skip` (used to append the payload after a header already written with
+/// [`write_descriptor_chain`]).
+fn write_descriptor_chain_at(mem: &mut Memory, descriptors: &[Descriptor], skip: usize, data: &[u8]) -> usize {
+    let mut consumed = 0usize;
+    let mut written = 0usize;
+    for desc in descriptors {
+        let desc_len = desc.len as usize;
+        let desc_start = consumed;
+        let desc_end = consumed + desc_len;
+        consumed = desc_end;
+        if desc_end <= skip { continue; }
+        if written >= data.len() { break; }
+
+        let start_in_desc = skip.saturating_sub(desc_start);
+        let avail = desc_len - start_in_desc;
+        let take = (data.len() - written).min(avail);
+        mem.write_slice(desc.addr as u32 + start_in_desc as u32, &data[written..written + take]);
+        written += take;
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::DRAM_BASE;
+    use crate::devices::virtio_9p::in_memory::{InMemoryFileSystem, InodeContent};
+    use crate::devices::virtio::{VRING_DESC_F_NEXT, VRING_DESC_F_WRITE, VIRTIO_F_EVENT_IDX};
+
+    const QUEUE_SIZE: u16 = 4;
+    const DESC_TABLE: u32 = DRAM_BASE + 0x1000;
+    const AVAIL_RING: u32 = DRAM_BASE + 0x2000;
+    const USED_RING: u32 = DRAM_BASE + 0x3000;
+    const REQ_BUF: u32 = DRAM_BASE + 0x4000;
+    const RESP_BUF: u32 = DRAM_BASE + 0x5000;
+    const SLOT_SIZE: u32 = 0x100;
+
+    fn setup_queue(device: &mut Virtio9p) {
+        let queue = &mut device.virtio.queues[0];
+        queue.num = QUEUE_SIZE as u32;
+        queue.ready = true;
+        queue.desc_addr = DESC_TABLE as u64;
+        queue.avail_addr = AVAIL_RING as u64;
+        queue.used_addr = USED_RING as u64;
+        queue.last_avail_idx = 0;
+    }
+
+    fn write_desc(mem: &mut Memory, idx: u16, addr: u32, len: u32, flags: u16, next: u16) {
+        let base = DESC_TABLE + (idx as u32) * 16;
+        mem.write32(base, addr);
+        mem.write32(base + 4, 0);
+        mem.write32(base + 8, len);
+        mem.write16(base + 12, flags);
+        mem.write16(base + 14, next);
+    }
+
+    fn build_tversion(tag: u16) -> Vec<u8> {
+        let version = b"9P2000.L";
+        let mut req = Vec::new();
+        req.extend_from_slice(&0u32.to_le_bytes());
+        req.push(P9_TVERSION);
+        req.extend_from_slice(&tag.to_le_bytes());
+        req.extend_from_slice(&8192u32.to_le_bytes());
+        req.extend_from_slice(&(version.len() as u16).to_le_bytes());
+        req.extend_from_slice(version);
+        let size = req.len() as u32;
+        req[0..4].copy_from_slice(&size.to_le_bytes());
+        req
+    }
+
+    /// Submit one Tversion request using descriptor pair `slot` (an input
+    /// descriptor holding the request, an output descriptor with room for
+    /// the response), and advance the avail ring to make it visible.
+    fn submit_request(mem: &mut Memory, avail_idx: u16, slot: u16) {
+        let req_addr = REQ_BUF + (slot as u32) * SLOT_SIZE;
+        let resp_addr = RESP_BUF + (slot as u32) * SLOT_SIZE;
+        let in_desc = slot * 2;
+        let out_desc = slot * 2 + 1;
+
+        let request = build_tversion(slot);
+        mem.write_slice(req_addr, &request);
+
+        write_desc(mem, in_desc, req_addr, request.len() as u32, VRING_DESC_F_NEXT, out_desc);
+        write_desc(mem, out_desc, resp_addr, SLOT_SIZE, VRING_DESC_F_WRITE, 0);
+
+        // avail->ring[avail_idx % num] = in_desc
+        let ring_addr = AVAIL_RING + 4 + (avail_idx as u32 % QUEUE_SIZE as u32) * 2;
+        mem.write16(ring_addr, in_desc);
+        // avail->idx = avail_idx + 1
+        mem.write16(AVAIL_RING + 2, avail_idx.wrapping_add(1));
+    }
+
+    #[test]
+    fn test_event_idx_suppresses_interrupt_until_requested_index() {
+        let mut device = Virtio9p::new("test", Backend::InMemory(InMemoryFileSystem::new()));
+        let mut mem = Memory::new(1);
+        setup_queue(&mut device);
+        device.virtio.driver_features = VIRTIO_F_EVENT_IDX;
+
+        // Driver asks to be interrupted once used->idx passes 1, i.e. after
+        // both of the first two requests have been completed.
+        let used_event_addr = AVAIL_RING + 4 + (QUEUE_SIZE as u32) * 2;
+        mem.write16(used_event_addr, 1);
+
+        submit_request(&mut mem, 0, 0);
+        submit_request(&mut mem, 1, 1);
+        device.virtio.queue_notify_pending.push_back(0);
+        device.process_queues(&mut mem);
+
+        assert!(device.virtio.interrupt_pending, "expected interrupt once used_event was crossed");
+        device.virtio.interrupt_status = 0;
+        device.virtio.interrupt_pending = false;
+
+        // Driver now asks for a much later index; a single further request
+        // shouldn't cross it, so no interrupt should be raised.
+        mem.write16(used_event_addr, 10);
+        submit_request(&mut mem, 2, 2);
+        device.virtio.queue_notify_pending.push_back(0);
+        device.process_queues(&mut mem);
+
+        assert!(!device.virtio.interrupt_pending, "interrupt should be suppressed until used_event is reached");
+    }
+
+    #[test]
+    fn test_process_queues_interrupts_once_per_call_without_event_idx() {
+        let mut device = Virtio9p::new("test", Backend::InMemory(InMemoryFileSystem::new()));
+        let mut mem = Memory::new(1);
+        setup_queue(&mut device);
+        // VIRTIO_F_EVENT_IDX not negotiated: every processed batch should
+        // still raise exactly one interrupt, same as before batching.
+
+        submit_request(&mut mem, 0, 0);
+        submit_request(&mut mem, 1, 1);
+        device.virtio.queue_notify_pending.push_back(0);
+        device.process_queues(&mut mem);
+
+        assert!(device.virtio.interrupt_pending);
+        assert_eq!(device.virtio.queues[0].used_idx(&mem), 2);
+    }
+
+    #[test]
+    fn test_readdir_pages_a_stable_sorted_snapshot_across_calls() {
+        let mut fs = InMemoryFileSystem::new();
+        let root = fs.attach().unwrap();
+
+        for name in ["c.txt", "a.txt", "b.txt", "d.txt"] {
+            fs.create(&root, name, 0o100644, 0).unwrap();
+        }
+
+        // Two pages of two entries each should walk the same sorted order
+        // as one page of everything, with `offset` meaning "index into that
+        // order" consistently across calls.
+        let page1 = fs.readdir(&root, 0, 4096).unwrap();
+        let names: Vec<&str> = page1.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["a.txt", "b.txt", "c.txt", "d.txt"]);
+
+        let next_offset = page1[1].offset;
+        let page2 = fs.readdir(&root, next_offset, 4096).unwrap();
+        let names2: Vec<&str> = page2.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names2, vec!["c.txt", "d.txt"]);
+
+        // Adding a file bumps dir_version, which should invalidate the
+        // cached snapshot on the next offset-0 listing.
+        fs.create(&root, "aa.txt", 0o100644, 0).unwrap();
+        let refreshed = fs.readdir(&root, 0, 4096).unwrap();
+        let names3: Vec<&str> = refreshed.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names3, vec!["a.txt", "aa.txt", "b.txt", "c.txt", "d.txt"]);
+    }
+
+    const O_CREAT: u32 = 0o100;
+
+    fn build_tattach(tag: u16, fid: u32) -> Vec<u8> {
+        let mut req = Vec::new();
+        req.extend_from_slice(&0u32.to_le_bytes());
+        req.push(P9_TATTACH);
+        req.extend_from_slice(&tag.to_le_bytes());
+        req.extend_from_slice(&fid.to_le_bytes());
+        req.extend_from_slice(&0u32.to_le_bytes()); // afid
+        req.extend_from_slice(&0u16.to_le_bytes()); // uname length (unused)
+        req.extend_from_slice(&0u16.to_le_bytes()); // aname length (unused)
+        let size = req.len() as u32;
+        req[0..4].copy_from_slice(&size.to_le_bytes());
+        req
+    }
+
+    fn build_twalk(tag: u16, fid: u32, newfid: u32, name: &str) -> Vec<u8> {
+        let mut req = Vec::new();
+        req.extend_from_slice(&0u32.to_le_bytes());
+        req.push(P9_TWALK);
+        req.extend_from_slice(&tag.to_le_bytes());
+        req.extend_from_slice(&fid.to_le_bytes());
+        req.extend_from_slice(&newfid.to_le_bytes());
+        req.extend_from_slice(&1u16.to_le_bytes()); // nwname
+        req.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        req.extend_from_slice(name.as_bytes());
+        let size = req.len() as u32;
+        req[0..4].copy_from_slice(&size.to_le_bytes());
+        req
+    }
+
+    fn build_tlcreate(tag: u16, fid: u32, name: &str, flags: u32, mode: u32) -> Vec<u8> {
+        let mut req = Vec::new();
+        req.extend_from_slice(&0u32.to_le_bytes());
+        req.push(P9_TLCREATE);
+        req.extend_from_slice(&tag.to_le_bytes());
+        req.extend_from_slice(&fid.to_le_bytes());
+        req.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        req.extend_from_slice(name.as_bytes());
+        req.extend_from_slice(&flags.to_le_bytes());
+        req.extend_from_slice(&mode.to_le_bytes());
+        let size = req.len() as u32;
+        req[0..4].copy_from_slice(&size.to_le_bytes());
+        req
+    }
+
+    fn build_tlopen(tag: u16, fid: u32, flags: u32) -> Vec<u8> {
+        let mut req = Vec::new();
+        req.extend_from_slice(&0u32.to_le_bytes());
+        req.push(P9_TLOPEN);
+        req.extend_from_slice(&tag.to_le_bytes());
+        req.extend_from_slice(&fid.to_le_bytes());
+        req.extend_from_slice(&flags.to_le_bytes());
+        let size = req.len() as u32;
+        req[0..4].copy_from_slice(&size.to_le_bytes());
+        req
+    }
+
+    fn build_twrite(tag: u16, fid: u32, offset: u64, data: &[u8]) -> Vec<u8> {
+        let mut req = Vec::new();
+        req.extend_from_slice(&0u32.to_le_bytes());
+        req.push(P9_TWRITE);
+        req.extend_from_slice(&tag.to_le_bytes());
+        req.extend_from_slice(&fid.to_le_bytes());
+        req.extend_from_slice(&offset.to_le_bytes());
+        req.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        req.extend_from_slice(data);
+        let size = req.len() as u32;
+        req[0..4].copy_from_slice(&size.to_le_bytes());
+        req
+    }
+
+    fn build_tread(tag: u16, fid: u32, offset: u64, count: u32) -> Vec<u8> {
+        let mut req = Vec::new();
+        req.extend_from_slice(&0u32.to_le_bytes());
+        req.push(P9_TREAD);
+        req.extend_from_slice(&tag.to_le_bytes());
+        req.extend_from_slice(&fid.to_le_bytes());
+        req.extend_from_slice(&offset.to_le_bytes());
+        req.extend_from_slice(&count.to_le_bytes());
+        let size = req.len() as u32;
+        req[0..4].copy_from_slice(&size.to_le_bytes());
+        req
+    }
+
+    fn build_tgetattr(tag: u16, fid: u32) -> Vec<u8> {
+        let mut req = Vec::new();
+        req.extend_from_slice(&0u32.to_le_bytes());
+        req.push(P9_TGETATTR);
+        req.extend_from_slice(&tag.to_le_bytes());
+        req.extend_from_slice(&fid.to_le_bytes());
+        req.extend_from_slice(&0u64.to_le_bytes()); // request_mask (unused)
+        let size = req.len() as u32;
+        req[0..4].copy_from_slice(&size.to_le_bytes());
+        req
+    }
+
+    fn resp_type(resp: &[u8]) -> u8 { resp[4] }
+    fn resp_errno(resp: &[u8]) -> u32 { u32::from_le_bytes([resp[7], resp[8], resp[9], resp[10]]) }
+    fn resp_rread_data(resp: &[u8]) -> Vec<u8> {
+        let count = u32::from_le_bytes([resp[7], resp[8], resp[9], resp[10]]) as usize;
+        resp[11..11 + count].to_vec()
+    }
+    fn resp_rgetattr_size(resp: &[u8]) -> u64 {
+        u64::from_le_bytes(resp[56..64].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_lopen_o_trunc_truncates_file_to_zero() {
+        let mut device = Virtio9p::new("test", Backend::InMemory(InMemoryFileSystem::new()));
+
+        device.process_message(&build_tattach(0, 0));
+        device.process_message(&build_tlcreate(0, 0, "f.txt", O_WRONLY | O_CREAT, 0o100644));
+        device.process_message(&build_twrite(0, 0, 0, b"hello"));
+
+        // Walk to the file from a fresh attach fid (fid 0 now refers to the
+        // file itself after Tlcreate, not the directory it was created in).
+        device.process_message(&build_tattach(0, 1));
+        device.process_message(&build_twalk(0, 1, 2, "f.txt"));
+        let resp = device.process_message(&build_tlopen(0, 2, O_RDONLY | O_TRUNC)).unwrap();
+        assert_eq!(resp_type(&resp), P9_RLOPEN);
+
+        let resp = device.process_message(&build_tgetattr(0, 2)).unwrap();
+        assert_eq!(resp_rgetattr_size(&resp), 0);
+    }
+
+    #[test]
+    fn test_o_rdonly_rejects_twrite_with_ebadf() {
+        let mut device = Virtio9p::new("test", Backend::InMemory(InMemoryFileSystem::new()));
+
+        device.process_message(&build_tattach(0, 0));
+        device.process_message(&build_tlcreate(0, 0, "g.txt", O_RDONLY | O_CREAT, 0o100644));
+
+        let resp = device.process_message(&build_twrite(0, 0, 0, b"nope")).unwrap();
+        assert_eq!(resp_type(&resp), P9_RLERROR);
+        assert_eq!(resp_errno(&resp), EBADF);
+    }
+
+    #[test]
+    fn test_o_wronly_rejects_tread_with_ebadf() {
+        let mut device = Virtio9p::new("test", Backend::InMemory(InMemoryFileSystem::new()));
+
+        device.process_message(&build_tattach(0, 0));
+        device.process_message(&build_tlcreate(0, 0, "h.txt", O_WRONLY | O_CREAT, 0o100644));
+
+        let resp = device.process_message(&build_tread(0, 0, 0, 64)).unwrap();
+        assert_eq!(resp_type(&resp), P9_RLERROR);
+        assert_eq!(resp_errno(&resp), EBADF);
+    }
+
+    #[test]
+    fn test_o_append_ignores_client_offset_and_writes_at_eof() {
+        let mut device = Virtio9p::new("test", Backend::InMemory(InMemoryFileSystem::new()));
+
+        device.process_message(&build_tattach(0, 0));
+        device.process_message(&build_tlcreate(0, 0, "log.txt", O_WRONLY | O_CREAT | O_APPEND, 0o100644));
+
+        // Both writes claim offset 0; O_APPEND must land the second one
+        // after the first regardless.
+        device.process_message(&build_twrite(0, 0, 0, b"first\n"));
+        device.process_message(&build_twrite(0, 0, 0, b"second\n"));
+
+        device.process_message(&build_tattach(0, 1));
+        device.process_message(&build_twalk(0, 1, 2, "log.txt"));
+        device.process_message(&build_tlopen(0, 2, O_RDONLY));
+        let resp = device.process_message(&build_tread(0, 2, 0, 64)).unwrap();
+        assert_eq!(resp_rread_data(&resp), b"first\nsecond\n");
+    }
+
+    /// Submit an arbitrary pre-built 9p message using descriptor pair
+    /// `slot`, the same way `submit_request` does for a fixed Tversion.
+    fn submit_message(mem: &mut Memory, avail_idx: u16, slot: u16, request: &[u8]) {
+        let req_addr = REQ_BUF + (slot as u32) * SLOT_SIZE;
+        let resp_addr = RESP_BUF + (slot as u32) * SLOT_SIZE;
+        let in_desc = slot * 2;
+        let out_desc = slot * 2 + 1;
+
+        mem.write_slice(req_addr, request);
+
+        write_desc(mem, in_desc, req_addr, request.len() as u32, VRING_DESC_F_NEXT, out_desc);
+        write_desc(mem, out_desc, resp_addr, SLOT_SIZE, VRING_DESC_F_WRITE, 0);
+
+        let ring_addr = AVAIL_RING + 4 + (avail_idx as u32 % QUEUE_SIZE as u32) * 2;
+        mem.write16(ring_addr, in_desc);
+        mem.write16(AVAIL_RING + 2, avail_idx.wrapping_add(1));
+    }
+
+    #[test]
+    fn test_tread_on_missing_blob_suspends_then_completes_via_provide_blob() {
+        let mut device = Virtio9p::new("test", Backend::InMemory(InMemoryFileSystem::new()));
+        let mut mem = Memory::new(1);
+        setup_queue(&mut device);
+
+        device.process_message(&build_tattach(0, 0));
+        device.process_message(&build_tlcreate(0, 0, "blob.bin", O_RDONLY | O_CREAT, 0o100644));
+        if let Backend::InMemory(fs) = &mut device.fs {
+            let root = fs.attach().unwrap();
+            let path = fs.walk(&root, "blob.bin").unwrap().path;
+            let inode = fs.inodes.get_mut(&path).unwrap();
+            inode.content = InodeContent::Hash("deadbeef".to_string());
+            inode.size = 4;
+        }
+
+        // Tread has to travel through the real queue - `process_message`
+        // alone has nowhere to park a suspended request.
+        submit_message(&mut mem, 0, 0, &build_tread(0, 0, 0, 64));
+        device.virtio.queue_notify_pending.push_back(0);
+        device.process_queues(&mut mem);
+
+        assert_eq!(device.virtio.queues[0].used_idx(&mem), 0, "read on a missing blob should suspend, not complete");
+        assert_eq!(device.suspended_requests.len(), 1);
+        assert_eq!(device.get_missing_blobs(), vec!["deadbeef".to_string()]);
+
+        device.provide_blob("deadbeef".to_string(), b"data".to_vec(), &mut mem);
+
+        assert!(device.suspended_requests.is_empty());
+        assert!(device.get_missing_blobs().is_empty());
+        assert_eq!(device.virtio.queues[0].used_idx(&mem), 1);
+        let resp = mem.read_slice(RESP_BUF, SLOT_SIZE as usize);
+        assert_eq!(resp_rread_data(&resp), b"data");
+    }
+
+    #[test]
+    fn test_debug_state_reports_suspended_request_and_awaited_hash() {
+        let mut device = Virtio9p::new("test", Backend::InMemory(InMemoryFileSystem::new()));
+        let mut mem = Memory::new(1);
+        setup_queue(&mut device);
+
+        device.process_message(&build_tattach(0, 0));
+        device.process_message(&build_tlcreate(0, 0, "blob.bin", O_RDONLY | O_CREAT, 0o100644));
+        if let Backend::InMemory(fs) = &mut device.fs {
+            let root = fs.attach().unwrap();
+            let path = fs.walk(&root, "blob.bin").unwrap().path;
+            let inode = fs.inodes.get_mut(&path).unwrap();
+            inode.content = InodeContent::Hash("deadbeef".to_string());
+            inode.size = 4;
+        }
+
+        let state = device.debug_state();
+        assert_eq!(state.open_fids, 1);
+        assert!(state.suspended_requests.is_empty());
+        assert!(state.missing_blobs.is_empty());
+
+        submit_message(&mut mem, 0, 0, &build_tread(0, 0, 0, 64));
+        device.virtio.queue_notify_pending.push_back(0);
+        device.process_queues(&mut mem);
+
+        let state = device.debug_state();
+        assert_eq!(state.suspended_requests.len(), 1);
+        assert_eq!(state.suspended_requests[0].awaited_hash, Some("deadbeef".to_string()));
+        assert_eq!(state.missing_blobs, vec!["deadbeef".to_string()]);
+    }
+
+    #[test]
+    fn test_chunked_read_only_waits_on_the_chunk_it_actually_needs() {
+        let mut device = Virtio9p::new("test", Backend::InMemory(InMemoryFileSystem::new()));
+        let mut mem = Memory::new(1);
+        setup_queue(&mut device);
+
+        device.process_message(&build_tattach(0, 0));
+        device.process_message(&build_tlcreate(0, 0, "big.bin", O_RDONLY | O_CREAT, 0o100644));
+        if let Backend::InMemory(fs) = &mut device.fs {
+            let root = fs.attach().unwrap();
+            let path = fs.walk(&root, "big.bin").unwrap().path;
+            let inode = fs.inodes.get_mut(&path).unwrap();
+            inode.content = InodeContent::Chunked(vec![
+                (0, "chunk0".to_string()),
+                (4, "chunk1".to_string()),
+            ]);
+            inode.size = 8;
+            fs.blob_cache.insert("chunk0".to_string(), b"abcd".to_vec());
+        }
+
+        // Confined entirely to the first (already-fetched) chunk - must
+        // complete inline without ever asking for "chunk1".
+        let resp = device.process_message(&build_tread(0, 0, 0, 4)).unwrap();
+        assert_eq!(resp_rread_data(&resp), b"abcd");
+        assert!(device.get_missing_blobs().is_empty());
+
+        // Spans into the second (missing) chunk - must suspend on just
+        // that chunk, not re-request the first one.
+        submit_message(&mut mem, 0, 0, &build_tread(0, 0, 0, 8));
+        device.virtio.queue_notify_pending.push_back(0);
+        device.process_queues(&mut mem);
+
+        assert_eq!(device.virtio.queues[0].used_idx(&mem), 0, "read spanning a missing chunk should suspend");
+        assert_eq!(device.get_missing_blobs(), vec!["chunk1".to_string()]);
+
+        device.provide_blob("chunk1".to_string(), b"efgh".to_vec(), &mut mem);
+
+        assert!(device.suspended_requests.is_empty());
+        assert_eq!(device.virtio.queues[0].used_idx(&mem), 1);
+        let resp = mem.read_slice(RESP_BUF, SLOT_SIZE as usize);
+        assert_eq!(resp_rread_data(&resp), b"abcdefgh");
+    }
+
+    #[test]
+    fn test_host_backend_large_read_suspends_then_completes_via_background_pool() {
+        use std::fs;
+
+        let dir = std::env::temp_dir()
+            .join(format!("otoriscv_virtio9p_async_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let data: Vec<u8> = (0..8192u32).map(|i| (i % 251) as u8).collect();
+        fs::write(dir.join("big.bin"), &data).unwrap();
+
+        // Lower the threshold well below `data.len()` so the read exercises
+        // the background-pool path without needing a multi-hundred-KB
+        // fixture file.
+        let mut host_fs = super::host::HostFileSystem::new(dir.to_str().unwrap());
+        host_fs.set_async_threshold(1024);
+        let mut device = Virtio9p::new("test", Backend::Host(host_fs));
+
+        device.process_message(&build_tattach(0, 0));
+        device.process_message(&build_twalk(0, 0, 1, "big.bin"));
+        device.process_message(&build_tlopen(0, 1, O_RDONLY));
+
+        // The read is above threshold, so the very first attempt must
+        // suspend rather than complete inline - even though the data is
+        // already sitting on disk and could be read instantly - proving
+        // the request really did get handed off to the background pool
+        // instead of blocking here.
+        assert!(
+            device.process_message(&build_tread(0, 1, 0, data.len() as u32)).is_none(),
+            "a read above the async threshold should suspend on the first attempt"
+        );
+
+        // Poll for completion the same way `System::run` drains it every
+        // call, bounded so a stuck pool fails the test instead of hanging.
+        let mut resp = None;
+        for _ in 0..500 {
+            if let Some(r) = device.process_message(&build_tread(0, 1, 0, data.len() as u32)) {
+                resp = Some(r);
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        let resp = resp.expect("async host read never completed");
+        assert_eq!(resp_type(&resp), P9_RREAD);
+        assert_eq!(resp_rread_data(&resp), data);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_process_queues_respects_work_budget_and_resumes_next_call() {
+        let mut device = Virtio9p::new("test", Backend::InMemory(InMemoryFileSystem::new()));
+        let mut mem = Memory::new(1);
+        setup_queue(&mut device);
+        device.set_work_budget(1);
+
+        submit_request(&mut mem, 0, 0);
+        submit_request(&mut mem, 1, 1);
+        device.virtio.queue_notify_pending.push_back(0);
+
+        let processed = device.process_queues(&mut mem);
+        assert_eq!(processed, 1, "only one descriptor should be serviced under a budget of 1");
+        assert_eq!(device.virtio.queues[0].used_idx(&mem), 1);
+        assert_eq!(
+            device.virtio.queue_notify_pending.front(),
+            Some(&0),
+            "leftover work should re-queue the queue index for the next call"
+        );
+        assert!(device.virtio.take_notify_dirty(), "leftover work should re-set the dirty flag");
+
+        let processed = device.process_queues(&mut mem);
+        assert_eq!(processed, 1, "the second call should finish the remaining descriptor");
+        assert_eq!(device.virtio.queues[0].used_idx(&mem), 2);
+        assert!(device.virtio.queue_notify_pending.is_empty());
+    }
+
+    #[test]
+    fn test_p9_reader_reads_fields_in_order() {
+        let mut payload = Vec::new();
+        payload.push(0x42u8);
+        payload.extend_from_slice(&0x1234u16.to_le_bytes());
+        payload.extend_from_slice(&0xdead_beefu32.to_le_bytes());
+        payload.extend_from_slice(&0x0102_0304_0506_0708u64.to_le_bytes());
+        payload.extend_from_slice(&3u16.to_le_bytes());
+        payload.extend_from_slice(b"abc");
+
+        let mut r = P9Reader::new(&payload);
+        assert_eq!(r.read_u8(), Ok(0x42));
+        assert_eq!(r.read_u16(), Ok(0x1234));
+        assert_eq!(r.read_u32(), Ok(0xdead_beef));
+        assert_eq!(r.read_u64(), Ok(0x0102_0304_0506_0708));
+        assert_eq!(r.read_string(), Ok("abc".to_string()));
+        assert!(r.rest().is_empty());
+    }
+
+    #[test]
+    fn test_p9_reader_returns_einval_on_truncated_input() {
+        let payload = [0u8; 3];
+        let mut r = P9Reader::new(&payload);
+        assert_eq!(r.read_u32(), Err(EINVAL));
+
+        let mut r = P9Reader::new(&payload);
+        assert_eq!(r.read_u8(), Ok(0));
+        // Only 2 bytes left, but read_string's declared length wants 4 more.
+        let mut with_len = vec![4u8, 0u8];
+        with_len.extend_from_slice(b"ab");
+        let mut r = P9Reader::new(&with_len);
+        assert_eq!(r.read_string(), Err(EINVAL));
+    }
+
+    #[test]
+    fn test_p9_writer_finalize_backpatches_size() {
+        let mut w = P9Writer::new(P9_RATTACH, 7);
+        let qid = Qid::new(P9_QTFILE, 42);
+        w.write_qid(&qid);
+        let resp = w.finalize(8192).unwrap();
+
+        let size = u32::from_le_bytes([resp[0], resp[1], resp[2], resp[3]]);
+        assert_eq!(size as usize, resp.len());
+        assert_eq!(resp[4], P9_RATTACH);
+        assert_eq!(u16::from_le_bytes([resp[5], resp[6]]), 7);
+    }
+
+    #[test]
+    fn test_p9_writer_finalize_rejects_reply_over_msize() {
+        let mut w = P9Writer::new(P9_RVERSION, 1);
+        w.write_bytes(&[0u8; 32]);
+        assert_eq!(w.finalize(16), Err(ENOMEM));
+    }
+
+    #[test]
+    fn test_export_import_tar_round_trips_files_and_nested_dirs() {
+        let mut fs = InMemoryFileSystem::new();
+        let root = fs.attach().unwrap();
+        let dir = fs.mkdir(&root, "home", 0o40755).unwrap();
+        let sub = fs.mkdir(&dir, "user", 0o40755).unwrap();
+        let file = fs.create(&sub, "hello.txt", 0o100644, 0).unwrap();
+        fs.write(&file, 0, b"hello, overlay").unwrap();
+
+        let tar = fs.export_tar();
+
+        let mut restored = InMemoryFileSystem::new();
+        restored.import_tar(&tar).unwrap();
+
+        let root = restored.attach().unwrap();
+        let dir = restored.walk(&root, "home").unwrap();
+        let sub = restored.walk(&dir, "user").unwrap();
+        let file = restored.walk(&sub, "hello.txt").unwrap();
+        let data = restored.read(&file, 0, 64).unwrap();
+        assert_eq!(data, b"hello, overlay");
+    }
+
+    #[test]
+    fn test_import_tar_rejects_path_traversal() {
+        let mut fs = InMemoryFileSystem::new();
+        let tar = fs.export_tar(); // empty archive as a base to tamper with
+
+        let mut evil_header = [0u8; 512];
+        let name = b"../escape";
+        evil_header[..name.len()].copy_from_slice(name);
+        evil_header[100..107].copy_from_slice(b"0000644"); // mode
+        evil_header[156] = b'0'; // regular file, size 0
+
+        let mut evil_tar = evil_header.to_vec();
+        evil_tar.extend_from_slice(&tar);
+
+        assert!(fs.import_tar(&evil_tar).is_err());
+    }
+}
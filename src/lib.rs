@@ -6,12 +6,15 @@
 use wasm_bindgen::prelude::*;
 
 pub mod cpu;
-mod memory;
-mod devices;
+pub mod memory;
+pub mod devices;
 mod system;
 pub mod snapshot;
+pub mod replay;
+pub mod rng;
 mod system64;
-pub use system::System;
+pub use system::{System, PanicEvent, BootMilestone, ProfileSample, ProfileBucket, aggregate_profile_samples, TimingModel, TohostResult, RunStopReason, ChunkedRunResult, RunOutcome, HaltReason, SystemPowerState, InputCrlfMode, IsaConfig, SbiMode};
+use crate::devices::TxOverflowPolicy;
 pub use system64::System64;
 
 
@@ -43,6 +46,27 @@ pub fn error(s: &str) {
     eprintln!("ERROR: {}", s);
 }
 
+/// Monotonic milliseconds from some arbitrary but fixed reference point -
+/// only differences between calls are meaningful. Backed by
+/// `performance.now()` in the browser and `Instant` natively, so
+/// `run_for_ms` can budget wall-clock time on either target.
+#[cfg(target_arch = "wasm32")]
+fn now_ms() -> f64 {
+    web_sys::window()
+        .and_then(|w| w.performance())
+        .map(|p| p.now())
+        .unwrap_or(0.0)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now_ms() -> f64 {
+    use std::sync::OnceLock;
+    use std::time::Instant;
+
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_secs_f64() * 1000.0
+}
+
 /// Helper macro for console logging
 #[macro_export]
 macro_rules! console_log {
@@ -68,35 +92,214 @@ impl Emulator {
         console_log!("Creating RISC-V emulator with {}MB RAM", memory_size_mb);
         
         let system = System::new(memory_size_mb, None)
-            .map_err(|e| JsValue::from_str(&e))?;
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
         
         Ok(Emulator { system })
     }
-    
+
+    /// Build and boot a machine from a JS object shaped like
+    /// `System::MachineConfig` - `{ ram_mb, kernel, initrd, cmdline,
+    /// jit_v2, fs, rng_seed }` - instead of hand-sequencing `new`,
+    /// `setup_linux*`, `enable_jit_v2`, and filesystem setup. See
+    /// `MachineConfig`'s docs for field defaults and `fs`'s shape.
+    pub fn from_config(config: JsValue) -> Result<Emulator, JsValue> {
+        let config: crate::system::MachineConfig = serde_wasm_bindgen::from_value(config)
+            .map_err(|e| JsValue::from_str(&format!("invalid machine config: {}", e)))?;
+        let system = System::from_config(&config)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(Emulator { system })
+    }
+
     /// Load kernel binary into RAM at specified address
     pub fn load_kernel(&mut self, data: &[u8], load_addr: u32) -> Result<(), JsValue> {
         self.system.load_binary(data, load_addr)
-            .map_err(|e| JsValue::from_str(&e))
+            .map_err(|e| JsValue::from_str(&e.to_string()))
     }
-    
+
+    /// Replace the boot ROM contents, e.g. with a real OpenSBI binary,
+    /// instead of the built-in Rust SBI stub. Pair with `set_reset_pc` if
+    /// the replacement's entry point isn't at the start of the ROM window.
+    pub fn load_boot_rom(&mut self, data: &[u8]) -> Result<(), JsValue> {
+        self.system.load_boot_rom(data)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Set the PC the CPU starts at after reset/boot.
+    pub fn set_reset_pc(&mut self, addr: u32) {
+        self.system.set_reset_pc(addr);
+    }
+
+    /// Choose how ecall-from-S is handled: pass `true` to let it trap to
+    /// `mtvec` like real hardware, for developing against a real SBI
+    /// implementation loaded via `load_boot_rom`; `false` (the default)
+    /// answers it directly in Rust.
+    pub fn set_sbi_mode(&mut self, firmware: bool) {
+        self.system.set_sbi_mode(if firmware { SbiMode::Firmware } else { SbiMode::Native });
+    }
+
     /// Setup Linux boot (generates DTB and sets up registers)
     pub fn setup_linux(&mut self, kernel: &[u8], cmdline: &str) -> Result<(), JsValue> {
         self.system.setup_linux_boot(kernel, cmdline)
-            .map_err(|e| JsValue::from_str(&e))
+            .map_err(|e| JsValue::from_str(&e.to_string()))
     }
     
     /// Setup Linux boot with initrd (generates DTB and sets up registers)
     pub fn setup_linux_with_initrd(&mut self, kernel: &[u8], initrd: &[u8], cmdline: &str) -> Result<(), JsValue> {
         self.system.setup_linux_boot_with_initrd(kernel, Some(initrd), cmdline)
-            .map_err(|e| JsValue::from_str(&e))
+            .map_err(|e| JsValue::from_str(&e.to_string()))
     }
-    
+
+    /// Begin a streaming load of a kernel or initrd image ("kernel" or
+    /// "initrd") of `total_size` bytes, avoiding the need to buffer the
+    /// whole image as one JS `ArrayBuffer` before copying it into guest RAM.
+    /// Follow with repeated `load_chunk` calls, then `finish_load`.
+    pub fn begin_load(&mut self, target: &str, total_size: u32) -> Result<(), JsValue> {
+        self.system.begin_load(target, total_size)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Write one chunk of the in-progress streaming load at `offset` bytes
+    /// into the image. Chunks may arrive out of order.
+    pub fn load_chunk(&mut self, offset: u32, chunk: &[u8]) -> Result<(), JsValue> {
+        self.system.load_chunk(offset, chunk)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Finish the in-progress streaming load started by `begin_load`.
+    pub fn finish_load(&mut self) -> Result<(), JsValue> {
+        self.system.finish_load()
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Finish Linux boot setup (DTB + boot registers) for a kernel (and
+    /// optional initrd) loaded via `begin_load`/`load_chunk`/`finish_load`.
+    pub fn setup_linux_boot_streamed(&mut self, cmdline: &str) -> Result<(), JsValue> {
+        self.system.setup_linux_boot_streamed(cmdline)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Like `setup_linux_with_initrd`, but boots from a caller-supplied DTB
+    /// instead of generating one, for devices we don't generate nodes for.
+    pub fn setup_linux_with_dtb(&mut self, kernel: &[u8], initrd: Option<Vec<u8>>, dtb_bytes: &[u8]) -> Result<(), JsValue> {
+        self.system.setup_linux_boot_with_dtb(kernel, initrd.as_deref(), dtb_bytes)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// The last DTB loaded for the guest, or an empty array if none yet.
+    pub fn get_dtb(&self) -> Vec<u8> {
+        self.system.get_dtb()
+    }
+
+    /// Human-readable dump of `get_dtb`'s node/property tree.
+    pub fn get_dtb_text(&self) -> String {
+        self.system.get_dtb_text()
+    }
+
+
     /// Run the emulator for a specified number of cycles
     /// Returns the number of cycles actually executed
     pub fn run(&mut self, cycles: u32) -> u32 {
         self.system.run(cycles)
     }
-    
+
+    /// Like `run`, but returns `[cyclesExecuted, reason]` where `reason` is
+    /// one of `"completed"`, `"wfi"`, `"poweredOff"`, `"rebootRequested"`,
+    /// `"breakpoint"`, `"pcZero"`, `"trap"`, `"trapLoop"`, `"limitReached"`
+    /// - `"breakpoint"` also carries the trapping address as a third
+    /// element. Lets embedders react appropriately (e.g. stop polling on
+    /// `"poweredOff"`) without a separate accessor call.
+    pub fn run_reason(&mut self, cycles: u32) -> Box<[JsValue]> {
+        let (executed, reason) = self.system.run_with_reason(cycles);
+        let mut out = vec![JsValue::from_f64(executed as f64)];
+        match reason {
+            HaltReason::Completed => out.push(JsValue::from_str("completed")),
+            HaltReason::Wfi => out.push(JsValue::from_str("wfi")),
+            HaltReason::PoweredOff => out.push(JsValue::from_str("poweredOff")),
+            HaltReason::RebootRequested => out.push(JsValue::from_str("rebootRequested")),
+            HaltReason::Breakpoint(addr) => {
+                out.push(JsValue::from_str("breakpoint"));
+                out.push(JsValue::from_f64(addr as f64));
+            }
+            HaltReason::PcZero => out.push(JsValue::from_str("pcZero")),
+            HaltReason::Trap => out.push(JsValue::from_str("trap")),
+            HaltReason::TrapLoop => out.push(JsValue::from_str("trapLoop")),
+            HaltReason::LimitReached => out.push(JsValue::from_str("limitReached")),
+            HaltReason::Stuck => out.push(JsValue::from_str("stuck")),
+        }
+        out.into_boxed_slice()
+    }
+
+    /// Set (or clear, passing `None`) a hard ceiling on the total number of
+    /// instructions this emulator will ever retire, enforced across every
+    /// `run`/`run_reason` call rather than just within one - see
+    /// `System::set_instruction_limit`.
+    pub fn set_instruction_limit(&mut self, limit: Option<u32>) {
+        self.system.set_instruction_limit(limit.map(|l| l as u64));
+    }
+
+    /// Set (or clear, passing `None`) the stuck-loop detector: `run_reason`
+    /// reports `"stuck"` once the PC has stayed within a small range with no
+    /// device I/O for `threshold` consecutive instructions - see
+    /// `System::set_stuck_detector`.
+    pub fn set_stuck_detector(&mut self, threshold: Option<u32>) {
+        self.system.set_stuck_detector(threshold);
+    }
+
+    /// Current guest power state: `"running"`, `"shutdown"`, or
+    /// `"rebootRequested"`.
+    pub fn get_power_state(&self) -> String {
+        match self.system.power_state() {
+            SystemPowerState::Running => "running".to_string(),
+            SystemPowerState::Shutdown => "shutdown".to_string(),
+            SystemPowerState::RebootRequested => "rebootRequested".to_string(),
+        }
+    }
+
+    /// If `true`, a guest-requested reboot is handled internally (reloading
+    /// the captured boot images) instead of surfacing as `"rebootRequested"`
+    /// from `run_reason`.
+    pub fn set_auto_reboot(&mut self, enable: bool) {
+        self.system.set_auto_reboot(enable);
+    }
+
+    /// Run cooperatively in chunks of `chunk_cycles`, calling
+    /// `should_continue` once per chunk boundary (not per instruction) so
+    /// the browser event loop stays responsive on long runs. Returning
+    /// `false` from `should_continue` (e.g. because `performance.now()` is
+    /// close to the frame deadline) stops early.
+    ///
+    /// Returns `[cyclesExecuted, reason]` where `reason` is one of
+    /// `"budget"`, `"callback"`, `"panic"`, `"exited"`, `"tohost"`.
+    pub fn run_chunked(&mut self, total_cycles: u32, chunk_cycles: u32, should_continue: js_sys::Function) -> Box<[JsValue]> {
+        let this = JsValue::null();
+        let result = self.system.run_chunked(total_cycles, chunk_cycles, || {
+            should_continue.call0(&this)
+                .map(|v| v.as_bool().unwrap_or(true))
+                .unwrap_or(true)
+        });
+
+        let reason = match result.reason {
+            RunStopReason::Budget => "budget",
+            RunStopReason::Callback => "callback",
+            RunStopReason::Panic => "panic",
+            RunStopReason::Exited => "exited",
+            RunStopReason::Tohost => "tohost",
+        };
+        vec![JsValue::from_f64(result.cycles as f64), JsValue::from_str(reason)].into_boxed_slice()
+    }
+
+    /// Run for roughly `budget_ms` of wall-clock time rather than a fixed
+    /// cycle count, so the caller doesn't need to guess a cycle budget as
+    /// guest IPS varies. Checks elapsed time once per chunk boundary (like
+    /// `run_chunked`), so actual overshoot is bounded by one chunk's worth
+    /// of execution rather than being exact. Returns instructions executed.
+    pub fn run_for_ms(&mut self, budget_ms: f64) -> u32 {
+        const CHUNK_CYCLES: u32 = 10_000;
+        let deadline = now_ms() + budget_ms;
+        let result = self.system.run_chunked(u32::MAX, CHUNK_CYCLES, || now_ms() < deadline);
+        result.cycles
+    }
+
     /// Enable or disable JIT v2 (advanced page-based JIT with CFG optimization)
     pub fn enable_jit_v2(&mut self, enable: bool) {
         self.system.enable_jit_v2(enable);
@@ -106,17 +309,279 @@ impl Emulator {
     pub fn is_halted(&self) -> bool {
         self.system.is_halted()
     }
-    
-    /// Send a character to UART (keyboard input)
+
+    /// Exit code set by a guest semihosting `SYS_EXIT` call, or `null` if
+    /// the guest hasn't exited.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.system.exit_code()
+    }
+
+    /// Raise or clear an arbitrary PLIC interrupt source line, as if an
+    /// external device asserted/deasserted it. Useful for testing guest
+    /// interrupt handling without wiring up a real device.
+    pub fn inject_irq(&mut self, source: u32, level: bool) {
+        self.system.inject_irq(source, level);
+    }
+
+    /// Map a device at `[base, base+size)` whose reads and writes call
+    /// back into JavaScript: `read_cb(offset) -> number` for every byte
+    /// read in range, `write_cb(offset, value)` for every byte write.
+    /// Both run synchronously within whichever `run`/`run_reason` call
+    /// triggered the access - don't block or run long-lived work in them.
+    /// A guest access that re-enters this same device from inside a
+    /// callback is dropped rather than re-entering it (see
+    /// `devices::callback::CallbackDevice`).
+    pub fn register_mmio_device(&mut self, base: u32, size: u32, read_cb: js_sys::Function, write_cb: js_sys::Function) {
+        // `CallbackDevice::new` requires `Send` closures (see its doc
+        // comment for why), but `js_sys::Function`/`JsValue` aren't `Send`
+        // in the wasm-bindgen version this crate is pinned to. That's fine
+        // here specifically: `wasm32` (the only target this method is ever
+        // actually called from) has no real threads, so these closures are
+        // never invoked anywhere but the one thread that registered them -
+        // `AssertSend` makes that single, narrow assumption explicit
+        // instead of asserting it for the whole `CallbackDevice` type.
+        struct AssertSend<T>(T);
+        unsafe impl<T> Send for AssertSend<T> {}
+        impl<T: FnMut(u32) -> u8> AssertSend<T> {
+            // A method call (rather than a `.0` field projection) forces
+            // Rust 2021's disjoint closure capture to take the whole
+            // `AssertSend` wrapper, not just the field inside it - capturing
+            // the field directly would silently capture the un-`Send`
+            // closure itself instead of the `Send`-asserting wrapper.
+            fn call_read(&mut self, offset: u32) -> u8 {
+                (self.0)(offset)
+            }
+        }
+        impl<T: FnMut(u32, u8)> AssertSend<T> {
+            fn call_write(&mut self, offset: u32, value: u8) {
+                (self.0)(offset, value)
+            }
+        }
+
+        let read_this = JsValue::null();
+        let mut read_state = AssertSend(move |offset: u32| -> u8 {
+            read_cb
+                .call1(&read_this, &JsValue::from_f64(offset as f64))
+                .ok()
+                .and_then(|v| v.as_f64())
+                .unwrap_or(0.0) as u8
+        });
+        let read = move |offset: u32| -> u8 { read_state.call_read(offset) };
+
+        let write_this = JsValue::null();
+        let mut write_state = AssertSend(move |offset: u32, value: u8| {
+            let _ = write_cb.call2(
+                &write_this,
+                &JsValue::from_f64(offset as f64),
+                &JsValue::from_f64(value as f64),
+            );
+        });
+        let write = move |offset: u32, value: u8| write_state.call_write(offset, value);
+
+        self.system.add_mmio_device(
+            Box::new(crate::devices::CallbackDevice::new(read, write)),
+            base,
+            size,
+        );
+    }
+
+    /// Enable/disable guest panic detection (Linux "Kernel panic -"/"Oops:"/"BUG:")
+    pub fn set_panic_detection(&mut self, enabled: bool) {
+        self.system.set_panic_detection(enabled);
+    }
+
+    /// Configure custom panic/oops patterns to scan for, e.g. for non-Linux
+    /// guests. Pass an empty array to disable scanning.
+    pub fn set_panic_patterns(&mut self, patterns: Vec<String>) {
+        self.system.set_panic_patterns(patterns);
+    }
+
+    /// Configure (or, passing all zeros, disable) a rough memory-latency
+    /// model: extra cycles charged per RAM access, per MMIO device access,
+    /// and per MMU TLB miss, folded into the guest-visible cycle counter
+    /// and CLINT `mtime`. Functional behavior is unchanged - only how fast
+    /// guest time appears to pass.
+    pub fn set_timing_model(&mut self, ram_cycles: u32, mmio_cycles: u32, tlb_miss_cycles: u32) {
+        self.system.set_timing_model(ram_cycles, mmio_cycles, tlb_miss_cycles);
+    }
+
+    /// Tune the JIT block-size cap, whether loads/stores split a block, and
+    /// v2's compile threshold, for measuring the IPS/compile-time tradeoff.
+    /// See `JitConfig`.
+    pub fn set_jit_config(&mut self, max_block_size: u32, split_on_mmio: bool, threshold: u32) {
+        self.system.set_jit_config(crate::cpu::rv32::jit::JitConfig {
+            max_block_size: max_block_size as usize,
+            split_on_mmio,
+            threshold,
+        });
+    }
+
+    /// Take the most recently detected panic event as `[pattern, context_text, pc, instruction_count]`,
+    /// or `undefined` if none has been detected since the last call.
+    pub fn take_panic_event(&mut self) -> JsValue {
+        match self.system.take_panic_event() {
+            Some(event) => {
+                let obj = js_sys::Object::new();
+                js_sys::Reflect::set(&obj, &"pattern".into(), &event.pattern.into()).ok();
+                js_sys::Reflect::set(&obj, &"context".into(), &String::from_utf8_lossy(&event.context).into_owned().into()).ok();
+                js_sys::Reflect::set(&obj, &"pc".into(), &event.pc.into()).ok();
+                js_sys::Reflect::set(&obj, &"instructionCount".into(), &(event.instruction_count as f64).into()).ok();
+                obj.into()
+            }
+            None => JsValue::undefined(),
+        }
+    }
+
+    /// Enable or disable recording of `IllegalInstruction` traps - see
+    /// `get_illegal_instructions`.
+    pub fn set_illegal_instruction_log(&mut self, enabled: bool) {
+        self.system.set_illegal_instruction_log(enabled);
+    }
+
+    /// Drain the illegal-instruction log as objects
+    /// `{pc, rawInst, opcode, funct3, funct7, count}`, most-hit encoding
+    /// first, so porting effort can be pointed at whatever's missing and
+    /// actually being hit. Empty unless `set_illegal_instruction_log(true)`
+    /// was called first.
+    pub fn get_illegal_instructions(&mut self) -> Vec<JsValue> {
+        self.system.take_illegal_instructions().into_iter().map(|r| {
+            let obj = js_sys::Object::new();
+            js_sys::Reflect::set(&obj, &"pc".into(), &r.pc.into()).ok();
+            js_sys::Reflect::set(&obj, &"rawInst".into(), &r.raw_inst.into()).ok();
+            js_sys::Reflect::set(&obj, &"opcode".into(), &r.opcode.into()).ok();
+            js_sys::Reflect::set(&obj, &"funct3".into(), &r.funct3.into()).ok();
+            js_sys::Reflect::set(&obj, &"funct7".into(), &r.funct7.into()).ok();
+            js_sys::Reflect::set(&obj, &"count".into(), &(r.count as f64).into()).ok();
+            obj.into()
+        }).collect()
+    }
+
+    /// Instructions retired so far in each privilege level, as
+    /// `[user, supervisor, machine]` - see
+    /// `System::privilege_instruction_counts`. Useful for spotting e.g.
+    /// excessive M-mode SBI handling during boot.
+    pub fn get_privilege_counts(&self) -> Vec<JsValue> {
+        self.system.privilege_instruction_counts()
+            .into_iter()
+            .map(|count| JsValue::from_f64(count as f64))
+            .collect()
+    }
+
+    /// Send a character to UART 0 (keyboard input)
     pub fn send_char(&mut self, c: u8) {
         self.system.uart_receive(c);
     }
+
+    /// Send a character to the UART at `idx`, e.g. a dedicated channel
+    /// mapped for `console=ttyS1`.
+    pub fn send_char_to(&mut self, idx: u32, c: u8) {
+        self.system.uart_receive_on(idx as usize, c);
+    }
+
+    /// Queue bytes (e.g. a pasted block of text) for gradual delivery to the
+    /// UART, instead of overrunning its FIFO with `send_char` calls.
+    pub fn queue_input(&mut self, bytes: Vec<u8>) {
+        self.system.queue_input(bytes);
+    }
+
+    /// Bytes queued via `queue_input` not yet delivered to the UART, so the
+    /// host can throttle further input.
+    pub fn input_pending(&self) -> u32 {
+        self.system.input_pending()
+    }
+
+    /// Queue `text` for gradual delivery to the UART, same as `queue_input`
+    /// but taking a JS string directly - the paste-friendly name the
+    /// terminal UI's clipboard handler calls.
+    pub fn paste_text(&mut self, text: String) {
+        self.system.queue_input(text.into_bytes());
+    }
+
+    /// Bytes queued by `paste_text` (or `queue_input`) not yet delivered to
+    /// the UART, so the UI can show paste progress.
+    pub fn pending_paste_bytes(&self) -> u32 {
+        self.system.input_pending()
+    }
+
+    /// Discard a paste in progress, e.g. because the user hit Ctrl-C in the
+    /// terminal UI before it finished delivering.
+    pub fn cancel_paste(&mut self) {
+        self.system.cancel_input();
+    }
+
+    /// Throttle paste delivery to roughly `chars_per_ms` guest
+    /// milliseconds instead of as fast as the UART FIFO drains, to mimic
+    /// human typing for a guest whose line discipline drops fast pastes.
+    /// Pass `null`/`undefined` to go back to unthrottled delivery.
+    pub fn set_paste_rate(&mut self, chars_per_ms: Option<f64>) {
+        self.system.set_paste_rate(chars_per_ms);
+    }
+
+    /// Set line-ending translation applied to bytes sent via `send_char`/
+    /// `queue_input`: one of `"none"`, `"cr_to_lf"`, `"lf_to_cr"`. Unknown
+    /// values are treated as `"none"`.
+    pub fn set_input_crlf_mode(&mut self, mode: &str) {
+        let mode = match mode {
+            "cr_to_lf" => InputCrlfMode::CrToLf,
+            "lf_to_cr" => InputCrlfMode::LfToCr,
+            _ => InputCrlfMode::None,
+        };
+        self.system.set_input_crlf_mode(mode);
+    }
     
-    /// Get pending UART output
+    /// Get pending output from UART 0
     pub fn get_uart_output(&mut self) -> Vec<u8> {
         self.system.uart_get_output()
     }
-    
+
+    /// Get pending output from the UART at `idx`
+    pub fn get_uart_output_from(&mut self, idx: u32) -> Vec<u8> {
+        self.system.uart_get_output_on(idx as usize)
+    }
+
+    /// Number of bytes pending on UART 0, so callers can size a buffer
+    /// before calling `drain_uart_into`.
+    pub fn uart_output_len(&self) -> u32 {
+        self.system.uart_output_len() as u32
+    }
+
+    /// Drain pending UART 0 output directly into the caller's wasm memory
+    /// at `ptr`, up to `len` bytes, without allocating a JS-side `Vec` the
+    /// way `get_uart_output` does. Returns the number of bytes written.
+    /// Intended for hot polling loops where `get_uart_output`'s per-call
+    /// allocation shows up.
+    #[cfg(target_arch = "wasm32")]
+    pub fn drain_uart_into(&mut self, ptr: u32, len: u32) -> u32 {
+        let mut buf = vec![0u8; len as usize];
+        let n = self.system.uart_drain_into(&mut buf);
+        let memory = wasm_bindgen::memory()
+            .dyn_into::<js_sys::WebAssembly::Memory>()
+            .unwrap();
+        let mem_view = js_sys::Uint8Array::new(&memory.buffer());
+        mem_view.set(&js_sys::Uint8Array::from(&buf[..n]), ptr);
+        n as u32
+    }
+
+    /// Configure UART 0's TX buffer cap and overflow policy, so a guest
+    /// that floods output with nobody draining it (backgrounded tab,
+    /// stalled consumer) can't grow host memory without bound. `policy` is
+    /// one of `"backpressure"` (default - report the transmitter busy once
+    /// full) or `"drop_oldest"` (evict the oldest byte, tracked by
+    /// `uart_tx_dropped`). Unknown values are treated as `"backpressure"`.
+    pub fn set_uart_tx_overflow_policy(&mut self, capacity: u32, policy: &str) {
+        let policy = match policy {
+            "drop_oldest" => TxOverflowPolicy::DropOldest,
+            _ => TxOverflowPolicy::Backpressure,
+        };
+        self.system.set_uart_tx_overflow_policy(capacity as usize, policy);
+    }
+
+    /// Bytes evicted from UART 0's TX buffer by the `"drop_oldest"`
+    /// overflow policy so far.
+    pub fn uart_tx_dropped(&self) -> u64 {
+        self.system.uart_tx_dropped()
+    }
+
     /// Get current PC for debugging
     pub fn get_pc(&self) -> u32 {
         self.system.get_pc()
@@ -133,14 +598,65 @@ impl Emulator {
     pub fn get_registers(&self) -> Vec<u32> {
         self.system.get_registers()
     }
-    
+
+    /// Cheap fingerprint of guest RAM for determinism checks - see
+    /// `System::ram_hash`.
+    pub fn ram_hash(&self) -> u64 {
+        self.system.ram_hash()
+    }
+
     pub fn read_memory(&self, addr: u32, size: u32) -> Vec<u8> {
         self.system.read_memory(addr, size)
     }
-    
+
+    /// Dump the flat contents of guest RAM - see `System::dump_ram`.
+    pub fn dump_ram(&self) -> Vec<u8> {
+        self.system.dump_ram()
+    }
+
+    /// Restore guest RAM from a `dump_ram` image - see `System::load_ram`.
+    pub fn load_ram(&mut self, data: &[u8]) -> Result<(), JsValue> {
+        self.system.load_ram(data).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Pointer to a zero-copy snapshot of guest RAM, for a live memory view
+    /// or framebuffer-over-RAM rendering without paying `read_memory`'s
+    /// per-call copy at high frame rates. Call this before every read to
+    /// resync dirtied pages first - see `Memory::sync_ram_view` for the sync
+    /// contract (it only re-copies pages the guest has touched, not all of
+    /// `ram_len()`).
+    ///
+    /// # Safety caveats for the JS side
+    /// - RAM is not wasm linear memory passed through directly - it's
+    ///   lazily paged on the Rust side (see `Memory::ram_pages`) so that
+    ///   untouched guest RAM is never allocated - so this points at an
+    ///   internally maintained snapshot buffer, not the guest's pages
+    ///   themselves. Reads against it are only as fresh as the last
+    ///   `ram_ptr()` call.
+    /// - The pointer is invalidated by anything that reallocates or
+    ///   replaces this `Emulator`'s `System` - restoring a snapshot,
+    ///   `reboot()`-ing, or the `Emulator` itself being dropped. Re-fetch
+    ///   it after any such call rather than caching it across one.
+    /// - `ram_len()` is constant for the lifetime of a given `Emulator`
+    ///   (RAM size is fixed at construction), so it only needs reading once.
+    pub fn ram_ptr(&mut self) -> *const u8 {
+        self.system.sync_ram_view().0
+    }
+
+    /// Length in bytes of the buffer `ram_ptr()` points to - see `ram_ptr`.
+    pub fn ram_len(&self) -> usize {
+        self.system.ram_size()
+    }
+
     pub fn reset(&mut self) {
         self.system.reset();
     }
+
+    /// Reseed the emulator's RNG, so two `Emulator`s seeded the same way and
+    /// driven by the same calls produce identical random output.
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        self.system.set_rng_seed(seed);
+    }
     
     /// Get missing blobs (SHA256 hashes) that need to be fetched
     pub fn get_missing_blobs(&self) -> Box<[JsValue]> {
@@ -155,28 +671,150 @@ impl Emulator {
     pub fn provide_blob(&mut self, hash: String, data: Vec<u8>) {
         self.system.provide_blob(hash, data);
     }
-    
+
+    /// Diagnostics for the 9p device - open fid count, suspended requests
+    /// (with the blob hash each is waiting on), and missing blobs. For an
+    /// embedder to show when a guest filesystem access looks stuck.
+    pub fn get_9p_state(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.system.get_9p_debug_state())
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Service devices (retry suspended 9p requests, pump virtio queues,
+    /// refresh interrupts, drain UART output) without executing any CPU
+    /// instructions. For when the UI has paused the guest but still needs
+    /// e.g. a 9p read to complete after `provide_blob` supplies the blob
+    /// it was waiting on.
+    pub fn service_devices(&mut self) -> Vec<u8> {
+        self.system.service_devices()
+    }
+
+    /// Whether the guest has requested a shutdown and the 9p filesystem
+    /// overlay still needs to be persisted - see `take_filesystem_overlay`.
+    pub fn poweroff_persist_pending(&self) -> bool {
+        self.system.poweroff_persist_pending()
+    }
+
+    /// Flush in-flight 9p writes and return a snapshot of the in-memory 9p
+    /// filesystem overlay to persist (e.g. to IndexedDB) before the page
+    /// drops this `Emulator` on guest poweroff. Empty if there's nothing to
+    /// persist - see `System::take_filesystem_overlay`.
+    pub fn take_filesystem_overlay(&mut self) -> Vec<u8> {
+        self.system.take_filesystem_overlay()
+    }
+
+    /// Restore a 9p filesystem overlay produced by `take_filesystem_overlay`,
+    /// e.g. one persisted on a previous poweroff, before booting.
+    pub fn load_filesystem_overlay(&mut self, data: &[u8]) -> Result<(), JsValue> {
+        self.system.load_filesystem_overlay(data)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Instantiate any JIT v1 basic blocks whose WASM bytecode is ready
+    /// but hasn't been compiled yet, off the critical path. Synchronously
+    /// compiling a WASM module past a few KB is something most browsers
+    /// refuse to do on the main thread, which is why `BlockCache` only
+    /// ever queues bytecode instead of instantiating it inline.
+    ///
+    /// Resolves to a value that must be passed to
+    /// `apply_compiled_wasm_blocks` to actually install the results -
+    /// this method can't do that itself, since installing needs `&mut
+    /// self` and the future outlives this call returning its `Promise`.
+    /// Until installed, the affected blocks keep running through the
+    /// interpreter.
+    #[cfg(target_arch = "wasm32")]
+    pub fn jit_compile_pending(&mut self) -> js_sys::Promise {
+        let pending = self.system.take_pending_wasm_compiles();
+        wasm_bindgen_futures::future_to_promise(async move {
+            let mut results = Vec::with_capacity(pending.len());
+            for (paddr, bytecode) in pending {
+                let module_id = cpu::rv32::jit::v1::codegen::runtime::CompiledWasmBlock::compile_async(&bytecode).await;
+                results.push((paddr, module_id));
+            }
+            serde_wasm_bindgen::to_value(&results).map_err(|e| JsValue::from_str(&e.to_string()))
+        })
+    }
+
+    /// Install the WASM modules `jit_compile_pending`'s promise resolved
+    /// to. Blocks invalidated in the meantime (e.g. by a FENCE.I) are
+    /// silently dropped rather than installed.
+    #[cfg(target_arch = "wasm32")]
+    pub fn apply_compiled_wasm_blocks(&mut self, results: JsValue) -> Result<(), JsValue> {
+        let results: Vec<(u32, Option<u32>)> = serde_wasm_bindgen::from_value(results)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        for (paddr, module_id) in results {
+            self.system.install_compiled_wasm_block(paddr, module_id);
+        }
+        Ok(())
+    }
+
+    /// JIT v1 WASM backend counters, as `[pending, compiled, failed]`. For
+    /// diagnosing whether a hot loop's blocks are actually making it
+    /// through `jit_compile_pending`/`apply_compiled_wasm_blocks` and into
+    /// compiled WASM, versus still falling back to the interpreter.
+    #[cfg(target_arch = "wasm32")]
+    pub fn wasm_jit_stats(&self) -> Vec<u32> {
+        let (pending, compiled, failed) = self.system.wasm_jit_stats();
+        vec![pending as u32, compiled as u32, failed as u32]
+    }
+
+    /// Search guest RAM for a byte pattern, for cheat-engine-style "find this
+    /// value" tooling. Capped at `MAX_SEARCH_RESULTS` matches - a search for
+    /// something like a lone zero byte can otherwise match millions of
+    /// addresses, which is a lot to marshal across the JS boundary.
+    pub fn search_memory(&self, pattern: &[u8], start: u32, end: u32, alignment: u32) -> MemorySearchResult {
+        MemorySearchResult::from_all(self.system.search_memory(pattern, start, end, alignment))
+    }
+
+    /// Like `search_memory`, but for a `u32` value read back the way the
+    /// guest itself would with `read32` (little-endian).
+    pub fn search_memory_u32(&self, value: u32, start: u32, end: u32, alignment: u32) -> MemorySearchResult {
+        MemorySearchResult::from_all(self.system.search_memory_u32(value, start, end, alignment))
+    }
+
+    /// Narrow a previous search's addresses down to the ones that still hold
+    /// `value` - the "value changed to X" step of a cheat-engine-style
+    /// search, without re-scanning all of RAM.
+    pub fn refine_memory_search_u32(&self, addresses: Vec<u32>, value: u32) -> MemorySearchResult {
+        MemorySearchResult::from_all(self.system.refine_memory_search_u32(&addresses, value))
+    }
+
     /// Serialize the entire emulator state to a binary blob (compressed with Zstd)
     pub fn get_state(&self) -> Result<Vec<u8>, JsValue> {
-        let serialized = bincode::serialize(&self.system)
-            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))?;
-            
-        // Level 0 is default compression
-        zstd::stream::encode_all(&serialized[..], 0)
-            .map_err(|e| JsValue::from_str(&format!("Compression error: {}", e)))
+        self.system.to_state_bytes()
+            .map_err(|e| JsValue::from_str(&e.to_string()))
     }
-    
+
     /// Restore the emulator state from a binary blob (compressed with Zstd)
     pub fn set_state(&mut self, state: &[u8]) -> Result<(), JsValue> {
-        let decompressed = zstd::stream::decode_all(state)
-             .map_err(|e| JsValue::from_str(&format!("Decompression error: {}", e)))?;
-             
-        let system: System = bincode::deserialize(&decompressed)
-            .map_err(|e| JsValue::from_str(&format!("Deserialization error: {}", e)))?;
+        let system = System::from_state_bytes(state)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
         self.system = system;
         Ok(())
     }
-    
+
+    /// Begin recording UART input and blob provisioning so a later bug can
+    /// be captured as a reproducible replay file with `stop_recording`.
+    pub fn start_recording(&mut self) -> Result<(), JsValue> {
+        self.system.start_recording()
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Stop recording and return a replay file for `Emulator::replay`.
+    pub fn stop_recording(&mut self) -> Result<Vec<u8>, JsValue> {
+        self.system.stop_recording()
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Reconstruct and re-run a session captured by `start_recording`/
+    /// `stop_recording`, replacing this emulator's state with the result.
+    pub fn replay(&mut self, replay_data: &[u8]) -> Result<(), JsValue> {
+        let system = System::replay(replay_data)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.system = system;
+        Ok(())
+    }
+
     /// Create a lightweight snapshot (CPU + devices + dirty pages only)
     /// 
     /// This is much smaller than get_state() (~100KB vs ~5MB) because it doesn't
@@ -189,18 +827,115 @@ impl Emulator {
         let initrd_opt = if initrd_size > 0 { Some(initrd_size) } else { None };
         let snapshot = self.system.create_snapshot(kernel_size, initrd_opt);
         snapshot.to_bytes()
-            .map_err(|e| JsValue::from_str(&e))
+            .map_err(|e| JsValue::from_str(&e.to_string()))
     }
     
     /// Restore from a lightweight snapshot
-    /// 
+    ///
     /// The same kernel/initrd must already be loaded using setup_linux_with_initrd()
-    /// before calling this method.
+    /// before calling this method; this is checked by content hash (along with
+    /// RAM size, cmdline, and format version) and fails instead of silently
+    /// restoring against the wrong boot image.
     pub fn restore_snapshot(&mut self, snapshot_data: &[u8]) -> Result<(), JsValue> {
         let snapshot = snapshot::LightweightSnapshot::from_bytes(snapshot_data)
-            .map_err(|e| JsValue::from_str(&e))?;
-        self.system.restore_snapshot(&snapshot);
-        Ok(())
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.system.restore_snapshot(&snapshot)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Begin a chunked snapshot for a caller that wants to hand it off to
+    /// something size-limited per write, like IndexedDB, instead of holding
+    /// the whole `create_snapshot`/`to_bytes` blob in memory at once. Follow
+    /// with repeated `snapshot_next_chunk` calls; the emulator can keep
+    /// running in between them.
+    pub fn snapshot_begin(&mut self, kernel_size: u32, initrd_size: u32) {
+        let initrd_opt = if initrd_size > 0 { Some(initrd_size) } else { None };
+        self.system.begin_snapshot_stream(kernel_size, initrd_opt);
+    }
+
+    /// Pull the next chunk from the stream started by `snapshot_begin`, or
+    /// `null` once the stream is exhausted. `max_bytes` bounds how many
+    /// dirty-page bytes get packed into one chunk.
+    pub fn snapshot_next_chunk(&mut self, max_bytes: usize) -> Result<Option<Vec<u8>>, JsValue> {
+        self.system.next_snapshot_chunk(max_bytes)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Feed one chunk produced by a remote `snapshot_next_chunk` into an
+    /// in-progress restore, starting one on the first call.
+    pub fn snapshot_restore_feed(&mut self, chunk: &[u8]) -> Result<(), JsValue> {
+        self.system.feed_snapshot_chunk(chunk)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Finish a restore started by `snapshot_restore_feed`: validate the fed
+    /// chunks form a complete stream, then apply it to this emulator.
+    pub fn snapshot_restore_end(&mut self) -> Result<(), JsValue> {
+        self.system.finish_snapshot_restore()
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Create a delta snapshot against whichever kernel/initrd/DTB are
+    /// currently loaded.
+    ///
+    /// This generalizes create_snapshot(): instead of a caller-supplied
+    /// kernel_size/initrd_size and a fixed 1MB-past-kernel heuristic, it
+    /// covers every touched RAM page and recognizes ones that still match
+    /// the loaded boot images byte-for-byte, storing a reference to the
+    /// image instead of the bytes. There's no separate "baseline" to name -
+    /// the baseline is simply whatever was loaded via setup_linux_boot()
+    /// before this is called, and set_state_delta() re-validates that by
+    /// content hash rather than trusting a caller-supplied id.
+    pub fn get_state_delta(&self) -> Result<Vec<u8>, JsValue> {
+        self.system.create_state_delta().to_bytes()
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Restore from a delta snapshot produced by get_state_delta().
+    ///
+    /// The same kernel/initrd (and, since boot regenerates it deterministically,
+    /// DTB) the delta was created against must already be loaded; this is
+    /// checked by content hash and fails instead of silently reconstructing
+    /// RAM from the wrong images.
+    pub fn set_state_delta(&mut self, delta_data: &[u8]) -> Result<(), JsValue> {
+        let delta = snapshot::StateDelta::from_bytes(delta_data)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.system.apply_state_delta(&delta)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+}
+
+/// Cap on the number of addresses `MemorySearchResult` will carry across the
+/// JS boundary in one call - a search with a short or common pattern (a lone
+/// zero byte, say) can otherwise match millions of addresses.
+const MAX_SEARCH_RESULTS: usize = 10_000;
+
+/// Result of an `Emulator` memory search: the matching addresses, capped at
+/// `MAX_SEARCH_RESULTS`, plus whether the real match count exceeded the cap.
+#[wasm_bindgen]
+pub struct MemorySearchResult {
+    addresses: Vec<u32>,
+    truncated: bool,
+}
+
+impl MemorySearchResult {
+    fn from_all(mut addresses: Vec<u32>) -> Self {
+        let truncated = addresses.len() > MAX_SEARCH_RESULTS;
+        addresses.truncate(MAX_SEARCH_RESULTS);
+        MemorySearchResult { addresses, truncated }
+    }
+}
+
+#[wasm_bindgen]
+impl MemorySearchResult {
+    #[wasm_bindgen(getter)]
+    pub fn addresses(&self) -> Vec<u32> {
+        self.addresses.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn truncated(&self) -> bool {
+        self.truncated
     }
 }
 
@@ -276,4 +1011,39 @@ mod tests {
         // Check RAM size
         assert_eq!(emu.system.read_memory(0x80000000, 4), emu2.system.read_memory(0x80000000, 4));
     }
+
+    #[test]
+    fn test_ram_ptr_and_len_describe_the_ram_region() {
+        let mut emu = Emulator::new(1).unwrap(); // 1MB RAM
+        assert_eq!(emu.ram_len(), 1024 * 1024);
+
+        let dummy_kernel = vec![0xAB, 0xCD, 0xEF, 0x01];
+        emu.system.load_binary(&dummy_kernel, 0x8000_0000).unwrap();
+
+        let ptr = emu.ram_ptr();
+        assert!(!ptr.is_null());
+        let view = unsafe { std::slice::from_raw_parts(ptr, emu.ram_len()) };
+        assert_eq!(&view[..4], &dummy_kernel[..]);
+    }
+
+    #[test]
+    fn test_run_for_ms_respects_time_budget() {
+        let mut emu = Emulator::new(1).unwrap();
+        let insts: [u32; 1] = [0x0000006f]; // jal x0, 0 (infinite self-loop)
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        emu.system.load_binary(&bytes, 0x8000_0000).unwrap();
+        emu.system.cpu.pc = 0x8000_0000;
+
+        let start = std::time::Instant::now();
+        let executed = emu.run_for_ms(20.0);
+        let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        assert!(executed > 0);
+        // Generous slack - should stop within a handful of chunk boundaries
+        // of the requested budget, not run away unbounded.
+        assert!(elapsed_ms < 200.0, "run_for_ms overshot budget: {elapsed_ms}ms");
+    }
 }
@@ -2,12 +2,31 @@ use std::env;
 use std::fs::File;
 use std::io::{self, Read, Write, stdout};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use flate2::read::GzDecoder;
 use zstd::stream::read::Decoder as ZstdDecoder;
 use std::time::{Duration, Instant};
 
 // Use the library crate's modules
-use otoriscv::{System, System64};
+use otoriscv::{System, System64, PanicEvent, BootMilestone, ProfileSample, aggregate_profile_samples, RunStopReason, SystemPowerState, InputCrlfMode};
+
+/// Set by `handle_sigint` so long runs can stop between chunks instead of
+/// the default SIGINT action tearing down the process mid-run and leaving
+/// the terminal in raw mode.
+static SIGINT_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_sigint(_sig: libc::c_int) {
+    SIGINT_RECEIVED.store(true, Ordering::SeqCst);
+}
+
+/// Install a SIGINT handler that just latches `SIGINT_RECEIVED` instead of
+/// terminating the process, so `run_chunked`'s `should_continue` callback
+/// can stop the emulation loop and let `main` restore the terminal.
+fn install_sigint_handler() {
+    unsafe {
+        libc::signal(libc::SIGINT, handle_sigint as usize);
+    }
+}
 
 // Set stdin to non-blocking mode
 fn set_nonblocking(fd: i32, nonblocking: bool) {
@@ -66,12 +85,66 @@ struct BenchmarkConfig {
     jit_v1: bool,
     jit_v2: bool,
     rv64: bool,
+    /// If set, statistically sample PCs during the run (RV32 only for now)
+    /// and dump the aggregate to this path on exit. See `--profile`.
+    profile_path: Option<String>,
 }
 
 struct BenchmarkResult {
     wall_time: Duration,
     boot_time: Option<Duration>,
     instructions: u64,
+    panic_event: Option<PanicEvent>,
+    tohost_result: Option<otoriscv::TohostResult>,
+    boot_milestones: Vec<BootMilestone>,
+    profile_samples: Vec<ProfileSample>,
+}
+
+/// Number of retired instructions between profile samples. Not currently
+/// user-configurable; chosen to keep the sample buffer's memory bounded
+/// while still resolving hot functions on multi-billion-instruction boots.
+const PROFILE_SAMPLE_INTERVAL: u64 = 1000;
+
+/// Write `--profile`'s aggregated (satp, pc-bucket) counts as JSON, one
+/// object per bucket, sorted hottest-first. Deliberately a single "frame"
+/// per entry for now (see the request this shipped for) - good enough to
+/// pipe into `jq` and reshape into flamegraph.pl's folded-stack format.
+fn write_profile_json(path: &str, samples: &[ProfileSample]) -> io::Result<()> {
+    const PC_BUCKET_SIZE: u32 = 64;
+    let buckets = aggregate_profile_samples(samples, PC_BUCKET_SIZE);
+
+    let mut json = String::from("[\n");
+    for (i, b) in buckets.iter().enumerate() {
+        if i > 0 {
+            json.push_str(",\n");
+        }
+        json.push_str(&format!(
+            "  {{\"satp\": \"{:#010x}\", \"pc\": \"{:#010x}\", \"count\": {}}}",
+            b.satp, b.pc_bucket, b.count
+        ));
+    }
+    json.push_str("\n]\n");
+
+    let mut f = File::create(path)?;
+    f.write_all(json.as_bytes())?;
+    println!("Profile dumped to {} ({} samples, {} buckets).", path, samples.len(), buckets.len());
+    Ok(())
+}
+
+/// Print per-milestone boot progress (time to kernel start, time to init,
+/// time to prompt, ...) in place of a single overall boot time. Does
+/// nothing if milestone tracking wasn't enabled.
+fn print_boot_milestones(milestones: &[BootMilestone]) {
+    if milestones.is_empty() {
+        return;
+    }
+    println!("  Boot milestones:");
+    for m in milestones {
+        match m.instruction_count {
+            Some(ic) => println!("    {}: reached at {} instructions (mtime={})", m.label, ic, m.mtime.unwrap_or(0)),
+            None => println!("    {}: not reached", m.label),
+        }
+    }
 }
 
 fn output_has_prompt(buffer: &[u8]) -> bool {
@@ -97,25 +170,39 @@ fn run_emulator(system: &mut System, config: &BenchmarkConfig) -> io::Result<Ben
     let mut stdin_buf = [0u8; 16];
     let mut prompt_buffer: Vec<u8> = Vec::new();
     const PROMPT_BUFFER_MAX: usize = 128;
-    
+    let mut panic_event = None;
+
+    if config.enabled {
+        system.set_panic_detection(true);
+        system.set_boot_milestones(true);
+    }
+
+    if config.profile_path.is_some() {
+        system.set_profiling(true, PROFILE_SAMPLE_INTERVAL);
+    }
+
+    system.set_input_crlf_mode(InputCrlfMode::CrToLf);
+
     loop {
         // Check for stdin input (non-blocking)
         let n = unsafe {
             libc::read(0, stdin_buf.as_mut_ptr() as *mut libc::c_void, stdin_buf.len())
         };
         if n > 0 {
-            for i in 0..n as usize {
-                // Convert CR to LF for consistency
-                let c = if stdin_buf[i] == b'\r' { b'\n' } else { stdin_buf[i] };
-                system.uart_receive(c);
-            }
+            // Queued (not sent straight to the UART) so a large piped
+            // stdin chunk - e.g. `cat script.sh | otoriscv ...` - gets
+            // trickled into the 16-byte RX FIFO instead of overrunning it.
+            system.queue_input(stdin_buf[..n as usize].to_vec());
         }
-        
-        // Run a batch of cycles
-        let cycles_to_run = 1000000;
-        let cycles_run = system.run(cycles_to_run);
+
+        // Run a batch of cycles, in Ctrl-C-responsive sub-chunks
+        let cycles_to_run = 1_000_000;
+        let result = system.run_chunked(cycles_to_run, cycles_to_run / 10, || {
+            !SIGINT_RECEIVED.load(Ordering::SeqCst)
+        });
+        let cycles_run = result.cycles;
         instructions += cycles_run as u64;
-        
+
         // Handle UART Output
         let output = system.uart_get_output();
         if !output.is_empty() {
@@ -136,11 +223,30 @@ fn run_emulator(system: &mut System, config: &BenchmarkConfig) -> io::Result<Ben
             }
         }
 
+        if result.reason == RunStopReason::Callback {
+            println!("\nInterrupted.");
+            break;
+        }
+
+        if let Some(event) = system.take_panic_event() {
+            panic_event = Some(event);
+            break;
+        }
+
+        if system.tohost_result().is_some() {
+            break;
+        }
+
+        if system.power_state() == SystemPowerState::Shutdown {
+            println!("\nGuest requested shutdown, exiting.");
+            break;
+        }
+
         if system.cpu.pc == 0 {
             println!("\nPC jumped to 0, halting.");
             break;
         }
-        
+
         if instructions > max_cycles {
             println!("\nTimeout reached, halting.");
             break;
@@ -151,11 +257,21 @@ fn run_emulator(system: &mut System, config: &BenchmarkConfig) -> io::Result<Ben
             break;
         }
     }
-    
+
+    let profile_samples = if config.profile_path.is_some() {
+        system.take_profile_samples()
+    } else {
+        Vec::new()
+    };
+
     Ok(BenchmarkResult {
         wall_time: start.elapsed(),
         boot_time,
         instructions: system.get_instruction_count(),
+        panic_event,
+        tohost_result: system.tohost_result(),
+        boot_milestones: system.get_boot_milestones(),
+        profile_samples,
     })
 }
 
@@ -173,14 +289,20 @@ fn run_emulator_64(system: &mut System64, config: &BenchmarkConfig) -> io::Resul
             libc::read(0, stdin_buf.as_mut_ptr() as *mut libc::c_void, stdin_buf.len())
         };
         if n > 0 {
+            // System64 doesn't have System's queue_input/RX-FIFO-aware
+            // pacing yet, so a large piped stdin chunk can still overrun
+            // the guest's UART FIFO here the way it used to for System.
             for i in 0..n as usize {
                 let c = if stdin_buf[i] == b'\r' { b'\n' } else { stdin_buf[i] };
                 system.uart_receive(c);
             }
         }
 
-        let cycles_to_run = 1000000;
-        let cycles_run = system.run(cycles_to_run);
+        let cycles_to_run = 1_000_000;
+        let result = system.run_chunked(cycles_to_run, cycles_to_run / 10, || {
+            !SIGINT_RECEIVED.load(Ordering::SeqCst)
+        });
+        let cycles_run = result.cycles;
         instructions += cycles_run as u64;
 
         let output = system.uart_get_output();
@@ -202,6 +324,11 @@ fn run_emulator_64(system: &mut System64, config: &BenchmarkConfig) -> io::Resul
             }
         }
 
+        if result.reason == RunStopReason::Callback {
+            println!("\nInterrupted.");
+            break;
+        }
+
         if system.cpu.pc == 0 {
             println!("\nPC jumped to 0, halting.");
             break;
@@ -221,6 +348,10 @@ fn run_emulator_64(system: &mut System64, config: &BenchmarkConfig) -> io::Resul
         wall_time: start.elapsed(),
         boot_time,
         instructions: system.get_instruction_count(),
+        panic_event: None,
+        tohost_result: None,
+        boot_milestones: Vec::new(),
+        profile_samples: Vec::new(),
     })
 }
 
@@ -234,12 +365,21 @@ fn main() -> io::Result<()> {
     let mut sig_end = 0u32;
     let mut raw_mode = false;
     let mut fs_path = String::new();
+    let mut tohost_addr: Option<u32> = None;
+    let mut isa: Option<String> = None;
+    let mut trace_mmio: Option<String> = None;
+    let mut log_commits: Option<String> = None;
+    let mut record_path: Option<String> = None;
+    let mut replay_path: Option<String> = None;
+    let mut persist_fs_path: Option<String> = None;
+    let mut import_fs_path: Option<String> = None;
     let mut config = BenchmarkConfig {
         enabled: false,
         exit_on_prompt: false,
         jit_v1: false,
         jit_v2: false,
         rv64: false,
+        profile_path: None,
     };
 
     let mut i = 1;
@@ -285,6 +425,42 @@ fn main() -> io::Result<()> {
             "--rv64" => {
                 config.rv64 = true;
             }
+            "--tohost" => {
+                i += 1;
+                tohost_addr = Some(u32::from_str_radix(args[i].trim_start_matches("0x"), 16).expect("Invalid tohost addr"));
+            }
+            "--isa" => {
+                i += 1;
+                isa = Some(args[i].clone());
+            }
+            "--trace-mmio" => {
+                i += 1;
+                trace_mmio = Some(args[i].clone());
+            }
+            "--log-commits" => {
+                i += 1;
+                log_commits = Some(args[i].clone());
+            }
+            "--profile" => {
+                i += 1;
+                config.profile_path = Some(args[i].clone());
+            }
+            "--record" => {
+                i += 1;
+                record_path = Some(args[i].clone());
+            }
+            "--replay" => {
+                i += 1;
+                replay_path = Some(args[i].clone());
+            }
+            "--persist-fs" => {
+                i += 1;
+                persist_fs_path = Some(args[i].clone());
+            }
+            "--import-fs" => {
+                i += 1;
+                import_fs_path = Some(args[i].clone());
+            }
             arg if !arg.starts_with("-") => {
                 kernel_path = arg.to_string();
             }
@@ -295,11 +471,26 @@ fn main() -> io::Result<()> {
         i += 1;
     }
 
+    if let Some(path) = &replay_path {
+        let data = std::fs::read(path)?;
+        let mut system = System::replay(&data).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let output = system.uart_get_output();
+        stdout().write_all(&output)?;
+        stdout().flush()?;
+        println!(
+            "\nReplay complete. instructions={} pc={:#010x}",
+            system.get_instruction_count(),
+            system.cpu.pc
+        );
+        return Ok(());
+    }
+
     if kernel_path.is_empty() {
-        eprintln!("Usage: {} <kernel-image> [--initrd <initrd>] [--ram <mb>] [--fs <host-path>] [--rv64] [--signature <file> --begin <addr> --end <addr>] [--raw] [--benchmark] [--jit-v1] [--jit-v2]", args[0]);
+        eprintln!("Usage: {} <kernel-image> [--initrd <initrd>] [--ram <mb>] [--fs <host-path>] [--persist-fs <tar>] [--import-fs <tar>] [--rv64] [--signature <file> --begin <addr> --end <addr>] [--tohost <addr>] [--isa <string>] [--trace-mmio uart|plic|clint|virtio] [--log-commits <output.log>] [--profile <output.json>] [--record <file>] [--raw] [--benchmark] [--jit-v1] [--jit-v2]", args[0]);
+        eprintln!("       {} --replay <file>", args[0]);
         std::process::exit(1);
     }
-    
+
     println!("OtoriscV CLI {}", if config.rv64 { "(RV64)" } else { "(RV32)" });
     println!("Loading kernel: {}", kernel_path);
     if !initrd_path.is_empty() {
@@ -345,7 +536,9 @@ fn main() -> io::Result<()> {
     } else {
         None
     };
-    
+
+    install_sigint_handler();
+
     // RV64 uses SBI earlycon (sbi_console_putchar), RV32 uses UART earlycon
     let cmdline = if config.rv64 {
         if initrd_data.is_some() {
@@ -364,6 +557,15 @@ fn main() -> io::Result<()> {
     // Dispatch between RV32 and RV64
     if config.rv64 {
         // RV64 mode
+        if config.profile_path.is_some() {
+            eprintln!("WARNING: --profile is not supported in RV64 mode yet, ignoring");
+        }
+        if record_path.is_some() {
+            eprintln!("WARNING: --record is not supported in RV64 mode yet, ignoring");
+        }
+        if persist_fs_path.is_some() || import_fs_path.is_some() {
+            eprintln!("WARNING: --persist-fs/--import-fs are not supported in RV64 mode yet, ignoring");
+        }
         let fs_option = if !fs_path.is_empty() { Some(fs_path.as_str()) } else { None };
         let mut system = System64::new(ram_size_mb, fs_option).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
 
@@ -415,6 +617,7 @@ fn main() -> io::Result<()> {
             } else {
                 println!("  Boot time: N/A (prompt not detected)");
             }
+            print_boot_milestones(&bench_result.boot_milestones);
             println!("  Instructions: {}", bench_result.instructions);
             println!("  IPS: {:.3}", ips);
             if tlb_total > 0 {
@@ -432,7 +635,29 @@ fn main() -> io::Result<()> {
             eprintln!("WARNING: JIT v2 is experimental and has known bugs (VA/PA mismatch)");
             system.enable_jit_v2(true);
         }
-        
+
+        if let Some(addr) = tohost_addr {
+            system.set_tohost_addr(addr);
+        }
+
+        if let Some(isa) = &isa {
+            system.set_isa(isa).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        }
+
+        if let Some(device) = &trace_mmio {
+            system.trace_mmio_device(device).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        }
+
+        if let Some(path) = &import_fs_path {
+            let tar_data = std::fs::read(path)?;
+            system.import_filesystem_tar(&tar_data).map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+            println!("Imported filesystem from: {}", path);
+        }
+
+        if log_commits.is_some() {
+            system.set_commit_log(true);
+        }
+
         if raw_mode {
             system.load_binary(&kernel_data, 0x80000000).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
             system.cpu.pc = 0x80000000;
@@ -444,19 +669,82 @@ fn main() -> io::Result<()> {
             ).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
         }
         
+        if record_path.is_some() {
+            system.start_recording().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        }
+
         println!("System ready. Starting emulation...");
         println!("-------------------------------------");
-        
+
         set_raw_terminal(true);
         set_nonblocking(0, true);
-        
+
         let result = run_emulator(&mut system, &config);
-        
+
         set_raw_terminal(false);
         set_nonblocking(0, false);
-        
+
         let bench_result = result?;
 
+        if let Some(path) = &record_path {
+            let data = system.stop_recording().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            std::fs::write(path, &data)?;
+            println!("\nWrote recording to {}", path);
+        }
+
+        if let Some(path) = &persist_fs_path {
+            match system.export_filesystem_tar() {
+                Some(tar_data) => {
+                    std::fs::write(path, &tar_data)?;
+                    println!("\nWrote filesystem to {}", path);
+                }
+                None => eprintln!("\nWARNING: --persist-fs has nothing to write for a host-backed (--fs) filesystem"),
+            }
+        }
+
+        if let Some(event) = &bench_result.panic_event {
+            eprintln!("\nGuest panic detected: \"{}\" at pc={:#010x}, instructions={}", event.pattern, event.pc, event.instruction_count);
+            eprintln!("--- context ---\n{}\n---------------", String::from_utf8_lossy(&event.context));
+            std::process::exit(1);
+        }
+
+        if let Some(result) = bench_result.tohost_result {
+            match result {
+                otoriscv::TohostResult::Pass => {
+                    println!("\ntohost: PASS");
+                }
+                otoriscv::TohostResult::Fail(test) => {
+                    println!("\ntohost: FAIL (test {})", test);
+                    std::process::exit(1);
+                }
+                otoriscv::TohostResult::Other(value) => {
+                    println!("\ntohost: wrote {:#010x} (not a pass/fail code, ignoring)", value);
+                }
+            }
+        }
+
+        if let Some(path) = &log_commits {
+            let commits = system.take_commit_log();
+            std::fs::write(path, commits.join("\n") + "\n").map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            println!("\nWrote {} commit-log lines to {}", commits.len(), path);
+        }
+
+        if trace_mmio.is_some() {
+            let trace = system.take_mmio_trace();
+            println!("\nMMIO trace ({} accesses):", trace.len());
+            for entry in &trace {
+                println!(
+                    "  [{:>10}] pc={:#010x} {} addr={:#010x} size={} value={:#010x}",
+                    entry.instruction_count,
+                    entry.pc,
+                    if entry.is_write { "W" } else { "R" },
+                    entry.addr,
+                    entry.size,
+                    entry.value
+                );
+            }
+        }
+
         if config.enabled {
             let wall_secs = bench_result.wall_time.as_secs_f64();
             let ips = if wall_secs > 0.0 {
@@ -477,6 +765,7 @@ fn main() -> io::Result<()> {
             } else {
                 println!("  Boot time: N/A (prompt not detected)");
             }
+            print_boot_milestones(&bench_result.boot_milestones);
             println!("  Instructions: {}", bench_result.instructions);
             println!("  IPS: {:.3}", ips);
             if tlb_total > 0 {
@@ -503,7 +792,11 @@ fn main() -> io::Result<()> {
             f.write_all(sig_data.as_bytes())?;
             println!("Signature dumped.");
         }
+
+        if let Some(path) = &config.profile_path {
+            write_profile_json(path, &bench_result.profile_samples)?;
+        }
     }
-    
+
     Ok(())
 }
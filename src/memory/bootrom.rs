@@ -0,0 +1,223 @@
+//! Tiny RISC-V assembler for synthesizing the minimal M-mode boot ROM.
+//!
+//! `init_boot_rom`/`init_boot_rom_rv64` used to hand-assemble a fixed
+//! instruction stream with the kernel entry point baked into a `lui`
+//! immediate. [`BootRomBuilder`] emits the same LUI/ADDI/CSRW/MRET
+//! sequence but parameterized, so callers can relocate the kernel entry
+//! or tweak delegation/counter setup without editing hex by hand.
+
+use crate::cpu::csr::{CSR_MCOUNTEREN, CSR_MEDELEG, CSR_MEPC, CSR_MIDELEG, CSR_MIE, CSR_MSTATUS, CSR_MTVEC};
+
+const OPCODE_LUI: u32 = 0x37;
+const OPCODE_OP_IMM: u32 = 0x13;
+const OPCODE_SYSTEM: u32 = 0x73;
+const OPCODE_JAL: u32 = 0x6f;
+
+const REG_T0: u32 = 5;
+const REG_T1: u32 = 6;
+
+/// Register `x0`, hardwired to zero.
+pub const NOP: u32 = 0x0000_0013;
+/// `mret`.
+pub const MRET: u32 = 0x3020_0073;
+
+fn u_type(opcode: u32, rd: u32, imm20: u32) -> u32 {
+    (imm20 << 12) | (rd << 7) | opcode
+}
+
+fn i_type(opcode: u32, funct3: u32, rd: u32, rs1: u32, imm12: i32) -> u32 {
+    (((imm12 as u32) & 0xfff) << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+fn j_type(opcode: u32, rd: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    let imm20 = (imm >> 20) & 1;
+    let imm10_1 = (imm >> 1) & 0x3ff;
+    let imm11 = (imm >> 11) & 1;
+    let imm19_12 = (imm >> 12) & 0xff;
+    (imm20 << 31) | (imm10_1 << 21) | (imm11 << 20) | (imm19_12 << 12) | (rd << 7) | opcode
+}
+
+fn lui(rd: u32, imm20: u32) -> u32 {
+    u_type(OPCODE_LUI, rd, imm20 & 0xf_ffff)
+}
+
+fn addi(rd: u32, rs1: u32, imm12: i32) -> u32 {
+    i_type(OPCODE_OP_IMM, 0, rd, rs1, imm12)
+}
+
+fn slli(rd: u32, rs1: u32, shamt: u32) -> u32 {
+    i_type(OPCODE_OP_IMM, 1, rd, rs1, shamt as i32)
+}
+
+fn srli(rd: u32, rs1: u32, shamt: u32) -> u32 {
+    i_type(OPCODE_OP_IMM, 5, rd, rs1, shamt as i32)
+}
+
+fn csrw(csr: u32, rs1: u32) -> u32 {
+    i_type(OPCODE_SYSTEM, 1, 0, rs1, csr as i32)
+}
+
+/// `jal rd, offset` (used for the SBI stub's `j 0` infinite loop).
+pub fn jal(rd: u32, offset: i32) -> u32 {
+    j_type(OPCODE_JAL, rd, offset)
+}
+
+/// Load a 32-bit immediate into `rd`, sign-extended (like `li` on RV32).
+/// Emits `lui`/`addi` and omits either instruction when it would be a no-op.
+fn li32(rd: u32, value: u32) -> Vec<u32> {
+    let upper = value.wrapping_add(0x800) >> 12;
+    let lower = value.wrapping_sub(upper << 12) as i32;
+    let mut code = Vec::new();
+    if upper != 0 {
+        code.push(lui(rd, upper));
+        if lower != 0 {
+            code.push(addi(rd, rd, lower));
+        }
+    } else {
+        code.push(addi(rd, 0, lower));
+    }
+    code
+}
+
+/// Load the low 32 bits of `value` into `rd` as a *zero*-extended 64-bit
+/// value, clearing the sign extension `lui`/`addi` would otherwise leave
+/// in bits 63:32 on RV64.
+fn li32_zext64(rd: u32, value: u32) -> Vec<u32> {
+    let mut code = li32(rd, value);
+    code.push(slli(rd, rd, 32));
+    code.push(srli(rd, rd, 32));
+    code
+}
+
+/// Builds the minimal M-mode boot ROM used to drop into the S-mode kernel,
+/// parameterized instead of hand-assembled.
+///
+/// Defaults match the historical hardcoded ROM: delegate exceptions 0-8 and
+/// 12-15 to S-mode (but not ecall-from-S), delegate SSI/STI/SEI interrupts,
+/// enable cycle/time/instret counters for S-mode, and point `mtvec` at the
+/// SBI stub immediately after the generated code.
+pub struct BootRomBuilder {
+    kernel_entry: u32,
+    rv64: bool,
+    medeleg: u32,
+    mideleg: u32,
+    mie: Option<u32>,
+    mcounteren: u32,
+    mtvec: Option<u32>,
+}
+
+impl BootRomBuilder {
+    pub fn new(kernel_entry: u32) -> Self {
+        BootRomBuilder {
+            kernel_entry,
+            rv64: false,
+            medeleg: 0xb1ff,
+            mideleg: 0x222,
+            mie: None,
+            mcounteren: 0x7,
+            mtvec: Some(0x1080),
+        }
+    }
+
+    pub fn rv64(mut self, rv64: bool) -> Self {
+        self.rv64 = rv64;
+        self
+    }
+
+    pub fn medeleg(mut self, mask: u32) -> Self {
+        self.medeleg = mask;
+        self
+    }
+
+    pub fn mideleg(mut self, mask: u32) -> Self {
+        self.mideleg = mask;
+        self
+    }
+
+    pub fn mie(mut self, mask: u32) -> Self {
+        self.mie = Some(mask);
+        self
+    }
+
+    pub fn mcounteren(mut self, mask: u32) -> Self {
+        self.mcounteren = mask;
+        self
+    }
+
+    /// Address of the S-mode trap handler stub, or `None` to leave `mtvec`
+    /// unset (e.g. when the caller wires up its own handler afterwards).
+    pub fn mtvec(mut self, addr: Option<u32>) -> Self {
+        self.mtvec = addr;
+        self
+    }
+
+    /// Assemble the instruction stream (does not include the SBI stub).
+    pub fn build(&self) -> Vec<u32> {
+        let mut code = Vec::new();
+
+        code.extend(li32(REG_T0, self.medeleg));
+        code.push(csrw(CSR_MEDELEG, REG_T0));
+
+        code.extend(li32(REG_T0, self.mideleg));
+        code.push(csrw(CSR_MIDELEG, REG_T0));
+
+        if let Some(mie) = self.mie {
+            code.extend(li32(REG_T0, mie));
+            code.push(csrw(CSR_MIE, REG_T0));
+        }
+
+        // MPP = Supervisor (01), MPIE = 1; also set FS = Initial (01) on
+        // RV64 so the kernel doesn't trap on its first FP instruction.
+        let mstatus_val: u32 = if self.rv64 { 0x2880 } else { 0x880 };
+        code.extend(li32(REG_T1, mstatus_val));
+        code.push(csrw(CSR_MSTATUS, REG_T1));
+
+        if self.rv64 {
+            code.extend(li32_zext64(REG_T0, self.kernel_entry));
+        } else {
+            code.extend(li32(REG_T0, self.kernel_entry));
+        }
+        code.push(csrw(CSR_MEPC, REG_T0));
+
+        if let Some(mtvec) = self.mtvec {
+            code.extend(li32(REG_T0, mtvec));
+            code.push(csrw(CSR_MTVEC, REG_T0));
+        }
+
+        code.extend(li32(REG_T0, self.mcounteren));
+        code.push(csrw(CSR_MCOUNTEREN, REG_T0));
+
+        code.push(MRET);
+        code
+    }
+
+    /// Assemble and write the boot ROM (little-endian) into `rom`, starting
+    /// at offset 0.
+    pub fn write_to(&self, rom: &mut [u8]) {
+        for (i, inst) in self.build().into_iter().enumerate() {
+            let offset = i * 4;
+            rom[offset..offset + 4].copy_from_slice(&inst.to_le_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_historical_rv32_encoding() {
+        let code = BootRomBuilder::new(0x8000_0000).build();
+        assert_eq!(code[0], lui(REG_T0, 0xb));
+        assert_eq!(code[1], addi(REG_T0, REG_T0, 0x1ff));
+        assert_eq!(*code.last().unwrap(), MRET);
+    }
+
+    #[test]
+    fn test_relocated_kernel_entry() {
+        let code = BootRomBuilder::new(0x8020_0000).build();
+        // mepc should be loaded with the relocated entry, not 0x80000000.
+        assert!(code.contains(&lui(REG_T0, 0x8_0200)));
+    }
+}
@@ -8,9 +8,24 @@
 //! 0x20000000 - 0x20001FFF: VirtIO device 1 (9p)
 //! 0x80000000 - ...:        RAM (DRAM_BASE)
 
+use std::cell::Cell;
 use std::sync::Arc;
 use serde::{Serialize, Deserialize};
 
+mod bootrom;
+pub use bootrom::BootRomBuilder;
+
+/// RAM is backed by fixed-size pages, allocated lazily on first write.
+/// A freshly booted guest touches a small fraction of a large RAM window,
+/// so most pages are never allocated at all.
+const PAGE_SIZE: usize = 4096;
+const PAGE_SHIFT: u32 = 12;
+const PAGE_MASK: usize = PAGE_SIZE - 1;
+
+fn new_page() -> Box<[u8]> {
+    vec![0u8; PAGE_SIZE].into_boxed_slice()
+}
+
 /// Trait for memory-mapped devices
 pub trait Device: Send + Sync {
     fn read8(&self, offset: u32) -> u8;
@@ -51,17 +66,44 @@ struct DeviceMapping {
 /// Memory subsystem with RAM and device mappings
 #[derive(Serialize, Deserialize)]
 pub struct Memory {
-    /// Main RAM (starts at DRAM_BASE)
-    ram: Vec<u8>,
+    /// Main RAM (starts at DRAM_BASE), backed by lazily-allocated pages.
+    /// A `None` slot is an untouched page that reads as all zeros without
+    /// ever being materialized - serializing this (get_state, snapshots)
+    /// only writes out the pages a guest has actually dirtied.
+    ram_pages: Vec<Option<Box<[u8]>>>,
+    ram_len: usize,
     ram_base: u32,
-    
+
+    /// One-entry "host TLB": the page index and raw pointer to its backing
+    /// bytes for whichever RAM page the unchecked fast accessors touched
+    /// last. Consecutive accesses to the same page (overwhelmingly the
+    /// common case for sequential code and data) reuse it instead of
+    /// re-indexing `ram_pages`. Invalidated implicitly whenever a different
+    /// page is touched; never points at a freed page, since pages are only
+    /// ever allocated, never dropped, once created.
+    #[serde(skip)]
+    last_page: Cell<Option<(usize, *mut u8)>>,
+
+    /// Contiguous snapshot of RAM for zero-copy JS access (memory
+    /// inspectors, framebuffer-over-RAM rendering), lazily allocated the
+    /// first time `sync_ram_view` is called and resynced from dirty pages
+    /// on every subsequent call. Not kept live automatically - see
+    /// `sync_ram_view` for the sync contract.
+    #[serde(skip)]
+    ram_view: Option<Vec<u8>>,
+
+    /// Set whenever the most recent `read*`/`write*` call landed on a
+    /// registered device rather than RAM/ROM; see `Bus::take_mmio_access`.
+    #[serde(skip)]
+    device_access: Cell<bool>,
+
     /// Boot ROM
     rom: Vec<u8>,
-    
+
     /// Device mappings
     #[serde(skip)]
     mappings: Vec<DeviceMapping>,
-    
+
     /// Actual devices (stored separately for mutability)
     #[serde(skip)]
     devices: Vec<Box<dyn Device>>,
@@ -82,6 +124,87 @@ pub trait Bus {
     fn write32(&mut self, addr: u32, value: u32);
     fn read64(&mut self, addr: u32) -> u64;
     fn write64(&mut self, addr: u32, value: u64);
+
+    /// Whether `addr` (a physical address, post-translation) can actually
+    /// supply instructions. Used by the instruction fetch path to raise a
+    /// real `InstructionAccessFault` instead of silently decoding whatever
+    /// bytes an unmapped region happens to read back as (typically zero).
+    /// Defaults to permissive for buses that don't need to distinguish.
+    fn is_executable(&self, _addr: u32) -> bool {
+        true
+    }
+
+    /// Whether `addr` (a physical address, post-translation) is real RAM.
+    /// LR/SC/AMO are only defined for memory, so the atomics path uses this
+    /// to fault on device/MMIO and unmapped addresses instead of treating a
+    /// device register as if it were an atomically-updatable memory cell.
+    /// Defaults to permissive for buses that don't need to distinguish.
+    fn is_ram(&self, _addr: u32) -> bool {
+        true
+    }
+
+    /// Whether `addr` (a physical address, post-translation) is the
+    /// read-only boot ROM. The store path uses this to raise a
+    /// `StoreAccessFault` instead of silently discarding the write, which
+    /// is what real ROM hardware does. Defaults to permissive (no ROM) for
+    /// buses that don't need to distinguish.
+    fn is_rom(&self, _addr: u32) -> bool {
+        false
+    }
+
+    /// Whether `addr` (a physical address, post-translation) lands on real
+    /// RAM, ROM, or a mapped device - i.e. is not genuinely unmapped space.
+    /// Only consulted when strict memory checking is enabled (see
+    /// `Cpu::strict_memory`); defaults to permissive so lenient buses never
+    /// need to implement it.
+    fn is_mapped(&self, _addr: u32) -> bool {
+        true
+    }
+
+    /// Record that the guest at `pc` attempted to store to the read-only
+    /// boot ROM at `addr`, for the debug API. Called right before the
+    /// caller raises `StoreAccessFault`. Defaults to a no-op for buses
+    /// without a boot ROM or debug logging to report to.
+    fn record_rom_write_attempt(&mut self, _pc: u32, _addr: u32) {}
+
+    /// Whether a host-imposed protection overlay (see
+    /// `System::add_protected_range`) permits loading from `addr` (a
+    /// physical address, post-translation). Distinct from RISC-V PMP, which
+    /// the guest itself controls. Defaults to permissive for buses that
+    /// don't support the overlay.
+    fn is_read_allowed(&self, _addr: u32) -> bool {
+        true
+    }
+
+    /// Like `is_read_allowed`, but for stores.
+    fn is_write_allowed(&self, _addr: u32) -> bool {
+        true
+    }
+
+    /// Consume (returning and clearing) whether the most recent `read*`/
+    /// `write*` call on this bus landed on a registered MMIO device rather
+    /// than RAM/ROM/unmapped space. The block JIT (`jit::v1::execute_block`)
+    /// polls this after every instruction and ends the block early on a hit,
+    /// since device state machines (e.g. the UART's) only advance when
+    /// System-level code runs between instructions - letting a compiled
+    /// block run dozens of device polls back-to-back without that round
+    /// trip can make a guest's poll loop appear to spin far longer under the
+    /// JIT than the interpreter. `Memory` overrides this with its tracked
+    /// flag; the default here covers buses with no devices to track.
+    fn take_mmio_access(&self) -> bool {
+        false
+    }
+
+    /// The live wall-clock counter backing the `time`/`timeh` CSRs, if this
+    /// bus has one. `System`'s `csr.time` is only refreshed from CLINT's
+    /// `mtime` every 64-cycle timer batch, so a `rdtime` read mid-batch
+    /// through the cached field alone could read a stale value; the CSR
+    /// read path queries this live instead. Defaults to `None` for buses
+    /// with no CLINT (e.g. bare `Memory` in unit tests), which just falls
+    /// back to the cached value.
+    fn mtime(&self) -> Option<u64> {
+        None
+    }
 }
 
 impl Bus for Memory {
@@ -116,107 +239,335 @@ impl Bus for Memory {
     fn write64(&mut self, addr: u32, value: u64) {
         Memory::write64(self, addr, value)
     }
+
+    fn is_executable(&self, addr: u32) -> bool {
+        Memory::is_executable(self, addr)
+    }
+
+    fn is_ram(&self, addr: u32) -> bool {
+        Memory::is_ram(self, addr)
+    }
+
+    fn is_rom(&self, addr: u32) -> bool {
+        Memory::is_rom(self, addr)
+    }
+
+    fn is_mapped(&self, addr: u32) -> bool {
+        Memory::is_mapped(self, addr)
+    }
+
+    fn take_mmio_access(&self) -> bool {
+        Memory::take_mmio_access(self)
+    }
 }
 
 impl Memory {
     pub fn new(ram_size_mb: u32) -> Self {
-        let ram_size = (ram_size_mb as usize) * 1024 * 1024;
-        
+        let ram_len = (ram_size_mb as usize) * 1024 * 1024;
+        let page_count = ram_len.div_ceil(PAGE_SIZE);
+
         Memory {
-            ram: vec![0u8; ram_size],
+            ram_pages: (0..page_count).map(|_| None).collect(),
+            ram_len,
             ram_base: DRAM_BASE,
+            last_page: Cell::new(None),
+            ram_view: None,
+            device_access: Cell::new(false),
             rom: vec![0u8; ROM_SIZE as usize],
             mappings: Vec::new(),
             devices: Vec::new(),
         }
     }
-    
+
     /// Get RAM size in bytes
     pub fn ram_size(&self) -> usize {
-        self.ram.len()
+        self.ram_len
     }
-    
-    /// Get direct access to RAM slice (for jor1k-style direct access optimization)
-    /// 
-    /// # Safety
-    /// Callers must ensure:
-    /// - Addresses are properly bounds-checked before access
-    /// - No aliasing violations when combined with other mutable access
+
+    /// Sanity-check invariants a corrupted or hostile deserialized `Memory`
+    /// might violate. `ram_len` must be one of the sizes `Memory::new` can
+    /// actually produce, and `ram_pages` must have exactly the number of
+    /// slots that size implies - a mismatch would make page-index
+    /// arithmetic elsewhere panic or read out of bounds.
+    pub fn validate(&self) -> Result<(), String> {
+        const MAX_RAM_MB: usize = 2048;
+        if self.ram_len == 0
+            || !self.ram_len.is_multiple_of(1024 * 1024)
+            || self.ram_len > MAX_RAM_MB * 1024 * 1024
+        {
+            return Err(format!("unsupported RAM size: {} bytes", self.ram_len));
+        }
+
+        let expected_pages = self.ram_len.div_ceil(PAGE_SIZE);
+        if self.ram_pages.len() != expected_pages {
+            return Err(format!(
+                "RAM page count {} doesn't match {} byte RAM size",
+                self.ram_pages.len(),
+                self.ram_len
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Iterate over every RAM page a guest has actually written to, as
+    /// `(page_addr, bytes)`. Used by snapshotting to avoid walking (and
+    /// allocating) untouched pages, which read as zero without existing.
+    pub(crate) fn touched_ram_pages(&self) -> impl Iterator<Item = (u32, &[u8])> {
+        self.ram_pages.iter().enumerate().filter_map(|(idx, page)| {
+            page.as_deref().map(|bytes| (self.ram_base + (idx * PAGE_SIZE) as u32, bytes))
+        })
+    }
+
+    /// Materialize (or resync) a contiguous, zero-copy-from-JS snapshot of
+    /// RAM into `ram_view` and return it. RAM itself is backed by lazily
+    /// allocated per-page buffers (see `ram_pages`) rather than one
+    /// contiguous allocation, so there is no single pointer into "live" RAM
+    /// to hand to JS without either giving up the lazy-paging memory saving
+    /// or copying on every access. This splits the difference: the first
+    /// call allocates a `ram_len`-byte buffer once, and every call (this one
+    /// included) copies every page the guest has ever touched - using
+    /// `touched_ram_pages` so untouched RAM is never walked - which is
+    /// O(dirty RAM) rather than O(`ram_len`) for the common case of a small
+    /// guest working set. Callers that need a fresh view (e.g. once per
+    /// rendered frame) call this before reading; the returned pointer stays valid
+    /// until `self` is dropped or replaced (snapshot restore, `reboot`),
+    /// since `ram_view` is only ever resynced in place, never reallocated
+    /// to a different size.
+    pub fn sync_ram_view(&mut self) -> &[u8] {
+        let ram_len = self.ram_len;
+        let view = self.ram_view.get_or_insert_with(|| vec![0u8; ram_len]);
+        for (idx, page) in self.ram_pages.iter().enumerate() {
+            if let Some(bytes) = page.as_deref() {
+                let offset = idx * PAGE_SIZE;
+                view[offset..offset + bytes.len()].copy_from_slice(bytes);
+            }
+        }
+        view
+    }
+
+    /// True if `addr` falls inside the boot ROM or RAM - the only regions
+    /// real hardware could ever fetch instructions from. Anything else
+    /// (MMIO devices, or genuinely unmapped space like address 0 below the
+    /// boot ROM's base) isn't executable.
+    pub fn is_executable(&self, addr: u32) -> bool {
+        (addr >= ROM_BASE && addr < ROM_BASE + ROM_SIZE)
+            || (addr >= self.ram_base && addr < self.ram_base + self.ram_len as u32)
+    }
+
+    /// True if `addr` falls inside main RAM. Unlike `is_executable`, this
+    /// excludes the boot ROM - it's read-only and not a valid AMO target.
+    pub fn is_ram(&self, addr: u32) -> bool {
+        addr >= self.ram_base && addr < self.ram_base + self.ram_len as u32
+    }
+
+    /// True if `addr` falls inside the boot ROM - real hardware would
+    /// reject a store there, and this repo's stores now do too.
+    pub fn is_rom(&self, addr: u32) -> bool {
+        addr >= ROM_BASE && addr < ROM_BASE + ROM_SIZE
+    }
+
+    /// True if `addr` is backed by ROM, RAM, or a registered device - i.e.
+    /// isn't genuinely unmapped space. Only consulted when strict memory
+    /// checking is enabled (see `Cpu::strict_memory`).
+    pub fn is_mapped(&self, addr: u32) -> bool {
+        self.is_rom(addr) || self.is_ram(addr) || self.find_device(addr).is_some()
+    }
+
     #[inline(always)]
-    pub fn ram_slice(&self) -> &[u8] {
-        &self.ram
+    fn page_of(offset: usize) -> (usize, usize) {
+        (offset >> PAGE_SHIFT, offset & PAGE_MASK)
     }
-    
-    /// Get mutable direct access to RAM slice
+
+    /// Read-only lookup of a page's backing bytes - `None` for an
+    /// untouched page, which the caller should treat as all zeros without
+    /// allocating anything.
     #[inline(always)]
-    pub fn ram_slice_mut(&mut self) -> &mut [u8] {
-        &mut self.ram
+    fn page(&self, page_idx: usize) -> Option<&[u8]> {
+        self.ram_pages[page_idx].as_deref()
     }
-    
+
+    /// Look up (and allocate, if untouched) a page for writing.
+    #[inline(always)]
+    fn page_mut(&mut self, page_idx: usize) -> &mut [u8] {
+        self.ram_pages[page_idx].get_or_insert_with(new_page)
+    }
+
+    fn ram_byte(&self, offset: usize) -> u8 {
+        let (page, poff) = Self::page_of(offset);
+        self.page(page).map_or(0, |p| p[poff])
+    }
+
+    fn ram_set_byte(&mut self, offset: usize, value: u8) {
+        let (page, poff) = Self::page_of(offset);
+        self.page_mut(page)[poff] = value;
+    }
+
+    /// Host pointer to `page_idx`'s backing bytes, going through (and
+    /// refreshing) the one-page cache described on `last_page`. Doesn't
+    /// allocate - returns `None` for an untouched page.
+    #[inline(always)]
+    fn cached_page_ptr(&self, page_idx: usize) -> Option<*const u8> {
+        if let Some((idx, ptr)) = self.last_page.get() {
+            if idx == page_idx {
+                return Some(ptr as *const u8);
+            }
+        }
+        let ptr = self.page(page_idx)?.as_ptr();
+        self.last_page.set(Some((page_idx, ptr as *mut u8)));
+        Some(ptr)
+    }
+
+    /// Like `cached_page_ptr`, but allocates the page (zero-filled) first
+    /// if it hasn't been touched yet, for the write side of the fast path.
+    #[inline(always)]
+    fn cached_page_ptr_mut(&mut self, page_idx: usize) -> *mut u8 {
+        if let Some((idx, ptr)) = self.last_page.get() {
+            if idx == page_idx {
+                return ptr;
+            }
+        }
+        let ptr = self.page_mut(page_idx).as_mut_ptr();
+        self.last_page.set(Some((page_idx, ptr)));
+        ptr
+    }
+
     /// Direct 32-bit RAM read (no bounds check - caller must ensure validity)
-    /// 
-    /// jor1k-style optimization: single array access, no function call overhead
+    ///
+    /// # Safety
+    ///
+    /// `offset + 3` must be within the RAM region (`offset + 3 < self.ram_len`).
     #[inline(always)]
     pub unsafe fn ram_read32_unchecked(&self, offset: usize) -> u32 {
-        debug_assert!(offset + 3 < self.ram.len());
-        let ptr = self.ram.as_ptr().add(offset) as *const u32;
-        ptr.read_unaligned()
+        debug_assert!(offset + 3 < self.ram_len);
+        let (page, poff) = Self::page_of(offset);
+        if poff + 3 < PAGE_SIZE {
+            return match self.cached_page_ptr(page) {
+                Some(ptr) => (ptr.add(poff) as *const u32).read_unaligned(),
+                None => 0,
+            };
+        }
+        // Straddles a page boundary - only possible right at the edge.
+        u32::from_le_bytes(std::array::from_fn(|i| self.ram_read8_unchecked(offset + i)))
     }
-    
+
     /// Direct 32-bit RAM write (no bounds check - caller must ensure validity)
+    ///
+    /// # Safety
+    ///
+    /// `offset + 3` must be within the RAM region (`offset + 3 < self.ram_len`).
     #[inline(always)]
     pub unsafe fn ram_write32_unchecked(&mut self, offset: usize, value: u32) {
-        debug_assert!(offset + 3 < self.ram.len());
-        let ptr = self.ram.as_mut_ptr().add(offset) as *mut u32;
-        ptr.write_unaligned(value);
+        debug_assert!(offset + 3 < self.ram_len);
+        let (page, poff) = Self::page_of(offset);
+        if poff + 3 < PAGE_SIZE {
+            let ptr = self.cached_page_ptr_mut(page);
+            (ptr.add(poff) as *mut u32).write_unaligned(value);
+            return;
+        }
+        for (i, byte) in value.to_le_bytes().into_iter().enumerate() {
+            self.ram_write8_unchecked(offset + i, byte);
+        }
     }
-    
+
     /// Direct 8-bit RAM read (no bounds check)
+    ///
+    /// # Safety
+    ///
+    /// `offset` must be within the RAM region (`offset < self.ram_len`).
     #[inline(always)]
     pub unsafe fn ram_read8_unchecked(&self, offset: usize) -> u8 {
-        debug_assert!(offset < self.ram.len());
-        *self.ram.get_unchecked(offset)
+        debug_assert!(offset < self.ram_len);
+        let (page, poff) = Self::page_of(offset);
+        self.cached_page_ptr(page).map_or(0, |ptr| *ptr.add(poff))
     }
-    
+
     /// Direct 8-bit RAM write (no bounds check)
+    ///
+    /// # Safety
+    ///
+    /// `offset` must be within the RAM region (`offset < self.ram_len`).
     #[inline(always)]
     pub unsafe fn ram_write8_unchecked(&mut self, offset: usize, value: u8) {
-        debug_assert!(offset < self.ram.len());
-        *self.ram.get_unchecked_mut(offset) = value;
+        debug_assert!(offset < self.ram_len);
+        let (page, poff) = Self::page_of(offset);
+        *self.cached_page_ptr_mut(page).add(poff) = value;
     }
-    
+
     /// Direct 16-bit RAM read (no bounds check)
+    ///
+    /// # Safety
+    ///
+    /// `offset + 1` must be within the RAM region (`offset + 1 < self.ram_len`).
     #[inline(always)]
     pub unsafe fn ram_read16_unchecked(&self, offset: usize) -> u16 {
-        debug_assert!(offset + 1 < self.ram.len());
-        let ptr = self.ram.as_ptr().add(offset) as *const u16;
-        ptr.read_unaligned()
+        debug_assert!(offset + 1 < self.ram_len);
+        let (page, poff) = Self::page_of(offset);
+        if poff + 1 < PAGE_SIZE {
+            return match self.cached_page_ptr(page) {
+                Some(ptr) => (ptr.add(poff) as *const u16).read_unaligned(),
+                None => 0,
+            };
+        }
+        u16::from_le_bytes(std::array::from_fn(|i| self.ram_read8_unchecked(offset + i)))
     }
-    
+
     /// Direct 16-bit RAM write (no bounds check)
+    ///
+    /// # Safety
+    ///
+    /// `offset + 1` must be within the RAM region (`offset + 1 < self.ram_len`).
     #[inline(always)]
     pub unsafe fn ram_write16_unchecked(&mut self, offset: usize, value: u16) {
-        debug_assert!(offset + 1 < self.ram.len());
-        let ptr = self.ram.as_mut_ptr().add(offset) as *mut u16;
-        ptr.write_unaligned(value);
+        debug_assert!(offset + 1 < self.ram_len);
+        let (page, poff) = Self::page_of(offset);
+        if poff + 1 < PAGE_SIZE {
+            let ptr = self.cached_page_ptr_mut(page);
+            (ptr.add(poff) as *mut u16).write_unaligned(value);
+            return;
+        }
+        for (i, byte) in value.to_le_bytes().into_iter().enumerate() {
+            self.ram_write8_unchecked(offset + i, byte);
+        }
     }
-    
+
     /// Direct 64-bit RAM read (no bounds check)
+    ///
+    /// # Safety
+    ///
+    /// `offset + 7` must be within the RAM region (`offset + 7 < self.ram_len`).
     #[inline(always)]
     pub unsafe fn ram_read64_unchecked(&self, offset: usize) -> u64 {
-        debug_assert!(offset + 7 < self.ram.len());
-        let ptr = self.ram.as_ptr().add(offset) as *const u64;
-        ptr.read_unaligned()
+        debug_assert!(offset + 7 < self.ram_len);
+        let (page, poff) = Self::page_of(offset);
+        if poff + 7 < PAGE_SIZE {
+            return match self.cached_page_ptr(page) {
+                Some(ptr) => (ptr.add(poff) as *const u64).read_unaligned(),
+                None => 0,
+            };
+        }
+        u64::from_le_bytes(std::array::from_fn(|i| self.ram_read8_unchecked(offset + i)))
     }
-    
+
     /// Direct 64-bit RAM write (no bounds check)
+    ///
+    /// # Safety
+    ///
+    /// `offset + 7` must be within the RAM region (`offset + 7 < self.ram_len`).
     #[inline(always)]
     pub unsafe fn ram_write64_unchecked(&mut self, offset: usize, value: u64) {
-        debug_assert!(offset + 7 < self.ram.len());
-        let ptr = self.ram.as_mut_ptr().add(offset) as *mut u64;
-        ptr.write_unaligned(value);
+        debug_assert!(offset + 7 < self.ram_len);
+        let (page, poff) = Self::page_of(offset);
+        if poff + 7 < PAGE_SIZE {
+            let ptr = self.cached_page_ptr_mut(page);
+            (ptr.add(poff) as *mut u64).write_unaligned(value);
+            return;
+        }
+        for (i, byte) in value.to_le_bytes().into_iter().enumerate() {
+            self.ram_write8_unchecked(offset + i, byte);
+        }
     }
-    
+
     /// Add a device at the specified address range
     pub fn add_device(&mut self, device: Box<dyn Device>, base: u32, size: u32) {
         let device_idx = self.devices.len();
@@ -230,82 +581,20 @@ impl Memory {
     }
     
     /// Initialize boot ROM with minimal SBI-like firmware
-    /// 
+    ///
     /// This sets up the system for Linux boot:
     /// 1. Delegate exceptions/interrupts to S-mode
-    /// 2. Set MPP to Supervisor mode  
-    /// 3. Set MEPC to kernel entry (0x80000000)
+    /// 2. Set MPP to Supervisor mode
+    /// 3. Set MEPC to `kernel_entry`
     /// 4. Use MRET to drop to S-mode and start kernel
-    pub fn init_boot_rom(&mut self) {
-        // Boot ROM at 0x1000
-        // Acts as minimal M-mode firmware like OpenSBI
-        //
-        // Linux expects:
-        // - a0 = hartid (already set by setup_linux_boot)
-        // - a1 = dtb address (already set by setup_linux_boot)
-        // - Running in S-mode with SBI available for ecalls
-        
-        let instructions: [u32; 29] = [
-            // === Setup exception delegation ===
-            // Delegate most exceptions to S-mode, but NOT ecall from S-mode
-            // medeleg = 0xB1FF (delegate exceptions 0-8, 12-15 to S-mode)
-            // Bit 8 (ecall from U) is delegated, bit 9 (ecall from S) is NOT
-            0x0000b2b7,           // lui t0, 0xB         ; t0 = 0xB000
-            0x1ff28293,           // addi t0, t0, 0x1FF  ; t0 = 0xB1FF
-            0x30229073,           // csrw medeleg, t0
-            
-            // Delegate S-mode interrupts (bits 1,5,9 = SSI, STI, SEI)
-            0x00000293,           // li t0, 0
-            0x22228293,           // addi t0, t0, 0x222  ; t0 = 0x222 (SSI+STI+SEI)
-            0x30329073,           // csrw mideleg, t0
-            
-            // === Setup mstatus for transition to S-mode ===
-            // Set MPP = Supervisor (01), MPIE = 1
-            // mstatus bits: MPP[12:11]=01 (S-mode), MPIE[7]=1
-            0x00000297,           // auipc t0, 0         ; t0 = PC (for computing addresses)
-            0x00001337,           // lui t1, 1           ; t1 = 0x1000
-            0x88030313,           // addi t1, t1, -0x780 ; t1 = 0x880 (MPP=01, MPIE=1)
-            0x30031073,           // csrw mstatus, t1
-            
-            // === Set mepc to kernel entry point ===
-            0x800002b7,           // lui t0, 0x80000     ; t0 = 0x80000000
-            0x34129073,           // csrw mepc, t0
-            
-            // === Set up mtvec for SBI trap handler ===
-            // Point to simple SBI handler at ROM address 0x1080
-            0x000012b7,           // lui t0, 0x1         ; t0 = 0x1000
-            0x08028293,           // addi t0, t0, 0x80   ; t0 = 0x1080
-            0x30529073,           // csrw mtvec, t0
-            
-            // === Enable counter access from S-mode ===
-            0x00700293,           // li t0, 7            ; enable cycle, time, instret
-            0x30629073,           // csrw mcounteren, t0
-            
-            // === Jump to S-mode kernel using MRET ===
-            0x30200073,           // mret
-            
-            // === Padding ===
-            0x00000013,           // nop
-            0x00000013,           // nop
-            0x00000013,           // nop
-            0x00000013,           // nop
-            0x00000013,           // nop
-            0x00000013,           // nop
-            0x00000013,           // nop
-            0x00000013,           // nop
-            0x00000013,           // nop
-            0x00000013,           // nop
-            0x00000013,           // nop
-        ];
-        
-        for (i, &inst) in instructions.iter().enumerate() {
-            let offset = i * 4;
-            self.rom[offset] = inst as u8;
-            self.rom[offset + 1] = (inst >> 8) as u8;
-            self.rom[offset + 2] = (inst >> 16) as u8;
-            self.rom[offset + 3] = (inst >> 24) as u8;
-        }
-        
+    ///
+    /// Linux expects:
+    /// - a0 = hartid (already set by setup_linux_boot)
+    /// - a1 = dtb address (already set by setup_linux_boot)
+    /// - Running in S-mode with SBI available for ecalls
+    pub fn init_boot_rom(&mut self, kernel_entry: u32) {
+        BootRomBuilder::new(kernel_entry).write_to(&mut self.rom);
+
         // Add SBI trap handler at offset 0x80 (address 0x1080)
         // This handles ecalls from S-mode (SBI calls)
         self.init_sbi_handler();
@@ -338,103 +627,134 @@ impl Memory {
     }
     
     /// Initialize boot ROM for RV64
-    /// Same logic as RV32 but for 64-bit architecture
-    pub fn init_boot_rom_rv64(&mut self) {
-        // Boot ROM at 0x1000
-        // Acts as minimal M-mode firmware (OpenSBI-like)
-        //
-        // Linux expects:
-        // - a0 = hartid (set by setup_linux_boot)
-        // - a1 = dtb address (set by setup_linux_boot)
-        // - Running in S-mode with SBI available
-        
-        let instructions: [u32; 27] = [
-            // === Setup exception delegation ===
-            // Delegate most exceptions to S-mode
-            // medeleg = 0xB1FF
-            0x0000b2b7,           // lui t0, 0xB
-            0x1ff28293,           // addi t0, t0, 0x1FF
-            0x30229073,           // csrw medeleg, t0
-            
-            // Delegate S-mode interrupts (SSI, STI, SEI)
-            0x00000293,           // li t0, 0
-            0x22228293,           // addi t0, t0, 0x222
-            0x30329073,           // csrw mideleg, t0
-            
-            // === Enable interrupts in mie ===
-            // Enable M-mode timer (MTIE bit 7) and S-mode interrupts
-            // mie = 0xAAA (bits 1,3,5,7,9,11 = SSI,MSI,STI,MTI,SEI,MEI)
-            // To correctly load 0xAAA on RV64:
-            // lui t0, 1 -> t0 = 0x1000
-            // addi t0, t0, -0x556 -> t0 = 0x1000 + (-0x556) = 0x1000 - 1366 = 0xAAA
-            // Note: addi -0x556 = addi 0xAAA (in 12-bit two's complement, 0xAAA represents -1366)
-            0x000012b7,           // lui t0, 1            ; t0 = 0x1000
-            0xaaa28293,           // addi t0, t0, -0x556  ; t0 = 0x1000 - 0x556 = 0xAAA
-            0x30429073,           // csrw mie, t0
-            
-            // === Setup mstatus for S-mode transition ===
-            // Set MPP = Supervisor (01), MPIE = 1, FS = Initial (01)
-            // 0x2880 = MPP[12:11]=01, MPIE[7]=1, FS[14:13]=01 (Initial)
-            0x00003337,           // lui t1, 3            ; t1 = 0x3000
-            0x88030313,           // addi t1, t1, -0x780  ; t1 = 0x3000 - 0x780 = 0x2880
-            0x30031073,           // csrw mstatus, t1
-            
-            // === Set mepc to kernel entry ===
-            // RV64: Need to set mepc = 0x0000_0000_8000_0000 (NOT sign-extended!)
-            // lui t0, 0x80000 produces 0xFFFF_FFFF_8000_0000 (sign-extended from bit 31)
-            // We need to clear the upper 32 bits using slli+srli
-            0x800002b7,           // lui t0, 0x80000      ; t0 = 0xFFFF_FFFF_8000_0000 (sign-extended)
-            0x02029293,           // slli t0, t0, 32     ; t0 = 0x8000_0000_0000_0000 (shift left, clears upper bits)
-            0x0202d293,           // srli t0, t0, 32     ; t0 = 0x0000_0000_8000_0000 (correct!)
-            0x34129073,           // csrw mepc, t0
-            
-            // === Set mtvec for SBI handler ===
-            0x000012b7,           // lui t0, 0x1          ; t0 = 0x1000
-            0x08028293,           // addi t0, t0, 0x80    ; t0 = 0x1080
-            0x30529073,           // csrw mtvec, t0
-            
-            // === Enable counter access from S-mode ===
-            0x00700293,           // li t0, 7
-            0x30629073,           // csrw mcounteren, t0
-            
-            // === Jump to S-mode using MRET ===
-            0x30200073,           // mret
-            
-            // === Padding ===
-            0x00000013,           // nop
-            0x00000013,           // nop
-            0x00000013,           // nop
-            0x00000013,           // nop
-            0x00000013,           // nop
-        ];
-        
-        for (i, &inst) in instructions.iter().enumerate() {
-            let offset = i * 4;
-            self.rom[offset] = inst as u8;
-            self.rom[offset + 1] = (inst >> 8) as u8;
-            self.rom[offset + 2] = (inst >> 16) as u8;
-            self.rom[offset + 3] = (inst >> 24) as u8;
-        }
-        
+    /// Same logic as RV32 but for 64-bit architecture: enables mie
+    /// up front and zero-extends `kernel_entry` into mepc so a `lui` of
+    /// a value with bit 31 set doesn't sign-extend into the upper 32 bits.
+    pub fn init_boot_rom_rv64(&mut self, kernel_entry: u32) {
+        BootRomBuilder::new(kernel_entry)
+            .rv64(true)
+            .mie(0xaaa) // SSI, MSI, STI, MTI, SEI, MEI
+            .write_to(&mut self.rom);
+
         // Add SBI trap handler stub
         self.init_sbi_handler();
     }
     
+    /// Replace the boot ROM contents wholesale, e.g. with a real OpenSBI
+    /// binary or a custom M-mode monitor, instead of the built-in stub
+    /// written by `init_boot_rom`. `data` is copied starting at offset 0;
+    /// the rest of the ROM window is zero-filled.
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), String> {
+        if data.len() > ROM_SIZE as usize {
+            return Err(format!(
+                "ROM image of {} bytes doesn't fit in the {} byte ROM window",
+                data.len(),
+                ROM_SIZE
+            ));
+        }
+
+        self.rom.iter_mut().for_each(|b| *b = 0);
+        self.rom[..data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
     /// Load binary data into RAM
     pub fn load_binary(&mut self, data: &[u8], addr: u32) -> Result<(), String> {
         if addr < self.ram_base {
             return Err(format!("Load address 0x{:08x} below RAM base", addr));
         }
-        
+
         let offset = (addr - self.ram_base) as usize;
-        if offset + data.len() > self.ram.len() {
+        if offset + data.len() > self.ram_len {
             return Err(format!("Binary too large for RAM"));
         }
-        
-        self.ram[offset..offset + data.len()].copy_from_slice(data);
+
+        for (i, byte) in data.iter().enumerate() {
+            self.ram_set_byte(offset + i, *byte);
+        }
         Ok(())
     }
-    
+
+    /// Bulk read of `len` bytes starting at `addr`.
+    ///
+    /// Takes a fast per-page path when the whole range falls inside RAM,
+    /// and falls back to `read8` per byte for ROM/MMIO or ranges that
+    /// straddle RAM boundaries.
+    pub fn read_slice(&self, addr: u32, len: usize) -> Vec<u8> {
+        if len == 0 {
+            return Vec::new();
+        }
+        if addr >= self.ram_base {
+            let offset = (addr - self.ram_base) as usize;
+            if let Some(end) = offset.checked_add(len) {
+                if end <= self.ram_len {
+                    return (offset..end).map(|o| self.ram_byte(o)).collect();
+                }
+            }
+        }
+        (0..len as u32).map(|i| self.read8(addr.wrapping_add(i))).collect()
+    }
+
+    /// Search RAM for `pattern`, checking every `alignment`-aligned address
+    /// in `[start, end)` and returning every match - for cheat-engine-style
+    /// guest memory search tooling. Compares directly against RAM bytes
+    /// (`ram_byte`) instead of going through `read8` and the device bus for
+    /// every candidate address, since a broad search can probe millions of
+    /// them.
+    pub fn search(&self, pattern: &[u8], start: u32, end: u32, alignment: u32) -> Vec<u32> {
+        let mut results = Vec::new();
+        if pattern.is_empty() || alignment == 0 {
+            return results;
+        }
+
+        let ram_end = self.ram_base + self.ram_len as u32;
+        let end = end.min(ram_end);
+        let mut addr = start.max(self.ram_base);
+        let rem = addr % alignment;
+        if rem != 0 {
+            addr += alignment - rem;
+        }
+
+        while addr < end {
+            let offset = (addr - self.ram_base) as usize;
+            if offset + pattern.len() <= self.ram_len
+                && (0..pattern.len()).all(|i| self.ram_byte(offset + i) == pattern[i])
+            {
+                results.push(addr);
+            }
+            addr = match addr.checked_add(alignment) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        results
+    }
+
+    /// Bulk write of `data` starting at `addr`.
+    ///
+    /// Mirrors [`Memory::read_slice`]: fast per-page copy inside RAM,
+    /// per-byte fallback otherwise.
+    pub fn write_slice(&mut self, addr: u32, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        if addr >= self.ram_base {
+            let offset = (addr - self.ram_base) as usize;
+            if let Some(end) = offset.checked_add(data.len()) {
+                if end <= self.ram_len {
+                    for (i, byte) in data.iter().enumerate() {
+                        self.ram_set_byte(offset + i, *byte);
+                    }
+                    return;
+                }
+            }
+        }
+        for (i, byte) in data.iter().enumerate() {
+            self.write8(addr.wrapping_add(i as u32), *byte);
+        }
+    }
+
     /// Find device for address
     fn find_device(&self, addr: u32) -> Option<(usize, u32)> {
         for mapping in &self.mappings {
@@ -449,85 +769,97 @@ impl Memory {
     pub fn read8(&self, addr: u32) -> u8 {
         // Check ROM
         if addr >= ROM_BASE && addr < ROM_BASE + ROM_SIZE {
+            self.device_access.set(false);
             return self.rom[(addr - ROM_BASE) as usize];
         }
-        
+
         // Check RAM
-        if addr >= self.ram_base && addr < self.ram_base + self.ram.len() as u32 {
-            return self.ram[(addr - self.ram_base) as usize];
+        if addr >= self.ram_base && addr < self.ram_base + self.ram_len as u32 {
+            self.device_access.set(false);
+            return self.ram_byte((addr - self.ram_base) as usize);
         }
-        
+
         // Check devices
         if let Some((idx, offset)) = self.find_device(addr) {
+            self.device_access.set(true);
             return self.devices[idx].read8(offset);
         }
-        
+
         // Unmapped - return 0
+        self.device_access.set(false);
         0
     }
-    
+
     /// Write 8 bits
     pub fn write8(&mut self, addr: u32, value: u8) {
         // Check RAM
-        if addr >= self.ram_base && addr < self.ram_base + self.ram.len() as u32 {
-            self.ram[(addr - self.ram_base) as usize] = value;
+        if addr >= self.ram_base && addr < self.ram_base + self.ram_len as u32 {
+            self.device_access.set(false);
+            self.ram_set_byte((addr - self.ram_base) as usize, value);
             return;
         }
-        
+
         // Check devices
         if let Some((idx, offset)) = self.find_device(addr) {
+            self.device_access.set(true);
             self.devices[idx].write8(offset, value);
             return;
         }
-        
+
         // Unmapped - ignore
+        self.device_access.set(false);
     }
-    
+
     /// Read 16 bits (little endian)
     pub fn read16(&self, addr: u32) -> u16 {
         // Check RAM (fast path)
-        if addr >= self.ram_base && addr + 1 < self.ram_base + self.ram.len() as u32 {
+        if addr >= self.ram_base && addr + 1 < self.ram_base + self.ram_len as u32 {
+            self.device_access.set(false);
             let offset = (addr - self.ram_base) as usize;
-            return u16::from_le_bytes([self.ram[offset], self.ram[offset + 1]]);
+            return u16::from_le_bytes([self.ram_byte(offset), self.ram_byte(offset + 1)]);
         }
-        
+
         // Check devices
         if let Some((idx, offset)) = self.find_device(addr) {
+            self.device_access.set(true);
             return self.devices[idx].read16(offset);
         }
-        
+
         // Fallback to byte reads
         let lo = self.read8(addr) as u16;
         let hi = self.read8(addr + 1) as u16;
         lo | (hi << 8)
     }
-    
+
     /// Write 16 bits (little endian)
     pub fn write16(&mut self, addr: u32, value: u16) {
         // Check RAM (fast path)
-        if addr >= self.ram_base && addr + 1 < self.ram_base + self.ram.len() as u32 {
+        if addr >= self.ram_base && addr + 1 < self.ram_base + self.ram_len as u32 {
+            self.device_access.set(false);
             let offset = (addr - self.ram_base) as usize;
             let bytes = value.to_le_bytes();
-            self.ram[offset] = bytes[0];
-            self.ram[offset + 1] = bytes[1];
+            self.ram_set_byte(offset, bytes[0]);
+            self.ram_set_byte(offset + 1, bytes[1]);
             return;
         }
-        
+
         // Check devices
         if let Some((idx, offset)) = self.find_device(addr) {
+            self.device_access.set(true);
             self.devices[idx].write16(offset, value);
             return;
         }
-        
+
         // Fallback to byte writes
         self.write8(addr, value as u8);
         self.write8(addr + 1, (value >> 8) as u8);
     }
-    
+
     /// Read 32 bits (little endian)
     pub fn read32(&self, addr: u32) -> u32 {
         // Check ROM
         if addr >= ROM_BASE && addr + 3 < ROM_BASE + ROM_SIZE {
+            self.device_access.set(false);
             let offset = (addr - ROM_BASE) as usize;
             return u32::from_le_bytes([
                 self.rom[offset],
@@ -536,23 +868,20 @@ impl Memory {
                 self.rom[offset + 3],
             ]);
         }
-        
+
         // Check RAM (fast path)
-        if addr >= self.ram_base && addr + 3 < self.ram_base + self.ram.len() as u32 {
+        if addr >= self.ram_base && addr + 3 < self.ram_base + self.ram_len as u32 {
+            self.device_access.set(false);
             let offset = (addr - self.ram_base) as usize;
-            return u32::from_le_bytes([
-                self.ram[offset],
-                self.ram[offset + 1],
-                self.ram[offset + 2],
-                self.ram[offset + 3],
-            ]);
+            return u32::from_le_bytes(std::array::from_fn(|i| self.ram_byte(offset + i)));
         }
-        
+
         // Check devices
         if let Some((idx, offset)) = self.find_device(addr) {
+            self.device_access.set(true);
             return self.devices[idx].read32(offset);
         }
-        
+
         // Fallback to byte reads
         let b0 = self.read8(addr) as u32;
         let b1 = self.read8(addr + 1) as u32;
@@ -560,80 +889,87 @@ impl Memory {
         let b3 = self.read8(addr + 3) as u32;
         b0 | (b1 << 8) | (b2 << 16) | (b3 << 24)
     }
-    
+
     /// Write 32 bits (little endian)
     pub fn write32(&mut self, addr: u32, value: u32) {
         // Check RAM (fast path)
-        if addr >= self.ram_base && addr + 3 < self.ram_base + self.ram.len() as u32 {
+        if addr >= self.ram_base && addr + 3 < self.ram_base + self.ram_len as u32 {
+            self.device_access.set(false);
             let offset = (addr - self.ram_base) as usize;
-            let bytes = value.to_le_bytes();
-            self.ram[offset] = bytes[0];
-            self.ram[offset + 1] = bytes[1];
-            self.ram[offset + 2] = bytes[2];
-            self.ram[offset + 3] = bytes[3];
+            for (i, byte) in value.to_le_bytes().into_iter().enumerate() {
+                self.ram_set_byte(offset + i, byte);
+            }
             return;
         }
-        
+
         // Check devices
         if let Some((idx, offset)) = self.find_device(addr) {
+            self.device_access.set(true);
             self.devices[idx].write32(offset, value);
             return;
         }
-        
+
         // Fallback to byte writes
         self.write8(addr, value as u8);
         self.write8(addr + 1, (value >> 8) as u8);
         self.write8(addr + 2, (value >> 16) as u8);
         self.write8(addr + 3, (value >> 24) as u8);
     }
-    
+
+    /// Consume and clear the "did the last access touch a device" flag -
+    /// see `Bus::take_mmio_access`.
+    pub fn take_mmio_access(&self) -> bool {
+        self.device_access.replace(false)
+    }
+
+    /// Read 32 bits, big endian - for the DTB, which the device tree spec
+    /// fixes as big-endian regardless of the guest's own endianness. Just a
+    /// byte-swapped `read32`, so callers don't have to remember whether
+    /// `.to_be()`/`.swap_bytes()` is the right call at each site.
+    pub fn read32_be(&self, addr: u32) -> u32 {
+        self.read32(addr).swap_bytes()
+    }
+
+    /// Write 32 bits, big endian. See `read32_be`.
+    pub fn write32_be(&mut self, addr: u32, value: u32) {
+        self.write32(addr, value.swap_bytes());
+    }
+
     /// Read 64 bits (little endian) - needed for RV64 and FLD
     pub fn read64(&self, addr: u32) -> u64 {
         // Check RAM (fast path)
-        if addr >= self.ram_base && addr + 7 < self.ram_base + self.ram.len() as u32 {
+        if addr >= self.ram_base && addr + 7 < self.ram_base + self.ram_len as u32 {
             let offset = (addr - self.ram_base) as usize;
-            return u64::from_le_bytes([
-                self.ram[offset],
-                self.ram[offset + 1],
-                self.ram[offset + 2],
-                self.ram[offset + 3],
-                self.ram[offset + 4],
-                self.ram[offset + 5],
-                self.ram[offset + 6],
-                self.ram[offset + 7],
-            ]);
+            return u64::from_le_bytes(std::array::from_fn(|i| self.ram_byte(offset + i)));
         }
-        
+
         // Fallback to two 32-bit reads
         let lo = self.read32(addr) as u64;
         let hi = self.read32(addr + 4) as u64;
         lo | (hi << 32)
     }
-    
+
     /// Write 64 bits (little endian) - needed for RV64 and FSD
     pub fn write64(&mut self, addr: u32, value: u64) {
         // Check RAM (fast path)
-        if addr >= self.ram_base && addr + 7 < self.ram_base + self.ram.len() as u32 {
+        if addr >= self.ram_base && addr + 7 < self.ram_base + self.ram_len as u32 {
             let offset = (addr - self.ram_base) as usize;
-            let bytes = value.to_le_bytes();
-            self.ram[offset] = bytes[0];
-            self.ram[offset + 1] = bytes[1];
-            self.ram[offset + 2] = bytes[2];
-            self.ram[offset + 3] = bytes[3];
-            self.ram[offset + 4] = bytes[4];
-            self.ram[offset + 5] = bytes[5];
-            self.ram[offset + 6] = bytes[6];
-            self.ram[offset + 7] = bytes[7];
+            for (i, byte) in value.to_le_bytes().into_iter().enumerate() {
+                self.ram_set_byte(offset + i, byte);
+            }
             return;
         }
-        
+
         // Fallback to two 32-bit writes
         self.write32(addr, value as u32);
         self.write32(addr + 4, (value >> 32) as u32);
     }
-    
+
     pub fn reset(&mut self) {
-        self.ram.fill(0);
+        for page in &mut self.ram_pages {
+            *page = None;
+        }
+        self.last_page.set(None);
         for device in &mut self.devices {
             device.reset();
         }
@@ -661,8 +997,61 @@ mod tests {
     fn test_load_binary() {
         let mut mem = Memory::new(1);
         let data = [0x13, 0x00, 0x00, 0x00]; // NOP instruction
-        
+
         mem.load_binary(&data, DRAM_BASE).unwrap();
         assert_eq!(mem.read32(DRAM_BASE), 0x00000013);
     }
+
+    #[test]
+    fn test_untouched_ram_reads_zero_without_allocating() {
+        let mut mem = Memory::new(16);
+        assert!(mem.ram_pages.iter().all(|p| p.is_none()));
+
+        assert_eq!(mem.read32(DRAM_BASE + 0x10_0000), 0);
+        assert!(mem.ram_pages.iter().all(|p| p.is_none()));
+
+        mem.write8(DRAM_BASE + 0x10_0000, 0x7f);
+        assert_eq!(mem.ram_pages.iter().filter(|p| p.is_some()).count(), 1);
+        assert_eq!(mem.read8(DRAM_BASE + 0x10_0000), 0x7f);
+    }
+
+    #[test]
+    fn test_read32_be_is_read32_byte_swapped() {
+        let mut mem = Memory::new(1);
+
+        // The FDT magic, stored the way a real DTB stores it: big-endian,
+        // i.e. byte order [d0, 0d, fe, ed].
+        mem.write32_be(DRAM_BASE, 0xd00dfeed);
+        assert_eq!(mem.read32_be(DRAM_BASE), 0xd00dfeed);
+
+        // The plain little-endian accessor sees the same bytes reversed.
+        assert_eq!(mem.read32(DRAM_BASE), 0xedfe0dd0);
+    }
+
+    #[test]
+    fn test_search_finds_pattern_at_every_aligned_occurrence() {
+        let mut mem = Memory::new(1);
+        mem.write32(DRAM_BASE, 0xdeadbeef);
+        mem.write32(DRAM_BASE + 8, 0xdeadbeef);
+        mem.write32(DRAM_BASE + 21, 0xdeadbeef); // unaligned - should be skipped at alignment 4
+
+        let found = mem.search(&0xdeadbeefu32.to_le_bytes(), DRAM_BASE, DRAM_BASE + 4096, 4);
+        assert_eq!(found, vec![DRAM_BASE, DRAM_BASE + 8]);
+
+        // Alignment 1 also picks up the unaligned copy.
+        let found_unaligned = mem.search(&0xdeadbeefu32.to_le_bytes(), DRAM_BASE, DRAM_BASE + 4096, 1);
+        assert_eq!(found_unaligned, vec![DRAM_BASE, DRAM_BASE + 8, DRAM_BASE + 21]);
+    }
+
+    #[test]
+    fn test_unchecked_accessors_cross_page_boundary_correctly() {
+        let mut mem = Memory::new(1);
+        let boundary = (PAGE_SIZE - 2) as u32; // straddles pages 0 and 1
+
+        unsafe {
+            mem.ram_write32_unchecked(boundary as usize, 0x11223344);
+            assert_eq!(mem.ram_read32_unchecked(boundary as usize), 0x11223344);
+        }
+        assert_eq!(mem.read32(DRAM_BASE + boundary), 0x11223344);
+    }
 }
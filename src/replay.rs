@@ -0,0 +1,62 @@
+//! Execution-replay recorder, for turning a bug hit interactively in the
+//! browser/CLI into a reproducible artifact.
+//!
+//! This emulator has no wall-clock or thread-scheduling nondeterminism of
+//! its own - `mtime` only advances with retired instructions, and there's a
+//! single execution thread - so the only things that can make two runs of
+//! the same guest image diverge are the calls a caller makes into it from
+//! outside: how many cycles it asks `run`/`run_with_reason` to execute at a
+//! time, and what UART bytes or virtio-9p blobs it feeds in between those
+//! calls. Recording that exact call sequence, in order, is therefore enough
+//! to reproduce a run exactly; there's no separate "deterministic mode"
+//! needed, and no need to time-stamp events against the instruction count
+//! (which doesn't advance uniformly - see `System::run_with_reason`'s
+//! SBI/trap handling).
+
+use serde::{Serialize, Deserialize};
+
+/// One call captured by `System::start_recording`, in the order it happened.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ReplayEvent {
+    /// A `run`/`run_with_reason(max_cycles)` call.
+    Run { max_cycles: u32 },
+    /// A `uart_receive_on(uart_idx, byte)` call.
+    UartInput { uart_idx: usize, byte: u8 },
+    /// A `provide_blob(hash, data)` call.
+    ProvideBlob { hash: String, data: Vec<u8> },
+}
+
+/// A recorded session: the state execution started from, and every call
+/// made into the system afterward, in order.
+#[derive(Serialize, Deserialize)]
+pub struct ReplayFile {
+    /// Version for compatibility checking.
+    pub version: u32,
+    /// A `System::to_state_bytes` blob captured by `start_recording`.
+    pub initial_state: Vec<u8>,
+    /// Calls recorded since `start_recording`, in the order they were made.
+    pub events: Vec<ReplayEvent>,
+}
+
+impl ReplayFile {
+    /// Current format version.
+    pub const VERSION: u32 = 1;
+
+    /// Serialize to bytes (compressed with zstd).
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let serialized = bincode::serialize(self)
+            .map_err(|e| format!("Serialization error: {}", e))?;
+
+        zstd::stream::encode_all(&serialized[..], 3)
+            .map_err(|e| format!("Compression error: {}", e))
+    }
+
+    /// Deserialize from bytes (compressed with zstd).
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        let decompressed = zstd::stream::decode_all(data)
+            .map_err(|e| format!("Decompression error: {}", e))?;
+
+        bincode::deserialize(&decompressed)
+            .map_err(|e| format!("Deserialization error: {}", e))
+    }
+}
@@ -0,0 +1,100 @@
+//! Deterministic, seedable PRNG for the handful of things in the emulator
+//! that need randomness (virtio-rng, ASLR-ish memory layout choices, and the
+//! like). Every consumer draws from the single `System`-owned instance
+//! (`System::rng`) instead of reaching for its own source, so two runs
+//! seeded the same way produce byte-for-byte identical guest-visible
+//! behavior - the same guarantee `crate::replay` relies on for host-driven
+//! inputs.
+//!
+//! Deliberately not `rand`: `rand`'s OS-backed sources need the `getrandom`
+//! crate's `js` feature to work at all on wasm32, and pulling in a whole RNG
+//! ecosystem crate is overkill for "give me a reproducible stream of bits".
+//! xorshift64* is a few lines, has no platform-specific behavior, and is
+//! more than good enough for non-cryptographic uses like these.
+
+use serde::{Serialize, Deserialize};
+
+/// A xorshift64* pseudo-random number generator, seeded once and then
+/// stepped forward by every draw.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Seed the generator. xorshift64* needs a nonzero state, so a seed of
+    /// 0 is remapped to an arbitrary fixed nonzero value rather than
+    /// producing a generator that's stuck outputting zero forever.
+    pub fn new(seed: u64) -> Self {
+        Rng { state: if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed } }
+    }
+
+    /// Next 64 bits of the stream.
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Next 32 bits of the stream (the high half of a `next_u64` draw,
+    /// which xorshift64* mixes better than the low half).
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Fill `buf` with successive bytes of the stream, e.g. for a virtio-rng
+    /// device fulfilling a guest read of arbitrary length.
+    pub fn fill_bytes(&mut self, buf: &mut [u8]) {
+        let mut chunks = buf.chunks_exact_mut(8);
+        for chunk in &mut chunks {
+            chunk.copy_from_slice(&self.next_u64().to_le_bytes());
+        }
+        let remainder = chunks.into_remainder();
+        if !remainder.is_empty() {
+            let tail = self.next_u64().to_le_bytes();
+            remainder.copy_from_slice(&tail[..remainder.len()]);
+        }
+    }
+}
+
+impl Default for Rng {
+    /// Fixed default seed, so a `System` that never calls `set_rng_seed`
+    /// still behaves deterministically instead of varying run to run.
+    fn default() -> Self {
+        Rng::new(0xdead_beef_cafe_babe)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_produces_identical_stream() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let seq_a: Vec<u64> = (0..8).map(|_| a.next_u64()).collect();
+        let seq_b: Vec<u64> = (0..8).map(|_| b.next_u64()).collect();
+        assert_eq!(seq_a, seq_b);
+    }
+
+    #[test]
+    fn test_different_seeds_diverge() {
+        let mut a = Rng::new(1);
+        let mut b = Rng::new(2);
+        let seq_a: Vec<u64> = (0..8).map(|_| a.next_u64()).collect();
+        let seq_b: Vec<u64> = (0..8).map(|_| b.next_u64()).collect();
+        assert_ne!(seq_a, seq_b);
+    }
+
+    #[test]
+    fn test_fill_bytes_handles_non_multiple_of_8_lengths() {
+        let mut rng = Rng::new(7);
+        let mut buf = [0u8; 11];
+        rng.fill_bytes(&mut buf);
+        assert!(buf.iter().any(|&b| b != 0));
+    }
+}
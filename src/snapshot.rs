@@ -4,10 +4,55 @@
 //! reducing snapshot size from ~5MB to <100KB.
 
 use std::collections::HashMap;
+use std::io::Read;
 use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use bincode::Options;
 use crate::cpu::{PrivilegeLevel, Fpu};
 use crate::cpu::rv32::Csr;
 
+/// Upper bound on how large decompressed/deserialized snapshot data is
+/// allowed to be - generous enough to comfortably hold every dirty page a
+/// snapshot could carry, but still a hard ceiling against a crafted blob
+/// trying to drive an unbounded allocation. Mirrors the guard
+/// `System::from_state_bytes` applies to full-state blobs.
+const MAX_DECOMPRESSED_SNAPSHOT_SIZE: u64 = 2200 * 1024 * 1024;
+const MAX_SNAPSHOT_BINCODE_SIZE: u64 = MAX_DECOMPRESSED_SNAPSHOT_SIZE;
+
+/// Decompress a Zstd frame, aborting once more than `max_size` bytes have
+/// come out. This guards against a crafted frame whose internal size hint
+/// undersells how much data it actually expands to.
+fn decompress_capped(data: &[u8], max_size: u64) -> Result<Vec<u8>, String> {
+    let decoder = zstd::stream::Decoder::new(data)
+        .map_err(|e| format!("Decompression error: {}", e))?;
+    let mut limited = decoder.take(max_size + 1);
+    let mut out = Vec::new();
+    limited
+        .read_to_end(&mut out)
+        .map_err(|e| format!("Decompression error: {}", e))?;
+
+    if out.len() as u64 > max_size {
+        return Err(format!("decompressed snapshot data exceeds {} byte limit", max_size));
+    }
+    Ok(out)
+}
+
+/// SHA-256 digest of `data`, used to fingerprint the exact kernel/initrd a
+/// `LightweightSnapshot` was created against so `System::restore_snapshot`
+/// can refuse to apply it to a same-size-but-different build - unlike
+/// `fnv1a` below, this needs to be collision-resistant, since a mismatch
+/// here silently corrupts a running guest rather than just costing a
+/// skipped optimization.
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// Render a hash as lowercase hex, for error messages that need to show the
+/// user (or a bug report) which hash was expected vs. actual.
+pub fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
 /// Lightweight snapshot that only saves changed state
 /// 
 /// This snapshot doesn't include the full RAM - it requires the same
@@ -19,16 +64,31 @@ pub struct LightweightSnapshot {
     
     /// Kernel size (for validation on restore)
     pub kernel_size: u32,
-    
-    /// Initrd size (for validation on restore)  
+
+    /// Initrd size (for validation on restore)
     pub initrd_size: Option<u32>,
-    
+
+    /// SHA-256 of the kernel bytes the snapshot was created against.
+    pub kernel_hash: [u8; 32],
+
+    /// SHA-256 of the initrd bytes the snapshot was created against, if any.
+    pub initrd_hash: Option<[u8; 32]>,
+
+    /// RAM size in bytes, since restoring against a differently-sized RAM
+    /// would place dirty pages against the wrong backing store.
+    pub ram_size: u32,
+
+    /// Kernel command line, since it's baked into the DTB the guest already
+    /// parsed at boot - restoring against a different cmdline would leave
+    /// the guest running with settings that don't match what it booted with.
+    pub cmdline: String,
+
     /// CPU state
     pub cpu: CpuSnapshot,
-    
-    /// UART state
-    pub uart: UartSnapshot,
-    
+
+    /// State for each UART, indexed the same as `System::uarts`.
+    pub uarts: Vec<UartSnapshot>,
+
     /// CLINT state
     pub clint: ClintSnapshot,
     
@@ -124,15 +184,28 @@ pub struct PlicSnapshot {
 pub const PAGE_SIZE: u32 = 4096;
 
 impl LightweightSnapshot {
-    /// Current snapshot version
-    pub const VERSION: u32 = 1;
-    
+    /// Current snapshot version. Bumped to 3 for the kernel/initrd hash,
+    /// RAM size, and cmdline fields added for stronger restore validation.
+    pub const VERSION: u32 = 3;
+
     /// Create a new empty snapshot
-    pub fn new(kernel_size: u32, initrd_size: Option<u32>) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        kernel_size: u32,
+        initrd_size: Option<u32>,
+        kernel_hash: [u8; 32],
+        initrd_hash: Option<[u8; 32]>,
+        ram_size: u32,
+        cmdline: String,
+    ) -> Self {
         LightweightSnapshot {
             version: Self::VERSION,
             kernel_size,
             initrd_size,
+            kernel_hash,
+            initrd_hash,
+            ram_size,
+            cmdline,
             cpu: CpuSnapshot {
                 pc: 0,
                 regs: [0; 32],
@@ -143,7 +216,7 @@ impl LightweightSnapshot {
                 reservation: None,
                 instruction_count: 0,
             },
-            uart: UartSnapshot {
+            uarts: vec![UartSnapshot {
                 ier: 0,
                 fcr: 0,
                 lcr: 0,
@@ -155,7 +228,7 @@ impl LightweightSnapshot {
                 dlm: 0,
                 rx_fifo: Vec::new(),
                 tx_output: Vec::new(),
-            },
+            }],
             clint: ClintSnapshot {
                 mtime: 0,
                 mtimecmp: 0,
@@ -184,12 +257,427 @@ impl LightweightSnapshot {
             .map_err(|e| format!("Compression error: {}", e))
     }
     
-    /// Deserialize from bytes (compressed with zstd)
+    /// Deserialize from bytes (compressed with zstd). The blob is untrusted
+    /// input (e.g. it may come from a web page calling into the wasm
+    /// build), so decompression and deserialization are both size-capped
+    /// rather than trusting an attacker-controlled frame/length outright.
     pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
-        let decompressed = zstd::stream::decode_all(data)
-            .map_err(|e| format!("Decompression error: {}", e))?;
-        
-        bincode::deserialize(&decompressed)
+        let decompressed = decompress_capped(data, MAX_DECOMPRESSED_SNAPSHOT_SIZE)?;
+
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .with_limit(MAX_SNAPSHOT_BINCODE_SIZE)
+            .deserialize(&decompressed)
+            .map_err(|e| format!("Deserialization error: {}", e))
+    }
+}
+
+/// FNV-1a, used to fingerprint boot artifacts for `StateDelta` validation.
+/// Not cryptographic - just cheap and dependency-free, and collisions would
+/// only cause a spurious "artifact mismatch" error rather than data
+/// corruption (the actual RAM contents are never trusted to a hash alone).
+fn fnv1a(data: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    let mut hash = OFFSET_BASIS;
+    for &byte in data {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(PRIME);
+    }
+    hash
+}
+
+/// A RAM range populated by a known boot image (kernel, initrd, DTB),
+/// recorded as it's loaded so `StateDelta` can recognize pages that still
+/// match it and skip storing them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArtifactRange {
+    /// Short identifier, e.g. "kernel", "initrd", "dtb".
+    pub name: String,
+    /// Address the artifact was loaded at.
+    pub addr: u32,
+    /// Length in bytes.
+    pub len: u32,
+    /// FNV-1a hash of the artifact's bytes at load time.
+    pub hash: u64,
+}
+
+impl ArtifactRange {
+    pub fn new(name: &str, addr: u32, data: &[u8]) -> Self {
+        ArtifactRange {
+            name: name.to_string(),
+            addr,
+            len: data.len() as u32,
+            hash: fnv1a(data),
+        }
+    }
+}
+
+/// A RAM page reconstructed by copying bytes out of an already-loaded
+/// artifact instead of storing them directly in the delta.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArtifactPageRef {
+    /// Address of the page (a multiple of `PAGE_SIZE`).
+    pub page_addr: u32,
+    /// Which artifact to copy the page from (see `ArtifactRange::name`).
+    pub artifact: String,
+    /// Byte offset into the artifact the page starts at.
+    pub artifact_offset: u32,
+}
+
+/// A snapshot that, like `LightweightSnapshot`, requires known boot images
+/// to already be loaded before restoring - but goes further by excluding
+/// *any* RAM page whose live bytes still match the corresponding range of
+/// a known artifact, not just pages after a fixed 1MB-past-kernel heuristic.
+/// Pages that still match a loaded artifact are recorded as
+/// `ArtifactPageRef`s (artifact id + offset) instead of raw bytes; anything
+/// else (modified pages, heap, stack) is stored directly in `dirty_pages`,
+/// same as `LightweightSnapshot`.
+#[derive(Serialize, Deserialize)]
+pub struct StateDelta {
+    /// Version for compatibility checking.
+    pub version: u32,
+
+    /// Artifacts the delta was computed against; `apply` fails if the
+    /// caller's currently-loaded artifacts don't match these by hash.
+    pub artifacts: Vec<ArtifactRange>,
+
+    /// CPU state.
+    pub cpu: CpuSnapshot,
+    /// State for each UART, indexed the same as `System::uarts`.
+    pub uarts: Vec<UartSnapshot>,
+    /// CLINT state.
+    pub clint: ClintSnapshot,
+    /// PLIC state.
+    pub plic: PlicSnapshot,
+
+    /// Pages reconstructable by copying out of a loaded artifact.
+    pub artifact_pages: Vec<ArtifactPageRef>,
+    /// Pages that don't match any artifact, stored directly.
+    pub dirty_pages: HashMap<u32, Vec<u8>>,
+}
+
+impl StateDelta {
+    /// Current format version.
+    pub const VERSION: u32 = 2;
+
+    /// Serialize to bytes (compressed with zstd).
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let serialized = bincode::serialize(self)
+            .map_err(|e| format!("Serialization error: {}", e))?;
+
+        zstd::stream::encode_all(&serialized[..], 3)
+            .map_err(|e| format!("Compression error: {}", e))
+    }
+
+    /// Deserialize from bytes (compressed with zstd). Same untrusted-input
+    /// treatment as `LightweightSnapshot::from_bytes`: capped decompression
+    /// and a size-limited bincode deserializer.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        let decompressed = decompress_capped(data, MAX_DECOMPRESSED_SNAPSHOT_SIZE)?;
+
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .with_limit(MAX_SNAPSHOT_BINCODE_SIZE)
+            .deserialize(&decompressed)
+            .map_err(|e| format!("Deserialization error: {}", e))
+    }
+}
+
+/// Everything in a `LightweightSnapshot` except `dirty_pages`, carried by
+/// the first `SnapshotChunk` a `SnapshotStream` yields.
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotHeader {
+    pub version: u32,
+    pub kernel_size: u32,
+    pub initrd_size: Option<u32>,
+    pub kernel_hash: [u8; 32],
+    pub initrd_hash: Option<[u8; 32]>,
+    pub ram_size: u32,
+    pub cmdline: String,
+    pub cpu: CpuSnapshot,
+    pub uarts: Vec<UartSnapshot>,
+    pub clint: ClintSnapshot,
+    pub plic: PlicSnapshot,
+    /// How many `SnapshotChunk::Page` chunks follow the header, so
+    /// `SnapshotReceiver::finish` can tell a truncated stream from a
+    /// complete one even if the final `End` chunk never arrives.
+    pub page_count: u32,
+}
+
+/// One self-describing piece of a `LightweightSnapshot`, sized to fit a
+/// caller-chosen byte budget - for streaming a snapshot out through
+/// something chunk-limited like IndexedDB instead of materializing the
+/// whole compressed blob in memory (and on the JS heap) at once.
+///
+/// A `SnapshotStream` always yields exactly one `Header`, then zero or more
+/// `Pages` batches (dirty pages, largest-first so a size-limited receiver
+/// fills up on the pages that matter most), then one `End`.
+#[derive(Serialize, Deserialize)]
+pub enum SnapshotChunk {
+    Header(Box<SnapshotHeader>),
+    Pages(Vec<(u32, Vec<u8>)>),
+    End,
+}
+
+impl SnapshotChunk {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        bincode::serialize(self).map_err(|e| format!("Serialization error: {}", e))
+    }
+
+    /// Deserialize a chunk produced by `SnapshotStream::next_chunk`. Chunks
+    /// arrive uncompressed (each is already a bounded batch of pages), so
+    /// only the bincode size limit applies here - same untrusted-input
+    /// treatment as `LightweightSnapshot::from_bytes`, minus the
+    /// decompression step.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .with_limit(MAX_SNAPSHOT_BINCODE_SIZE)
+            .deserialize(data)
             .map_err(|e| format!("Deserialization error: {}", e))
     }
 }
+
+/// Drives the send side of a chunked snapshot: `LightweightSnapshot::new`
+/// captures state eagerly, same as `create_snapshot` already does, so every
+/// chunk this yields is carved out of data that was already copied out of
+/// the running guest before the first `next_chunk` call - the guest can
+/// keep running in between calls without the snapshot's contents changing
+/// out from under it. This sidesteps needing real copy-on-write page
+/// tracking in `Memory` (which doesn't exist today) while still giving the
+/// caller a snapshot that's consistent at a single point in time.
+pub struct SnapshotStream {
+    header: Option<Box<SnapshotHeader>>,
+    pages: std::collections::VecDeque<(u32, Vec<u8>)>,
+    end_sent: bool,
+}
+
+impl SnapshotStream {
+    pub fn new(snapshot: LightweightSnapshot) -> Self {
+        let page_count = snapshot.dirty_pages.len() as u32;
+        let header = Box::new(SnapshotHeader {
+            version: snapshot.version,
+            kernel_size: snapshot.kernel_size,
+            initrd_size: snapshot.initrd_size,
+            kernel_hash: snapshot.kernel_hash,
+            initrd_hash: snapshot.initrd_hash,
+            ram_size: snapshot.ram_size,
+            cmdline: snapshot.cmdline,
+            cpu: snapshot.cpu,
+            uarts: snapshot.uarts,
+            clint: snapshot.clint,
+            plic: snapshot.plic,
+            page_count,
+        });
+
+        // Largest pages first, so a receiver that can only fit so many
+        // chunks before giving up keeps the ones worth the most bytes.
+        let mut pages: Vec<(u32, Vec<u8>)> = snapshot.dirty_pages.into_iter().collect();
+        pages.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then(a.0.cmp(&b.0)));
+
+        SnapshotStream {
+            header: Some(header),
+            pages: pages.into(),
+            end_sent: false,
+        }
+    }
+
+    /// Produce the next chunk, encoded and ready to hand to the caller, or
+    /// `None` once `End` has already been sent. `max_bytes` is a soft cap on
+    /// the `Pages` batches: pages are packed in until adding the next one
+    /// would exceed it, but a single page is never split across chunks, so
+    /// a page bigger than `max_bytes` on its own is still sent alone.
+    pub fn next_chunk(&mut self, max_bytes: usize) -> Result<Option<Vec<u8>>, String> {
+        if let Some(header) = self.header.take() {
+            return SnapshotChunk::Header(header).to_bytes().map(Some);
+        }
+        if !self.pages.is_empty() {
+            let mut batch = Vec::new();
+            let mut batch_len = 0usize;
+            while let Some((_, data)) = self.pages.front() {
+                if !batch.is_empty() && batch_len + data.len() > max_bytes {
+                    break;
+                }
+                let (addr, data) = self.pages.pop_front().unwrap();
+                batch_len += data.len();
+                batch.push((addr, data));
+            }
+            return SnapshotChunk::Pages(batch).to_bytes().map(Some);
+        }
+        if !self.end_sent {
+            self.end_sent = true;
+            return SnapshotChunk::End.to_bytes().map(Some);
+        }
+        Ok(None)
+    }
+}
+
+/// Drives the receive side of a chunked snapshot, accepting `SnapshotChunk`s
+/// produced by a `SnapshotStream` in order and reassembling them into a
+/// `LightweightSnapshot`.
+#[derive(Default)]
+pub struct SnapshotReceiver {
+    header: Option<Box<SnapshotHeader>>,
+    dirty_pages: HashMap<u32, Vec<u8>>,
+    end_received: bool,
+}
+
+impl SnapshotReceiver {
+    pub fn new() -> Self {
+        SnapshotReceiver::default()
+    }
+
+    /// Feed one chunk produced by `SnapshotStream::next_chunk`. Chunks must
+    /// arrive in the order `SnapshotStream` yields them - a `Pages` batch or
+    /// `End` before the `Header` is rejected rather than silently buffered.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), String> {
+        if self.end_received {
+            return Err("snapshot stream already ended".to_string());
+        }
+        match SnapshotChunk::from_bytes(chunk)? {
+            SnapshotChunk::Header(header) => {
+                if self.header.is_some() {
+                    return Err("duplicate snapshot header chunk".to_string());
+                }
+                self.header = Some(header);
+            }
+            SnapshotChunk::Pages(pages) => {
+                if self.header.is_none() {
+                    return Err("snapshot page chunk arrived before the header".to_string());
+                }
+                for (addr, data) in pages {
+                    self.dirty_pages.insert(addr, data);
+                }
+            }
+            SnapshotChunk::End => {
+                if self.header.is_none() {
+                    return Err("snapshot end chunk arrived before the header".to_string());
+                }
+                self.end_received = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Validate that a complete stream was received and reassemble it into
+    /// a `LightweightSnapshot`. Errors (rather than returning a partial
+    /// snapshot) if `End` never arrived or fewer pages showed up than the
+    /// header promised.
+    pub fn finish(self) -> Result<LightweightSnapshot, String> {
+        let header = self.header.ok_or("no snapshot header was received")?;
+        if !self.end_received {
+            return Err("snapshot stream ended without an End chunk".to_string());
+        }
+        if self.dirty_pages.len() != header.page_count as usize {
+            return Err(format!(
+                "incomplete snapshot: header promised {} pages, received {}",
+                header.page_count, self.dirty_pages.len()
+            ));
+        }
+
+        Ok(LightweightSnapshot {
+            version: header.version,
+            kernel_size: header.kernel_size,
+            initrd_size: header.initrd_size,
+            kernel_hash: header.kernel_hash,
+            initrd_hash: header.initrd_hash,
+            ram_size: header.ram_size,
+            cmdline: header.cmdline,
+            cpu: header.cpu,
+            uarts: header.uarts,
+            clint: header.clint,
+            plic: header.plic,
+            dirty_pages: self.dirty_pages,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lightweight_snapshot_bytes_round_trip() {
+        let snapshot = LightweightSnapshot::new(4096, None, [0u8; 32], None, 16 * 1024 * 1024, "console=ttyS0".to_string());
+        let bytes = snapshot.to_bytes().unwrap();
+        let restored = LightweightSnapshot::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.kernel_size, snapshot.kernel_size);
+        assert_eq!(restored.cmdline, snapshot.cmdline);
+    }
+
+    #[test]
+    fn test_lightweight_snapshot_from_bytes_rejects_truncated_blob() {
+        let snapshot = LightweightSnapshot::new(4096, None, [0u8; 32], None, 16 * 1024 * 1024, "console=ttyS0".to_string());
+        let bytes = snapshot.to_bytes().unwrap();
+        assert!(LightweightSnapshot::from_bytes(&bytes[..bytes.len() / 2]).is_err());
+    }
+
+    // Hand-craft a bincode payload for a `Vec<u8>` (here, `cmdline`'s length
+    // prefix) that claims to be enormous but only actually contains a
+    // couple of bytes, wrapped in a valid Zstd frame as if it were a real
+    // serialized snapshot. The bincode size limit should reject this before
+    // it tries to allocate anything close to the claimed length.
+    fn absurd_length_prefix_frame() -> Vec<u8> {
+        let mut fake_payload = Vec::new();
+        fake_payload.extend_from_slice(&(u64::MAX / 2).to_le_bytes());
+        fake_payload.extend_from_slice(&[0u8, 1, 2, 3]);
+        zstd::stream::encode_all(&fake_payload[..], 0).unwrap()
+    }
+
+    #[test]
+    fn test_lightweight_snapshot_from_bytes_rejects_absurd_length_prefix() {
+        assert!(LightweightSnapshot::from_bytes(&absurd_length_prefix_frame()).is_err());
+    }
+
+    #[test]
+    fn test_state_delta_bytes_round_trip() {
+        let delta = StateDelta {
+            version: StateDelta::VERSION,
+            artifacts: vec![ArtifactRange::new("kernel", crate::memory::DRAM_BASE, &[0x13, 0x00, 0x00, 0x00])],
+            cpu: CpuSnapshot {
+                pc: crate::memory::DRAM_BASE,
+                regs: [0; 32],
+                fpu: Fpu::new(),
+                csr: Csr::new(),
+                priv_level: PrivilegeLevel::Machine,
+                wfi: false,
+                reservation: None,
+                instruction_count: 0,
+            },
+            uarts: Vec::new(),
+            clint: ClintSnapshot { mtime: 0, mtimecmp: 0, msip: false },
+            plic: PlicSnapshot {
+                priority: [0; 32],
+                pending: 0,
+                enable_m: 0,
+                enable_s: 0,
+                threshold_m: 0,
+                threshold_s: 0,
+                claim_m: 0,
+                claim_s: 0,
+            },
+            artifact_pages: Vec::new(),
+            dirty_pages: HashMap::new(),
+        };
+        let bytes = delta.to_bytes().unwrap();
+        let restored = StateDelta::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.version, delta.version);
+        assert_eq!(restored.artifacts.len(), 1);
+    }
+
+    #[test]
+    fn test_state_delta_from_bytes_rejects_absurd_length_prefix() {
+        assert!(StateDelta::from_bytes(&absurd_length_prefix_frame()).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_chunk_from_bytes_rejects_absurd_length_prefix() {
+        // `SnapshotChunk` isn't Zstd-compressed, so the fake payload is fed
+        // straight in rather than wrapped in a Zstd frame first.
+        let mut fake_payload = Vec::new();
+        fake_payload.extend_from_slice(&(u64::MAX / 2).to_le_bytes());
+        fake_payload.extend_from_slice(&[0u8, 1, 2, 3]);
+        assert!(SnapshotChunk::from_bytes(&fake_payload).is_err());
+    }
+}
@@ -5,20 +5,30 @@
 use crate::cpu::Cpu;
 use crate::cpu::csr::*;
 use crate::cpu::rv32::{BlockCache, BlockResult, execute_block, mmu::AccessType};
-use crate::cpu::rv32::jit::{JitState, RegionResult, execute_region, HEAT_PER_BLOCK, Page};
-use crate::memory::{Memory, DRAM_BASE};
-use crate::devices::{Uart, Clint, Plic, Virtio9p};
+use crate::cpu::rv32::jit::{JitConfig, JitState, RegionResult, execute_region, HEAT_PER_BLOCK, Page};
+use crate::memory::{Device, Memory, DRAM_BASE};
+use crate::devices::{Uart, Clint, Plic, Virtio9p, TxOverflowPolicy};
 use crate::devices::virtio_9p::{Backend, in_memory::InMemoryFileSystem};
 #[cfg(not(target_arch = "wasm32"))]
 use crate::devices::virtio_9p::host::HostFileSystem;
 use serde::{Serialize, Deserialize};
+use bincode::Options;
 #[allow(unused_imports)]
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::io::Read;
 
 // Device base addresses (matching jor1k)
-const CLINT_BASE: u32 = 0x0200_0000;
-const CLINT_SIZE: u32 = 0x0001_0000;
-const UART_BASE: u32 = 0x0300_0000;
+// CLINT's address map is shared with `System64` and `dtb::generate_fdt` via
+// `crate::devices::map` rather than redefined per system - see that
+// module's doc comment.
+const CLINT_BASE: u32 = crate::devices::map::CLINT_BASE as u32;
+const CLINT_SIZE: u32 = crate::devices::map::CLINT_SIZE as u32;
+// MMIO bases for the UARTs, laid out back to back: UART 0 is the
+// interactive console, UART 1 is free for a dedicated channel (e.g. a
+// kernel log split out via `console=ttyS1`).
+const UART_BASES: [u32; 2] = [0x0300_0000, 0x0300_1000];
 const UART_SIZE: u32 = 0x0000_1000;
 const PLIC_BASE: u32 = 0x0400_0000;
 const PLIC_SIZE: u32 = 0x0400_0000;
@@ -27,8 +37,73 @@ const PLIC_SIZE: u32 = 0x0400_0000;
 const VIRTIO_BASE: u32 = 0x2000_0000;
 const VIRTIO_SIZE: u32 = 0x0000_1000;
 
-// UART interrupt line on PLIC
-const UART_IRQ: u32 = 10;
+// UART interrupt lines on PLIC, one per entry in `UART_BASES`.
+const UART_IRQS: [u32; 2] = [10, 11];
+// VirtIO 9p interrupt line on PLIC
+const VIRTIO_IRQ: u32 = 1;
+
+/// Permission bits for `System::add_protected_range`. This overlay is a
+/// host-imposed restriction checked by `SystemBus` on every access, distinct
+/// from RISC-V PMP (which the guest itself controls via CSRs).
+pub const PROT_READ: u8 = 0b001;
+pub const PROT_WRITE: u8 = 0b010;
+pub const PROT_EXEC: u8 = 0b100;
+
+/// Permission bitmask (see `PROT_READ`/`PROT_WRITE`/`PROT_EXEC`) still
+/// allowed for `addr` after intersecting every protected range that
+/// contains it. Addresses covered by no range are fully permissive.
+///
+/// Shared with `system64::SystemBus64` so RV32 and RV64 apply the exact
+/// same overlay semantics to `System::add_protected_range`/
+/// `System64::add_protected_range` ranges.
+pub(crate) fn protected_perms(ranges: &[(u32, u32, u8)], addr: u32) -> u8 {
+    let mut allowed = PROT_READ | PROT_WRITE | PROT_EXEC;
+    for &(base, size, perms) in ranges {
+        if addr >= base && addr < base.wrapping_add(size) {
+            allowed &= perms;
+        }
+    }
+    allowed
+}
+
+// Header prepended to `to_state_bytes` blobs, checked by `from_state_bytes`
+// before any decompression is attempted.
+const STATE_MAGIC: [u8; 4] = *b"ORV1";
+const STATE_FORMAT_VERSION: u32 = 1;
+// Generous upper bounds on decompressed/deserialized state size, just to
+// keep a corrupted or hostile blob from driving an unbounded allocation.
+const MAX_DECOMPRESSED_STATE_SIZE: u64 = 2200 * 1024 * 1024;
+const MAX_BINCODE_SIZE: u64 = MAX_DECOMPRESSED_STATE_SIZE;
+
+/// Effective virtual address touched by `inst` if it's a load, store, or
+/// AMO, for the `mem 0x...` annotation on a `step_traced` commit-log line.
+/// Decoded straight from the raw instruction word and the pre-execution
+/// register file rather than threaded through `Bus`, so this adds no
+/// overhead to the interpreter's hot path outside of commit logging.
+fn traced_mem_vaddr(inst: u32, regs_before: &[u32; 32]) -> Option<u32> {
+    use crate::cpu::rv32::decode::{DecodedInst, OP_AMO, OP_LOAD, OP_STORE};
+
+    let opcode = inst & 0x7F;
+    let rs1 = ((inst >> 15) & 0x1F) as usize;
+    let base = regs_before[rs1];
+    match opcode {
+        OP_LOAD => Some(base.wrapping_add(DecodedInst::imm_i(inst) as u32)),
+        OP_STORE => Some(base.wrapping_add(DecodedInst::imm_s(inst) as u32)),
+        OP_AMO => Some(base),
+        _ => None,
+    }
+}
+
+/// Index of the UART whose MMIO range contains `addr`, if any. The UARTs
+/// are laid out contiguously in `UART_BASES`, so this is a single
+/// division rather than a per-UART range scan.
+fn uart_index_for(addr: u32) -> Option<usize> {
+    if addr < UART_BASES[0] {
+        return None;
+    }
+    let idx = ((addr - UART_BASES[0]) / UART_SIZE) as usize;
+    if idx < UART_BASES.len() { Some(idx) } else { None }
+}
 
 /// System state
 #[derive(Serialize, Deserialize)]
@@ -37,7 +112,10 @@ pub struct System {
     memory: Memory,
     
     // Direct device references (since we can't easily downcast)
-    uart: Uart,
+    /// One entry per MMIO base in `UART_BASES`. UART 0 is the interactive
+    /// console; the un-indexed `uart_receive`/`uart_get_output` methods
+    /// delegate to it for compatibility with older callers.
+    uarts: Vec<Uart>,
     pub clint: Clint,
     plic: Plic,
     virtio9p: Virtio9p,
@@ -53,17 +131,1034 @@ pub struct System {
     // Use JIT v2 instead of v1
     #[serde(default)]
     use_jit_v2: bool,
+
+    /// Most recent guest panic/oops detected on the UART output stream, if
+    /// any (see `set_panic_detection`). Not persisted across snapshots.
+    #[serde(skip)]
+    pending_panic_event: Option<PanicEvent>,
+
+    /// In-progress streaming load started by `begin_load`, if any.
+    #[serde(skip)]
+    pending_load: Option<PendingLoad>,
+
+    /// Size of the kernel most recently loaded via the streaming API, used
+    /// by `setup_linux_boot_streamed` to place the initrd/DTB.
+    #[serde(skip)]
+    streamed_kernel_size: Option<u32>,
+
+    /// (start, end) of the initrd most recently loaded via the streaming
+    /// API, if any.
+    #[serde(skip)]
+    streamed_initrd_range: Option<(u32, u32)>,
+
+    /// MMIO address watched for riscv-tests style `tohost` writes, if
+    /// configured via `set_tohost_addr`.
+    tohost_addr: Option<u32>,
+
+    /// Raw value most recently written to `tohost_addr`, staged by the bus
+    /// for `run` to decode and act on. Not persisted across snapshots.
+    #[serde(skip)]
+    tohost_pending: Option<u32>,
+
+    /// Decoded result of the most recent `tohost` write, if any.
+    #[serde(skip)]
+    tohost_result: Option<TohostResult>,
+
+    /// ISA string set via `set_isa`, used for the DTB `riscv,isa` property.
+    #[serde(default = "default_isa_string")]
+    isa_string: String,
+
+    /// MMIO ranges currently being traced, as (base, size), set by
+    /// `trace_mmio`. Empty means tracing is off.
+    #[serde(skip)]
+    mmio_trace_ranges: Vec<(u32, u32)>,
+
+    /// Host-imposed physical-address protection overlay, as (base, size,
+    /// perms), set by `add_protected_range`. Empty means no restriction
+    /// beyond whatever RAM/ROM/device mapping already applies.
+    #[serde(skip)]
+    protected_ranges: Vec<(u32, u32, u8)>,
+
+    /// Ring buffer of recorded accesses to `mmio_trace_ranges`, drained by
+    /// `take_mmio_trace`.
+    #[serde(skip)]
+    mmio_trace_buf: Vec<MmioTraceEntry>,
+
+    /// Current guest-requested power state, driven by SBI SRST / the legacy
+    /// shutdown ecall. See `SystemPowerState`.
+    #[serde(default)]
+    power_state: SystemPowerState,
+
+    /// If `true`, a `RebootRequested` power state is handled internally by
+    /// `run_with_reason` (reloading `boot_artifacts` and continuing) instead
+    /// of being surfaced to the caller as `HaltReason::RebootRequested`.
+    #[serde(default)]
+    auto_reboot: bool,
+
+    /// If `true`, `reset()` replaces an in-memory 9p filesystem with an
+    /// empty one instead of leaving it as-is. Off by default, so guest
+    /// writes to the in-memory overlay survive a reset the way they always
+    /// have - this only matters to an embedder that wants an explicit
+    /// "factory reset" button. Has no effect on a host-backed filesystem,
+    /// whose writes already live outside the `System` entirely.
+    #[serde(default)]
+    wipe_fs_on_reset: bool,
+
+    /// Set when the guest requests a shutdown (legacy SBI shutdown or SBI
+    /// SRST with a shutdown type), cleared by `take_filesystem_overlay`.
+    /// Lets an embedder that tears the `System` down on poweroff notice it
+    /// needs to persist the 9p filesystem overlay first instead of polling
+    /// `power_state` every cycle. Not persisted across snapshots - a
+    /// restored system starts with no pending persist request.
+    #[serde(skip)]
+    filesystem_persist_pending: bool,
+
+    /// Kernel/initrd/cmdline captured by `setup_linux_boot*`, kept around so
+    /// `reboot` can bring the guest back up without the embedder re-sending
+    /// the boot images.
+    #[serde(skip)]
+    boot_artifacts: Option<BootArtifacts>,
+
+    /// Boot-progress milestones being tracked (see `set_boot_milestones`),
+    /// in configured order. Not persisted across snapshots.
+    #[serde(skip)]
+    boot_milestones: Vec<BootMilestone>,
+
+    /// Statistical PC profiler state, active when `set_profiling(true, _)`
+    /// has been called. Not persisted across snapshots.
+    #[serde(skip)]
+    profiler: Option<Profiler>,
+
+    /// Configurable memory-latency model set by `set_timing_model`, `None`
+    /// (off) by default. Not persisted across snapshots.
+    #[serde(skip)]
+    timing_model: Option<TimingModel>,
+
+    /// Penalty cycles accumulated by `SystemBus` during the step currently
+    /// in flight, drained into the CSR cycle counter and CLINT `mtime` by
+    /// `run_with_reason` once the step completes.
+    #[serde(skip)]
+    timing_penalty: u64,
+
+    /// Running total of accesses `SystemBus` has dispatched to a device
+    /// (CLINT/UART/PLIC/virtio9p) rather than RAM/ROM, across the whole
+    /// system's lifetime. `run_with_reason`'s stuck-loop detector diffs this
+    /// across a step to tell a tight I/O-polling loop (which keeps making
+    /// this counter move) from a genuinely stuck compute loop.
+    #[serde(skip)]
+    mmio_access_total: u64,
+
+    /// Threshold set by `set_stuck_detector`: if the PC stays within a small
+    /// range with no device I/O for this many instructions, `run_with_reason`
+    /// stops with `HaltReason::Stuck`. `None` (the default) disables the
+    /// check. Not persisted across snapshots, like `instruction_limit`.
+    #[serde(skip)]
+    stuck_detector_threshold: Option<u32>,
+
+    /// RAM ranges populated by known boot images (kernel, initrd, DTB),
+    /// recorded as each is loaded so `create_state_delta` can recognize
+    /// pages that still match one and skip storing them. Not persisted
+    /// across snapshots - `create_state_delta`/`apply_state_delta` require
+    /// the caller to have just reloaded the same images.
+    #[serde(skip)]
+    loaded_artifacts: Vec<crate::snapshot::ArtifactRange>,
+
+    /// DTB bytes generated by the most recent `finalize_linux_boot`, kept
+    /// around (like `boot_artifacts` keeps the kernel/initrd) so
+    /// `create_state_delta` has something to diff RAM pages against.
+    #[serde(skip)]
+    boot_dtb: Option<Vec<u8>>,
+
+    /// Number of SBI timer-set calls handled (legacy `set_timer` or
+    /// `SBI_EXT_TIME`'s `sbi_set_timer`), for `get_sbi_timer_calls`. An
+    /// Sstc-aware kernel writes `stimecmp` directly and should drive this
+    /// to near-zero after boot. Not persisted across snapshots.
+    #[serde(skip)]
+    sbi_timer_calls: u64,
+
+    /// Number of SBI calls handled in total, backing the SBI PMU
+    /// extension's "SBI calls" firmware counter. Unlike `sbi_timer_calls`
+    /// this is persisted, since `PmuCounter::baseline` snapshots it and a
+    /// restored counter needs the same underlying value to diff against.
+    #[serde(default)]
+    sbi_call_count: u64,
+
+    /// Per-counter state for the SBI PMU extension's fixed counters, see
+    /// `PmuCounter`. `#[serde(default)]` lets snapshots taken before this
+    /// extension existed still deserialize, with every counter stopped.
+    #[serde(default)]
+    pmu_counters: [PmuCounter; PMU_NUM_COUNTERS],
+
+    /// Host-provided input bytes not yet delivered to the UART RX FIFO
+    /// (see `queue_input`). Drained a byte at a time as FIFO space frees so
+    /// a large paste doesn't overrun it. Not persisted across snapshots.
+    #[serde(skip)]
+    input_queue: VecDeque<u8>,
+
+    /// Minimum CLINT `mtime` ticks between two bytes released from
+    /// `input_queue` (see `set_paste_rate`), or `None` to release a byte
+    /// every time the FIFO has room (the historical, unthrottled
+    /// behavior). Not persisted across snapshots.
+    #[serde(skip)]
+    paste_ticks_per_char: Option<u64>,
+
+    /// `mtime` value at or after which `pump_input_queue` may release the
+    /// next queued byte, maintained when `paste_ticks_per_char` is set.
+    /// Not persisted across snapshots.
+    #[serde(skip)]
+    paste_next_release: u64,
+
+    /// Line-ending translation applied by `uart_receive`, set by
+    /// `set_input_crlf_mode`. Not persisted across snapshots.
+    #[serde(skip)]
+    input_crlf_mode: InputCrlfMode,
+
+    /// Whether `run_with_reason` should log each retired instruction in
+    /// Spike's commit-log format (see `set_commit_log`). Forces plain
+    /// interpretation instead of block/JIT execution while on.
+    #[serde(skip)]
+    commit_log_enabled: bool,
+
+    /// Commit-log lines accumulated since the last `take_commit_log`,
+    /// bounded by `COMMIT_LOG_CAPACITY`. Not persisted across snapshots.
+    #[serde(skip)]
+    commit_log: Vec<String>,
+
+    /// Guest stores into the boot ROM observed since the last
+    /// `take_rom_write_attempts`, bounded by `ROM_WRITE_LOG_CAPACITY`.
+    /// Recorded whenever a store faults into ROM, independent of
+    /// `Cpu::strict_memory`. Not persisted across snapshots.
+    #[serde(skip)]
+    rom_write_attempts: Vec<RomWriteAttempt>,
+
+    /// Initial PC after `reset()`/boot, set via `set_reset_pc`. Defaults to
+    /// the boot ROM's base address, matching the built-in firmware; callers
+    /// supplying their own boot ROM via `load_boot_rom` may want to point
+    /// this somewhere else within it.
+    #[serde(default = "default_reset_pc")]
+    reset_pc: u32,
+
+    /// Whether ecall-from-S is intercepted in Rust or delivered to M-mode
+    /// like real hardware, set via `set_sbi_mode`.
+    #[serde(default)]
+    sbi_mode: SbiMode,
+
+    /// Hard ceiling on `cpu.instruction_count`, set via
+    /// `set_instruction_limit`. Unlike `run`/`run_with_reason`'s
+    /// `max_cycles`, this is checked every step regardless of how many
+    /// separate calls it takes to reach it, so a sandboxed guest can't
+    /// outrun it by being called in a loop. `None` (the default) means no
+    /// limit. Not persisted across snapshots - a caller restoring a
+    /// snapshot into a sandbox re-applies whatever limit it wants.
+    #[serde(skip)]
+    instruction_limit: Option<u64>,
+
+    /// In-progress recording started by `start_recording`, if any. Not
+    /// persisted across snapshots.
+    #[serde(skip)]
+    recording: Option<RecordingState>,
+
+    /// Outgoing chunked snapshot started by `begin_snapshot_stream`, if any.
+    #[serde(skip)]
+    snapshot_stream: Option<crate::snapshot::SnapshotStream>,
+
+    /// Incoming chunked snapshot being reassembled by `feed_snapshot_chunk`,
+    /// if any.
+    #[serde(skip)]
+    snapshot_receiver: Option<crate::snapshot::SnapshotReceiver>,
+
+    /// Deterministic RNG all device/randomness consumers draw from (see
+    /// `crate::rng`), seeded via `set_rng_seed`. Persisted across snapshots
+    /// so restoring one and continuing produces the same random sequence a
+    /// live run would have. `#[serde(default)]` lets old state blobs from
+    /// before this field existed still deserialize, falling back to the
+    /// fixed default seed.
+    #[serde(default)]
+    rng: crate::rng::Rng,
+
+    /// Hart count requested via `set_hart_count`, validated to `1..=MAX_HARTS`.
+    /// This only records the count a caller wants - `cpu`, `clint`, and
+    /// `plic` are still single-hart throughout this crate (and `System64`),
+    /// so setting this above 1 does not yet bring up a second hart. See
+    /// `set_hart_count` for why real multi-hart execution isn't part of this
+    /// change.
+    #[serde(default = "default_hart_count")]
+    hart_count: u32,
+
+    /// Whether `run_with_reason` should record every `IllegalInstruction`
+    /// trap (see `set_illegal_instruction_log`). Off by default since a
+    /// guest that's already booting cleanly has no use for it.
+    #[serde(skip)]
+    illegal_instruction_log_enabled: bool,
+
+    /// Illegal-instruction encounters observed since the last
+    /// `take_illegal_instructions`, aggregated by raw instruction bits so a
+    /// hot unimplemented encoding shows up as one entry with a rising count
+    /// instead of flooding the log with duplicates. Bounded by
+    /// `ILLEGAL_INSTRUCTION_LOG_CAPACITY` distinct encodings. Not persisted
+    /// across snapshots.
+    #[serde(skip)]
+    illegal_instructions: std::collections::HashMap<u32, IllegalInstructionRecord>,
+}
+
+/// Upper bound accepted by `set_hart_count`. Matches common small-SMP guest
+/// configurations (e.g. QEMU virt's default `-smp 4`) without requiring
+/// this crate's single `Cpu`/`Clint`/`Plic` to somehow represent more harts
+/// than a guest kernel is likely to be tested against.
+pub const MAX_HARTS: u32 = 4;
+
+fn default_hart_count() -> u32 {
+    1
+}
+
+/// Internal sampling state for `set_profiling`/`take_profile_samples`.
+struct Profiler {
+    /// Sample every this many retired instructions.
+    interval: u64,
+    /// Instruction count at or past which the next sample should fire.
+    next_sample: u64,
+    /// Ring buffer of collected samples, bounded by `PROFILE_CAPACITY`.
+    samples: Vec<ProfileSample>,
+}
+
+impl Profiler {
+    fn new(interval: u64, instruction_count: u64) -> Self {
+        Self {
+            interval: interval.max(1),
+            next_sample: instruction_count,
+            samples: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, pc: u32, satp: u32, priv_level: crate::cpu::PrivilegeLevel, instruction_count: u64) {
+        if self.samples.len() >= PROFILE_CAPACITY {
+            self.samples.remove(0);
+        }
+        self.samples.push(ProfileSample { pc, satp, priv_level, instruction_count });
+        self.next_sample = instruction_count + self.interval;
+    }
+}
+
+fn default_isa_string() -> String {
+    "rv32imafd".to_string()
+}
+
+fn default_reset_pc() -> u32 {
+    crate::memory::ROM_BASE
+}
+
+/// Value reported for `mimpid`/`sbi_get_mimpid` by default, bumped when the
+/// emulator's guest-visible behavior changes in a way worth a kernel
+/// detecting. Not tied to the crate's own semver.
+const DEFAULT_IMPL_ID: u32 = 1;
+
+/// Bundles the identity/extension fields that need to agree across the
+/// `misa`/`mvendorid`/`marchid`/`mimpid` CSRs, the SBI base extension, and
+/// the DTB `riscv,isa` property - so a kernel that cross-checks them (e.g.
+/// for `/proc/cpuinfo`) doesn't see contradictions. Apply with
+/// `System::set_isa_config`, or pass to `System::new_with_isa` to configure
+/// a fresh system, e.g. for conformance tests that need a reduced ISA.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IsaConfig {
+    /// ISA extension string, e.g. "imafd" or "ima" (an `rv32`/`rv64` prefix
+    /// is accepted but ignored - see `Cpu::set_isa`).
+    pub extensions: String,
+    /// `mvendorid`/`sbi_get_mvendorid`: JEDEC vendor ID, 0 = not implemented.
+    pub vendor_id: u32,
+    /// `marchid`/`sbi_get_marchid`: microarchitecture ID, 0 = not implemented.
+    pub arch_id: u32,
+    /// `mimpid`/`sbi_get_mimpid`: implementation version.
+    pub impl_id: u32,
+}
+
+impl Default for IsaConfig {
+    fn default() -> Self {
+        IsaConfig {
+            extensions: default_isa_string(),
+            vendor_id: 0,
+            arch_id: 0,
+            impl_id: DEFAULT_IMPL_ID,
+        }
+    }
+}
+
+/// Outcome of a guest write to `tohost`, decoded per the riscv-tests htif
+/// pass/fail protocol: bit 0 set means the target program is done; the
+/// remaining bits, if nonzero, give the (1-based) number of the failing
+/// test.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TohostResult {
+    /// All tests passed.
+    Pass,
+    /// The test numbered `test` (from the `tohost` value's upper bits) failed.
+    Fail(u32),
+    /// A device command block address rather than a plain pass/fail code
+    /// (e.g. character output); not decoded further.
+    Other(u32),
+}
+
+/// Why `run_chunked` stopped before its total cycle budget was exhausted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunStopReason {
+    /// Ran the full requested `total_cycles`.
+    Budget,
+    /// `should_continue` returned `false` between chunks.
+    Callback,
+    /// A guest panic/oops was detected (see `set_panic_detection`).
+    Panic,
+    /// The guest exited via semihosting `SYS_EXIT`.
+    Exited,
+    /// The guest wrote to the watched `tohost` address.
+    Tohost,
+}
+
+/// Result of `run_chunked`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkedRunResult {
+    /// Total cycles actually executed across all chunks.
+    pub cycles: u32,
+    /// Why execution stopped.
+    pub reason: RunStopReason,
+}
+
+/// Result of `run_program`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunOutcome {
+    /// All bytes the guest wrote to the UART over the run.
+    pub output: Vec<u8>,
+    /// Instructions (cycles) actually executed.
+    pub instructions: u64,
+    /// Why execution stopped.
+    pub halt_reason: RunStopReason,
+}
+
+/// Why `run_with_reason` returned before or at its cycle budget, at a finer
+/// grain than `RunStopReason` — this distinguishes CPU-level stop conditions
+/// (WFI, an unhandled trap) rather than caller-facing ones (a cooperative
+/// callback returning false).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HaltReason {
+    /// Ran the full requested cycle budget without otherwise stopping.
+    Completed,
+    /// The CPU executed WFI and no enabled interrupt source can ever wake
+    /// it (no pending interrupt and the timer is not armed).
+    Wfi,
+    /// The guest powered itself off (SBI SRST shutdown, or the legacy
+    /// shutdown ecall).
+    PoweredOff,
+    /// The guest asked to reboot (SBI SRST cold/warm reset) and
+    /// `auto_reboot` is off, so it's up to the caller to decide whether and
+    /// how to bring it back (see `reboot`).
+    RebootRequested,
+    /// Hit an EBREAK at the given address.
+    Breakpoint(u32),
+    /// Control reached PC 0, almost always a jump through a null pointer.
+    PcZero,
+    /// A trap occurred with no handler installed for it (`mtvec`/`stvec`
+    /// both unset), so continuing would just trap again at PC 0.
+    Trap,
+    /// The same instruction faulted and trapped repeatedly - a handler is
+    /// installed, but it isn't making progress (e.g. it faults itself), so
+    /// continuing would just spin forever.
+    TrapLoop,
+    /// `cpu.instruction_count` reached the ceiling set by
+    /// `set_instruction_limit`.
+    LimitReached,
+    /// The PC stayed within a small range with no device I/O for at least
+    /// `set_stuck_detector`'s threshold - unlike `TrapLoop`, no trap is
+    /// involved, so this catches a guest spinning on a compute-only
+    /// infinite loop (e.g. `j .`) that would otherwise run out the clock.
+    Stuck,
+}
+
+/// One recorded MMIO access, captured by `trace_mmio`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MmioTraceEntry {
+    /// Guest instruction count at the time of the access.
+    pub instruction_count: u64,
+    /// Guest PC of the block/instruction that performed the access (block
+    /// granularity for JIT-executed code, exact for the interpreter).
+    pub pc: u32,
+    /// MMIO address accessed.
+    pub addr: u32,
+    /// Access width in bytes (1, 2, or 4).
+    pub size: u8,
+    /// `true` for a write, `false` for a read.
+    pub is_write: bool,
+    /// Value read or written.
+    pub value: u32,
+}
+
+/// Maximum number of entries kept by the MMIO trace ring buffer; oldest
+/// entries are dropped once full so a long trace can't grow unbounded.
+const MMIO_TRACE_CAPACITY: usize = 8192;
+
+/// Bound on `System::commit_log`, same policy as `MMIO_TRACE_CAPACITY`:
+/// once full, further lines are silently dropped rather than growing
+/// unbounded, since a caller comparing against Spike is expected to drain
+/// it regularly with `take_commit_log`.
+const COMMIT_LOG_CAPACITY: usize = 65536;
+
+/// Bound on `System::rom_write_attempts`, same drop-oldest-on-drain policy
+/// as `MMIO_TRACE_CAPACITY`.
+const ROM_WRITE_LOG_CAPACITY: usize = 256;
+
+/// One recorded guest store into the read-only boot ROM, captured
+/// regardless of whether `Cpu::strict_memory` is set - a guest write here
+/// is always a bug, so it's always worth surfacing to the debug API.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RomWriteAttempt {
+    /// Guest PC of the instruction that attempted the write.
+    pub pc: u32,
+    /// ROM address the guest tried to write to.
+    pub addr: u32,
+}
+
+/// Bound on the number of distinct raw encodings tracked by
+/// `System::illegal_instructions`, same drop-oldest-encoding-not-tracked
+/// policy as `ROM_WRITE_LOG_CAPACITY`: once full, a brand-new encoding is
+/// dropped rather than evicting one already being counted, since the goal
+/// is ranking the encodings actually worth porting, not exhaustive coverage.
+const ILLEGAL_INSTRUCTION_LOG_CAPACITY: usize = 256;
+
+/// One unimplemented encoding observed as an `IllegalInstruction` trap,
+/// aggregated by `raw_inst` and returned by `take_illegal_instructions`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IllegalInstructionRecord {
+    /// Guest PC of the first instruction that hit this encoding.
+    pub pc: u32,
+    /// The raw 32-bit instruction word that faulted.
+    pub raw_inst: u32,
+    /// `raw_inst` bits [6:0], the base RISC-V opcode field.
+    pub opcode: u32,
+    /// `raw_inst` bits [14:12] (funct3). Meaningless for encodings that
+    /// don't use it, but cheap to always report.
+    pub funct3: u32,
+    /// `raw_inst` bits [31:25] (funct7). Same caveat as `funct3`.
+    pub funct7: u32,
+    /// Number of times this exact `raw_inst` has trapped since the log was
+    /// last drained.
+    pub count: u64,
+}
+
+/// One statistically-sampled point of execution, captured by
+/// `set_profiling` and drained by `take_profile_samples`. Sampling
+/// piggybacks on the existing timer-batch boundary in `run_with_reason`,
+/// so a sample reflects wherever `pc` was at the start of the next
+/// executed block rather than an exact instruction - for JIT/block
+/// execution, an entire block is attributed to its first instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProfileSample {
+    /// Guest PC at the time of the sample.
+    pub pc: u32,
+    /// `satp` at the time of the sample, so samples from different address
+    /// spaces (e.g. different processes under the same guest) don't get
+    /// aggregated together.
+    pub satp: u32,
+    /// Privilege level at the time of the sample.
+    pub priv_level: crate::cpu::PrivilegeLevel,
+    /// Guest instruction count at the time of the sample.
+    pub instruction_count: u64,
+}
+
+/// One (satp, pc-bucket) aggregate produced by `aggregate_profile_samples`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProfileBucket {
+    pub satp: u32,
+    pub pc_bucket: u32,
+    pub count: u64,
+}
+
+/// Aggregate raw samples into per-(satp, pc-bucket) hit counts, coarsening
+/// `pc` down to `bucket_size`-aligned buckets (e.g. 64 merges samples that
+/// land in the same small function) so a flame graph has fewer, denser
+/// frames instead of one per exact PC. Returned sorted hottest-first.
+pub fn aggregate_profile_samples(samples: &[ProfileSample], bucket_size: u32) -> Vec<ProfileBucket> {
+    let bucket_size = bucket_size.max(1);
+    let mut counts: std::collections::HashMap<(u32, u32), u64> = std::collections::HashMap::new();
+    for s in samples {
+        let bucket = (s.pc / bucket_size) * bucket_size;
+        *counts.entry((s.satp, bucket)).or_insert(0) += 1;
+    }
+    let mut buckets: Vec<ProfileBucket> = counts
+        .into_iter()
+        .map(|((satp, pc_bucket), count)| ProfileBucket { satp, pc_bucket, count })
+        .collect();
+    buckets.sort_by(|a, b| b.count.cmp(&a.count));
+    buckets
+}
+
+/// Maximum number of profile samples retained by `set_profiling`; oldest
+/// samples are dropped once full, same policy as the MMIO trace buffer.
+const PROFILE_CAPACITY: usize = 65536;
+
+/// Cycles folded into `timing_penalty` per virtio-9p descriptor serviced by
+/// `pump_virtio`, so guest-visible time advances while heavy I/O is being
+/// processed instead of it looking free. Rough order-of-magnitude estimate
+/// for a descriptor's worth of MMIO-adjacent work, not a modeled figure.
+const VIRTIO_DESC_CYCLES: u64 = 16;
+
+/// Configurable memory-access latency model for rough guest-perceived
+/// timing, off by default (see `System::set_timing_model`). Purely
+/// additive: penalty cycles accumulated by `SystemBus` and the MMU are
+/// folded into the CSR cycle counter and CLINT `mtime` alongside the
+/// normal per-instruction accounting, so functional behavior - including
+/// how many instructions retire - is unchanged; only how fast
+/// guest-visible time passes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimingModel {
+    /// Extra cycles charged per RAM access.
+    pub ram_cycles: u32,
+    /// Extra cycles charged per MMIO device access.
+    pub mmio_cycles: u32,
+    /// Extra cycles charged per MMU TLB miss.
+    pub tlb_miss_cycles: u32,
+}
+
+/// Line-ending translation applied to bytes delivered to the UART by
+/// `uart_receive`/`queue_input`, set with `System::set_input_crlf_mode`.
+/// Centralizes the `\r`-to-`\n` rewrite the CLI otherwise hardcodes in its
+/// own stdin loop, so wasm and other embedders get the same terminal
+/// behavior without reimplementing it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputCrlfMode {
+    /// Deliver bytes unchanged (default).
+    #[default]
+    None,
+    /// Rewrite `\r` to `\n`, e.g. a raw-mode terminal sending Enter as CR.
+    CrToLf,
+    /// Rewrite `\n` to `\r`, for peers that expect CR-terminated lines.
+    LfToCr,
+}
+
+/// Destination of an in-progress `begin_load`/`load_chunk`/`finish_load`
+/// streaming transfer.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LoadTarget {
+    Kernel,
+    Initrd,
+}
+
+/// State tracked between `begin_load` and `finish_load`.
+struct PendingLoad {
+    target: LoadTarget,
+    base_addr: u32,
+    total_size: u32,
+}
+
+/// Page size used to align boot image placement within RAM.
+const BOOT_PAGE_SIZE: u32 = 0x1000;
+
+/// Minimum gap kept after the kernel image before the next region (the
+/// initrd, or the DTB reservation if there's no initrd) starts - the
+/// kernel needs headroom beyond its raw on-disk length for early setup
+/// and BSS that `load_binary` doesn't account for.
+const KERNEL_GAP: u32 = 0x10_0000; // 1MB
+
+/// Upper bound reserved for the DTB at the top of RAM when computing
+/// `BootLayout`. The real DTB is generated (and sized) only after the
+/// layout is computed - see `System::load_dtb_and_boot` - so this just
+/// needs to comfortably cover what `devices::dtb::generate_fdt` produces.
+const DTB_RESERVE: u32 = 64 * 1024;
+
+/// Non-overlapping, page-aligned memory map for a Linux boot image,
+/// computed by `compute_boot_layout`. Regions are laid out low-to-high in
+/// RAM, each page-aligned and separated by at least `KERNEL_GAP`:
+///
+/// ```text
+/// DRAM_BASE                                                     ram_end
+///   [ kernel ][ KERNEL_GAP ][ initrd (optional) ][   dtb_reserve   ]
+/// ```
+struct BootLayout {
+    /// End of the kernel image (`DRAM_BASE + kernel_len`), plus `KERNEL_GAP`
+    /// - the floor every other region must start above.
+    min_next_region: u32,
+    /// `(start, end)` of the initrd region, if one was requested.
+    initrd: Option<(u32, u32)>,
+    /// `(start, end)` reserved for the DTB, at the top of RAM.
+    dtb_reserve: (u32, u32),
+}
+
+/// Compute `BootLayout` for a kernel of `kernel_len` bytes (and, if
+/// `initrd_len` is given, an initrd of that many bytes) in `ram_size`
+/// bytes of RAM starting at `DRAM_BASE`. Returns
+/// `SystemError::NotEnoughRam` if the regions don't all fit without
+/// overlapping.
+fn compute_boot_layout(ram_size: u32, kernel_len: u32, initrd_len: Option<u32>) -> Result<BootLayout, SystemError> {
+    let not_enough_ram = || SystemError::NotEnoughRam { kernel_len, initrd_len: initrd_len.unwrap_or(0) };
+
+    let ram_end = DRAM_BASE.checked_add(ram_size).ok_or_else(not_enough_ram)?;
+    let kernel_end = DRAM_BASE.checked_add(kernel_len).ok_or_else(not_enough_ram)?;
+    let min_next_region = kernel_end.checked_add(KERNEL_GAP).ok_or_else(not_enough_ram)?;
+
+    let dtb_reserve_start = ram_end.saturating_sub(DTB_RESERVE) & !(BOOT_PAGE_SIZE - 1);
+    if dtb_reserve_start < min_next_region {
+        return Err(not_enough_ram());
+    }
+
+    let initrd = match initrd_len {
+        Some(len) => {
+            let initrd_start = dtb_reserve_start.saturating_sub(len) & !(BOOT_PAGE_SIZE - 1);
+            if initrd_start < min_next_region {
+                return Err(not_enough_ram());
+            }
+            Some((initrd_start, initrd_start + len))
+        }
+        None => None,
+    };
+
+    Ok(BootLayout { min_next_region, initrd, dtb_reserve: (dtb_reserve_start, ram_end) })
+}
+
+/// State tracked between `start_recording` and `stop_recording`.
+struct RecordingState {
+    /// `to_state_bytes` snapshot captured when recording started.
+    initial_state: Vec<u8>,
+    /// Nondeterministic inputs observed since then.
+    events: Vec<crate::replay::ReplayEvent>,
+}
+
+/// Boot images captured at `setup_linux_boot*` time, so `reboot` can restore
+/// guest RAM to a pristine boot state instead of requiring the embedder to
+/// hold onto and re-supply them.
+struct BootArtifacts {
+    kernel: Vec<u8>,
+    initrd: Option<Vec<u8>>,
+    cmdline: String,
+    /// SHA-256 of `kernel`, computed once here so `create_snapshot`/
+    /// `restore_snapshot` don't have to re-hash it on every call.
+    kernel_hash: [u8; 32],
+    /// SHA-256 of `initrd`, if any.
+    initrd_hash: Option<[u8; 32]>,
+    /// If the boot used `setup_linux_boot_with_dtb`, the exact DTB bytes it
+    /// was given - `reboot` replays those verbatim instead of regenerating
+    /// one from `cmdline` (which is empty for this kind of boot anyway).
+    user_dtb: Option<Vec<u8>>,
+}
+
+/// Guest-requested power state, set from the SBI SRST handler (and the
+/// legacy shutdown ecall) and observed by `run`/`run_with_reason` so a
+/// guest asking to power off or reboot doesn't just spin in WFI for the
+/// rest of the caller's cycle budget.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SystemPowerState {
+    /// Normal operation.
+    #[default]
+    Running,
+    /// The guest asked to power off (SBI SRST shutdown, or the legacy
+    /// shutdown ecall).
+    Shutdown,
+    /// The guest asked to reboot (SBI SRST cold or warm reboot).
+    RebootRequested,
+}
+
+/// How ecall-from-S is handled, set via `System::set_sbi_mode`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SbiMode {
+    /// Intercept ecall-from-S in Rust and answer it directly, bypassing
+    /// whatever's installed at `mtvec`. This is the fast path used by
+    /// `setup_linux_boot` and friends, and what every existing embedder
+    /// expects.
+    #[default]
+    Native,
+    /// Let ecall-from-S trap to M-mode like real hardware would, so a real
+    /// firmware/SBI implementation loaded via `load_boot_rom` gets to
+    /// handle it instead of the Rust shortcut.
+    Firmware,
+}
+
+/// Number of fixed counters exposed by the SBI PMU extension (see
+/// `handle_sbi_call`'s `SBI_EXT_PMU` arm): 2 hardware counters (cycles,
+/// instructions) plus 3 firmware counters backed by stats this emulator
+/// already tracks (TLB misses, JIT compiles, SBI calls).
+const PMU_NUM_COUNTERS: usize = 5;
+
+/// Per-counter state for one of the SBI PMU extension's fixed counters.
+/// The underlying stat (e.g. `cpu.instruction_count`) only ever grows, so
+/// `sbi_pmu_counter_start`/`_stop` snapshot it rather than resetting it.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+struct PmuCounter {
+    /// Set between a `sbi_pmu_counter_start` and its matching
+    /// `sbi_pmu_counter_stop`.
+    running: bool,
+    /// The underlying stat's value when this counter was last started.
+    baseline: u64,
+    /// Delta banked by previous start/stop cycles (plus `initial_value`, if
+    /// `sbi_pmu_counter_start`'s `INIT_VALUE` flag was set), since the
+    /// underlying stat itself is never reset.
+    banked: u64,
+}
+
+impl PmuCounter {
+    /// Current delta `sbi_pmu_counter_fw_read` should report: `banked`,
+    /// plus however much the underlying stat has moved since `baseline` if
+    /// the counter is currently running.
+    fn value(&self, current_raw: u64) -> u64 {
+        self.banked + if self.running { current_raw.wrapping_sub(self.baseline) } else { 0 }
+    }
+}
+
+/// A guest kernel panic/oops caught by the UART output scanner.
+#[derive(Clone, Debug)]
+pub struct PanicEvent {
+    /// The pattern that matched (e.g. "Kernel panic -")
+    pub pattern: String,
+    /// Up to 512 bytes of TX output leading up to and including the match
+    pub context: Vec<u8>,
+    /// Guest instruction count at the time of detection
+    pub instruction_count: u64,
+    /// Guest PC at the time of detection
+    pub pc: u32,
+}
+
+/// Default patterns used by `set_panic_detection(true)` for Linux guests.
+pub const DEFAULT_PANIC_PATTERNS: &[&str] = &["Kernel panic -", "Oops:", "BUG:"];
+
+/// One boot-progress marker tracked by `set_boot_milestones`, reported by
+/// `get_boot_milestones`.
+#[derive(Clone, Debug)]
+pub struct BootMilestone {
+    /// Short human-readable name (e.g. "kernel").
+    pub label: String,
+    /// UART output markers that trip this milestone (any one is enough).
+    patterns: Vec<String>,
+    /// Whether the marker has been seen yet.
+    pub reached: bool,
+    /// Guest instruction count when the marker was seen.
+    pub instruction_count: Option<u64>,
+    /// Guest `mtime` (CLINT) when the marker was seen.
+    pub mtime: Option<u64>,
+}
+
+/// Default milestones used by `set_boot_milestones(true)` for Linux guests,
+/// in the order a normal boot crosses them. The shell-prompt patterns match
+/// the ones `output_has_prompt` in `main.rs` looks for.
+const DEFAULT_BOOT_MILESTONES: &[(&str, &[&str])] = &[
+    ("firmware", &["OpenSBI"]),
+    ("kernel", &["Linux version"]),
+    ("init", &["Run /sbin/init"]),
+    ("prompt", &["\n# ", "\n$ ", "\n~ $", "\n~# "]),
+];
+
+/// Error returned by a fallible `System` operation.
+///
+/// Most variants carry just enough detail to reconstruct the message (and,
+/// for embedders that care, to match on the failure kind instead of
+/// scraping `to_string()`). Failures bubbled up from a lower layer (`Cpu`,
+/// `Memory`, `bincode`, `zstd`, `crate::replay`) that don't yet have their
+/// own structured error type land in `Other`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SystemError {
+    InvalidRamSize(u32),
+    InvalidHartCount { max: u32, got: u32 },
+    UnknownMmioTarget(String),
+    UnknownLoadTarget(String),
+    ImageTooLarge { total_size: u32 },
+    NoStreamingLoad,
+    ChunkOutOfRange,
+    NoKernelForInitrd,
+    NoStreamedKernel,
+    NotEnoughRam { kernel_len: u32, initrd_len: u32 },
+    DtbOverlapsInitrd { dtb_len: u32, dtb_start: u32, dtb_end: u32, initrd_start: u32, initrd_end: u32 },
+    InvalidDtb(crate::devices::dtb::DtbError),
+    NoBootArtifacts,
+    NoBootArtifactsForSnapshot,
+    ArtifactRequiredNotLoaded(String),
+    ArtifactMismatch(String),
+    ArtifactReferencedNotLoaded(String),
+    ArtifactTooShort(String),
+    RamImageSizeMismatch { actual: usize, expected: usize },
+    SnapshotVersionMismatch { found: u32, expected: u32 },
+    SnapshotMismatch(String),
+    NoActiveRecording,
+    NoActiveSnapshotStream,
+    NoActiveSnapshotRestore,
+    SnapshotStreamError(String),
+    StateBlobTooShort,
+    BadStateMagic,
+    UnsupportedStateVersion { found: u32, expected: u32 },
+    PcNotMapped(u32),
+    UartCountMismatch { found: usize, expected: usize },
+    DecompressedTooLarge { limit: u64 },
+    /// A `MachineConfig` passed to `from_config` described an invalid
+    /// machine. `field` names the offending config field so an embedder
+    /// can point the error straight back at what they set.
+    InvalidMachineConfig { field: &'static str, reason: String },
+    Other(String),
+}
+
+impl std::fmt::Display for SystemError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SystemError::InvalidRamSize(mb) => write!(f, "Invalid RAM size: {}MB", mb),
+            SystemError::InvalidHartCount { max, got } => {
+                write!(f, "hart count must be 1..={}, got {}", max, got)
+            }
+            SystemError::UnknownMmioTarget(target) => write!(
+                f,
+                "unknown MMIO trace target '{}' (expected uart|plic|clint|virtio)",
+                target
+            ),
+            SystemError::UnknownLoadTarget(target) => write!(f, "Unknown load target: {}", target),
+            SystemError::ImageTooLarge { total_size } => {
+                write!(f, "Image of {} bytes doesn't fit in RAM", total_size)
+            }
+            SystemError::NoStreamingLoad => write!(f, "no streaming load in progress"),
+            SystemError::ChunkOutOfRange => write!(f, "chunk extends past the declared total_size"),
+            SystemError::NoKernelForInitrd => write!(f, "kernel must be loaded before initrd"),
+            SystemError::NoStreamedKernel => write!(f, "no kernel loaded via the streaming API"),
+            SystemError::NotEnoughRam { kernel_len, initrd_len } => write!(
+                f,
+                "Not enough RAM for kernel ({} bytes) and initrd ({} bytes)",
+                kernel_len, initrd_len
+            ),
+            SystemError::DtbOverlapsInitrd { dtb_len, dtb_start, dtb_end, initrd_start, initrd_end } => write!(
+                f,
+                "DTB ({} bytes at 0x{:08x}-0x{:08x}) would overlap the initrd (0x{:08x}-0x{:08x})",
+                dtb_len, dtb_start, dtb_end, initrd_start, initrd_end
+            ),
+            SystemError::InvalidDtb(err) => write!(f, "{}", err),
+            SystemError::NoBootArtifacts => write!(f, "no boot images captured to reboot from"),
+            SystemError::NoBootArtifactsForSnapshot => {
+                write!(f, "no kernel/initrd loaded to restore the snapshot against")
+            }
+            SystemError::ArtifactRequiredNotLoaded(name) => {
+                write!(f, "delta requires artifact '{}' but it is not loaded", name)
+            }
+            SystemError::ArtifactMismatch(name) => write!(
+                f,
+                "loaded artifact '{}' does not match the one the delta was created against",
+                name
+            ),
+            SystemError::ArtifactReferencedNotLoaded(name) => {
+                write!(f, "delta references artifact '{}' but it is not loaded", name)
+            }
+            SystemError::ArtifactTooShort(name) => {
+                write!(f, "artifact '{}' too short for referenced page", name)
+            }
+            SystemError::RamImageSizeMismatch { actual, expected } => write!(
+                f,
+                "RAM image is {} bytes, expected {} for this system's RAM size",
+                actual, expected
+            ),
+            SystemError::SnapshotVersionMismatch { found, expected } => write!(
+                f,
+                "snapshot format version {} does not match this build's version {}",
+                found, expected
+            ),
+            SystemError::SnapshotMismatch(detail) => write!(f, "{}", detail),
+            SystemError::NoActiveRecording => write!(f, "no recording in progress"),
+            SystemError::NoActiveSnapshotStream => write!(f, "no snapshot stream in progress"),
+            SystemError::NoActiveSnapshotRestore => write!(f, "no snapshot restore in progress"),
+            SystemError::SnapshotStreamError(msg) => write!(f, "snapshot stream error: {}", msg),
+            SystemError::StateBlobTooShort => write!(f, "state blob is too short to contain a header"),
+            SystemError::BadStateMagic => write!(f, "state blob has an unrecognized magic number"),
+            SystemError::UnsupportedStateVersion { found, expected } => write!(
+                f,
+                "unsupported state format version {} (expected {})",
+                found, expected
+            ),
+            SystemError::PcNotMapped(pc) => write!(f, "pc {:#x} does not point at mapped memory", pc),
+            SystemError::UartCountMismatch { found, expected } => write!(
+                f,
+                "uart count {} does not match expected {}",
+                found, expected
+            ),
+            SystemError::DecompressedTooLarge { limit } => {
+                write!(f, "decompressed state exceeds {} byte limit", limit)
+            }
+            SystemError::InvalidMachineConfig { field, reason } => {
+                write!(f, "MachineConfig.{}: {}", field, reason)
+            }
+            SystemError::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SystemError {}
+
+impl From<String> for SystemError {
+    fn from(msg: String) -> Self {
+        SystemError::Other(msg)
+    }
+}
+
+impl From<SystemError> for String {
+    fn from(err: SystemError) -> Self {
+        err.to_string()
+    }
+}
+
+/// Filesystem backend for `MachineConfig`, with any initial contents to
+/// seed it with before the guest ever gets a chance to see it. Mirrors the
+/// `Backend` choice `System::new` makes from an `Option<&str>` path, but
+/// lets `from_config` populate it before boot setup runs.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub enum FsConfig {
+    /// In-memory 9p backend (the default), optionally seeded from a
+    /// `System::take_filesystem_overlay`-shaped blob.
+    #[default]
+    InMemory,
+    /// In-memory 9p backend seeded from a `take_filesystem_overlay` blob.
+    InMemoryWithOverlay(Vec<u8>),
+    /// Host-backed 9p, mounted at this path. Not available on wasm32 -
+    /// `from_config` falls back to an empty in-memory filesystem there,
+    /// same as `System::new`.
+    Host(String),
+}
+
+/// Everything needed to boot a usable machine, gathered into one value so
+/// `System::from_config` can perform the `new`/filesystem-population/
+/// `setup_linux_boot*`/`enable_jit_v2` sequence in the one order that's
+/// actually correct - e.g. the filesystem has to exist before it can be
+/// seeded, and boot setup has to run before anything depends on the guest
+/// having started. The lower-level methods `from_config` calls are still
+/// there for callers who need to deviate from this sequence; reach for
+/// this first.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MachineConfig {
+    /// RAM size in MiB. See `System::new`.
+    pub ram_mb: u32,
+    /// Kernel image to load at `DRAM_BASE`. Empty skips boot setup
+    /// entirely, leaving a bare machine for `load_binary`/`--raw`-style use.
+    pub kernel: Vec<u8>,
+    /// Initrd image, placed after the kernel. Only valid alongside a
+    /// non-empty `kernel`.
+    pub initrd: Option<Vec<u8>>,
+    /// Kernel command line.
+    pub cmdline: String,
+    /// Whether to enable the v2 (CFG-optimizing) JIT backend.
+    pub jit_v2: bool,
+    /// Filesystem backend and initial contents.
+    pub fs: FsConfig,
+    /// RNG seed for deterministic runs. See `System::set_rng_seed`.
+    pub rng_seed: Option<u64>,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        MachineConfig {
+            ram_mb: 128,
+            kernel: Vec::new(),
+            initrd: None,
+            cmdline: "console=ttyS0".to_string(),
+            jit_v2: false,
+            fs: FsConfig::default(),
+            rng_seed: None,
+        }
+    }
 }
 
 impl System {
     /// Create a new system with the specified RAM size and optional host FS path
-    pub fn new(ram_size_mb: u32, fs_path: Option<&str>) -> Result<Self, String> {
+    pub fn new(ram_size_mb: u32, fs_path: Option<&str>) -> Result<Self, SystemError> {
         if ram_size_mb == 0 || ram_size_mb > 2048 {
-            return Err(format!("Invalid RAM size: {}MB", ram_size_mb));
+            return Err(SystemError::InvalidRamSize(ram_size_mb));
         }
         
         let mut memory = Memory::new(ram_size_mb);
-        memory.init_boot_rom();
+        memory.init_boot_rom(DRAM_BASE);
         
         let fs_backend = if let Some(_path) = fs_path {
             #[cfg(not(target_arch = "wasm32"))]
@@ -82,90 +1177,886 @@ impl System {
         Ok(System {
             cpu: Cpu::new(),
             memory,
-            uart: Uart::new(UART_IRQ),
+            uarts: UART_IRQS.iter().map(|&irq| Uart::new(irq)).collect(),
             clint: Clint::new(),
             plic: Plic::new(),
             virtio9p: Virtio9p::new("rootfs", fs_backend),
             block_cache: BlockCache::new(),
             jit_state: JitState::new(),
             use_jit_v2: false,  // Disabled by default, enable with --jit-v2 flag
+            pending_panic_event: None,
+            pending_load: None,
+            streamed_kernel_size: None,
+            streamed_initrd_range: None,
+            tohost_addr: None,
+            tohost_pending: None,
+            tohost_result: None,
+            isa_string: default_isa_string(),
+            mmio_trace_ranges: Vec::new(),
+            protected_ranges: Vec::new(),
+            mmio_trace_buf: Vec::new(),
+            power_state: SystemPowerState::Running,
+            auto_reboot: false,
+            wipe_fs_on_reset: false,
+            filesystem_persist_pending: false,
+            boot_artifacts: None,
+            boot_milestones: Vec::new(),
+            profiler: None,
+            timing_model: None,
+            timing_penalty: 0,
+            mmio_access_total: 0,
+            stuck_detector_threshold: None,
+            loaded_artifacts: Vec::new(),
+            boot_dtb: None,
+            sbi_timer_calls: 0,
+            sbi_call_count: 0,
+            pmu_counters: [PmuCounter::default(); PMU_NUM_COUNTERS],
+            input_queue: VecDeque::new(),
+            paste_ticks_per_char: None,
+            paste_next_release: 0,
+            input_crlf_mode: InputCrlfMode::None,
+            commit_log_enabled: false,
+            commit_log: Vec::new(),
+            rom_write_attempts: Vec::new(),
+            reset_pc: default_reset_pc(),
+            sbi_mode: SbiMode::default(),
+            instruction_limit: None,
+            recording: None,
+            snapshot_stream: None,
+            snapshot_receiver: None,
+            rng: crate::rng::Rng::default(),
+            hart_count: default_hart_count(),
+            illegal_instruction_log_enabled: false,
+            illegal_instructions: std::collections::HashMap::new(),
         })
     }
-    
+
+    /// Build and boot a machine from a `MachineConfig` in the one order
+    /// that's actually correct: create the system, seed the filesystem,
+    /// run boot setup, then flip on the JIT and RNG seed. Returns a
+    /// `SystemError::InvalidMachineConfig` naming the offending field for
+    /// combinations that don't make sense (e.g. an initrd with no kernel).
+    pub fn from_config(config: &MachineConfig) -> Result<Self, SystemError> {
+        if config.initrd.is_some() && config.kernel.is_empty() {
+            return Err(SystemError::InvalidMachineConfig {
+                field: "initrd",
+                reason: "initrd was provided but kernel is empty".to_string(),
+            });
+        }
+
+        let fs_path = match &config.fs {
+            FsConfig::Host(path) => Some(path.as_str()),
+            FsConfig::InMemory | FsConfig::InMemoryWithOverlay(_) => None,
+        };
+        let mut sys = System::new(config.ram_mb, fs_path)?;
+
+        if let Some(seed) = config.rng_seed {
+            sys.set_rng_seed(seed);
+        }
+
+        if let FsConfig::InMemoryWithOverlay(overlay) = &config.fs {
+            sys.load_filesystem_overlay(overlay)?;
+        }
+
+        if !config.kernel.is_empty() {
+            sys.setup_linux_boot_with_initrd(&config.kernel, config.initrd.as_deref(), &config.cmdline)?;
+        }
+
+        sys.enable_jit_v2(config.jit_v2);
+
+        Ok(sys)
+    }
+
+    /// Set (or clear, with `None`) a hard ceiling on the total number of
+    /// instructions this system will ever retire, checked by
+    /// `run`/`run_with_reason` after every block/instruction regardless of
+    /// `max_cycles` - useful for sandboxing untrusted guest code, where a
+    /// caller needs a limit that can't be exceeded even across many
+    /// separate `run` calls. Once `cpu.instruction_count` reaches the
+    /// limit, `run_with_reason` stops with `HaltReason::LimitReached`.
+    pub fn set_instruction_limit(&mut self, limit: Option<u64>) {
+        self.instruction_limit = limit;
+    }
+
+    /// Set (or clear, with `None`) the stuck-loop detector: if the PC stays
+    /// within a small range with no device I/O for `threshold` consecutive
+    /// instructions, `run_with_reason` stops with `HaltReason::Stuck`
+    /// instead of burning the rest of the cycle budget on a compute-only
+    /// infinite loop. Off by default - unlike `instruction_limit`, a tight
+    /// polling loop or busy-wait is legitimate guest behavior, so this is
+    /// opt-in for callers that know their guest should always be making
+    /// forward progress (e.g. a conformance test, not an interactive OS).
+    pub fn set_stuck_detector(&mut self, threshold: Option<u32>) {
+        self.stuck_detector_threshold = threshold;
+    }
+
+    /// Record how many harts a caller wants (`1..=MAX_HARTS`), for guests
+    /// that want to see a non-1 `riscv,boot-hart`/HSM hart count.
+    ///
+    /// This is deliberately scoped down from full SMP: actually bringing up
+    /// a second hart needs a per-hart `Cpu` (with its own WFI/reservation
+    /// state and JIT block cache), a CLINT/PLIC that address multiple harts
+    /// instead of the fixed hart 0 they implement today, an HSM `hart_start`
+    /// that can place a secondary at a start address with `a1` set to the
+    /// opaque parameter, per-hart DTB `cpu` nodes and interrupt-controller
+    /// phandles, and a scheduler that interleaves harts inside `run` - a
+    /// change that touches `Cpu`, `Clint`, `Plic`, `System64`, the snapshot
+    /// format, and `dtb.rs` all at once, and deserves its own design pass
+    /// rather than landing half-threaded-through alongside everything else.
+    /// This just reserves the count so that work has somewhere to start;
+    /// `cpu`/`clint`/`plic` remain single-hart until it does.
+    pub fn set_hart_count(&mut self, count: u32) -> Result<(), SystemError> {
+        if count == 0 || count > MAX_HARTS {
+            return Err(SystemError::InvalidHartCount { max: MAX_HARTS, got: count });
+        }
+        self.hart_count = count;
+        Ok(())
+    }
+
+    /// Hart count set via `set_hart_count` (default 1). See its docs for why
+    /// this doesn't yet change how many harts actually execute.
+    pub fn hart_count(&self) -> u32 {
+        self.hart_count
+    }
+
+    /// Reseed the system's RNG (see `crate::rng`). Two systems seeded the
+    /// same way and driven by the same calls draw identical random values
+    /// from every device/feature that pulls from `rng_next_u32`/
+    /// `rng_next_u64`/`rng_fill_bytes`.
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        self.rng = crate::rng::Rng::new(seed);
+    }
+
+    /// Draw the next 32 bits from the system's RNG. For device/feature code
+    /// that needs randomness (virtio-rng, ASLR-ish layout choices) instead
+    /// of reaching for a source of its own, so `set_rng_seed` covers it too.
+    pub fn rng_next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    /// Draw the next 64 bits from the system's RNG. See `rng_next_u32`.
+    pub fn rng_next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    /// Fill `buf` with bytes drawn from the system's RNG. See `rng_next_u32`.
+    pub fn rng_fill_bytes(&mut self, buf: &mut [u8]) {
+        self.rng.fill_bytes(buf);
+    }
+
     /// Enable JIT v2 (advanced page-based JIT with CFG optimization)
     pub fn enable_jit_v2(&mut self, enable: bool) {
         self.use_jit_v2 = enable;
     }
-    
-    /// Load a binary at the specified address
-    pub fn load_binary(&mut self, data: &[u8], addr: u32) -> Result<(), String> {
-        self.memory.load_binary(data, addr)
+
+    /// Apply tunable JIT knobs (block-size cap, MMIO-splitting, v2's
+    /// compile threshold) to both backends, for measuring the IPS/compile-
+    /// time tradeoff. Doesn't touch already-compiled blocks.
+    pub fn set_jit_config(&mut self, config: JitConfig) {
+        self.block_cache.configure(&config);
+        self.jit_state.configure(&config);
+        self.jit_state.set_threshold(config.threshold);
+    }
+
+    /// Drain JIT v1 WASM bytecode that's ready to instantiate but hasn't
+    /// been handed off yet. See `Emulator::jit_compile_pending`.
+    #[cfg(target_arch = "wasm32")]
+    pub fn take_pending_wasm_compiles(&mut self) -> Vec<(u32, Vec<u8>)> {
+        self.block_cache.take_pending_wasm_compiles()
+    }
+
+    /// Install a JIT v1 WASM module instantiated from bytecode handed out
+    /// by `take_pending_wasm_compiles`. See `Emulator::apply_compiled_wasm_blocks`.
+    #[cfg(target_arch = "wasm32")]
+    pub fn install_compiled_wasm_block(&mut self, paddr: u32, module_id: Option<u32>) {
+        self.block_cache.install_compiled_wasm_block(paddr, module_id);
+    }
+
+    /// JIT v1 WASM backend counters: `(pending, compiled, failed)`. See
+    /// `BlockCache`'s `wasm_pending`/`wasm_compiled`/`wasm_compile_failed`.
+    #[cfg(target_arch = "wasm32")]
+    pub fn wasm_jit_stats(&self) -> (u64, u64, u64) {
+        (
+            self.block_cache.wasm_pending,
+            self.block_cache.wasm_compiled,
+            self.block_cache.wasm_compile_failed,
+        )
+    }
+
+    /// Configure which extensions are enabled via an ISA string (e.g.
+    /// "rv32ima"), for conformance testing. Instructions from a disabled
+    /// extension trap `IllegalInstruction`, and the string also drives the
+    /// `riscv,isa` DTB property for future boots. Leaves `mvendorid`/
+    /// `marchid`/`mimpid` untouched - use `set_isa_config` to change those
+    /// too.
+    pub fn set_isa(&mut self, isa: &str) -> Result<(), SystemError> {
+        self.cpu.set_isa(isa)?;
+        self.isa_string = isa.to_string();
+        Ok(())
+    }
+
+    /// Like `set_isa`, but also sets `mvendorid`/`marchid`/`mimpid` so the
+    /// CSRs, the SBI base extension, and the DTB all agree.
+    pub fn set_isa_config(&mut self, config: IsaConfig) -> Result<(), SystemError> {
+        self.cpu.set_isa(&config.extensions)?;
+        self.cpu.csr.mvendorid = config.vendor_id;
+        self.cpu.csr.marchid = config.arch_id;
+        self.cpu.csr.mimpid = config.impl_id;
+        self.isa_string = config.extensions;
+        Ok(())
+    }
+
+    /// Construct a `System` with a non-default `IsaConfig` applied up
+    /// front, e.g. for a conformance test that needs F/D disabled.
+    pub fn new_with_isa(ram_size_mb: u32, fs_path: Option<&str>, config: IsaConfig) -> Result<Self, SystemError> {
+        let mut system = Self::new(ram_size_mb, fs_path)?;
+        system.set_isa_config(config)?;
+        Ok(system)
+    }
+
+    /// Enable or disable MMIO tracing for the `[base, base+size)` range.
+    /// Accesses that land in an enabled range are recorded (see
+    /// `take_mmio_trace`) for device driver debugging.
+    pub fn trace_mmio(&mut self, base: u32, size: u32, enable: bool) {
+        self.mmio_trace_ranges.retain(|&(b, s)| (b, s) != (base, size));
+        if enable {
+            self.mmio_trace_ranges.push((base, size));
+        }
+    }
+
+    /// Forbid the guest from accessing the physical range `[base, base+size)`
+    /// beyond what `perms` (`PROT_READ`/`PROT_WRITE`/`PROT_EXEC`, OR'd
+    /// together) allows. A disallowed load/store/fetch raises the matching
+    /// access fault to the guest instead of succeeding. This is a
+    /// host-imposed overlay checked on every physical access regardless of
+    /// what the guest's own RISC-V PMP configuration says - it's meant for
+    /// carving out e.g. a shared-buffer region the host itself owns.
+    pub fn add_protected_range(&mut self, base: u32, size: u32, perms: u8) {
+        self.protected_ranges.push((base, size, perms));
+    }
+
+    /// Remove every protection overlay previously added by
+    /// `add_protected_range`.
+    pub fn clear_protected_ranges(&mut self) {
+        self.protected_ranges.clear();
+    }
+
+    /// Map `device` into physical address space at `[base, base+size)`.
+    /// Unlike the hardcoded UART/CLINT/PLIC/VirtIO devices above, this
+    /// range is dispatched generically by `Memory` - see
+    /// `devices::callback::CallbackDevice` for a closure-backed `Device`
+    /// used by the wasm bindings to let JavaScript prototype a device.
+    pub fn add_mmio_device(&mut self, device: Box<dyn Device>, base: u32, size: u32) {
+        self.memory.add_device(device, base, size);
+    }
+
+    /// Drain and return the accesses recorded since the last call.
+    pub fn take_mmio_trace(&mut self) -> Vec<MmioTraceEntry> {
+        std::mem::take(&mut self.mmio_trace_buf)
+    }
+
+    /// Enable or disable a per-instruction commit log in Spike's `core   0:
+    /// 0xPC (0xINSN) xN 0xVAL ...` format, for diffing against Spike's own
+    /// `--log-commits` output to localize an interpreter divergence. Forces
+    /// plain interpretation (bypassing the block cache/JIT) while enabled,
+    /// so it costs real hot-loop time - leave it off outside of debugging.
+    pub fn set_commit_log(&mut self, enabled: bool) {
+        self.commit_log_enabled = enabled;
+    }
+
+    /// Drain and return the commit-log lines recorded since the last call.
+    pub fn take_commit_log(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.commit_log)
+    }
+
+    /// Enable or disable strict memory checking: with this on, a load or
+    /// store to a genuinely unmapped physical address (not ROM, RAM, or a
+    /// device) raises an access fault instead of silently reading zero or
+    /// discarding the write. Off by default to preserve the emulator's
+    /// historical lenient behavior; CI harnesses that want to catch a guest
+    /// wandering off into unmapped space should turn it on.
+    pub fn set_strict_memory(&mut self, enabled: bool) {
+        self.cpu.strict_memory = enabled;
+    }
+
+    /// Drain and return the ROM write attempts recorded since the last
+    /// call. Populated whenever a guest store faults into the boot ROM,
+    /// regardless of `set_strict_memory`.
+    pub fn take_rom_write_attempts(&mut self) -> Vec<RomWriteAttempt> {
+        std::mem::take(&mut self.rom_write_attempts)
+    }
+
+    /// Enable or disable recording of `IllegalInstruction` traps (see
+    /// `take_illegal_instructions`), to find which unimplemented encodings
+    /// a guest is actually hitting instead of guessing from a stack trace.
+    pub fn set_illegal_instruction_log(&mut self, enabled: bool) {
+        self.illegal_instruction_log_enabled = enabled;
+        if !enabled {
+            self.illegal_instructions.clear();
+        }
+    }
+
+    /// Drain and return the illegal-instruction encounters recorded since
+    /// the last call, most-hit encoding first. Empty if
+    /// `set_illegal_instruction_log` is off.
+    pub fn take_illegal_instructions(&mut self) -> Vec<IllegalInstructionRecord> {
+        let mut records: Vec<IllegalInstructionRecord> =
+            std::mem::take(&mut self.illegal_instructions).into_values().collect();
+        records.sort_by(|a, b| b.count.cmp(&a.count));
+        records
+    }
+
+    /// Instructions retired so far in each privilege level, indexed
+    /// `[user, supervisor, machine]` - see `Cpu::priv_instruction_counts`.
+    pub fn privilege_instruction_counts(&self) -> [u64; 3] {
+        self.cpu.priv_instruction_counts
+    }
+
+    /// Current value of the stat backing SBI PMU fixed counter `idx` (see
+    /// `handle_sbi_call`'s `SBI_EXT_PMU` arm for what each index maps to).
+    /// Panics on an out-of-range index; callers are expected to validate
+    /// against `PMU_NUM_COUNTERS` first.
+    fn pmu_raw_counter(&self, idx: usize) -> u64 {
+        match idx {
+            0 => self.cpu.csr.cycle,
+            1 => self.cpu.instruction_count,
+            2 => self.cpu.tlb_stats().1,
+            3 => self.block_cache.compiles,
+            4 => self.sbi_call_count,
+            _ => unreachable!("pmu counter index {idx} out of range"),
+        }
+    }
+
+    /// Record one `IllegalInstruction` trap for `take_illegal_instructions`,
+    /// called from `run_with_reason` right before the trap is delivered to
+    /// the guest. No-op unless `set_illegal_instruction_log` is on.
+    fn record_illegal_instruction(&mut self, pc: u32, raw_inst: u32) {
+        if !self.illegal_instruction_log_enabled {
+            return;
+        }
+        if let Some(record) = self.illegal_instructions.get_mut(&raw_inst) {
+            record.count += 1;
+        } else if self.illegal_instructions.len() < ILLEGAL_INSTRUCTION_LOG_CAPACITY {
+            self.illegal_instructions.insert(raw_inst, IllegalInstructionRecord {
+                pc,
+                raw_inst,
+                opcode: raw_inst & 0x7f,
+                funct3: (raw_inst >> 12) & 0x7,
+                funct7: (raw_inst >> 25) & 0x7f,
+                count: 1,
+            });
+        }
+    }
+
+    /// Enable MMIO tracing for a device by name (`uart`, `plic`, `clint`, or
+    /// `virtio`), for the `--trace-mmio` CLI flag. `uart` traces UART 0 only;
+    /// use `trace_mmio` directly with `UART_BASES[1]` for the second UART.
+    pub fn trace_mmio_device(&mut self, device: &str) -> Result<(), SystemError> {
+        let (base, size) = match device {
+            "uart" => (UART_BASES[0], UART_SIZE),
+            "plic" => (PLIC_BASE, PLIC_SIZE),
+            "clint" => (CLINT_BASE, CLINT_SIZE),
+            "virtio" => (VIRTIO_BASE, VIRTIO_SIZE),
+            other => return Err(SystemError::UnknownMmioTarget(other.to_string())),
+        };
+        self.trace_mmio(base, size, true);
+        Ok(())
+    }
+
+    /// Enable/disable guest panic detection using the default patterns
+    /// (`DEFAULT_PANIC_PATTERNS`). Use `set_panic_patterns` for custom
+    /// patterns, e.g. for non-Linux guests. Scans UART 0's output only.
+    pub fn set_panic_detection(&mut self, enabled: bool) {
+        if enabled {
+            let patterns: Vec<String> = DEFAULT_PANIC_PATTERNS.iter().map(|s| s.to_string()).collect();
+            self.uarts[0].set_panic_patterns(&patterns);
+        } else {
+            self.uarts[0].set_panic_patterns(&[]);
+        }
+    }
+
+    /// Configure custom panic/oops patterns to scan for on UART 0's output
+    /// stream. Pass an empty slice to disable scanning.
+    pub fn set_panic_patterns(&mut self, patterns: Vec<String>) {
+        self.uarts[0].set_panic_patterns(&patterns);
+    }
+
+    /// Take the most recently detected panic event, if any, clearing it.
+    pub fn take_panic_event(&mut self) -> Option<PanicEvent> {
+        self.pending_panic_event.take()
+    }
+
+    /// Enable/disable boot-progress milestone tracking using the default
+    /// markers (`DEFAULT_BOOT_MILESTONES`): firmware handoff, kernel start,
+    /// init, and shell prompt. Reuses the same UART pattern scanner as
+    /// `set_panic_detection`. See `get_boot_milestones` for progress.
+    pub fn set_boot_milestones(&mut self, enabled: bool) {
+        if enabled {
+            self.boot_milestones = DEFAULT_BOOT_MILESTONES
+                .iter()
+                .map(|(label, patterns)| BootMilestone {
+                    label: label.to_string(),
+                    patterns: patterns.iter().map(|p| p.to_string()).collect(),
+                    reached: false,
+                    instruction_count: None,
+                    mtime: None,
+                })
+                .collect();
+            let all_patterns: Vec<String> = self
+                .boot_milestones
+                .iter()
+                .flat_map(|m| m.patterns.iter().cloned())
+                .collect();
+            self.uarts[0].set_milestone_patterns(&all_patterns);
+        } else {
+            self.boot_milestones.clear();
+            self.uarts[0].set_milestone_patterns(&[]);
+        }
+    }
+
+    /// Current boot milestone progress, in configured order. Each entry is
+    /// updated in place as its marker is seen on the UART output stream.
+    pub fn get_boot_milestones(&self) -> Vec<BootMilestone> {
+        self.boot_milestones.clone()
+    }
+
+    /// Enable or disable statistical PC profiling. When enabled, every
+    /// `sample_every` retired instructions a sample of `(pc, satp,
+    /// priv_level)` is recorded from whatever execution path is currently
+    /// active - see `ProfileSample`. Disabling drops any collected samples.
+    pub fn set_profiling(&mut self, enabled: bool, sample_every: u64) {
+        if enabled {
+            self.profiler = Some(Profiler::new(sample_every, self.cpu.instruction_count));
+        } else {
+            self.profiler = None;
+        }
+    }
+
+    /// Drain and return every profile sample collected since the last call
+    /// (or since `set_profiling` was enabled). Empty if profiling is off.
+    pub fn take_profile_samples(&mut self) -> Vec<ProfileSample> {
+        self.profiler.as_mut().map(|p| std::mem::take(&mut p.samples)).unwrap_or_default()
+    }
+
+    /// Configure (or, passing all zeros, disable) a memory-latency model:
+    /// `ram_cycles`/`mmio_cycles` extra cycles are charged per RAM/MMIO
+    /// access and `tlb_miss_cycles` per MMU TLB miss, all folded into the
+    /// CSR cycle counter and CLINT `mtime` by `run_with_reason`. This only
+    /// changes how fast guest-visible time passes - functional behavior is
+    /// unaffected.
+    pub fn set_timing_model(&mut self, ram_cycles: u32, mmio_cycles: u32, tlb_miss_cycles: u32) {
+        if ram_cycles == 0 && mmio_cycles == 0 && tlb_miss_cycles == 0 {
+            self.timing_model = None;
+        } else {
+            self.timing_model = Some(TimingModel { ram_cycles, mmio_cycles, tlb_miss_cycles });
+        }
+    }
+
+    /// Exit code set by a guest semihosting `SYS_EXIT` call, if any.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.cpu.exit_code
+    }
+
+    /// Watch `addr` for a riscv-tests style `tohost` write. Once the guest
+    /// writes there, `run` stops early and `tohost_result` reports the
+    /// decoded outcome.
+    pub fn set_tohost_addr(&mut self, addr: u32) {
+        self.tohost_addr = Some(addr);
+    }
+
+    /// Decoded result of the most recent `tohost` write, if any (see
+    /// `set_tohost_addr`).
+    pub fn tohost_result(&self) -> Option<TohostResult> {
+        self.tohost_result
+    }
+
+    /// Current guest power state (see `SystemPowerState`).
+    pub fn power_state(&self) -> SystemPowerState {
+        self.power_state
+    }
+
+    /// Whether the guest has requested a shutdown since the last
+    /// `take_filesystem_overlay` call. An embedder that tears the `System`
+    /// down on poweroff (rather than keeping it resident) should poll this
+    /// after `run`/`run_with_reason` returns `HaltReason::PoweredOff` and
+    /// persist the overlay before dropping the emulator.
+    pub fn poweroff_persist_pending(&self) -> bool {
+        self.filesystem_persist_pending
+    }
+
+    /// Retry 9p requests suspended on a missing blob, then drain the
+    /// virtqueue, so a persistence snapshot taken right after doesn't miss
+    /// writes that were still in flight. Shared by `take_filesystem_overlay`
+    /// and `export_filesystem_tar`.
+    fn drain_virtio9p(&mut self) {
+        self.virtio9p.retry_suspended_requests(&mut self.memory);
+        self.virtio9p.process_queues(&mut self.memory);
+    }
+
+    /// Flush any virtio-9p writes still in flight (retrying requests
+    /// suspended on a missing blob, then draining the queue) and return a
+    /// serialized snapshot of the in-memory 9p filesystem overlay, clearing
+    /// `poweroff_persist_pending`. Returns an empty `Vec` if there's no
+    /// in-memory overlay to persist - either nothing has changed, or the
+    /// filesystem is backed directly by the host (`Backend::Host`), whose
+    /// writes already land on disk as they happen.
+    ///
+    /// This is the wasm-facing half of the poweroff persistence hook: an
+    /// embedder polls `poweroff_persist_pending` and ships the resulting
+    /// opaque blob off to e.g. IndexedDB. Native callers that want a
+    /// human-inspectable archive instead should use `export_filesystem_tar`.
+    pub fn take_filesystem_overlay(&mut self) -> Vec<u8> {
+        self.drain_virtio9p();
+        self.filesystem_persist_pending = false;
+
+        match &self.virtio9p.fs {
+            Backend::InMemory(fs) => bincode::serialize(fs).unwrap_or_default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            Backend::Host(_) => Vec::new(),
+        }
+    }
+
+    /// Restore the in-memory 9p filesystem overlay from a blob produced by
+    /// `take_filesystem_overlay`. Errors (rather than silently ignoring the
+    /// blob) if the current backend isn't `Backend::InMemory` - a
+    /// host-backed filesystem has no overlay to replace.
+    pub fn load_filesystem_overlay(&mut self, data: &[u8]) -> Result<(), SystemError> {
+        match &mut self.virtio9p.fs {
+            Backend::InMemory(fs) => {
+                *fs = bincode::deserialize(data)
+                    .map_err(|e| SystemError::Other(format!("filesystem overlay deserialization error: {}", e)))?;
+                Ok(())
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            Backend::Host(_) => Err(SystemError::Other(
+                "current filesystem backend is host-backed and has no overlay to restore".to_string(),
+            )),
+        }
+    }
+
+    /// Native counterpart to `take_filesystem_overlay`: drain outstanding
+    /// 9p writes and export the in-memory filesystem as a ustar archive
+    /// (see `InMemoryFileSystem::export_tar`), for the CLI's `--persist-fs`.
+    /// Returns `None` for a host-backed filesystem, which has nothing to
+    /// export - its writes already land on the mounted host directory.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn export_filesystem_tar(&mut self) -> Option<Vec<u8>> {
+        self.drain_virtio9p();
+        self.filesystem_persist_pending = false;
+
+        match &self.virtio9p.fs {
+            Backend::InMemory(fs) => Some(fs.export_tar()),
+            Backend::Host(_) => None,
+        }
+    }
+
+    /// Native counterpart to `load_filesystem_overlay`: replace the
+    /// in-memory filesystem's contents with a ustar archive produced by
+    /// `export_filesystem_tar` (or a typical `tar`), for the CLI's
+    /// `--import-fs`. Errors if the current backend is host-backed.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn import_filesystem_tar(&mut self, data: &[u8]) -> Result<(), SystemError> {
+        match &mut self.virtio9p.fs {
+            Backend::InMemory(fs) => fs.import_tar(data).map_err(SystemError::Other),
+            Backend::Host(_) => Err(SystemError::Other(
+                "current filesystem backend is host-backed and has no overlay to import into".to_string(),
+            )),
+        }
+    }
+
+    /// If `true`, `run`/`run_with_reason` handle a `RebootRequested` power
+    /// state internally (via `reboot`) instead of returning
+    /// `HaltReason::RebootRequested` to the caller. Off by default, since
+    /// `reboot` fails if no boot images were captured (e.g. `--raw` mode).
+    pub fn set_auto_reboot(&mut self, enable: bool) {
+        self.auto_reboot = enable;
+    }
+
+    /// Control whether `reset()` wipes an in-memory 9p filesystem. Off by
+    /// default (writes survive reset); an embedder implementing a "factory
+    /// reset" can turn this on before calling `reset()`.
+    pub fn set_wipe_fs_on_reset(&mut self, enable: bool) {
+        self.wipe_fs_on_reset = enable;
+    }
+
+    /// Reload the kernel/initrd/cmdline captured by the most recent
+    /// `setup_linux_boot*` call and reset the CPU, bringing the guest back
+    /// up as if freshly booted. Returns an error if no boot images were
+    /// captured (e.g. `load_binary` + manual PC setup was used instead).
+    pub fn reboot(&mut self) -> Result<(), SystemError> {
+        let artifacts = self.boot_artifacts.take()
+            .ok_or(SystemError::NoBootArtifacts)?;
+        let initrd = artifacts.initrd.as_deref();
+        let result = match &artifacts.user_dtb {
+            Some(dtb) => self.setup_linux_boot_with_dtb(&artifacts.kernel, initrd, dtb),
+            None => self.setup_linux_boot_with_initrd(&artifacts.kernel, initrd, &artifacts.cmdline),
+        };
+        self.power_state = SystemPowerState::Running;
+        result
+    }
+
+    /// Load a binary at the specified address
+    pub fn load_binary(&mut self, data: &[u8], addr: u32) -> Result<(), SystemError> {
+        Ok(self.memory.load_binary(data, addr)?)
+    }
+
+    /// Replace the boot ROM contents with `data`, e.g. a real OpenSBI binary
+    /// or a custom M-mode monitor, in place of the built-in Rust SBI stub
+    /// written by `init_boot_rom`. Takes effect on the next reset/boot;
+    /// pair with `set_reset_pc` if the replacement ROM's entry point isn't
+    /// at the start of the ROM window.
+    pub fn load_boot_rom(&mut self, data: &[u8]) -> Result<(), SystemError> {
+        Ok(self.memory.load_rom(data)?)
+    }
+
+    /// Set the PC the CPU starts at after `reset()` or a fresh boot, in
+    /// case a custom ROM loaded via `load_boot_rom` doesn't start at the
+    /// boot ROM's base address. Defaults to that base address, matching the
+    /// built-in firmware.
+    pub fn set_reset_pc(&mut self, addr: u32) {
+        self.reset_pc = addr;
+    }
+
+    /// Choose how ecall-from-S is handled: `Native` (default) answers it
+    /// directly in Rust, `Firmware` lets it trap to whatever's installed at
+    /// `mtvec` so a real SBI implementation loaded via `load_boot_rom` can
+    /// handle it instead.
+    pub fn set_sbi_mode(&mut self, mode: SbiMode) {
+        self.sbi_mode = mode;
     }
 
     /// Setup system for Linux booting
     /// Loads kernel image and generates/loads DTB
-    pub fn setup_linux_boot(&mut self, kernel: &[u8], cmdline: &str) -> Result<(), String> {
+    pub fn setup_linux_boot(&mut self, kernel: &[u8], cmdline: &str) -> Result<(), SystemError> {
         self.setup_linux_boot_with_initrd(kernel, None, cmdline)
     }
     
     /// Setup system for Linux booting with optional initrd
     /// Loads kernel, initrd (if provided), and generates DTB
-    pub fn setup_linux_boot_with_initrd(&mut self, kernel: &[u8], initrd: Option<&[u8]>, cmdline: &str) -> Result<(), String> {
+    pub fn setup_linux_boot_with_initrd(&mut self, kernel: &[u8], initrd: Option<&[u8]>, cmdline: &str) -> Result<(), SystemError> {
         // Load kernel at DRAM_BASE (0x80000000)
         self.load_binary(kernel, DRAM_BASE)?;
-        
-        let ram_size = self.memory.ram_size();
-        let ram_size_mb = (ram_size / 1024 / 1024) as u32;
-        
-        // Calculate addresses for initrd and DTB
-        // Layout: [kernel] ... [initrd aligned to 4KB] [DTB aligned to 4KB] [end of RAM]
-        let ram_end = DRAM_BASE + ram_size as u32;
-        
-        // Load initrd if provided
-        let initrd_info = if let Some(initrd_data) = initrd {
-            // Place initrd before DTB, aligned to page boundary
-            // Reserve space for DTB (typically ~4KB, reserve 64KB to be safe)
-            let dtb_reserve = 64 * 1024;
-            let initrd_end = (ram_end - dtb_reserve) & !0xFFF; // Align down to 4KB
-            let initrd_start = (initrd_end - initrd_data.len() as u32) & !0xFFF; // Align down
-            
-            // Make sure initrd doesn't overlap kernel
-            let kernel_end = DRAM_BASE + kernel.len() as u32;
-            if initrd_start < kernel_end + 0x100000 { // Leave at least 1MB gap
-                return Err(format!(
-                    "Not enough RAM for kernel ({} bytes) and initrd ({} bytes)", 
-                    kernel.len(), initrd_data.len()
-                ));
-            }
-            
+
+        let initrd_range = if let Some(initrd_data) = initrd {
+            let initrd_start = self.place_initrd(kernel.len() as u32, initrd_data.len() as u32)?;
             self.load_binary(initrd_data, initrd_start)?;
-            println!("  Initrd loaded at 0x{:08x}-0x{:08x} ({} bytes)", 
+            println!("  Initrd loaded at 0x{:08x}-0x{:08x} ({} bytes)",
                      initrd_start, initrd_start + initrd_data.len() as u32, initrd_data.len());
-            
             Some((initrd_start, initrd_start + initrd_data.len() as u32))
         } else {
             None
         };
-        
-        // Generate DTB with initrd info
-        let dtb = crate::devices::dtb::generate_fdt(ram_size_mb, cmdline, initrd_info);
-        
-        // Load DTB at end of RAM (aligned to 4KB)
-        let dtb_addr = if initrd_info.is_some() {
-            // Place after initrd
-            let (_, initrd_end) = initrd_info.unwrap();
-            (initrd_end + 0x1000) & !0xFFF // Align up with some padding
+
+        self.finalize_linux_boot(kernel.len() as u32, initrd_range, cmdline)
+    }
+
+    /// Like `setup_linux_boot_with_initrd`, but skips DTB generation
+    /// entirely and boots from a caller-supplied blob instead, for advanced
+    /// users who need to describe devices we don't generate nodes for yet.
+    /// The blob's FDT header and total size are validated against available
+    /// RAM before it's trusted enough to load into guest memory.
+    pub fn setup_linux_boot_with_dtb(&mut self, kernel: &[u8], initrd: Option<&[u8]>, dtb_bytes: &[u8]) -> Result<(), SystemError> {
+        crate::devices::dtb::validate_header(dtb_bytes, self.memory.ram_size() as u32)
+            .map_err(SystemError::InvalidDtb)?;
+
+        self.load_binary(kernel, DRAM_BASE)?;
+
+        let initrd_range = if let Some(initrd_data) = initrd {
+            let initrd_start = self.place_initrd(kernel.len() as u32, initrd_data.len() as u32)?;
+            self.load_binary(initrd_data, initrd_start)?;
+            println!("  Initrd loaded at 0x{:08x}-0x{:08x} ({} bytes)",
+                     initrd_start, initrd_start + initrd_data.len() as u32, initrd_data.len());
+            Some((initrd_start, initrd_start + initrd_data.len() as u32))
         } else {
-            // No initrd, place at end of RAM
-            (ram_end - dtb.len() as u32) & !0xFFF
+            None
         };
-        
-        // Actually, let's put DTB at end of RAM to be safe
-        let dtb_addr = (ram_end - dtb.len() as u32) & !0xFFF;
-        
+
+        self.finalize_linux_boot_with_dtb(kernel.len() as u32, initrd_range, dtb_bytes.to_vec())
+    }
+
+    /// The last DTB loaded for the guest by `setup_linux_boot*`, or an empty
+    /// `Vec` if none has booted yet.
+    pub fn get_dtb(&self) -> Vec<u8> {
+        self.boot_dtb.clone().unwrap_or_default()
+    }
+
+    /// Human-readable dump of `get_dtb`'s node/property tree - see
+    /// `devices::dtb::dump_text`. Returns a placeholder message instead of
+    /// an error if no DTB has been generated yet.
+    pub fn get_dtb_text(&self) -> String {
+        match &self.boot_dtb {
+            Some(dtb) => crate::devices::dtb::dump_text(dtb)
+                .unwrap_or_else(|e| format!("(invalid DTB: {})", e)),
+            None => "(no DTB generated yet)".to_string(),
+        }
+    }
+
+    /// Begin a streaming load of a kernel or initrd image directly into
+    /// guest RAM, for hosts that can't afford to buffer the whole image in
+    /// wasm before copying it in (see `load_chunk`/`finish_load`).
+    ///
+    /// `target` is `"kernel"` or `"initrd"`. Loading an initrd this way
+    /// requires the kernel to have already been loaded (streamed or not),
+    /// since its size determines where the initrd is placed.
+    pub fn begin_load(&mut self, target: &str, total_size: u32) -> Result<(), SystemError> {
+        let ram_size = self.memory.ram_size() as u32;
+
+        let (target, base_addr) = match target {
+            "kernel" => (LoadTarget::Kernel, DRAM_BASE),
+            "initrd" => {
+                let kernel_len = self.streamed_kernel_size
+                    .ok_or(SystemError::NoKernelForInitrd)?;
+                (LoadTarget::Initrd, self.place_initrd(kernel_len, total_size)?)
+            }
+            other => return Err(SystemError::UnknownLoadTarget(other.to_string())),
+        };
+
+        if (base_addr - DRAM_BASE) as u64 + total_size as u64 > ram_size as u64 {
+            return Err(SystemError::ImageTooLarge { total_size });
+        }
+
+        self.pending_load = Some(PendingLoad { target, base_addr, total_size });
+        Ok(())
+    }
+
+    /// Write one chunk of a streaming load (see `begin_load`) at `offset`
+    /// bytes into the image, directly into guest RAM. Chunks may arrive in
+    /// any order as long as they land within `[0, total_size)`.
+    pub fn load_chunk(&mut self, offset: u32, chunk: &[u8]) -> Result<(), SystemError> {
+        let pending = self.pending_load.as_ref()
+            .ok_or(SystemError::NoStreamingLoad)?;
+
+        if offset as u64 + chunk.len() as u64 > pending.total_size as u64 {
+            return Err(SystemError::ChunkOutOfRange);
+        }
+
+        Ok(self.memory.load_binary(chunk, pending.base_addr + offset)?)
+    }
+
+    /// Finish the streaming load started by `begin_load`. For the kernel
+    /// path, DTB generation and boot register setup are deferred until
+    /// `setup_linux_boot_streamed` is called.
+    pub fn finish_load(&mut self) -> Result<(), SystemError> {
+        let pending = self.pending_load.take()
+            .ok_or(SystemError::NoStreamingLoad)?;
+
+        match pending.target {
+            LoadTarget::Kernel => self.streamed_kernel_size = Some(pending.total_size),
+            LoadTarget::Initrd => {
+                self.streamed_initrd_range = Some((pending.base_addr, pending.base_addr + pending.total_size));
+            }
+        }
+        Ok(())
+    }
+
+    /// Finish Linux boot setup (DTB + boot registers) for a kernel (and
+    /// optional initrd) loaded via `begin_load`/`load_chunk`/`finish_load`.
+    pub fn setup_linux_boot_streamed(&mut self, cmdline: &str) -> Result<(), SystemError> {
+        if self.streamed_kernel_size.is_none() {
+            return Err(SystemError::NoStreamedKernel);
+        }
+        let initrd_range = self.streamed_initrd_range;
+        let kernel_len = self.streamed_kernel_size.unwrap();
+        self.finalize_linux_boot(kernel_len, initrd_range, cmdline)
+    }
+
+    /// Compute where an initrd of `initrd_len` bytes should be placed given
+    /// a kernel of `kernel_len` bytes already loaded at `DRAM_BASE`, leaving
+    /// room for the DTB at the end of RAM.
+    fn place_initrd(&self, kernel_len: u32, initrd_len: u32) -> Result<u32, SystemError> {
+        let layout = compute_boot_layout(self.memory.ram_size() as u32, kernel_len, Some(initrd_len))?;
+        Ok(layout.initrd.expect("initrd_len was Some, so compute_boot_layout always places an initrd").0)
+    }
+
+    /// Generate the DTB, load it, and set up CPU boot registers for Linux
+    /// boot. Shared tail of `setup_linux_boot_with_initrd` and
+    /// `setup_linux_boot_streamed` — assumes the kernel (and initrd, if
+    /// `initrd_range` is `Some`) are already resident in RAM.
+    fn finalize_linux_boot(&mut self, kernel_len: u32, initrd_range: Option<(u32, u32)>, cmdline: &str) -> Result<(), SystemError> {
+        self.capture_boot_images(kernel_len, initrd_range, cmdline, None);
+
+        let ram_size_mb = (self.memory.ram_size() / 1024 / 1024) as u32;
+        let dtb = crate::devices::dtb::generate_fdt(ram_size_mb, cmdline, initrd_range, &self.isa_string);
+
+        self.load_dtb_and_boot(kernel_len, initrd_range, dtb)
+    }
+
+    /// Like `finalize_linux_boot`, but the caller supplies the exact DTB
+    /// bytes to use instead of generating one - see `setup_linux_boot_with_dtb`.
+    fn finalize_linux_boot_with_dtb(&mut self, kernel_len: u32, initrd_range: Option<(u32, u32)>, dtb: Vec<u8>) -> Result<(), SystemError> {
+        self.capture_boot_images(kernel_len, initrd_range, "", Some(dtb.clone()));
+        self.load_dtb_and_boot(kernel_len, initrd_range, dtb)
+    }
+
+    /// Capture the kernel/initrd images now, while they're still pristine
+    /// in RAM, so `reboot` can restore them later without the embedder
+    /// holding on to (or re-sending) the originals.
+    fn capture_boot_images(&mut self, kernel_len: u32, initrd_range: Option<(u32, u32)>, cmdline: &str, user_dtb: Option<Vec<u8>>) {
+        let kernel = self.memory.read_slice(DRAM_BASE, kernel_len as usize);
+        let initrd = initrd_range.map(|(start, end)| self.memory.read_slice(start, (end - start) as usize));
+
+        self.loaded_artifacts.clear();
+        self.record_boot_artifact("kernel", DRAM_BASE, &kernel);
+        if let (Some((start, _)), Some(initrd_data)) = (initrd_range, &initrd) {
+            self.record_boot_artifact("initrd", start, initrd_data);
+        }
+
+        let kernel_hash = crate::snapshot::sha256(&kernel);
+        let initrd_hash = initrd.as_deref().map(crate::snapshot::sha256);
+        self.boot_artifacts = Some(BootArtifacts {
+            kernel,
+            initrd,
+            cmdline: cmdline.to_string(),
+            kernel_hash,
+            initrd_hash,
+            user_dtb,
+        });
+    }
+
+    /// Load `dtb` at the end of RAM and set up CPU boot registers for
+    /// Linux boot. Shared tail of `finalize_linux_boot` and
+    /// `finalize_linux_boot_with_dtb` - assumes the kernel (and initrd, if
+    /// `initrd_range` is `Some`) are already resident in RAM.
+    fn load_dtb_and_boot(&mut self, kernel_len: u32, initrd_range: Option<(u32, u32)>, dtb: Vec<u8>) -> Result<(), SystemError> {
+        let initrd_len = initrd_range.map(|(start, end)| end - start);
+        let layout = compute_boot_layout(self.memory.ram_size() as u32, kernel_len, initrd_len)?;
+
+        // `compute_boot_layout` only reserves an upper bound for the DTB,
+        // since its real size (driven by cmdline length, number of ISA
+        // extensions/aliases, etc.) isn't known until it's generated - place
+        // it as high in that reservation as it actually needs, page-aligned.
+        let dtb_addr = (layout.dtb_reserve.1 - dtb.len() as u32) & !(BOOT_PAGE_SIZE - 1);
+        let dtb_end = dtb_addr + dtb.len() as u32;
+
+        // The DTB can still land on top of the initrd despite fitting its
+        // reservation - a pathologically long cmdline can blow past the
+        // reservation's headroom. Catch it here rather than silently
+        // corrupting the initrd the guest is about to unpack.
+        if let Some((initrd_start, initrd_end)) = initrd_range {
+            if dtb_addr < initrd_end && initrd_start < dtb_end {
+                return Err(SystemError::DtbOverlapsInitrd {
+                    dtb_len: dtb.len() as u32,
+                    dtb_start: dtb_addr,
+                    dtb_end,
+                    initrd_start,
+                    initrd_end,
+                });
+            }
+        }
+
+        // An oversized DTB that doesn't collide with the initrd (or there
+        // is none) can still have been pushed back far enough to land on
+        // the kernel - the same "not enough RAM" every other region reports.
+        if dtb_addr < layout.min_next_region {
+            return Err(SystemError::NotEnoughRam { kernel_len, initrd_len: initrd_len.unwrap_or(0) });
+        }
+
         self.load_binary(&dtb, dtb_addr)?;
         println!("  DTB loaded at 0x{:08x} ({} bytes)", dtb_addr, dtb.len());
-        
+
+        self.record_boot_artifact("dtb", dtb_addr, &dtb);
+        self.boot_dtb = Some(dtb);
+
         // Setup CPU State for Linux boot via boot ROM
         // Boot ROM at 0x1000 will:
         // 1. Set up medeleg/mideleg for exception delegation
@@ -177,54 +2068,344 @@ impl System {
         // We just need to set up the registers that Linux expects:
         // a0 (x10) = hartid (0)
         // a1 (x11) = dtb address
-        self.cpu.reset();  // PC = 0x1000 (boot ROM)
+        self.cpu.reset();
+        self.cpu.pc = self.reset_pc; // boot ROM, or a custom one set via `set_reset_pc`
         self.cpu.write_reg(10, 0);       // a0 = hartid
         self.cpu.write_reg(11, dtb_addr); // a1 = dtb address
-        
+
         Ok(())
     }
-    
+
+    /// Record (or replace) the range a known boot artifact was loaded at,
+    /// so `create_state_delta` can recognize RAM pages that still match it.
+    fn record_boot_artifact(&mut self, name: &str, addr: u32, data: &[u8]) {
+        self.loaded_artifacts.retain(|a| a.name != name);
+        self.loaded_artifacts.push(crate::snapshot::ArtifactRange::new(name, addr, data));
+    }
+
+    /// Original bytes for a loaded artifact, by name — the source
+    /// `create_state_delta`/`apply_state_delta` copy artifact pages out of.
+    fn artifact_bytes(&self, name: &str) -> Option<&[u8]> {
+        match name {
+            "kernel" => self.boot_artifacts.as_ref().map(|a| a.kernel.as_slice()),
+            "initrd" => self.boot_artifacts.as_ref().and_then(|a| a.initrd.as_deref()),
+            "dtb" => self.boot_dtb.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// If `page_bytes` (the live contents of the RAM page at `page_addr`)
+    /// still exactly matches the corresponding slice of a loaded artifact,
+    /// return that artifact's name and the byte offset the page starts at.
+    fn matching_artifact_page(&self, page_addr: u32, page_bytes: &[u8]) -> Option<(String, u32)> {
+        let page_len = page_bytes.len() as u32;
+        for artifact in &self.loaded_artifacts {
+            if page_addr < artifact.addr || page_addr + page_len > artifact.addr + artifact.len {
+                continue;
+            }
+            let offset = page_addr - artifact.addr;
+            let original = self.artifact_bytes(&artifact.name)?;
+            let slice = original.get(offset as usize..(offset + page_len) as usize)?;
+            if slice == page_bytes {
+                return Some((artifact.name.clone(), offset));
+            }
+        }
+        None
+    }
+
+    /// Snapshot state as a `StateDelta`: like `create_snapshot`, but covers
+    /// all touched RAM pages (not just those past a fixed offset from the
+    /// kernel) and further skips any page that still matches a loaded boot
+    /// artifact byte-for-byte, recording a reference to it instead of the
+    /// raw bytes. `apply_state_delta` requires the same artifacts (kernel,
+    /// initrd, DTB) to already be loaded, validated by content hash.
+    pub fn create_state_delta(&self) -> crate::snapshot::StateDelta {
+        use crate::snapshot::{ArtifactPageRef, ClintSnapshot, CpuSnapshot, PlicSnapshot, StateDelta, UartSnapshot};
+
+        let mut delta = StateDelta {
+            version: StateDelta::VERSION,
+            artifacts: self.loaded_artifacts.clone(),
+            cpu: CpuSnapshot {
+                pc: self.cpu.pc,
+                regs: self.cpu.regs,
+                fpu: self.cpu.fpu.clone(),
+                csr: self.cpu.csr.clone(),
+                priv_level: self.cpu.priv_level,
+                wfi: self.cpu.wfi,
+                reservation: self.cpu.reservation,
+                instruction_count: self.cpu.instruction_count,
+            },
+            uarts: self.uarts.iter().map(|_| UartSnapshot {
+                ier: 0,
+                fcr: 0,
+                lcr: 0,
+                mcr: 0,
+                lsr: 0x60,
+                msr: 0,
+                scr: 0,
+                dll: 0,
+                dlm: 0,
+                rx_fifo: Vec::new(),
+                tx_output: Vec::new(),
+            }).collect(),
+            clint: ClintSnapshot {
+                mtime: self.clint.get_mtime(),
+                mtimecmp: self.clint.get_mtimecmp(),
+                msip: self.clint.software_interrupt,
+            },
+            plic: PlicSnapshot {
+                priority: [0; 32],
+                pending: 0,
+                enable_m: 0,
+                enable_s: 0,
+                threshold_m: 0,
+                threshold_s: 0,
+                claim_m: 0,
+                claim_s: 0,
+            },
+            artifact_pages: Vec::new(),
+            dirty_pages: std::collections::HashMap::new(),
+        };
+
+        for (page_addr, page_bytes) in self.memory.touched_ram_pages() {
+            if page_bytes.iter().all(|&b| b == 0) {
+                continue;
+            }
+            match self.matching_artifact_page(page_addr, page_bytes) {
+                Some((artifact, artifact_offset)) => {
+                    delta.artifact_pages.push(ArtifactPageRef { page_addr, artifact, artifact_offset });
+                }
+                None => {
+                    delta.dirty_pages.insert(page_addr, page_bytes.to_vec());
+                }
+            }
+        }
+
+        delta
+    }
+
+    /// Restore state from a `StateDelta`. The same boot artifacts the delta
+    /// was created against (by name and content hash) must already be
+    /// loaded via `setup_linux_boot`/`setup_linux_boot_with_initrd` — this
+    /// does not itself load a kernel or initrd.
+    pub fn apply_state_delta(&mut self, delta: &crate::snapshot::StateDelta) -> Result<(), SystemError> {
+        for artifact in &delta.artifacts {
+            let current = self.loaded_artifacts.iter().find(|a| a.name == artifact.name)
+                .ok_or_else(|| SystemError::ArtifactRequiredNotLoaded(artifact.name.clone()))?;
+            if current.hash != artifact.hash || current.len != artifact.len {
+                return Err(SystemError::ArtifactMismatch(artifact.name.clone()));
+            }
+        }
+
+        for page_ref in &delta.artifact_pages {
+            let original = self.artifact_bytes(&page_ref.artifact)
+                .ok_or_else(|| SystemError::ArtifactReferencedNotLoaded(page_ref.artifact.clone()))?;
+            let start = page_ref.artifact_offset as usize;
+            let end = start + crate::snapshot::PAGE_SIZE as usize;
+            let bytes = original.get(start..end)
+                .ok_or_else(|| SystemError::ArtifactTooShort(page_ref.artifact.clone()))?
+                .to_vec();
+            for (i, byte) in bytes.iter().enumerate() {
+                self.memory.write8(page_ref.page_addr + i as u32, *byte);
+            }
+        }
+
+        for (&page_addr, bytes) in &delta.dirty_pages {
+            for (i, byte) in bytes.iter().enumerate() {
+                self.memory.write8(page_addr + i as u32, *byte);
+            }
+        }
+
+        self.cpu.pc = delta.cpu.pc;
+        self.cpu.regs = delta.cpu.regs;
+        self.cpu.fpu = delta.cpu.fpu.clone();
+        self.cpu.csr = delta.cpu.csr.clone();
+        self.cpu.priv_level = delta.cpu.priv_level;
+        self.cpu.wfi = delta.cpu.wfi;
+        self.cpu.reservation = delta.cpu.reservation;
+        self.cpu.instruction_count = delta.cpu.instruction_count;
+
+        self.clint.set_mtime(delta.clint.mtime);
+        self.clint.write32(0x4000, delta.clint.mtimecmp as u32);
+        self.clint.write32(0x4004, (delta.clint.mtimecmp >> 32) as u32);
+        self.clint.write32(0x0000, if delta.clint.msip { 1 } else { 0 });
+
+        self.block_cache.reset();
+        self.jit_state.invalidate_all();
+        self.cpu.mmu.reset();
+        self.cpu.icache.reset();
+
+        Ok(())
+    }
+
     /// Run the emulator for a specified number of cycles
     /// Returns the number of cycles actually executed
     pub fn run(&mut self, max_cycles: u32) -> u32 {
+        self.run_with_reason(max_cycles).0
+    }
+
+    /// Like `run`, but also reports why execution stopped. Useful for
+    /// embedders that want to react differently to WFI, a breakpoint, or a
+    /// guest running off into unmapped/unhandled territory rather than
+    /// polling separate accessors after every call.
+    pub fn run_with_reason(&mut self, max_cycles: u32) -> (u32, HaltReason) {
+        if let Some(rec) = &mut self.recording {
+            rec.events.push(crate::replay::ReplayEvent::Run { max_cycles });
+        }
+
+        // A caller handing us PC 0 before the guest has ever executed an
+        // instruction (e.g. `reset()` without a real entry point set up
+        // afterwards) is almost certainly a mistake - flag it up front
+        // instead of quietly trying to fetch from address 0. Once the guest
+        // has actually run, PC can legitimately land on 0 transiently as
+        // part of some boot ROM trap-handling paths, so this is only a
+        // one-shot check at entry, not a standing invariant.
+        if self.cpu.pc == 0 && self.cpu.instruction_count == 0 {
+            return (0, HaltReason::PcZero);
+        }
+
+        // Pick up any host filesystem reads/writes that finished on
+        // `HostFileSystem`'s background pool since the last call, the same
+        // way a missing blob arriving via `provide_blob` completes a
+        // suspended request - the guest went on executing instructions the
+        // whole time the I/O was in flight instead of blocking on it here.
+        self.virtio9p.retry_suspended_requests(&mut self.memory);
+
         let mut cycles = 0u32;
         let debug = std::env::var("RISCV_DEBUG").is_ok();
-        
+
         // Batch size for timer updates (jor1k uses 64)
         const TIMER_BATCH: u32 = 64;
-        
-        while cycles < max_cycles {
+
+        // Tracks a trap handler that's itself faulting: if the same PC
+        // delivers a trap this many times in a row with no successful
+        // instruction executed in between, the guest's handler is stuck
+        // rather than making progress, so `HaltReason::TrapLoop` is more
+        // useful than silently burning the rest of the cycle budget.
+        const TRAP_LOOP_THRESHOLD: u32 = 16;
+        let mut trap_loop_pc: Option<u32> = None;
+        let mut trap_loop_count: u32 = 0;
+
+        // Tracks forward progress for `set_stuck_detector`: as long as every
+        // PC visited falls within a `STUCK_WINDOW_SPAN`-byte range and no
+        // device I/O happens, `stuck_window_instrs` keeps growing; either a
+        // PC outside the range or a device access (diffed via
+        // `mmio_access_total`, since `take_mmio_access` is already consumed
+        // by the JIT's own early-exit check) means real progress and resets
+        // the window. Unused unless `stuck_detector_threshold` is set.
+        const STUCK_WINDOW_SPAN: u32 = 64;
+        let mut stuck_window: Option<(u32, u32)> = None;
+        let mut stuck_window_instrs: u64 = 0;
+        let mut stuck_window_mmio_total: u64 = self.mmio_access_total;
+
+        while cycles < max_cycles {
             // Batched timer update - only every TIMER_BATCH cycles
             if cycles & (TIMER_BATCH - 1) == 0 {
                 self.clint.tick(TIMER_BATCH as u64);
                 self.cpu.csr.time = self.clint.get_mtime();
-                
+
                 // Check for interrupts (also batched)
                 self.update_interrupts();
-                
+
                 // Handle pending interrupts
                 if let Some(trap) = self.cpu.check_interrupts() {
                     self.cpu.handle_trap(trap);
                 }
+
+                // Statistically sample execution for `set_profiling`. Piggybacks
+                // on this batch boundary so the disabled cost is a single
+                // `None` check and the enabled cost is one comparison per batch.
+                if let Some(profiler) = &mut self.profiler {
+                    if self.cpu.instruction_count >= profiler.next_sample {
+                        profiler.record(self.cpu.pc, self.cpu.csr.satp, self.cpu.priv_level, self.cpu.instruction_count);
+                    }
+                }
+
+                // Record any boot milestones crossed since the last poll.
+                // Unlike the panic scanner this never stops the run early.
+                while let Some((pattern, _context)) = self.uarts[0].take_milestone_match() {
+                    if let Some(m) = self
+                        .boot_milestones
+                        .iter_mut()
+                        .find(|m| !m.reached && m.patterns.iter().any(|p| *p == pattern))
+                    {
+                        m.reached = true;
+                        m.instruction_count = Some(self.cpu.instruction_count);
+                        m.mtime = Some(self.cpu.csr.time);
+                    }
+                }
+
+                // Check whether the UART panic scanner tripped and stop
+                // early instead of waiting for the caller's cycle budget.
+                if let Some((pattern, context)) = self.uarts[0].take_panic_match() {
+                    self.pending_panic_event = Some(PanicEvent {
+                        pattern,
+                        context,
+                        instruction_count: self.cpu.instruction_count,
+                        pc: self.cpu.pc,
+                    });
+                    return (cycles, HaltReason::Completed);
+                }
             }
-            
-            // Check for cache invalidation request from CPU (FENCE.I, SFENCE.VMA)
-            if self.cpu.cache_invalidation_pending {
+
+            // Check for a block cache/JIT invalidation request from the CPU
+            // (FENCE.I, SFENCE.VMA). Note this is unrelated to TLB flushing:
+            // SFENCE.VMA invalidates the MMU's TLB synchronously inside the
+            // CPU, not through this flag.
+            if self.cpu.icache_invalidation_pending {
                 self.block_cache.invalidate_all();
                 self.jit_state.invalidate_all();
-                self.cpu.cache_invalidation_pending = false;
+                self.cpu.icache_invalidation_pending = false;
             }
-            
-            // If waiting for interrupt, check if any interrupt is pending
+
+            // Trickle any queued host input into the UART RX FIFO as space
+            // frees up, instead of dumping it in all at once.
+            self.pump_input_queue();
+
+            // Forward any semihosting console output (SYS_WRITEC/SYS_WRITE0)
+            // to the UART stream, and stop early on SYS_EXIT.
+            if !self.cpu.semihosting_output.is_empty() {
+                let output = std::mem::take(&mut self.cpu.semihosting_output);
+                self.uarts[0].write_bytes(&output);
+            }
+            if self.cpu.exit_code.is_some() {
+                return (cycles, HaltReason::Completed);
+            }
+
+            // If waiting for interrupt, check if any interrupt is pending.
+            // Re-derive mip from device state on every spin (not just on the
+            // batch boundary above) so an external interrupt arriving while
+            // parked - e.g. UART input or a virtio queue notification - wakes
+            // the CPU immediately instead of waiting for the next timer tick.
             // WFI wakes when (mip & mie) != 0, regardless of global enables
             if self.cpu.wfi {
+                self.update_interrupts();
                 let pending = self.cpu.csr.mip & self.cpu.csr.mie;
                 if pending != 0 {
                     self.cpu.wfi = false;
                     // The interrupt will be handled on next iteration
                 } else {
-                    // Fast-forward to next timer interrupt instead of spinning
-                    let ticks_to_timer = self.clint.ticks_until_interrupt();
+                    // Fast-forward to next timer interrupt instead of spinning.
+                    // Two independent timer sources can be armed: CLINT's
+                    // mtimecmp (legacy/SBI path) and, with Sstc enabled,
+                    // stimecmp (set directly by the guest, no CLINT write
+                    // involved) - a WFI parked on the latter alone must not
+                    // be reported as "nothing can ever wake this".
+                    let ticks_to_clint = if self.clint.get_mtimecmp() == u64::MAX {
+                        None
+                    } else {
+                        Some(self.clint.ticks_until_interrupt())
+                    };
+                    let ticks_to_stimecmp = if self.cpu.csr.menvcfgh & MENVCFGH_STCE != 0 {
+                        Some(self.cpu.csr.stimecmp.saturating_sub(self.cpu.csr.time))
+                    } else {
+                        None
+                    };
+                    let ticks_to_timer = match (ticks_to_clint, ticks_to_stimecmp) {
+                        (Some(a), Some(b)) => a.min(b),
+                        (Some(a), None) | (None, Some(a)) => a,
+                        (None, None) => 0,
+                    };
                     if ticks_to_timer > 0 {
                         // Skip directly to timer, but don't exceed max_cycles
                         let skip = ticks_to_timer.min((max_cycles - cycles) as u64) as u32;
@@ -235,41 +2416,262 @@ impl System {
                             continue;
                         }
                     }
+                    // No interrupt source can ever fire (nothing pending,
+                    // timer not armed): report it instead of spinning out
+                    // the rest of the budget one cycle at a time.
+                    if ticks_to_timer == 0 {
+                        return (cycles, HaltReason::Wfi);
+                    }
                     cycles += 1;
                     continue;
                 }
             }
-            
-            // Try block-based execution
-            match self.step_block() {
+
+            // Try block-based execution. A trap that we intercept before
+            // handing to the guest (breakpoint, or no handler installed at
+            // all) records the reason to return, but doesn't `return`
+            // immediately - a `tohost` write earlier in the same block must
+            // still be observed below.
+            // Sample TLB misses across the step so a nonzero
+            // `tlb_miss_cycles` in the timing model can be charged below;
+            // `None` keeps this a single comparison.
+            let tlb_misses_before = self.timing_model.map(|_| self.cpu.mmu.tlb_stats().1);
+
+            let mut halt_reason = None;
+            // The commit log needs one line per retired instruction, which
+            // the block-based JIT paths can't give us - fall back to plain
+            // interpretation while it's enabled, same as the JIT already
+            // does for blocks it can't compile.
+            let step_result = if self.commit_log_enabled {
+                self.step_traced().map(|_| 1)
+            } else {
+                self.step_block()
+            };
+            match step_result {
                 Ok(inst_count) => {
                     cycles += inst_count;
-                    self.cpu.csr.cycle = self.cpu.csr.cycle.wrapping_add(inst_count as u64);
+                    self.cpu.csr.advance(inst_count as u64);
+                    trap_loop_count = 0;
+
+                    if let Some(threshold) = self.stuck_detector_threshold {
+                        let pc = self.cpu.pc;
+                        let mmio_total = self.mmio_access_total;
+                        let widened = stuck_window.map(|(lo, hi)| (lo.min(pc), hi.max(pc)));
+                        let still_in_window = matches!(
+                            widened,
+                            Some((lo, hi)) if hi - lo <= STUCK_WINDOW_SPAN
+                        );
+                        if still_in_window && mmio_total == stuck_window_mmio_total {
+                            stuck_window = widened;
+                            stuck_window_instrs += inst_count as u64;
+                        } else {
+                            stuck_window = Some((pc, pc));
+                            stuck_window_instrs = inst_count as u64;
+                            stuck_window_mmio_total = mmio_total;
+                        }
+                        if stuck_window_instrs >= threshold as u64 {
+                            halt_reason = Some(HaltReason::Stuck);
+                        }
+                    }
                 }
                 Err(trap) => {
-                    // Handle SBI calls from S-mode directly in Rust
-                    if let crate::cpu::trap::Trap::EnvironmentCallFromS = trap {
+                    // Handle SBI calls from S-mode directly in Rust, unless
+                    // `sbi_mode` is `Firmware`, in which case this falls
+                    // through to the normal trap delivery below so a real
+                    // SBI implementation at `mtvec` gets a shot at it.
+                    if matches!(trap, crate::cpu::trap::Trap::EnvironmentCallFromS)
+                        && self.sbi_mode == SbiMode::Native
+                    {
                         if debug {
                             let eid = self.cpu.regs[17];
                             let a0 = self.cpu.regs[10];
                             eprintln!("[SBI] eid={:#x} a0={:#x} PC={:#010x}", eid, a0, self.cpu.pc);
                         }
                         self.handle_sbi_call();
+                        match self.power_state {
+                            SystemPowerState::Shutdown => halt_reason = Some(HaltReason::PoweredOff),
+                            SystemPowerState::RebootRequested => {
+                                if !(self.auto_reboot && self.reboot().is_ok()) {
+                                    halt_reason = Some(HaltReason::RebootRequested);
+                                }
+                            }
+                            SystemPowerState::Running => {}
+                        }
+                    } else if let crate::cpu::trap::Trap::Breakpoint(addr) = trap {
+                        halt_reason = Some(HaltReason::Breakpoint(addr));
+                    } else if self.cpu.csr.mtvec == 0 && self.cpu.csr.stvec == 0 {
+                        // No trap handler installed anywhere - delivering
+                        // this would just vector PC to 0 and trap again.
+                        halt_reason = Some(HaltReason::Trap);
                     } else {
                         if debug {
                             eprintln!("[TRAP] {:?} at PC={:#010x}", trap, self.cpu.pc);
                         }
+                        let fault_pc = self.cpu.pc;
+                        if let crate::cpu::trap::Trap::IllegalInstruction(raw_inst) = trap {
+                            self.record_illegal_instruction(fault_pc, raw_inst);
+                        }
                         self.cpu.handle_trap(trap);
+                        if trap_loop_pc == Some(fault_pc) {
+                            trap_loop_count += 1;
+                        } else {
+                            trap_loop_pc = Some(fault_pc);
+                            trap_loop_count = 1;
+                        }
+                        if trap_loop_count >= TRAP_LOOP_THRESHOLD {
+                            halt_reason = Some(HaltReason::TrapLoop);
+                        }
                     }
                     cycles += 1;
-                    self.cpu.csr.cycle = self.cpu.csr.cycle.wrapping_add(1);
+                    self.cpu.csr.advance(1);
+                }
+            }
+
+            // Fold this step's TLB-miss penalty in with whatever
+            // `SystemBus` already accumulated for RAM/MMIO accesses, then
+            // drain the total into the cycle counter and CLINT `mtime`.
+            if let Some(before) = tlb_misses_before {
+                if let Some(model) = self.timing_model {
+                    let (_, after) = self.cpu.mmu.tlb_stats();
+                    self.timing_penalty += (after - before) * model.tlb_miss_cycles as u64;
+                }
+            }
+            if self.timing_penalty > 0 {
+                self.cpu.csr.advance(self.timing_penalty);
+                self.clint.tick(self.timing_penalty);
+                self.timing_penalty = 0;
+            }
+
+            // A `tohost` write, if we're watching one, means the guest is
+            // done; decode and stop instead of waiting for the cycle budget.
+            if let Some(value) = self.tohost_pending.take() {
+                self.tohost_result = Some(if value & 1 == 0 {
+                    TohostResult::Other(value)
+                } else if value >> 1 == 0 {
+                    TohostResult::Pass
+                } else {
+                    TohostResult::Fail(value >> 1)
+                });
+                return (cycles, HaltReason::Completed);
+            }
+
+            // A hard sandboxing ceiling takes priority over any other halt
+            // reason computed above for this same step - block execution is
+            // already capped to 64 instructions (see `BlockCache::compile_block`),
+            // so this can overshoot `instruction_limit` by at most that much.
+            if let Some(limit) = self.instruction_limit {
+                if self.cpu.instruction_count >= limit {
+                    return (cycles, HaltReason::LimitReached);
                 }
             }
+
+            if let Some(reason) = halt_reason {
+                return (cycles, reason);
+            }
         }
-        
-        cycles
+
+        (cycles, HaltReason::Completed)
     }
-    
+
+    /// Run in a cooperative, chunked fashion so a caller (browser event
+    /// loop, CLI Ctrl-C handler, future debugger) can interrupt a long
+    /// budget between chunks instead of blocking for the whole thing.
+    ///
+    /// Runs `chunk_cycles` at a time via `run` (which already resumes
+    /// correctly between calls — all CPU/timer state lives on `self`, so
+    /// there's no per-call preamble to redo), calling `should_continue`
+    /// once per chunk boundary; returning `false` stops early. Also stops
+    /// early on any condition that already makes `run` return before
+    /// exhausting its budget (guest panic, semihosting exit, `tohost`).
+    pub fn run_chunked(
+        &mut self,
+        total_cycles: u32,
+        chunk_cycles: u32,
+        mut should_continue: impl FnMut() -> bool,
+    ) -> ChunkedRunResult {
+        let mut cycles = 0u32;
+
+        while cycles < total_cycles {
+            let this_chunk = chunk_cycles.min(total_cycles - cycles);
+            cycles += self.run(this_chunk);
+
+            if self.pending_panic_event.is_some() {
+                return ChunkedRunResult { cycles, reason: RunStopReason::Panic };
+            }
+            if self.cpu.exit_code.is_some() {
+                return ChunkedRunResult { cycles, reason: RunStopReason::Exited };
+            }
+            if self.tohost_result.is_some() {
+                return ChunkedRunResult { cycles, reason: RunStopReason::Tohost };
+            }
+            if !should_continue() {
+                return ChunkedRunResult { cycles, reason: RunStopReason::Callback };
+            }
+        }
+
+        ChunkedRunResult { cycles, reason: RunStopReason::Budget }
+    }
+
+    /// Boot `kernel` and run it to completion (or `max_instructions`,
+    /// whichever comes first), collecting all UART output along the way.
+    ///
+    /// An empty `cmdline` loads `kernel` as a raw freestanding binary at
+    /// `DRAM_BASE` (mirrors `--raw` in the CLI); a non-empty `cmdline` boots
+    /// it as a Linux kernel via `setup_linux_boot`. `stdin`, if given, is
+    /// pushed into the UART's RX FIFO before the run starts.
+    ///
+    /// A lighter-weight sibling of `main.rs`'s benchmark loop (no
+    /// TTY/Ctrl-C/panic-detection/profiling support), meant for integration
+    /// tests that just want to run a guest and assert on its output.
+    pub fn run_program(
+        &mut self,
+        kernel: &[u8],
+        cmdline: &str,
+        stdin: Option<&[u8]>,
+        max_instructions: u64,
+    ) -> Result<RunOutcome, SystemError> {
+        if cmdline.is_empty() {
+            self.load_binary(kernel, DRAM_BASE)?;
+            self.cpu.pc = DRAM_BASE;
+        } else {
+            self.setup_linux_boot(kernel, cmdline)?;
+        }
+
+        if let Some(input) = stdin {
+            for &b in input {
+                self.uart_receive(b);
+            }
+        }
+
+        const CHUNK_CYCLES: u32 = 1_000_000;
+
+        let mut output = Vec::new();
+        let mut instructions = 0u64;
+        let mut halt_reason = RunStopReason::Budget;
+
+        while instructions < max_instructions {
+            let remaining = max_instructions - instructions;
+            let this_chunk = remaining.min(CHUNK_CYCLES as u64) as u32;
+
+            let result = self.run_chunked(this_chunk, this_chunk, || true);
+            instructions += result.cycles as u64;
+            output.extend_from_slice(&self.uart_get_output());
+
+            if result.reason != RunStopReason::Budget {
+                halt_reason = result.reason;
+                break;
+            }
+
+            if result.cycles == 0 {
+                // WFI with no viable wake source, or otherwise stuck -
+                // no more progress is possible.
+                break;
+            }
+        }
+
+        Ok(RunOutcome { output, instructions, halt_reason })
+    }
+
     /// Try to execute using JIT v2 compiled region
     /// Returns Some(cycles) if JIT execution happened, None if no JIT region available
     /// 
@@ -285,10 +2687,21 @@ impl System {
         // Create bus for MMU translation and execution
         let mut bus = SystemBus::new(
             &mut self.memory,
-            &mut self.uart,
+            &mut self.uarts,
             &mut self.clint,
             &mut self.plic,
             &mut self.virtio9p,
+            self.tohost_addr,
+            &mut self.tohost_pending,
+            self.cpu.pc,
+            self.cpu.instruction_count,
+            &self.mmio_trace_ranges,
+            &mut self.mmio_trace_buf,
+            self.timing_model,
+            &mut self.timing_penalty,
+            &mut self.rom_write_attempts,
+            &self.protected_ranges,
+            &mut self.mmio_access_total,
         );
         
         // Translate VA to PA
@@ -315,6 +2728,11 @@ impl System {
         
         // Try to execute using compiled region (keyed by PA)
         if let Some(region) = self.jit_state.get_region(page) {
+            // Privilege level active while this region runs - captured
+            // before execution so a trailing MRET/SRET is attributed to
+            // the mode that executed it, not the mode it switched into
+            // (see `Cpu::record_retired_in`).
+            let priv_before = self.cpu.priv_level;
             // Execute the compiled region
             match execute_region(&mut self.cpu, &mut bus, region, paddr) {
                 RegionResult::Continue(next_pc) => {
@@ -324,22 +2742,22 @@ impl System {
                         .sum::<usize>() as u32;
                     let inst_count = inst_count.max(1);
                     drop(bus);
-                    self.virtio9p.process_queues(&mut self.memory);
-                    self.cpu.instruction_count += inst_count as u64;
-                    self.cpu.csr.cycle = self.cpu.csr.cycle.wrapping_add(inst_count as u64);
+                    self.pump_virtio();
+                    self.cpu.record_retired_in(priv_before, inst_count as u64);
+                    self.cpu.csr.advance(inst_count as u64);
                     return Some(inst_count);
                 }
                 RegionResult::Exit(next_pc) => {
                     self.cpu.pc = next_pc;
                     drop(bus);
-                    self.virtio9p.process_queues(&mut self.memory);
-                    self.cpu.instruction_count += 1;
-                    self.cpu.csr.cycle = self.cpu.csr.cycle.wrapping_add(1);
+                    self.pump_virtio();
+                    self.cpu.record_retired_in(priv_before, 1);
+                    self.cpu.csr.advance(1);
                     return Some(1);
                 }
                 RegionResult::Trap(trap) => {
                     drop(bus);
-                    self.virtio9p.process_queues(&mut self.memory);
+                    self.pump_virtio();
                     self.cpu.handle_trap(trap);
                     return Some(1);
                 }
@@ -368,10 +2786,21 @@ impl System {
         // Create bus for translation
         let mut bus = SystemBus::new(
             &mut self.memory,
-            &mut self.uart,
+            &mut self.uarts,
             &mut self.clint,
             &mut self.plic,
             &mut self.virtio9p,
+            self.tohost_addr,
+            &mut self.tohost_pending,
+            self.cpu.pc,
+            self.cpu.instruction_count,
+            &self.mmio_trace_ranges,
+            &mut self.mmio_trace_buf,
+            self.timing_model,
+            &mut self.timing_penalty,
+            &mut self.rom_write_attempts,
+            &self.protected_ranges,
+            &mut self.mmio_access_total,
         );
         
         let paddr = match self.cpu.mmu.translate(
@@ -387,26 +2816,31 @@ impl System {
                 return Err(crate::cpu::trap::Trap::from_cause(cause, self.cpu.pc));
             }
         };
-        
+
+        if !bus.is_executable(paddr) {
+            return Err(crate::cpu::trap::Trap::InstructionAccessFault(self.cpu.pc));
+        }
+
         // Try to get cached block
         let block_exists = self.block_cache.get(paddr).is_some();
         
         if block_exists {
             // Re-borrow to satisfy borrow checker
             let block = self.block_cache.get_block(paddr).unwrap();
-            let inst_count = block.inst_count;
-            
+            let priv_before = self.cpu.priv_level;
+
             // Execute the block
             match execute_block(&mut self.cpu, block, &mut bus) {
-                BlockResult::Continue(_) => {
+                BlockResult::Continue(_, executed) => {
                     drop(bus);
-                    self.virtio9p.process_queues(&mut self.memory);
-                    self.cpu.instruction_count += inst_count as u64;
-                    Ok(inst_count)
+                    self.pump_virtio();
+                    self.cpu.record_retired_in(priv_before, executed as u64);
+                    Ok(executed)
                 }
-                BlockResult::Trap(trap) => {
+                BlockResult::Trap(trap, retired) => {
                     drop(bus);
-                    self.virtio9p.process_queues(&mut self.memory);
+                    self.pump_virtio();
+                    self.cpu.record_retired_in(priv_before, retired as u64);
                     Err(trap)
                 }
                 BlockResult::Interpret => {
@@ -420,19 +2854,20 @@ impl System {
             // Compile new block
             self.block_cache.compile_block(&mut bus, paddr);
             let block = self.block_cache.get_block(paddr).unwrap();
-            let inst_count = block.inst_count;
-            
+            let priv_before = self.cpu.priv_level;
+
             // Execute the newly compiled block
             match execute_block(&mut self.cpu, block, &mut bus) {
-                BlockResult::Continue(_) => {
+                BlockResult::Continue(_, executed) => {
                     drop(bus);
-                    self.virtio9p.process_queues(&mut self.memory);
-                    self.cpu.instruction_count += inst_count as u64;
-                    Ok(inst_count)
+                    self.pump_virtio();
+                    self.cpu.record_retired_in(priv_before, executed as u64);
+                    Ok(executed)
                 }
-                BlockResult::Trap(trap) => {
+                BlockResult::Trap(trap, retired) => {
                     drop(bus);
-                    self.virtio9p.process_queues(&mut self.memory);
+                    self.pump_virtio();
+                    self.cpu.record_retired_in(priv_before, retired as u64);
                     Err(trap)
                 }
                 BlockResult::Interpret => {
@@ -473,10 +2908,21 @@ impl System {
         // Create a temporary bus that has access to everything
         let mut bus = SystemBus::new(
             &mut self.memory,
-            &mut self.uart,
+            &mut self.uarts,
             &mut self.clint,
             &mut self.plic,
             &mut self.virtio9p,
+            self.tohost_addr,
+            &mut self.tohost_pending,
+            self.cpu.pc,
+            self.cpu.instruction_count,
+            &self.mmio_trace_ranges,
+            &mut self.mmio_trace_buf,
+            self.timing_model,
+            &mut self.timing_penalty,
+            &mut self.rom_write_attempts,
+            &self.protected_ranges,
+            &mut self.mmio_access_total,
         );
         
         let result = self.cpu.step(&mut bus);
@@ -486,11 +2932,83 @@ impl System {
         // (Borrow checker: bus holds mutable refs to fields, so bus must die before we use them again)
         drop(bus);
         
-        self.virtio9p.process_queues(&mut self.memory);
-        
+        self.pump_virtio();
+
         result
     }
-    
+
+    /// Like `step_with_devices`, but also appends a Spike-style commit-log
+    /// line to `commit_log` (see `set_commit_log`) so the same program can
+    /// be run under Spike and the two logs diffed to localize a divergence.
+    /// Peeking the raw instruction costs an extra translation per step, so
+    /// this is only ever called while commit logging is enabled.
+    fn step_traced(&mut self) -> Result<(), crate::cpu::trap::Trap> {
+        let pc_before = self.cpu.pc;
+        let raw_inst = {
+            let mut bus = SystemBus::new(
+                &mut self.memory,
+                &mut self.uarts,
+                &mut self.clint,
+                &mut self.plic,
+                &mut self.virtio9p,
+                self.tohost_addr,
+                &mut self.tohost_pending,
+                self.cpu.pc,
+                self.cpu.instruction_count,
+                &self.mmio_trace_ranges,
+                &mut self.mmio_trace_buf,
+                self.timing_model,
+                &mut self.timing_penalty,
+                &mut self.rom_write_attempts,
+                &self.protected_ranges,
+                &mut self.mmio_access_total,
+            );
+            let satp = self.cpu.csr.satp;
+            let mstatus = self.cpu.csr.mstatus;
+            let priv_level = self.cpu.priv_level;
+            self.cpu.mmu.translate(pc_before, AccessType::Instruction, priv_level, &mut bus, satp, mstatus)
+                .ok()
+                .map(|paddr| bus.read32(paddr))
+        };
+
+        let regs_before = self.cpu.regs;
+        let mcause_before = self.cpu.csr.mcause;
+        let mepc_before = self.cpu.csr.mepc;
+        let scause_before = self.cpu.csr.scause;
+        let sepc_before = self.cpu.csr.sepc;
+
+        let result = self.step_with_devices();
+
+        if let Some(inst) = raw_inst {
+            let mut line = format!("core   0: 0x{:08x} (0x{:08x})", pc_before, inst);
+            for (i, (before, after)) in regs_before.iter().zip(self.cpu.regs.iter()).enumerate().skip(1) {
+                if before != after {
+                    line.push_str(&format!(" x{} 0x{:08x}", i, after));
+                }
+            }
+            if self.cpu.csr.mcause != mcause_before {
+                line.push_str(&format!(" c{:x} 0x{:08x}", CSR_MCAUSE, self.cpu.csr.mcause));
+            }
+            if self.cpu.csr.mepc != mepc_before {
+                line.push_str(&format!(" c{:x} 0x{:08x}", CSR_MEPC, self.cpu.csr.mepc));
+            }
+            if self.cpu.csr.scause != scause_before {
+                line.push_str(&format!(" c{:x} 0x{:08x}", CSR_SCAUSE, self.cpu.csr.scause));
+            }
+            if self.cpu.csr.sepc != sepc_before {
+                line.push_str(&format!(" c{:x} 0x{:08x}", CSR_SEPC, self.cpu.csr.sepc));
+            }
+            if let Some(vaddr) = traced_mem_vaddr(inst, &regs_before) {
+                line.push_str(&format!(" mem 0x{:08x}", vaddr));
+            }
+            if self.commit_log.len() < COMMIT_LOG_CAPACITY {
+                self.commit_log.push(line);
+            }
+        }
+
+        result
+    }
+
     /// Handle SBI (Supervisor Binary Interface) calls from S-mode
     /// 
     /// SBI provides M-mode services to S-mode OS like Linux.
@@ -506,6 +3024,7 @@ impl System {
         let fid = self.cpu.read_reg(16);  // a6 = Function ID
         let a0 = self.cpu.read_reg(10);
         let a1 = self.cpu.read_reg(11);
+        self.sbi_call_count += 1;
 
         // SBI error codes
         const SBI_SUCCESS: u32 = 0;
@@ -515,12 +3034,15 @@ impl System {
         const SBI_EXT_LEGACY_SET_TIMER: u32 = 0;
         const SBI_EXT_LEGACY_CONSOLE_PUTCHAR: u32 = 1;
         const SBI_EXT_LEGACY_CONSOLE_GETCHAR: u32 = 2;
+        const SBI_EXT_LEGACY_SHUTDOWN: u32 = 8;
         const SBI_EXT_BASE: u32 = 0x10;
         const SBI_EXT_TIME: u32 = 0x54494D45;  // "TIME"
         const SBI_EXT_IPI: u32 = 0x735049;     // "sPI"
         const SBI_EXT_RFENCE: u32 = 0x52464E43; // "RFNC"
         const SBI_EXT_HSM: u32 = 0x48534D;     // "HSM"
         const SBI_EXT_SRST: u32 = 0x53525354;  // "SRST"
+        const SBI_EXT_DBCN: u32 = 0x4442434E;  // "DBCN"
+        const SBI_EXT_PMU: u32 = 0x504D55;     // "PMU"
         
         let (error, value) = match eid {
             SBI_EXT_LEGACY_SET_TIMER => {
@@ -529,21 +3051,32 @@ impl System {
                 self.clint.write32(0x4004, a1);      // mtimecmp high
                 // Clear pending timer interrupt when new timer is set
                 self.cpu.csr.clear_interrupt_pending(MIP_STIP);
+                self.sbi_timer_calls += 1;
                 (SBI_SUCCESS, 0)
             }
             
             SBI_EXT_LEGACY_CONSOLE_PUTCHAR => {
                 // Legacy console_putchar: a0 = character
-                self.uart.write8(0, a0 as u8);
+                self.uarts[0].write8(0, a0 as u8);
                 (SBI_SUCCESS, 0)
             }
             
             SBI_EXT_LEGACY_CONSOLE_GETCHAR => {
                 // Legacy console_getchar: returns character in a0, or -1 if none
-                // For now, return -1 (no input available)
-                ((-1i32) as u32, 0)
+                match self.uarts[0].try_read_byte() {
+                    Some(c) => (c as u32, 0),
+                    None => ((-1i32) as u32, 0),
+                }
             }
-            
+
+            SBI_EXT_LEGACY_SHUTDOWN => {
+                // SBI v0.1 sbi_shutdown: no arguments, never returns.
+                self.power_state = SystemPowerState::Shutdown;
+                self.filesystem_persist_pending = true;
+                self.cpu.wfi = true;
+                (SBI_SUCCESS, 0)
+            }
+
             SBI_EXT_BASE => {
                 // Base extension - provides SBI version info
                 match fid {
@@ -563,14 +3096,16 @@ impl System {
                             0x735049 => 0,                    // SBI_EXT_IPI - not available
                             0x52464E43 => 0,                  // SBI_EXT_RFENCE - not available
                             0x48534D => 0,                    // SBI_EXT_HSM - not available
-                            0x53525354 => 0,                  // SBI_EXT_SRST - not available
+                            0x53525354 => 1,                  // SBI_EXT_SRST
+                            0x4442434E => 1,                  // SBI_EXT_DBCN
+                            0x504D55 => 1,                    // SBI_EXT_PMU
                             _ => 0,
                         };
                         (SBI_SUCCESS, available)
                     }
-                    4 => (SBI_SUCCESS, 0),            // sbi_get_mvendorid
-                    5 => (SBI_SUCCESS, 0),            // sbi_get_marchid
-                    6 => (SBI_SUCCESS, 0),            // sbi_get_mimpid
+                    4 => (SBI_SUCCESS, self.cpu.csr.mvendorid), // sbi_get_mvendorid
+                    5 => (SBI_SUCCESS, self.cpu.csr.marchid),   // sbi_get_marchid
+                    6 => (SBI_SUCCESS, self.cpu.csr.mimpid),    // sbi_get_mimpid
                     _ => (SBI_ERR_NOT_SUPPORTED, 0),
                 }
             }
@@ -583,6 +3118,7 @@ impl System {
                         self.clint.write32(0x4000, a0);
                         self.clint.write32(0x4004, a1);
                         self.cpu.csr.clear_interrupt_pending(MIP_STIP);
+                        self.sbi_timer_calls += 1;
                         (SBI_SUCCESS, 0)
                     }
                     _ => (SBI_ERR_NOT_SUPPORTED, 0),
@@ -595,18 +3131,199 @@ impl System {
             }
             
             SBI_EXT_SRST => {
-                // System reset
+                // System reset: a0 = reset type (0 = shutdown, 1 = cold
+                // reboot, 2 = warm reboot), a1 = reset reason.
+                const SBI_SRST_TYPE_COLD_REBOOT: u32 = 1;
+                const SBI_SRST_TYPE_WARM_REBOOT: u32 = 2;
                 match fid {
                     0 => {
                         // sbi_system_reset
-                        eprintln!("SBI system reset requested");
-                        self.cpu.wfi = true;  // Halt
+                        self.power_state = if a0 == SBI_SRST_TYPE_COLD_REBOOT || a0 == SBI_SRST_TYPE_WARM_REBOOT {
+                            eprintln!("SBI system reset requested: reboot (reason {:#x})", a1);
+                            SystemPowerState::RebootRequested
+                        } else {
+                            eprintln!("SBI system reset requested: shutdown (reason {:#x})", a1);
+                            self.filesystem_persist_pending = true;
+                            SystemPowerState::Shutdown
+                        };
+                        self.cpu.wfi = true;  // Halt until run() notices the power state change
                         (SBI_SUCCESS, 0)
                     }
                     _ => (SBI_ERR_NOT_SUPPORTED, 0),
                 }
             }
-            
+
+            SBI_EXT_DBCN => {
+                // Debug console extension. Only the read path is
+                // implemented (write/write_byte guests can just use the
+                // UART MMIO directly).
+                match fid {
+                    2 => {
+                        // sbi_debug_console_read: a0 = num_bytes, a1 =
+                        // base_addr_lo (a2, the high half, is unused on
+                        // rv32 - guest addresses are already 32-bit).
+                        let num_bytes = a0;
+                        let base_addr = a1;
+                        let mut read = 0u32;
+                        while read < num_bytes {
+                            match self.uarts[0].try_read_byte() {
+                                Some(c) => {
+                                    self.memory.write8(base_addr.wrapping_add(read), c);
+                                    read += 1;
+                                }
+                                None => break,
+                            }
+                        }
+                        (SBI_SUCCESS, read)
+                    }
+                    _ => (SBI_ERR_NOT_SUPPORTED, 0),
+                }
+            }
+
+            SBI_EXT_PMU => {
+                // PMU extension: two hardware counters backed by CSRs the
+                // guest could also read directly with rdcycle/rdinstret,
+                // plus firmware counters exposing stats that otherwise have
+                // no CSR of their own. `counter_fw_read` supports reading
+                // any of the five uniformly, which is a convenience beyond
+                // the spec (real hardware counters aren't read this way)
+                // but keeps the emulator side simple.
+                const SBI_ERR_INVALID_PARAM: u32 = (-3i32) as u32;
+                const SBI_ERR_ALREADY_STARTED: u32 = (-6i32) as u32;
+                const SBI_ERR_ALREADY_STOPPED: u32 = (-7i32) as u32;
+                const PMU_EVENT_TYPE_HW: u32 = 0;
+                const PMU_EVENT_TYPE_FW: u32 = 0xf;
+                const PMU_HW_CPU_CYCLES: u32 = 1;
+                const PMU_HW_INSTRUCTIONS: u32 = 2;
+                const PMU_FW_PLATFORM: u32 = 16;
+                const PMU_START_FLAG_INIT_VALUE: u32 = 1 << 0;
+                const PMU_STOP_FLAG_RESET: u32 = 1 << 0;
+
+                // Counters selected by `base`/`mask`: every index `i` in
+                // `0..PMU_NUM_COUNTERS` with `i >= base` and bit `i - base`
+                // set in `mask`.
+                let selected = |base: u32, mask: u32| -> Vec<usize> {
+                    (0..PMU_NUM_COUNTERS)
+                        .filter(|&i| {
+                            (i as u32) >= base && (mask >> (i as u32 - base)) & 1 != 0
+                        })
+                        .collect()
+                };
+
+                match fid {
+                    0 => (SBI_SUCCESS, PMU_NUM_COUNTERS as u32), // sbi_pmu_num_counters
+                    1 => {
+                        // sbi_pmu_counter_get_info: a0 = counter_idx
+                        let idx = a0 as usize;
+                        if idx >= PMU_NUM_COUNTERS {
+                            (SBI_ERR_INVALID_PARAM, 0)
+                        } else if idx < 2 {
+                            // Hardware counter: bits[17:12] = width-1 (64-bit
+                            // counters), bits[11:0] = CSR number.
+                            let csr_num = 0xC00 + idx as u32; // cycle=0xC00, instret=0xC02
+                            (SBI_SUCCESS, (63 << 12) | csr_num)
+                        } else {
+                            // Firmware counter: just the type bit.
+                            (SBI_SUCCESS, 1 << 31)
+                        }
+                    }
+                    2 => {
+                        // sbi_pmu_counter_config_matching: a0 = base, a1 =
+                        // mask, a2 = flags, a3 = event_idx (a4, the
+                        // platform event_data, is only consulted for
+                        // SBI_PMU_FW_PLATFORM).
+                        let flags = self.cpu.read_reg(12);
+                        let event_idx = self.cpu.read_reg(13);
+                        let event_data = self.cpu.read_reg(14);
+                        let event_type = (event_idx >> 16) & 0xF;
+                        let event_code = event_idx & 0xFFFF;
+                        let wanted = match (event_type, event_code) {
+                            (PMU_EVENT_TYPE_HW, PMU_HW_CPU_CYCLES) => Some(0),
+                            (PMU_EVENT_TYPE_HW, PMU_HW_INSTRUCTIONS) => Some(1),
+                            (PMU_EVENT_TYPE_FW, PMU_FW_PLATFORM) => match event_data {
+                                0 => Some(2), // TLB misses
+                                1 => Some(3), // JIT compiles
+                                2 => Some(4), // SBI calls
+                                _ => None,
+                            },
+                            _ => None,
+                        };
+                        // `SBI_PMU_CFG_FLAG_AUTO_START` isn't implemented -
+                        // matching always leaves the counter stopped, same
+                        // as a fresh reset; the guest calls counter_start
+                        // itself afterwards.
+                        let _ = flags;
+                        match wanted.filter(|idx| selected(a0, a1).contains(idx)) {
+                            Some(idx) => {
+                                let raw = self.pmu_raw_counter(idx);
+                                self.pmu_counters[idx] = PmuCounter { running: false, baseline: raw, banked: 0 };
+                                (SBI_SUCCESS, idx as u32)
+                            }
+                            None => (SBI_ERR_NOT_SUPPORTED, 0),
+                        }
+                    }
+                    3 => {
+                        // sbi_pmu_counter_start: a0 = base, a1 = mask, a2 =
+                        // start_flags, a3 = initial_value (low 32 bits; high
+                        // 32 bits in a4 are ignored here, same simplification
+                        // as counter_fw_read only returning one half).
+                        let flags = self.cpu.read_reg(12);
+                        let initial_value = self.cpu.read_reg(13);
+                        let indices = selected(a0, a1);
+                        if indices.is_empty() {
+                            (SBI_ERR_INVALID_PARAM, 0)
+                        } else if indices.iter().any(|&i| self.pmu_counters[i].running) {
+                            (SBI_ERR_ALREADY_STARTED, 0)
+                        } else {
+                            for idx in indices {
+                                let raw = self.pmu_raw_counter(idx);
+                                self.pmu_counters[idx] = PmuCounter {
+                                    running: true,
+                                    baseline: raw,
+                                    banked: if flags & PMU_START_FLAG_INIT_VALUE != 0 { initial_value as u64 } else { 0 },
+                                };
+                            }
+                            (SBI_SUCCESS, 0)
+                        }
+                    }
+                    4 => {
+                        // sbi_pmu_counter_stop: a0 = base, a1 = mask, a2 = stop_flags
+                        let flags = self.cpu.read_reg(12);
+                        let indices = selected(a0, a1);
+                        if indices.is_empty() {
+                            (SBI_ERR_INVALID_PARAM, 0)
+                        } else if indices.iter().any(|&i| !self.pmu_counters[i].running) {
+                            (SBI_ERR_ALREADY_STOPPED, 0)
+                        } else {
+                            for idx in indices {
+                                let raw = self.pmu_raw_counter(idx);
+                                let counter = &mut self.pmu_counters[idx];
+                                counter.banked = if flags & PMU_STOP_FLAG_RESET != 0 {
+                                    0
+                                } else {
+                                    counter.value(raw)
+                                };
+                                counter.running = false;
+                            }
+                            (SBI_SUCCESS, 0)
+                        }
+                    }
+                    5 | 6 => {
+                        // sbi_pmu_counter_fw_read / _fw_read_hi: a0 = counter_idx.
+                        let idx = a0 as usize;
+                        if idx >= PMU_NUM_COUNTERS {
+                            (SBI_ERR_INVALID_PARAM, 0)
+                        } else {
+                            let raw = self.pmu_raw_counter(idx);
+                            let delta = self.pmu_counters[idx].value(raw);
+                            let word = if fid == 6 { (delta >> 32) as u32 } else { delta as u32 };
+                            (SBI_SUCCESS, word)
+                        }
+                    }
+                    _ => (SBI_ERR_NOT_SUPPORTED, 0),
+                }
+            }
+
             _ => {
                 // Unknown extension - return not supported
                 (SBI_ERR_NOT_SUPPORTED, 0)
@@ -628,9 +3345,18 @@ impl System {
         // The kernel in S-mode sees STIP (which is delegated via mideleg)
         if self.clint.timer_interrupt {
             self.cpu.csr.set_interrupt_pending(MIP_MTIP);
-            self.cpu.csr.set_interrupt_pending(MIP_STIP);
         } else {
             self.cpu.csr.clear_interrupt_pending(MIP_MTIP);
+        }
+
+        // Sstc: with menvcfg.STCE set, the kernel arms stimecmp directly
+        // (no SBI ecall) and STIP tracks time >= stimecmp on its own,
+        // alongside the CLINT-driven path above.
+        let sstc_pending = self.cpu.csr.menvcfgh & MENVCFGH_STCE != 0
+            && self.cpu.csr.time >= self.cpu.csr.stimecmp;
+        if self.clint.timer_interrupt || sstc_pending {
+            self.cpu.csr.set_interrupt_pending(MIP_STIP);
+        } else {
             self.cpu.csr.clear_interrupt_pending(MIP_STIP);
         }
         
@@ -640,15 +3366,24 @@ impl System {
             self.cpu.csr.clear_interrupt_pending(MIP_MSIP);
         }
         
-        // UART -> PLIC
+        // UARTs -> PLIC
         // Note: PLIC pending bits are cleared via claim/complete mechanism
         // We only raise interrupts here, the UART interrupt is level-triggered
-        if self.uart.has_interrupt() {
-            self.plic.raise_interrupt(UART_IRQ);
+        for (uart, &irq) in self.uarts.iter().zip(UART_IRQS.iter()) {
+            if uart.has_interrupt() {
+                self.plic.raise_interrupt(irq);
+            } else {
+                self.plic.clear_interrupt(irq);
+            }
+        }
+
+        // VirtIO 9p -> PLIC
+        if self.virtio9p.virtio.interrupt_pending {
+            self.plic.raise_interrupt(VIRTIO_IRQ);
         } else {
-            self.plic.clear_interrupt(UART_IRQ);
+            self.plic.clear_interrupt(VIRTIO_IRQ);
         }
-        
+
         // PLIC -> CPU
         if self.plic.m_external_interrupt {
             self.cpu.csr.set_interrupt_pending(MIP_MEIP);
@@ -668,16 +3403,189 @@ impl System {
         self.cpu.wfi
     }
     
-    /// Send a character to UART
+    /// Send a character to UART 0, translated per `set_input_crlf_mode`.
+    /// Delegates to `uart_receive_on(0, c)`.
     pub fn uart_receive(&mut self, c: u8) {
-        self.uart.receive_char(c);
+        self.uart_receive_on(0, c);
+    }
+
+    /// Send a character to the UART at `idx`, translated per
+    /// `set_input_crlf_mode`.
+    pub fn uart_receive_on(&mut self, idx: usize, c: u8) {
+        let c = match self.input_crlf_mode {
+            InputCrlfMode::None => c,
+            InputCrlfMode::CrToLf if c == b'\r' => b'\n',
+            InputCrlfMode::LfToCr if c == b'\n' => b'\r',
+            _ => c,
+        };
+        if let Some(rec) = &mut self.recording {
+            rec.events.push(crate::replay::ReplayEvent::UartInput { uart_idx: idx, byte: c });
+        }
+        self.uarts[idx].receive_char(c);
+        self.wake_from_wfi_if_pending();
+    }
+
+    /// Configure line-ending translation for bytes delivered via
+    /// `uart_receive`/`queue_input`, e.g. `CrToLf` for a raw-mode terminal
+    /// that sends Enter as `\r`.
+    pub fn set_input_crlf_mode(&mut self, mode: InputCrlfMode) {
+        self.input_crlf_mode = mode;
+    }
+
+    /// Queue bytes for delivery to the UART RX FIFO, trickled in a byte at a
+    /// time (by `run_with_reason`) as FIFO space frees up. Use this instead
+    /// of `uart_receive` for host-driven input like a pasted block of text,
+    /// which would otherwise overrun the FIFO and lose bytes.
+    pub fn queue_input(&mut self, bytes: Vec<u8>) {
+        self.input_queue.extend(bytes);
+    }
+
+    /// Number of queued input bytes not yet delivered to the UART, so the
+    /// host can throttle how fast it calls `queue_input`.
+    pub fn input_pending(&self) -> u32 {
+        self.input_queue.len() as u32
+    }
+
+    /// Discard every byte queued by `queue_input` that hasn't reached the
+    /// UART yet (e.g. the user aborted a paste mid-flight), and return how
+    /// many bytes were dropped.
+    pub fn cancel_input(&mut self) -> u32 {
+        let dropped = self.input_queue.len() as u32;
+        self.input_queue.clear();
+        dropped
+    }
+
+    /// Throttle `queue_input` delivery to roughly `chars_per_ms` guest
+    /// milliseconds, converting through the CLINT `timebase-frequency`
+    /// (see `devices::dtb::TIMEBASE_HZ`) since that's the only notion of
+    /// "guest time" this emulator tracks. `None` (the default) delivers a
+    /// queued byte every time the UART RX FIFO has room, as fast as the
+    /// guest drains it; a real terminal paste doesn't need to be this fast,
+    /// but a guest with a flaky line discipline can be more forgiving of a
+    /// pace closer to human typing.
+    pub fn set_paste_rate(&mut self, chars_per_ms: Option<f64>) {
+        self.paste_ticks_per_char = chars_per_ms.and_then(|rate| {
+            if rate <= 0.0 {
+                return None;
+            }
+            let ticks = (crate::devices::dtb::TIMEBASE_HZ as f64 / 1000.0) / rate;
+            Some(ticks.round().max(1.0) as u64)
+        });
+        self.paste_next_release = self.clint.get_mtime();
+    }
+
+    /// Deliver one queued input byte to the UART if it has room and, when
+    /// `set_paste_rate` has throttled delivery, enough guest time has
+    /// passed since the last byte. Called every iteration of the run loop.
+    fn pump_input_queue(&mut self) {
+        if self.input_queue.is_empty() || !self.uarts[0].rx_has_room() {
+            return;
+        }
+        if let Some(ticks_per_char) = self.paste_ticks_per_char {
+            if self.clint.get_mtime() < self.paste_next_release {
+                return;
+            }
+            self.paste_next_release = self.clint.get_mtime() + ticks_per_char;
+        }
+        if let Some(c) = self.input_queue.pop_front() {
+            self.uart_receive(c);
+        }
+    }
+
+    /// Service the virtio-9p queues, but only when something has actually
+    /// changed since the last call (`take_notify_dirty`) - a guest that
+    /// never touches virtio shouldn't pay for a queue scan on every single
+    /// block. `process_queues` itself is budget-limited per call, so heavy
+    /// I/O (e.g. `make -j` reading many files at once) can't monopolize a
+    /// block and starve timer delivery; whatever descriptors it does
+    /// process are charged to `timing_penalty` like any other device
+    /// access, so guest time keeps advancing while I/O is serviced instead
+    /// of the work looking free from the guest clock's perspective.
+    fn pump_virtio(&mut self) {
+        if !self.virtio9p.virtio.take_notify_dirty() {
+            return;
+        }
+        let processed = self.virtio9p.process_queues(&mut self.memory);
+        self.timing_penalty += processed as u64 * VIRTIO_DESC_CYCLES;
+    }
+
+    /// Service devices without executing any CPU instructions: retries any
+    /// virtio-9p requests suspended on a missing blob, processes any
+    /// still-pending virtio-9p queue entries, refreshes `mip` from device
+    /// state, and returns any UART 0 output produced. For a host that has
+    /// paused the guest (stopped calling `run`) but still wants to drain
+    /// I/O that doesn't depend on guest execution - e.g. finishing a 9p
+    /// read once the blob it was waiting on arrives.
+    pub fn service_devices(&mut self) -> Vec<u8> {
+        self.virtio9p.retry_suspended_requests(&mut self.memory);
+        self.virtio9p.process_queues(&mut self.memory);
+        self.update_interrupts();
+        self.uart_get_output()
+    }
+
+    /// Raise or clear an arbitrary PLIC interrupt source line, as if an
+    /// external device asserted/deasserted it. Lets test harnesses exercise
+    /// the S/M external interrupt paths (SEIP/MEIP) deterministically
+    /// without wiring up a real device.
+    pub fn inject_irq(&mut self, source: u32, level: bool) {
+        if level {
+            self.plic.raise_interrupt(source);
+        } else {
+            self.plic.clear_interrupt(source);
+        }
+        self.update_interrupts();
+        self.wake_from_wfi_if_pending();
+    }
+
+    /// Refresh mip from device state and, if that newly satisfies a pending
+    /// interrupt, wake a parked (WFI) CPU immediately rather than leaving it
+    /// halted until the next timer-driven `update_interrupts` call.
+    fn wake_from_wfi_if_pending(&mut self) {
+        if !self.cpu.wfi {
+            return;
+        }
+        self.update_interrupts();
+        if self.cpu.csr.mip & self.cpu.csr.mie != 0 {
+            self.cpu.wfi = false;
+        }
     }
     
-    /// Get pending UART output
+    /// Get pending output from UART 0. Delegates to `uart_get_output_on(0)`.
     pub fn uart_get_output(&mut self) -> Vec<u8> {
-        self.uart.get_output()
+        self.uart_get_output_on(0)
     }
-    
+
+    /// Get pending output from the UART at `idx`.
+    pub fn uart_get_output_on(&mut self, idx: usize) -> Vec<u8> {
+        self.uarts[idx].get_output()
+    }
+
+    /// Number of bytes pending in UART 0's output buffer.
+    pub fn uart_output_len(&self) -> usize {
+        self.uarts[0].output_len()
+    }
+
+    /// Drain as much pending output from UART 0 into `buf` as fits, without
+    /// allocating. Returns the number of bytes copied. See
+    /// `Uart::drain_into`.
+    pub fn uart_drain_into(&mut self, buf: &mut [u8]) -> usize {
+        self.uarts[0].drain_into(buf)
+    }
+
+    /// Configure UART 0's TX buffer cap and overflow policy, so a guest that
+    /// floods output with nobody draining it can't grow host memory without
+    /// bound. Defaults to `DEFAULT_TX_CAPACITY` bytes with
+    /// `TxOverflowPolicy::Backpressure`. See `Uart::set_tx_overflow_policy`.
+    pub fn set_uart_tx_overflow_policy(&mut self, capacity: usize, policy: TxOverflowPolicy) {
+        self.uarts[0].set_tx_overflow_policy(capacity, policy);
+    }
+
+    /// Bytes evicted from UART 0's TX buffer by the `DropOldest` overflow
+    /// policy so far.
+    pub fn uart_tx_dropped(&self) -> u64 {
+        self.uarts[0].tx_dropped()
+    }
+
     /// Get current PC
     pub fn get_pc(&self) -> u32 {
         self.cpu.pc
@@ -692,11 +3600,53 @@ impl System {
         self.cpu.tlb_stats()
     }
 
+    /// `(lookups, hits, evictions)` for the per-instruction decode cache.
+    pub fn get_icache_stats(&self) -> (u64, u64, u64) {
+        self.cpu.icache_stats()
+    }
+
+    /// Resize the instruction decode cache to hold at most `pages` pages
+    /// (each page covers 1024 instructions) before evicting the oldest one,
+    /// discarding whatever is currently cached. For experimenting with the
+    /// hit-rate/memory tradeoff on a given workload.
+    pub fn set_icache_size(&mut self, pages: usize) {
+        self.cpu.icache = crate::cpu::rv32::ICache::with_capacity(pages);
+    }
+
+    /// Number of SBI timer-set calls (legacy `set_timer` or
+    /// `SBI_EXT_TIME`'s `sbi_set_timer`) handled so far. A guest that
+    /// switches to the Sstc extension (`stimecmp`, no SBI involved) should
+    /// drive this to near-zero after boot.
+    pub fn get_sbi_timer_calls(&self) -> u64 {
+        self.sbi_timer_calls
+    }
+
     /// Get all register values (x0-x31)
     pub fn get_registers(&self) -> Vec<u32> {
         self.cpu.regs.to_vec()
     }
-    
+
+    /// Cheap 64-bit fingerprint (FNV-1a) of guest RAM, for CI determinism
+    /// checks that want to assert two runs reached identical state without
+    /// shipping megabytes of memory around - pair with `get_registers`/
+    /// `get_pc` for the rest of the state. Only hashes pages the guest has
+    /// actually written (see `Memory::touched_ram_pages`), each keyed by
+    /// its address, so it's O(dirty pages) rather than O(RAM size) and two
+    /// runs that wrote the same pages with the same content hash equal
+    /// regardless of how much untouched RAM surrounds them.
+    pub fn ram_hash(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+        const FNV_PRIME: u64 = 0x100000001b3;
+        let mut hash = FNV_OFFSET;
+        for (page_addr, bytes) in self.memory.touched_ram_pages() {
+            for byte in page_addr.to_le_bytes().into_iter().chain(bytes.iter().copied()) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        }
+        hash
+    }
+
     /// Read debugging memory (safe, no side effects)
     pub fn read_memory(&self, addr: u32, size: u32) -> Vec<u8> {
         let mut data = Vec::with_capacity(size as usize);
@@ -711,24 +3661,116 @@ impl System {
         data
     }
     
+    /// Dump the flat contents of guest RAM, for inspection with an external
+    /// disassembler or `hexdump` - unlike `to_state_bytes`, this is just the
+    /// raw bytes with no CPU/device state and no bincode/zstd framing.
+    pub fn dump_ram(&self) -> Vec<u8> {
+        self.memory.read_slice(DRAM_BASE, self.memory.ram_size())
+    }
+
+    /// RAM size in bytes, for sizing a zero-copy view from `sync_ram_view`.
+    pub fn ram_size(&self) -> usize {
+        self.memory.ram_size()
+    }
+
+    /// Resync the zero-copy RAM view and return `(ptr, len)` into it, for
+    /// JS memory inspectors and framebuffer-over-RAM rendering without a
+    /// per-frame `read_memory`/`dump_ram` copy - see `Memory::sync_ram_view`
+    /// for the sync contract and `Emulator::ram_ptr` for the safety notes
+    /// that apply to the pointer once it crosses into JS.
+    pub fn sync_ram_view(&mut self) -> (*const u8, usize) {
+        let view = self.memory.sync_ram_view();
+        (view.as_ptr(), view.len())
+    }
+
+    /// Restore guest RAM from a `dump_ram` image. `data` must be exactly
+    /// `ram_size()` bytes - anything else means it came from a
+    /// differently-sized system, and loading it would silently leave part
+    /// of RAM untouched (if too short) or drop trailing bytes (if too long).
+    pub fn load_ram(&mut self, data: &[u8]) -> Result<(), SystemError> {
+        let expected = self.memory.ram_size();
+        if data.len() != expected {
+            return Err(SystemError::RamImageSizeMismatch { actual: data.len(), expected });
+        }
+        self.memory.write_slice(DRAM_BASE, data);
+        Ok(())
+    }
+
+    /// Search guest RAM for `pattern` at every `alignment`-aligned address
+    /// in `[start, end)`, for cheat-engine-style "find this value" tooling.
+    /// See `Memory::search` for how this stays fast even for a pattern (like
+    /// a lone zero byte) that could otherwise match millions of addresses.
+    pub fn search_memory(&self, pattern: &[u8], start: u32, end: u32, alignment: u32) -> Vec<u32> {
+        self.memory.search(pattern, start, end, alignment)
+    }
+
+    /// Like `search_memory`, but for a `u32` interpreted the same way the
+    /// guest itself would read it back with `read32` (little-endian).
+    pub fn search_memory_u32(&self, value: u32, start: u32, end: u32, alignment: u32) -> Vec<u32> {
+        self.search_memory(&value.to_le_bytes(), start, end, alignment)
+    }
+
+    /// Narrow a previous `search_memory`/`search_memory_u32` result down to
+    /// the addresses that still hold `pattern` - the "value changed to X"
+    /// step of a cheat-engine-style search, without re-scanning all of RAM.
+    pub fn refine_memory_search(&self, addresses: &[u32], pattern: &[u8]) -> Vec<u32> {
+        addresses
+            .iter()
+            .copied()
+            .filter(|&addr| self.memory.read_slice(addr, pattern.len()) == pattern)
+            .collect()
+    }
+
+    /// Like `refine_memory_search`, but for a `u32` compared the same way
+    /// `search_memory_u32` matched it.
+    pub fn refine_memory_search_u32(&self, addresses: &[u32], value: u32) -> Vec<u32> {
+        addresses
+            .iter()
+            .copied()
+            .filter(|&addr| self.memory.read32(addr) == value)
+            .collect()
+    }
+
     /// Reset the system
     pub fn reset(&mut self) {
         self.cpu.reset();
+        self.cpu.pc = self.reset_pc;
         self.memory.reset();
-        self.uart.reset();
+        for uart in &mut self.uarts {
+            uart.reset();
+        }
         self.clint.reset();
         self.plic.reset();
         self.virtio9p.reset();
+        if self.wipe_fs_on_reset {
+            if let Backend::InMemory(_) = &self.virtio9p.fs {
+                self.virtio9p.fs = Backend::InMemory(InMemoryFileSystem::new());
+            }
+        }
     }
     
     /// Get missing blobs for lazy loading
     pub fn get_missing_blobs(&self) -> Vec<String> {
         self.virtio9p.get_missing_blobs()
     }
-    
+
+    /// Diagnostics for the 9p device: open fids, suspended requests and the
+    /// blobs they're waiting on, and missing blobs - for an embedder to show
+    /// when a guest filesystem access looks hung.
+    pub fn get_9p_debug_state(&self) -> crate::devices::virtio_9p::Debug9pState {
+        self.virtio9p.debug_state()
+    }
+
     /// Provide a blob for lazy loading
     pub fn provide_blob(&mut self, hash: String, data: Vec<u8>) {
+        if let Some(rec) = &mut self.recording {
+            rec.events.push(crate::replay::ReplayEvent::ProvideBlob {
+                hash: hash.clone(),
+                data: data.clone(),
+            });
+        }
         self.virtio9p.provide_blob(hash, data, &mut self.memory);
+        self.wake_from_wfi_if_pending();
     }
     
     /// Create a lightweight snapshot of the current state
@@ -737,9 +3779,15 @@ impl System {
     /// To restore, the same kernel/initrd must be loaded first.
     pub fn create_snapshot(&self, kernel_size: u32, initrd_size: Option<u32>) -> crate::snapshot::LightweightSnapshot {
         use crate::snapshot::*;
-        
-        let mut snapshot = LightweightSnapshot::new(kernel_size, initrd_size);
-        
+
+        let (kernel_hash, initrd_hash, cmdline) = match &self.boot_artifacts {
+            Some(artifacts) => (artifacts.kernel_hash, artifacts.initrd_hash, artifacts.cmdline.clone()),
+            None => ([0u8; 32], None, String::new()),
+        };
+        let ram_size = self.memory.ram_size() as u32;
+
+        let mut snapshot = LightweightSnapshot::new(kernel_size, initrd_size, kernel_hash, initrd_hash, ram_size, cmdline);
+
         // CPU state
         snapshot.cpu = CpuSnapshot {
             pc: self.cpu.pc,
@@ -752,8 +3800,8 @@ impl System {
             instruction_count: self.cpu.instruction_count,
         };
         
-        // UART state
-        snapshot.uart = UartSnapshot {
+        // UART state, one entry per UART
+        snapshot.uarts = self.uarts.iter().map(|_| UartSnapshot {
             ier: 0,
             fcr: 0,
             lcr: 0,
@@ -765,8 +3813,8 @@ impl System {
             dlm: 0,
             rx_fifo: Vec::new(),
             tx_output: Vec::new(),
-        };
-        
+        }).collect();
+
         // CLINT state
         snapshot.clint = ClintSnapshot {
             mtime: self.clint.get_mtime(),
@@ -819,11 +3867,69 @@ impl System {
     }
     
     /// Restore from a lightweight snapshot
-    /// 
-    /// The kernel and initrd must already be loaded before calling this.
-    pub fn restore_snapshot(&mut self, snapshot: &crate::snapshot::LightweightSnapshot) {
-        use crate::snapshot::PAGE_SIZE;
-        
+    ///
+    /// The kernel and initrd must already be loaded before calling this,
+    /// and are checked by content hash (not just size) against the ones the
+    /// snapshot was created from, along with RAM size, cmdline, and the
+    /// snapshot format version - restoring against a mismatched boot image
+    /// would otherwise silently corrupt the guest instead of failing loudly.
+    pub fn restore_snapshot(&mut self, snapshot: &crate::snapshot::LightweightSnapshot) -> Result<(), SystemError> {
+        use crate::snapshot::{hex_string, PAGE_SIZE};
+
+        if snapshot.version != crate::snapshot::LightweightSnapshot::VERSION {
+            return Err(SystemError::SnapshotVersionMismatch {
+                found: snapshot.version,
+                expected: crate::snapshot::LightweightSnapshot::VERSION,
+            });
+        }
+
+        let artifacts = self.boot_artifacts.as_ref()
+            .ok_or(SystemError::NoBootArtifactsForSnapshot)?;
+
+        if artifacts.kernel_hash != snapshot.kernel_hash {
+            return Err(SystemError::SnapshotMismatch(format!(
+                "wrong kernel for this snapshot (expected sha256 {}, loaded kernel is {})",
+                hex_string(&snapshot.kernel_hash), hex_string(&artifacts.kernel_hash)
+            )));
+        }
+
+        match (artifacts.initrd_hash, snapshot.initrd_hash) {
+            (Some(actual), Some(expected)) if actual != expected => {
+                return Err(SystemError::SnapshotMismatch(format!(
+                    "wrong initrd for this snapshot (expected sha256 {}, loaded initrd is {})",
+                    hex_string(&expected), hex_string(&actual)
+                )));
+            }
+            (None, Some(expected)) => {
+                return Err(SystemError::SnapshotMismatch(format!(
+                    "snapshot expects an initrd (sha256 {}) but none is loaded",
+                    hex_string(&expected)
+                )));
+            }
+            (Some(actual), None) => {
+                return Err(SystemError::SnapshotMismatch(format!(
+                    "snapshot was created without an initrd, but one is loaded (sha256 {})",
+                    hex_string(&actual)
+                )));
+            }
+            _ => {}
+        }
+
+        let ram_size = self.memory.ram_size() as u32;
+        if ram_size != snapshot.ram_size {
+            return Err(SystemError::SnapshotMismatch(format!(
+                "RAM size mismatch: snapshot expects {} bytes, this system has {} bytes",
+                snapshot.ram_size, ram_size
+            )));
+        }
+
+        if artifacts.cmdline != snapshot.cmdline {
+            return Err(SystemError::SnapshotMismatch(format!(
+                "kernel cmdline mismatch: snapshot expects {:?}, currently booted with {:?}",
+                snapshot.cmdline, artifacts.cmdline
+            )));
+        }
+
         // Restore CPU state
         self.cpu.pc = snapshot.cpu.pc;
         self.cpu.regs = snapshot.cpu.regs;
@@ -853,7 +3959,201 @@ impl System {
         self.jit_state.invalidate_all();
         self.cpu.mmu.reset();
         self.cpu.icache.reset();
+
+        Ok(())
+    }
+
+    /// Begin a chunked snapshot (see `next_snapshot_chunk`), for a host that
+    /// wants to hand a lightweight snapshot to something chunk-limited like
+    /// IndexedDB instead of buffering the whole compressed blob `to_bytes`
+    /// would produce. Captures state the same way `create_snapshot` does, so
+    /// the emulator is free to keep running once this returns - later
+    /// `next_snapshot_chunk` calls only drain data already copied out here.
+    pub fn begin_snapshot_stream(&mut self, kernel_size: u32, initrd_size: Option<u32>) {
+        let snapshot = self.create_snapshot(kernel_size, initrd_size);
+        self.snapshot_stream = Some(crate::snapshot::SnapshotStream::new(snapshot));
+    }
+
+    /// Produce the next chunk of the stream started by `begin_snapshot_stream`,
+    /// or `None` once the stream is exhausted (clearing it so a stale call
+    /// afterward returns `NoActiveSnapshotStream` instead of `None` again).
+    /// `max_bytes` is a soft cap - see `SnapshotStream::next_chunk`.
+    pub fn next_snapshot_chunk(&mut self, max_bytes: usize) -> Result<Option<Vec<u8>>, SystemError> {
+        let stream = self.snapshot_stream.as_mut()
+            .ok_or(SystemError::NoActiveSnapshotStream)?;
+
+        let chunk = stream.next_chunk(max_bytes).map_err(SystemError::SnapshotStreamError)?;
+        if chunk.is_none() {
+            self.snapshot_stream = None;
+        }
+        Ok(chunk)
+    }
+
+    /// Feed one chunk produced by a remote `next_snapshot_chunk` into the
+    /// in-progress restore, starting a new one on the first call. Chunks
+    /// must arrive in the order they were produced.
+    pub fn feed_snapshot_chunk(&mut self, chunk: &[u8]) -> Result<(), SystemError> {
+        self.snapshot_receiver
+            .get_or_insert_with(crate::snapshot::SnapshotReceiver::new)
+            .feed(chunk)
+            .map_err(SystemError::SnapshotStreamError)
+    }
+
+    /// Finish a restore started by `feed_snapshot_chunk`: validate that the
+    /// stream was complete, then apply it the same way `restore_snapshot`
+    /// applies a whole `LightweightSnapshot`.
+    pub fn finish_snapshot_restore(&mut self) -> Result<(), SystemError> {
+        let receiver = self.snapshot_receiver.take()
+            .ok_or(SystemError::NoActiveSnapshotRestore)?;
+        let snapshot = receiver.finish().map_err(SystemError::SnapshotStreamError)?;
+        self.restore_snapshot(&snapshot)
+    }
+
+    /// Serialize the entire emulator state to a versioned, Zstd-compressed
+    /// blob suitable for `from_state_bytes`.
+    pub fn to_state_bytes(&self) -> Result<Vec<u8>, SystemError> {
+        let serialized = bincode::serialize(self)
+            .map_err(|e| format!("serialization error: {}", e))?;
+        let compressed = zstd::stream::encode_all(&serialized[..], 0)
+            .map_err(|e| format!("compression error: {}", e))?;
+
+        let mut out = Vec::with_capacity(8 + compressed.len());
+        out.extend_from_slice(&STATE_MAGIC);
+        out.extend_from_slice(&STATE_FORMAT_VERSION.to_le_bytes());
+        out.extend_from_slice(&compressed);
+        Ok(out)
+    }
+
+    /// Restore a `System` from a blob produced by `to_state_bytes`.
+    ///
+    /// The blob is untrusted input (e.g. it may come from a web page calling
+    /// into the wasm build), so this rejects malformed headers, caps how much
+    /// memory decompression/deserialization can use, and sanity-checks the
+    /// deserialized state before handing it back rather than trusting
+    /// attacker-controlled `Vec` lengths and struct contents outright.
+    pub fn from_state_bytes(data: &[u8]) -> Result<System, SystemError> {
+        if data.len() < 8 {
+            return Err(SystemError::StateBlobTooShort);
+        }
+        let (header, body) = data.split_at(8);
+        if header[..4] != STATE_MAGIC {
+            return Err(SystemError::BadStateMagic);
+        }
+        let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if version != STATE_FORMAT_VERSION {
+            return Err(SystemError::UnsupportedStateVersion { found: version, expected: STATE_FORMAT_VERSION });
+        }
+
+        let decompressed = decompress_capped(body, MAX_DECOMPRESSED_STATE_SIZE)?;
+
+        let system: System = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .with_limit(MAX_BINCODE_SIZE)
+            .deserialize(&decompressed)
+            .map_err(|e| format!("deserialization error: {}", e))?;
+
+        system.validate()?;
+        Ok(system)
+    }
+
+    /// Begin recording nondeterministic inputs (UART bytes, blob
+    /// provisioning) for later reproduction with `replay`. Captures the
+    /// current full state via `to_state_bytes` as the starting point;
+    /// calling this again while already recording discards the
+    /// in-progress one and starts over.
+    pub fn start_recording(&mut self) -> Result<(), SystemError> {
+        let initial_state = self.to_state_bytes()?;
+        self.recording = Some(RecordingState { initial_state, events: Vec::new() });
+        Ok(())
+    }
+
+    /// Whether a recording is currently in progress.
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Stop recording and return a replay file (see `replay`) capturing
+    /// everything observed since `start_recording`, or an error if no
+    /// recording was in progress.
+    pub fn stop_recording(&mut self) -> Result<Vec<u8>, SystemError> {
+        let state = self.recording.take()
+            .ok_or(SystemError::NoActiveRecording)?;
+        let file = crate::replay::ReplayFile {
+            version: crate::replay::ReplayFile::VERSION,
+            initial_state: state.initial_state,
+            events: state.events,
+        };
+        Ok(file.to_bytes()?)
+    }
+
+    /// Reconstruct and re-run a session recorded by `start_recording`/
+    /// `stop_recording`: restores the initial state, then replays every
+    /// recorded `run`/`uart_receive`/`provide_blob` call in the exact order
+    /// the original caller made them, reproducing the same execution -
+    /// this system has no nondeterminism of its own (see `crate::replay`),
+    /// so driving it through the same calls in the same order is enough.
+    pub fn replay(data: &[u8]) -> Result<System, SystemError> {
+        let file = crate::replay::ReplayFile::from_bytes(data)?;
+        if file.version != crate::replay::ReplayFile::VERSION {
+            return Err(SystemError::Other(format!(
+                "unsupported replay file version {} (expected {})",
+                file.version, crate::replay::ReplayFile::VERSION
+            )));
+        }
+
+        let mut system = System::from_state_bytes(&file.initial_state)?;
+
+        for event in file.events {
+            match event {
+                crate::replay::ReplayEvent::Run { max_cycles } => {
+                    system.run_with_reason(max_cycles);
+                }
+                crate::replay::ReplayEvent::UartInput { uart_idx, byte } => {
+                    system.uart_receive_on(uart_idx, byte);
+                }
+                crate::replay::ReplayEvent::ProvideBlob { hash, data } => {
+                    system.provide_blob(hash, data);
+                }
+            }
+        }
+
+        Ok(system)
+    }
+
+    /// Sanity-check invariants a corrupted or hostile deserialized `System`
+    /// might violate, before it's trusted enough to run or to swap into a
+    /// live emulator.
+    fn validate(&self) -> Result<(), SystemError> {
+        self.memory.validate()?;
+
+        if !self.memory.is_mapped(self.cpu.pc) {
+            return Err(SystemError::PcNotMapped(self.cpu.pc));
+        }
+
+        if self.uarts.len() != UART_BASES.len() {
+            return Err(SystemError::UartCountMismatch { found: self.uarts.len(), expected: UART_BASES.len() });
+        }
+
+        Ok(())
+    }
+}
+
+/// Decompress a Zstd frame, aborting once more than `max_size` bytes have
+/// come out. This guards against a crafted frame whose internal size hint
+/// undersells how much data it actually expands to.
+fn decompress_capped(data: &[u8], max_size: u64) -> Result<Vec<u8>, SystemError> {
+    let decoder = zstd::stream::Decoder::new(data)
+        .map_err(|e| format!("decompression error: {}", e))?;
+    let mut limited = decoder.take(max_size + 1);
+    let mut out = Vec::new();
+    limited
+        .read_to_end(&mut out)
+        .map_err(|e| format!("decompression error: {}", e))?;
+
+    if out.len() as u64 > max_size {
+        return Err(SystemError::DecompressedTooLarge { limit: max_size });
     }
+    Ok(out)
 }
 
 /// Bus implementation that routes to devices
@@ -863,12 +4163,44 @@ impl System {
 /// - MMIO accesses use function call dispatch (cold path)
 struct SystemBus<'a> {
     memory: &'a mut Memory,
-    uart: &'a mut Uart,
+    uarts: &'a mut Vec<Uart>,
     clint: &'a mut Clint,
     plic: &'a mut Plic,
     virtio9p: &'a mut Virtio9p,
     /// Cached RAM size for bounds checking
     ram_size: usize,
+    /// Address to watch for `tohost` writes, if configured.
+    tohost_addr: Option<u32>,
+    /// Set when a `tohost` write is observed; drained by `System::run`.
+    tohost_pending: &'a mut Option<u32>,
+    /// Guest PC and instruction count to stamp onto MMIO trace entries
+    /// (block granularity for JIT-executed code).
+    pc: u32,
+    instruction_count: u64,
+    /// MMIO ranges to trace, if any. Empty means tracing is off.
+    trace_ranges: &'a [(u32, u32)],
+    /// Ring buffer accesses matching `trace_ranges` are appended to.
+    trace_buf: &'a mut Vec<MmioTraceEntry>,
+    /// Latency model to charge accesses against, if configured.
+    timing_model: Option<TimingModel>,
+    /// Penalty cycles accumulated this step, drained by `run_with_reason`.
+    timing_penalty: &'a mut u64,
+    /// ROM write attempts observed this step, drained by
+    /// `System::take_rom_write_attempts`.
+    rom_write_log: &'a mut Vec<RomWriteAttempt>,
+    /// Host-imposed access overlay set by `System::add_protected_range`.
+    /// Empty means no restriction.
+    protected_ranges: &'a [(u32, u32, u8)],
+    /// Set by the dispatch below whenever the most recent access landed on
+    /// CLINT/UART/PLIC/virtio9p rather than RAM/ROM; see
+    /// `Bus::take_mmio_access`.
+    mmio_access: Cell<bool>,
+    /// Running total mirroring `System::mmio_access_total`, bumped by
+    /// `mark_mmio` alongside `mmio_access` - unlike that `Cell`, this isn't
+    /// consumed by the JIT early-exit check, so `run_with_reason`'s
+    /// stuck-loop detector can diff it across a step without racing that
+    /// path for the same signal.
+    mmio_access_total: &'a mut u64,
 }
 
 use crate::memory::Bus;
@@ -876,21 +4208,88 @@ use crate::memory::Bus;
 impl<'a> SystemBus<'a> {
     /// Create a new SystemBus with cached RAM size
     #[inline]
+    #[allow(clippy::too_many_arguments)]
     fn new(
         memory: &'a mut Memory,
-        uart: &'a mut Uart,
+        uarts: &'a mut Vec<Uart>,
         clint: &'a mut Clint,
         plic: &'a mut Plic,
         virtio9p: &'a mut Virtio9p,
+        tohost_addr: Option<u32>,
+        tohost_pending: &'a mut Option<u32>,
+        pc: u32,
+        instruction_count: u64,
+        trace_ranges: &'a [(u32, u32)],
+        trace_buf: &'a mut Vec<MmioTraceEntry>,
+        timing_model: Option<TimingModel>,
+        timing_penalty: &'a mut u64,
+        rom_write_log: &'a mut Vec<RomWriteAttempt>,
+        protected_ranges: &'a [(u32, u32, u8)],
+        mmio_access_total: &'a mut u64,
     ) -> Self {
         let ram_size = memory.ram_size();
         SystemBus {
             memory,
-            uart,
+            uarts,
             clint,
             plic,
             virtio9p,
             ram_size,
+            tohost_addr,
+            tohost_pending,
+            pc,
+            instruction_count,
+            trace_ranges,
+            trace_buf,
+            timing_model,
+            timing_penalty,
+            rom_write_log,
+            protected_ranges,
+            mmio_access: Cell::new(false),
+            mmio_access_total,
+        }
+    }
+
+    /// Record that the access just dispatched landed on a device rather
+    /// than RAM/ROM: sets the consuming `mmio_access` flag `take_mmio_access`
+    /// reads, and bumps `mmio_access_total` so a diff across the step
+    /// survives that consumption.
+    #[inline(always)]
+    fn mark_mmio(&mut self) {
+        self.mmio_access.set(true);
+        *self.mmio_access_total += 1;
+    }
+
+    /// Add the configured per-access penalty (RAM or MMIO) to the running
+    /// total, if a timing model is set. A `None` model keeps this a single
+    /// predictable branch, matching `trace_access`'s style.
+    #[inline(always)]
+    fn charge(&mut self, is_mmio: bool) {
+        if let Some(model) = self.timing_model {
+            *self.timing_penalty += (if is_mmio { model.mmio_cycles } else { model.ram_cycles }) as u64;
+        }
+    }
+
+    /// Record `addr`/`size`/`is_write`/`value` if it falls within a traced
+    /// MMIO range. The `is_empty` check keeps this a single predictable
+    /// branch on the hot path when tracing is off.
+    #[inline(always)]
+    fn trace_access(&mut self, addr: u32, size: u8, is_write: bool, value: u32) {
+        if self.trace_ranges.is_empty() {
+            return;
+        }
+        if self.trace_ranges.iter().any(|&(base, len)| addr >= base && addr < base + len) {
+            if self.trace_buf.len() >= MMIO_TRACE_CAPACITY {
+                self.trace_buf.remove(0);
+            }
+            self.trace_buf.push(MmioTraceEntry {
+                instruction_count: self.instruction_count,
+                pc: self.pc,
+                addr,
+                size,
+                is_write,
+                value,
+            });
         }
     }
     
@@ -911,54 +4310,133 @@ impl<'a> SystemBus<'a> {
 }
 
 impl<'a> Bus for SystemBus<'a> {
+    fn is_executable(&self, addr: u32) -> bool {
+        self.memory.is_executable(addr) && protected_perms(self.protected_ranges, addr) & PROT_EXEC != 0
+    }
+
+    fn is_read_allowed(&self, addr: u32) -> bool {
+        protected_perms(self.protected_ranges, addr) & PROT_READ != 0
+    }
+
+    fn is_write_allowed(&self, addr: u32) -> bool {
+        protected_perms(self.protected_ranges, addr) & PROT_WRITE != 0
+    }
+
+    fn is_ram(&self, addr: u32) -> bool {
+        self.memory.is_ram(addr)
+    }
+
+    fn is_rom(&self, addr: u32) -> bool {
+        self.memory.is_rom(addr)
+    }
+
+    fn is_mapped(&self, addr: u32) -> bool {
+        self.ram_offset(addr).is_some()
+            || self.memory.is_rom(addr)
+            || (addr >= CLINT_BASE && addr < CLINT_BASE + CLINT_SIZE)
+            || uart_index_for(addr).is_some()
+            || (addr >= PLIC_BASE && addr < PLIC_BASE + PLIC_SIZE)
+            || (addr >= VIRTIO_BASE && addr < VIRTIO_BASE + VIRTIO_SIZE)
+    }
+
+    fn mtime(&self) -> Option<u64> {
+        Some(self.clint.get_mtime())
+    }
+
+    fn take_mmio_access(&self) -> bool {
+        self.mmio_access.replace(false)
+    }
+
+    fn record_rom_write_attempt(&mut self, pc: u32, addr: u32) {
+        if self.rom_write_log.len() >= ROM_WRITE_LOG_CAPACITY {
+            self.rom_write_log.remove(0);
+        }
+        self.rom_write_log.push(RomWriteAttempt { pc, addr });
+    }
+
     fn read8(&mut self, addr: u32) -> u8 {
         // jor1k-style: fast path for RAM using direct access
         if let Some(offset) = self.ram_offset(addr) {
+            self.charge(false);
+            self.mmio_access.set(false);
             // SAFETY: ram_offset ensures offset is within bounds
             return unsafe { self.memory.ram_read8_unchecked(offset) };
         }
         // Device checks (less common)
         if addr >= CLINT_BASE && addr < CLINT_BASE + CLINT_SIZE {
-            return self.clint.read8(addr - CLINT_BASE);
+            self.charge(true);
+            self.mark_mmio();
+            let value = self.clint.read8(addr - CLINT_BASE);
+            self.trace_access(addr, 1, false, value as u32);
+            return value;
         }
-        if addr >= UART_BASE && addr < UART_BASE + UART_SIZE {
-            return self.uart.read8(addr - UART_BASE);
+        if let Some(idx) = uart_index_for(addr) {
+            self.charge(true);
+            self.mark_mmio();
+            let value = self.uarts[idx].read8(addr - UART_BASES[idx]);
+            self.trace_access(addr, 1, false, value as u32);
+            return value;
         }
         if addr >= PLIC_BASE && addr < PLIC_BASE + PLIC_SIZE {
-            return self.plic.read8(addr - PLIC_BASE);
+            self.charge(true);
+            self.mark_mmio();
+            let value = self.plic.read8(addr - PLIC_BASE);
+            self.trace_access(addr, 1, false, value as u32);
+            return value;
         }
         if addr >= VIRTIO_BASE && addr < VIRTIO_BASE + VIRTIO_SIZE {
-            return self.virtio9p.read8(addr - VIRTIO_BASE);
+            self.charge(true);
+            self.mark_mmio();
+            let value = self.virtio9p.read8(addr - VIRTIO_BASE);
+            self.trace_access(addr, 1, false, value as u32);
+            return value;
         }
-        // Fallback to memory for other addresses (ROM, etc)
+        // Fallback to memory for other addresses (ROM, etc) - not a
+        // throttle-worthy device, see `take_mmio_access`.
+        self.mmio_access.set(false);
         self.memory.read8(addr)
     }
-    
+
     fn write8(&mut self, addr: u32, value: u8) {
         // jor1k-style: fast path for RAM using direct access
         if let Some(offset) = self.ram_offset(addr) {
+            self.charge(false);
+            self.mmio_access.set(false);
             // SAFETY: ram_offset ensures offset is within bounds
             unsafe { self.memory.ram_write8_unchecked(offset, value) };
             return;
         }
         // Device checks
         if addr >= CLINT_BASE && addr < CLINT_BASE + CLINT_SIZE {
+            self.charge(true);
+            self.mark_mmio();
+            self.trace_access(addr, 1, true, value as u32);
             self.clint.write8(addr - CLINT_BASE, value);
             return;
         }
-        if addr >= UART_BASE && addr < UART_BASE + UART_SIZE {
-            self.uart.write8(addr - UART_BASE, value);
+        if let Some(idx) = uart_index_for(addr) {
+            self.charge(true);
+            self.mark_mmio();
+            self.trace_access(addr, 1, true, value as u32);
+            self.uarts[idx].write8(addr - UART_BASES[idx], value);
             return;
         }
         if addr >= PLIC_BASE && addr < PLIC_BASE + PLIC_SIZE {
+            self.charge(true);
+            self.mark_mmio();
+            self.trace_access(addr, 1, true, value as u32);
             self.plic.write8(addr - PLIC_BASE, value);
             return;
         }
         if addr >= VIRTIO_BASE && addr < VIRTIO_BASE + VIRTIO_SIZE {
+            self.charge(true);
+            self.mark_mmio();
+            self.trace_access(addr, 1, true, value as u32);
             self.virtio9p.write8(addr - VIRTIO_BASE, value);
             return;
         }
         // Fallback
+        self.mmio_access.set(false);
         self.memory.write8(addr, value);
     }
     
@@ -967,6 +4445,8 @@ impl<'a> Bus for SystemBus<'a> {
         if let Some(offset) = self.ram_offset(addr) {
             // Check we have room for 16-bit read
             if offset + 1 < self.ram_size {
+                self.charge(false);
+                self.mmio_access.set(false);
                 // SAFETY: bounds checked above
                 return unsafe { self.memory.ram_read16_unchecked(offset) };
             }
@@ -976,12 +4456,14 @@ impl<'a> Bus for SystemBus<'a> {
         let hi = self.read8(addr + 1) as u16;
         lo | (hi << 8)
     }
-    
+
     fn write16(&mut self, addr: u32, value: u16) {
         // jor1k-style: fast path for RAM using direct access
         if let Some(offset) = self.ram_offset(addr) {
             // Check we have room for 16-bit write
             if offset + 1 < self.ram_size {
+                self.charge(false);
+                self.mmio_access.set(false);
                 // SAFETY: bounds checked above
                 unsafe { self.memory.ram_write16_unchecked(offset, value) };
                 return;
@@ -991,38 +4473,64 @@ impl<'a> Bus for SystemBus<'a> {
         self.write8(addr, value as u8);
         self.write8(addr + 1, (value >> 8) as u8);
     }
-    
+
     fn read32(&mut self, addr: u32) -> u32 {
         // jor1k-style: fast path for RAM using direct access
         if let Some(offset) = self.ram_offset(addr) {
             // Check we have room for 32-bit read
             if offset + 3 < self.ram_size {
+                self.charge(false);
+                self.mmio_access.set(false);
                 // SAFETY: bounds checked above
                 return unsafe { self.memory.ram_read32_unchecked(offset) };
             }
         }
         // Device checks
         if addr >= CLINT_BASE && addr < CLINT_BASE + CLINT_SIZE {
-            return self.clint.read32(addr - CLINT_BASE);
+            self.charge(true);
+            self.mark_mmio();
+            let value = self.clint.read32(addr - CLINT_BASE);
+            self.trace_access(addr, 4, false, value);
+            return value;
         }
-        if addr >= UART_BASE && addr < UART_BASE + UART_SIZE {
-            return self.uart.read32(addr - UART_BASE);
+        if let Some(idx) = uart_index_for(addr) {
+            self.charge(true);
+            self.mark_mmio();
+            let value = self.uarts[idx].read32(addr - UART_BASES[idx]);
+            self.trace_access(addr, 4, false, value);
+            return value;
         }
         if addr >= PLIC_BASE && addr < PLIC_BASE + PLIC_SIZE {
-            return self.plic.read32(addr - PLIC_BASE);
+            self.charge(true);
+            self.mark_mmio();
+            let value = self.plic.read32(addr - PLIC_BASE);
+            self.trace_access(addr, 4, false, value);
+            return value;
         }
         if addr >= VIRTIO_BASE && addr < VIRTIO_BASE + VIRTIO_SIZE {
-            return self.virtio9p.read32(addr - VIRTIO_BASE);
+            self.charge(true);
+            self.mark_mmio();
+            let value = self.virtio9p.read32(addr - VIRTIO_BASE);
+            self.trace_access(addr, 4, false, value);
+            return value;
         }
         // Fallback
+        self.mmio_access.set(false);
         self.memory.read32(addr)
     }
-    
+
     fn write32(&mut self, addr: u32, value: u32) {
+        // riscv-tests `tohost` cell normally lives inside RAM, so this has
+        // to be checked before the RAM fast path below.
+        if self.tohost_addr == Some(addr) {
+            *self.tohost_pending = Some(value);
+        }
         // jor1k-style: fast path for RAM using direct access
         if let Some(offset) = self.ram_offset(addr) {
             // Check we have room for 32-bit write
             if offset + 3 < self.ram_size {
+                self.charge(false);
+                self.mmio_access.set(false);
                 // SAFETY: bounds checked above
                 unsafe { self.memory.ram_write32_unchecked(offset, value) };
                 return;
@@ -1030,60 +4538,80 @@ impl<'a> Bus for SystemBus<'a> {
         }
         // Device checks
         if addr >= CLINT_BASE && addr < CLINT_BASE + CLINT_SIZE {
+            self.charge(true);
+            self.mark_mmio();
+            self.trace_access(addr, 4, true, value);
             self.clint.write32(addr - CLINT_BASE, value);
             return;
         }
-        if addr >= UART_BASE && addr < UART_BASE + UART_SIZE {
-            self.uart.write32(addr - UART_BASE, value);
+        if let Some(idx) = uart_index_for(addr) {
+            self.charge(true);
+            self.mark_mmio();
+            self.trace_access(addr, 4, true, value);
+            self.uarts[idx].write32(addr - UART_BASES[idx], value);
             return;
         }
         if addr >= PLIC_BASE && addr < PLIC_BASE + PLIC_SIZE {
+            self.charge(true);
+            self.mark_mmio();
+            self.trace_access(addr, 4, true, value);
             self.plic.write32(addr - PLIC_BASE, value);
             return;
         }
         if addr >= VIRTIO_BASE && addr < VIRTIO_BASE + VIRTIO_SIZE {
+            self.charge(true);
+            self.mark_mmio();
+            self.trace_access(addr, 4, true, value);
             self.virtio9p.write32(addr - VIRTIO_BASE, value);
             return;
         }
         // Fallback
+        self.mmio_access.set(false);
         self.memory.write32(addr, value);
     }
-    
+
     fn read64(&mut self, addr: u32) -> u64 {
         // jor1k-style: fast path for RAM using direct access
         if let Some(offset) = self.ram_offset(addr) {
             // Check we have room for 64-bit read
             if offset + 7 < self.ram_size {
+                self.charge(false);
+                self.mmio_access.set(false);
                 // SAFETY: bounds checked above
                 return unsafe { self.memory.ram_read64_unchecked(offset) };
             }
         }
-        // CLINT has 64-bit registers (mtime, mtimecmp)
+        // CLINT has 64-bit registers (mtime, mtimecmp); read atomically so a
+        // tick() landing between two 32-bit halves can't tear the value.
         if addr >= CLINT_BASE && addr < CLINT_BASE + CLINT_SIZE {
-            let lo = self.clint.read32(addr - CLINT_BASE) as u64;
-            let hi = self.clint.read32(addr - CLINT_BASE + 4) as u64;
-            return lo | (hi << 32);
+            self.charge(true);
+            self.mark_mmio();
+            return self.clint.read64(addr - CLINT_BASE);
         }
         // Default: compose from two 32-bit reads
         let lo = self.read32(addr) as u64;
         let hi = self.read32(addr + 4) as u64;
         lo | (hi << 32)
     }
-    
+
     fn write64(&mut self, addr: u32, value: u64) {
         // jor1k-style: fast path for RAM using direct access
         if let Some(offset) = self.ram_offset(addr) {
             // Check we have room for 64-bit write
             if offset + 7 < self.ram_size {
+                self.charge(false);
+                self.mmio_access.set(false);
                 // SAFETY: bounds checked above
                 unsafe { self.memory.ram_write64_unchecked(offset, value) };
                 return;
             }
         }
-        // CLINT has 64-bit registers (mtime, mtimecmp)
+        // CLINT has 64-bit registers (mtime, mtimecmp); write atomically so a
+        // tick() landing between two 32-bit halves can't tear the value.
         if addr >= CLINT_BASE && addr < CLINT_BASE + CLINT_SIZE {
-            self.clint.write32(addr - CLINT_BASE, value as u32);
-            self.clint.write32(addr - CLINT_BASE + 4, (value >> 32) as u32);
+            self.charge(true);
+            self.mark_mmio();
+            self.clint.write64(addr - CLINT_BASE, value);
             return;
         }
         // Default: decompose into two 32-bit writes
@@ -1116,10 +4644,2149 @@ mod tests {
         assert!(dtb_addr < DRAM_BASE + 16 * 1024 * 1024);
         assert_eq!(dtb_addr & 0xFFF, 0); // Aligned
         
-        // Check DTB magic (FDT is big-endian, so we read bytes or swap)
-        // 0xd00dfeed stored as [d0, 0d, fe, ed]
-        // read32 (LE) reads as 0xedfe0dd0
-        let magic_val = sys.memory.read32(dtb_addr);
-        assert_eq!(magic_val.to_be(), 0xd00dfeed);
+        // The FDT header is big-endian regardless of guest endianness.
+        assert_eq!(sys.memory.read32_be(dtb_addr), 0xd00dfeed);
+    }
+
+    #[test]
+    fn test_setup_linux_boot_errors_instead_of_overwriting_initrd_with_oversized_dtb() {
+        // `place_initrd` reserves a fixed 64KB for the DTB up front; a
+        // cmdline big enough to blow past that should be rejected rather
+        // than silently landing on top of the initrd it just placed.
+        let mut sys = System::new(8, None).unwrap(); // 8MB RAM
+        let dummy_kernel = vec![0x13, 0x00, 0x00, 0x00]; // NOP
+        let dummy_initrd = vec![0xAB; 4096];
+        let huge_cmdline = "console=ttyS0 module.param=1 ".repeat(4000); // >> 64KB
+
+        let err = sys
+            .setup_linux_boot_with_initrd(&dummy_kernel, Some(&dummy_initrd), &huge_cmdline)
+            .unwrap_err();
+        assert!(err.to_string().contains("overlap"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_compute_boot_layout_rejects_ram_too_small_for_kernel_and_dtb_reserve() {
+        // 1 page of RAM - nowhere near enough for a kernel, the 1MB
+        // KERNEL_GAP, and the DTB reservation.
+        match compute_boot_layout(BOOT_PAGE_SIZE, 4, None) {
+            Err(SystemError::NotEnoughRam { .. }) => {}
+            other => panic!("expected Err(NotEnoughRam), got is_ok={}", other.is_ok()),
+        }
+    }
+
+    #[test]
+    fn test_compute_boot_layout_fits_a_tight_initrd_exactly() {
+        // All page-aligned already, so there's no alignment slack to hide a
+        // too-small RAM size: kernel + KERNEL_GAP + initrd + DTB_RESERVE
+        // exactly fills `ram_size`.
+        let kernel_len = BOOT_PAGE_SIZE;
+        let initrd_len = BOOT_PAGE_SIZE;
+        let ram_size = kernel_len + KERNEL_GAP + initrd_len + DTB_RESERVE;
+
+        let layout = compute_boot_layout(ram_size, kernel_len, Some(initrd_len)).unwrap();
+        let (initrd_start, initrd_end) = layout.initrd.unwrap();
+        assert_eq!(initrd_end - initrd_start, initrd_len);
+        assert_eq!(layout.dtb_reserve.1, DRAM_BASE + ram_size);
+
+        // Shrink RAM by one page and the same layout must fail instead of
+        // silently overlapping the kernel.
+        match compute_boot_layout(ram_size - BOOT_PAGE_SIZE, kernel_len, Some(initrd_len)) {
+            Err(SystemError::NotEnoughRam { .. }) => {}
+            other => panic!("expected Err(NotEnoughRam), got is_ok={}", other.is_ok()),
+        }
+    }
+
+    #[test]
+    fn test_compute_boot_layout_regions_are_page_aligned_and_non_overlapping() {
+        let kernel_len = 4096 + 17; // deliberately not page-aligned
+        let initrd_len = 12345;
+        let ram_size = 16 * 1024 * 1024;
+
+        let layout = compute_boot_layout(ram_size, kernel_len, Some(initrd_len)).unwrap();
+        let (initrd_start, initrd_end) = layout.initrd.unwrap();
+        let (dtb_start, dtb_end) = layout.dtb_reserve;
+        let kernel_end = DRAM_BASE + kernel_len;
+
+        assert_eq!(initrd_start % BOOT_PAGE_SIZE, 0);
+        assert_eq!(dtb_start % BOOT_PAGE_SIZE, 0);
+
+        // Non-overlapping and in the documented low-to-high order.
+        assert!(kernel_end <= initrd_start);
+        assert!(initrd_end <= dtb_start);
+        assert!(dtb_end == DRAM_BASE + ram_size);
+    }
+
+    #[test]
+    fn test_setup_linux_boot_with_dtb_uses_supplied_blob() {
+        let mut sys = System::new(16, None).unwrap();
+        let dummy_kernel = vec![0x13, 0x00, 0x00, 0x00]; // NOP
+        let dtb = crate::devices::dtb::generate_fdt(16, "console=ttyS0", None, "rv32imafdc");
+
+        sys.setup_linux_boot_with_dtb(&dummy_kernel, None, &dtb).unwrap();
+
+        assert_eq!(sys.cpu.pc, 0x1000); // Boot ROM address
+        assert_eq!(sys.cpu.read_reg(10), 0); // a0 = hartid
+        let dtb_addr = sys.cpu.read_reg(11); // a1
+        assert_eq!(sys.memory.read32_be(dtb_addr), 0xd00dfeed);
+
+        assert_eq!(sys.get_dtb(), dtb);
+        assert!(sys.get_dtb_text().contains("bootargs = \"console=ttyS0\";"));
+    }
+
+    #[test]
+    fn test_setup_linux_boot_with_dtb_rejects_invalid_header() {
+        let mut sys = System::new(16, None).unwrap();
+        let dummy_kernel = vec![0x13, 0x00, 0x00, 0x00]; // NOP
+        let bad_dtb = vec![0u8; 10]; // too small to even hold a header
+
+        let err = sys.setup_linux_boot_with_dtb(&dummy_kernel, None, &bad_dtb).unwrap_err();
+        assert!(matches!(err, SystemError::InvalidDtb(_)), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_reboot_replays_supplied_dtb_rather_than_regenerating() {
+        let mut sys = System::new(16, None).unwrap();
+        let dummy_kernel = vec![0x13, 0x00, 0x00, 0x00]; // NOP
+        let dtb = crate::devices::dtb::generate_fdt(16, "console=ttyS0", None, "rv32imafdc");
+
+        sys.setup_linux_boot_with_dtb(&dummy_kernel, None, &dtb).unwrap();
+        sys.reboot().unwrap();
+
+        assert_eq!(sys.get_dtb(), dtb);
+    }
+
+    #[test]
+    fn test_get_dtb_text_before_boot_reports_no_dtb() {
+        let sys = System::new(16, None).unwrap();
+        assert_eq!(sys.get_dtb_text(), "(no DTB generated yet)");
+    }
+
+    #[test]
+    fn test_instruction_limit_halts_execution_near_the_ceiling() {
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+
+        // A long straight run of NOPs so the whole program compiles into a
+        // single JIT block - block execution is capped at 64 instructions
+        // (see `BlockCache::compile_block`), so the limit can be overshot
+        // by at most that much.
+        const NOP: u32 = 0x0000_0013;
+        let mut bytes = Vec::new();
+        for _ in 0..1000 {
+            bytes.extend_from_slice(&NOP.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        sys.set_instruction_limit(Some(500));
+        let (_, reason) = sys.run_with_reason(1000);
+
+        assert_eq!(reason, HaltReason::LimitReached);
+        assert!(sys.cpu.instruction_count >= 500);
+        assert!(sys.cpu.instruction_count < 500 + 64);
+    }
+
+    #[test]
+    fn test_stuck_detector_halts_on_a_compute_only_infinite_loop() {
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+
+        // `j .` - spins on the same instruction forever with no traps and
+        // no device I/O, so neither `TrapLoop` nor anything else would ever
+        // catch it.
+        const JAL_X0_SELF: u32 = 0x0000_006F;
+        sys.load_binary(&JAL_X0_SELF.to_le_bytes(), DRAM_BASE).unwrap();
+
+        sys.set_stuck_detector(Some(1000));
+        let (_, reason) = sys.run_with_reason(10_000);
+
+        assert_eq!(reason, HaltReason::Stuck);
+    }
+
+    #[test]
+    fn test_stuck_detector_does_not_trip_on_a_tight_uart_polling_loop() {
+        const UART_BASE: u32 = 0x0300_0000;
+        const LSR_OFFSET: u32 = 5;
+        const LUI_A1_UART_BASE: u32 = (UART_BASE & 0xFFFF_F000) | (11 << 7) | 0x37; // lui a1, UART_BASE>>12
+        const LB_A0_LSR_A1: u32 = (LSR_OFFSET << 20) | (11 << 15) | (10 << 7) | 0x03; // lb a0, LSR_OFFSET(a1)
+        const JAL_X0_BACK_4: u32 = 0xFFDF_F06F; // jal x0, -4 (jump back to the lb above)
+
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&LUI_A1_UART_BASE.to_le_bytes());
+        bytes.extend_from_slice(&LB_A0_LSR_A1.to_le_bytes());
+        bytes.extend_from_slice(&JAL_X0_BACK_4.to_le_bytes());
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        // Tighter than the UART read loop's own instruction count so a
+        // naive "same small PC range" check with no I/O awareness would
+        // false-positive on this legitimate busy-wait.
+        sys.set_stuck_detector(Some(50));
+        let (_, reason) = sys.run_with_reason(10_000);
+
+        assert_ne!(reason, HaltReason::Stuck);
+    }
+
+    #[test]
+    fn test_jit_config_max_block_size_changes_compiled_block_count() {
+        const NOP: u32 = 0x0000_0013;
+        let mut bytes = Vec::new();
+        for _ in 0..256 {
+            bytes.extend_from_slice(&NOP.to_le_bytes());
+        }
+
+        let compiled_blocks = |cap: usize| -> u64 {
+            let mut sys = System::new(16, None).unwrap();
+            sys.cpu.pc = DRAM_BASE;
+            sys.set_jit_config(crate::cpu::rv32::jit::JitConfig {
+                max_block_size: cap,
+                split_on_mmio: false,
+                threshold: crate::cpu::rv32::jit::JIT_THRESHOLD,
+            });
+            sys.load_binary(&bytes, DRAM_BASE).unwrap();
+            sys.run(256);
+            sys.block_cache.compiles
+        };
+
+        // 256 straight-line NOPs under an 8-instruction cap must split into
+        // more blocks than under a 128-instruction cap.
+        assert!(compiled_blocks(8) > compiled_blocks(128));
+    }
+
+    #[test]
+    fn test_mmio_access_ends_jit_block_early_matching_interpreter_progress() {
+        // Straight-line loads have no branch between them, so before
+        // `Bus::take_mmio_access` the interpreter-fallback loop in
+        // `execute_block` would run every cached instruction - up to
+        // `max_block_size` - before returning, even though each UART
+        // access needs the System-level round trip in between to see
+        // fresh device state. Over a small cycle budget the JIT path
+        // should retire roughly as many instructions as single-step
+        // interpretation (forced via the commit log, see `set_commit_log`),
+        // not silently jump ahead to the end of the whole compiled block.
+        const UART_BASE: u32 = 0x0300_0000;
+        const LSR_OFFSET: u32 = 5;
+        const LUI_A1_UART_BASE: u32 = (UART_BASE & 0xFFFF_F000) | (11 << 7) | 0x37; // lui a1, UART_BASE>>12
+        const LB_A0_LSR_A1: u32 = (LSR_OFFSET << 20) | (11 << 15) | (10 << 7) | 0x03; // lb a0, LSR_OFFSET(a1)
+        const JAL_X0_SELF: u32 = 0x0000_006F; // jal x0, 0 (spin forever)
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&LUI_A1_UART_BASE.to_le_bytes());
+        for _ in 0..40 {
+            bytes.extend_from_slice(&LB_A0_LSR_A1.to_le_bytes());
+        }
+        bytes.extend_from_slice(&JAL_X0_SELF.to_le_bytes());
+
+        let run_for = |commit_log: bool, budget: u32| -> u64 {
+            let mut sys = System::new(1, None).unwrap();
+            sys.cpu.pc = DRAM_BASE;
+            sys.set_commit_log(commit_log);
+            sys.load_binary(&bytes, DRAM_BASE).unwrap();
+            sys.run(budget);
+            sys.cpu.instruction_count
+        };
+
+        for budget in [2, 5, 10, 20] {
+            let jit_count = run_for(false, budget);
+            let interp_count = run_for(true, budget);
+            let ratio = jit_count as f64 / interp_count.max(1) as f64;
+            assert!(
+                (0.5..=2.0).contains(&ratio),
+                "budget={budget} jit={jit_count} interp={interp_count}"
+            );
+        }
+
+        // Sanity: the straight-line run of 40 MMIO reads must actually get
+        // split into more than one compiled block, not treated as one
+        // atomic unit - otherwise the ratio check above wouldn't be
+        // exercising the fix at all.
+        let mut sys = System::new(1, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.run(20);
+        assert!(sys.block_cache.compiles > 1);
+    }
+
+    #[test]
+    fn test_privilege_instruction_counts_track_a_machine_to_supervisor_transition() {
+        use crate::cpu::csr::MSTATUS_MPP;
+        use crate::cpu::PrivilegeLevel;
+
+        let mut sys = System::new(1, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.priv_level = PrivilegeLevel::Machine;
+        // MPP = Supervisor, so the `mret` below drops into S-mode rather
+        // than wherever MPP happened to reset to.
+        sys.cpu.csr.mstatus =
+            (sys.cpu.csr.mstatus & !MSTATUS_MPP) | ((PrivilegeLevel::Supervisor as u32) << 11);
+        sys.cpu.csr.mepc = DRAM_BASE + 12;
+
+        const NOP: u32 = 0x0000_0013;
+        const MRET: u32 = 0x3020_0073;
+        const JAL_X0_SELF: u32 = 0x0000_006F; // jal x0, 0 (spin forever)
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&NOP.to_le_bytes());       // 0: M-mode
+        bytes.extend_from_slice(&NOP.to_le_bytes());       // 4: M-mode
+        bytes.extend_from_slice(&MRET.to_le_bytes());      // 8: M-mode -> switches to S-mode
+        bytes.extend_from_slice(&NOP.to_le_bytes());       // 12: S-mode
+        bytes.extend_from_slice(&NOP.to_le_bytes());       // 16: S-mode
+        bytes.extend_from_slice(&NOP.to_le_bytes());       // 20: S-mode
+        bytes.extend_from_slice(&JAL_X0_SELF.to_le_bytes()); // 24: S-mode, terminator
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        sys.run(7);
+
+        let [user, supervisor, machine] = sys.privilege_instruction_counts();
+        assert_eq!(user, 0);
+        assert_eq!(machine, 3, "the two NOPs and the mret itself ran in M-mode");
+        assert_eq!(supervisor, 4, "the three NOPs and the closing jal ran in S-mode");
+    }
+
+    #[test]
+    fn test_replay_reproduces_a_recorded_interactive_session() {
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.priv_level = crate::cpu::PrivilegeLevel::Supervisor;
+
+        // Echo loop, unrolled for 4 bytes: getchar (SBI EID 2) leaves the
+        // byte in a0, then putchar (SBI EID 1) writes whatever's in a0
+        // straight back out - no branches needed since we drive exactly
+        // one byte through per iteration.
+        const GETCHAR: u32 = 0x0020_0893; // addi a7, x0, 2
+        const PUTCHAR: u32 = 0x0010_0893; // addi a7, x0, 1
+        const ECALL: u32 = 0x0000_0073;
+
+        let mut bytes = Vec::new();
+        for _ in 0..4 {
+            for inst in [GETCHAR, ECALL, PUTCHAR, ECALL] {
+                bytes.extend_from_slice(&inst.to_le_bytes());
+            }
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        sys.start_recording().unwrap();
+        assert!(sys.is_recording());
+
+        for &b in b"ping" {
+            sys.uart_receive(b);
+            // Each `ecall` ends its JIT block (see `BlockCache::compile_block`),
+            // so one echo iteration takes two blocks: getchar+ecall, then
+            // putchar+ecall.
+            sys.run(2);
+        }
+
+        let recording = sys.stop_recording().unwrap();
+        assert!(!sys.is_recording());
+
+        let output = sys.uart_get_output();
+        assert_eq!(output, b"ping");
+
+        let mut replayed = System::replay(&recording).unwrap();
+        assert_eq!(replayed.uart_get_output(), output);
+        assert_eq!(replayed.cpu.regs, sys.cpu.regs);
+        assert_eq!(replayed.cpu.instruction_count, sys.cpu.instruction_count);
+    }
+
+    #[test]
+    fn test_search_memory_u32_and_refine_by_value_changed() {
+        let mut sys = System::new(16, None).unwrap();
+
+        // Seed a handful of known values via load_binary, like a guest
+        // would leave them in its heap/globals.
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&100u32.to_le_bytes());
+        bytes.extend_from_slice(&999u32.to_le_bytes());
+        bytes.extend_from_slice(&100u32.to_le_bytes());
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        let hits = sys.search_memory_u32(100, DRAM_BASE, DRAM_BASE + 4096, 4);
+        assert_eq!(hits, vec![DRAM_BASE, DRAM_BASE + 8]);
+
+        // "Value changed to X": one of the two candidates changes, the
+        // other doesn't - refine should keep only the one that matches.
+        sys.memory.write32(DRAM_BASE, 200);
+        let refined = sys.refine_memory_search_u32(&hits, 200);
+        assert_eq!(refined, vec![DRAM_BASE]);
+    }
+
+    #[test]
+    fn test_dump_ram_round_trips_and_rejects_wrong_size() {
+        let mut sys = System::new(1, None).unwrap();
+
+        let mut pattern = Vec::new();
+        for i in 0..256u32 {
+            pattern.extend_from_slice(&i.to_le_bytes());
+        }
+        sys.load_binary(&pattern, DRAM_BASE).unwrap();
+
+        let dump = sys.dump_ram();
+        assert_eq!(dump.len(), sys.memory.ram_size());
+        assert_eq!(&dump[..pattern.len()], &pattern[..]);
+
+        // Zero RAM, then reload from the dump and verify it comes back.
+        sys.memory.write_slice(DRAM_BASE, &vec![0u8; pattern.len()]);
+        assert_ne!(sys.read_memory(DRAM_BASE, pattern.len() as u32), pattern);
+
+        sys.load_ram(&dump).unwrap();
+        assert_eq!(sys.read_memory(DRAM_BASE, pattern.len() as u32), pattern);
+
+        assert!(sys.load_ram(&dump[..dump.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_set_hart_count_validates_range() {
+        let mut sys = System::new(16, None).unwrap();
+        assert_eq!(sys.hart_count(), 1);
+
+        sys.set_hart_count(4).unwrap();
+        assert_eq!(sys.hart_count(), 4);
+
+        assert!(sys.set_hart_count(0).is_err());
+        assert!(sys.set_hart_count(MAX_HARTS + 1).is_err());
+        // A rejected count leaves the previous one in place.
+        assert_eq!(sys.hart_count(), 4);
+    }
+
+    #[test]
+    fn test_rng_seed_determines_identical_or_divergent_output() {
+        let mut a = System::new(16, None).unwrap();
+        let mut b = System::new(16, None).unwrap();
+        a.set_rng_seed(1234);
+        b.set_rng_seed(1234);
+        let draws_a: Vec<u64> = (0..8).map(|_| a.rng_next_u64()).collect();
+        let draws_b: Vec<u64> = (0..8).map(|_| b.rng_next_u64()).collect();
+        assert_eq!(draws_a, draws_b);
+
+        let mut c = System::new(16, None).unwrap();
+        c.set_rng_seed(5678);
+        let draws_c: Vec<u64> = (0..8).map(|_| c.rng_next_u64()).collect();
+        assert_ne!(draws_a, draws_c);
+    }
+
+    #[test]
+    fn test_ram_hash_matches_across_identical_runs_and_differs_for_different_state() {
+        // lui x6, 0x80000  (loads DRAM_BASE into x6)
+        // addi x5, x0, <imm>
+        // sw x5, 0(x6)
+        const LUI_X6_DRAM_BASE: u32 = 0x8000_0337;
+        const SW_X5_X6: u32 = 0x0053_2023;
+        let program = |imm: u32| -> Vec<u8> {
+            let addi_x5 = (imm << 20) | (5 << 7) | 0x13;
+            let mut bytes = Vec::new();
+            bytes.extend_from_slice(&LUI_X6_DRAM_BASE.to_le_bytes());
+            bytes.extend_from_slice(&addi_x5.to_le_bytes());
+            bytes.extend_from_slice(&SW_X5_X6.to_le_bytes());
+            bytes
+        };
+
+        let run = |imm: u32| -> u64 {
+            let mut sys = System::new(16, None).unwrap();
+            sys.cpu.pc = DRAM_BASE;
+            sys.load_binary(&program(imm), DRAM_BASE).unwrap();
+            sys.run(3);
+            assert_eq!(sys.read_memory(DRAM_BASE, 4), imm.to_le_bytes());
+            sys.ram_hash()
+        };
+
+        let first_run = run(0xAB);
+        let second_run = run(0xAB);
+        assert_eq!(first_run, second_run, "two runs of the same program should fingerprint identically");
+
+        let different_program = run(0xCD);
+        assert_ne!(first_run, different_program, "different final RAM contents should fingerprint differently");
+    }
+
+    #[test]
+    fn test_illegal_instruction_log_aggregates_repeated_encodings_by_count() {
+        let config = IsaConfig { extensions: "rv32ima".to_string(), ..IsaConfig::default() };
+        let mut sys = System::new_with_isa(16, None, config).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.csr.mtvec = DRAM_BASE + 0x1000; // install a handler so the trap is actually delivered
+        sys.set_illegal_instruction_log(true);
+
+        // fadd.s f1, f2, f3, repeated three times - illegal because F/D
+        // aren't in the default ISA string. mtvec points at more of the
+        // same encoding, so each retrap hits it again.
+        const FADD_S: u32 = 0x0031_00d3;
+        let mut bytes = Vec::new();
+        for _ in 0..3 {
+            bytes.extend_from_slice(&FADD_S.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.load_binary(&bytes, DRAM_BASE + 0x1000).unwrap();
+
+        sys.run(3);
+
+        let records = sys.take_illegal_instructions();
+        assert_eq!(records.len(), 1);
+        let record = records[0];
+        assert_eq!(record.raw_inst, FADD_S);
+        assert_eq!(record.count, 3);
+        assert_eq!(record.opcode, FADD_S & 0x7f);
+        assert_eq!(record.funct3, (FADD_S >> 12) & 0x7);
+        assert_eq!(record.funct7, (FADD_S >> 25) & 0x7f);
+
+        // Draining clears it until the next trap.
+        assert!(sys.take_illegal_instructions().is_empty());
+
+        // Disabling the log discards anything buffered and stops recording.
+        sys.cpu.pc = DRAM_BASE;
+        sys.run(1);
+        assert_eq!(sys.take_illegal_instructions().len(), 1);
+        sys.cpu.pc = DRAM_BASE;
+        sys.set_illegal_instruction_log(false);
+        sys.run(1);
+        assert!(sys.take_illegal_instructions().is_empty());
+    }
+
+    #[test]
+    fn test_sstc_stimecmp_raises_stip_independent_of_clint() {
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.csr.menvcfgh = MENVCFGH_STCE;
+        sys.cpu.csr.stimecmp = 100;
+
+        sys.cpu.csr.time = 50;
+        sys.update_interrupts();
+        assert_eq!(sys.cpu.csr.mip & MIP_STIP, 0);
+
+        sys.cpu.csr.time = 150;
+        sys.update_interrupts();
+        assert_ne!(sys.cpu.csr.mip & MIP_STIP, 0);
+        // CLINT never fired, so MTIP must stay clear.
+        assert_eq!(sys.cpu.csr.mip & MIP_MTIP, 0);
+    }
+
+    #[test]
+    fn test_wfi_wakes_for_stimecmp_alone_even_when_clint_never_armed() {
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.wfi = true;
+        sys.cpu.csr.mie |= MIP_STIP;
+        sys.cpu.csr.menvcfgh = MENVCFGH_STCE;
+        sys.cpu.csr.stimecmp = 1000; // CLINT's mtimecmp is left at its u64::MAX default.
+
+        let (_, reason) = sys.run_with_reason(100_000);
+
+        // Previously this reported Wfi immediately because only CLINT was
+        // consulted; stimecmp being armed must make the run loop fast-
+        // forward time and actually wake the hart instead.
+        assert_ne!(reason, HaltReason::Wfi);
+        assert!(!sys.cpu.wfi);
+        assert!(sys.cpu.csr.time >= 1000);
+    }
+
+    #[test]
+    fn test_rdtime_reads_live_clint_mtime_within_a_single_batch() {
+        // `csr.time` is only resynced from CLINT once per TIMER_BATCH (64)
+        // cycles inside `run`, but `execute_system` now refreshes it from
+        // `Bus::mtime` right before a `time`/`timeh` read, so two rdtime
+        // reads issued via `step_with_devices` (which never runs that
+        // periodic resync) must still observe CLINT ticking in between.
+        use crate::cpu::rv32::csr::CSR_TIME;
+
+        const FUNCT3_CSRRS: u32 = 0b010;
+        const OPCODE_SYSTEM: u32 = 0x73;
+        let rdtime = (CSR_TIME << 20) | (FUNCT3_CSRRS << 12) | (1 << 7) | OPCODE_SYSTEM;
+
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&rdtime.to_le_bytes());
+        bytes.extend_from_slice(&rdtime.to_le_bytes());
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        sys.clint.tick(10);
+        sys.step_with_devices().unwrap();
+        let first = sys.cpu.regs[1];
+
+        sys.clint.tick(10);
+        sys.step_with_devices().unwrap();
+        let second = sys.cpu.regs[1];
+
+        assert_ne!(first, second);
+        assert_eq!(second as u64, sys.clint.get_mtime());
+    }
+
+    #[test]
+    fn test_rdtime_rdtimeh_reconstruct_a_monotonic_value_across_a_32bit_wrap() {
+        // Both halves come from the same live `Bus::mtime()` snapshot (see
+        // `execute_system`'s CSR_TIME/CSR_TIMEH handling), so reconstructing
+        // the full 64-bit time from back-to-back time/timeh reads must keep
+        // increasing even as the low half wraps through zero.
+        use crate::cpu::rv32::csr::{CSR_TIME, CSR_TIMEH};
+
+        const FUNCT3_CSRRS: u32 = 0b010;
+        const OPCODE_SYSTEM: u32 = 0x73;
+        let rdtime = (CSR_TIME << 20) | (FUNCT3_CSRRS << 12) | (1 << 7) | OPCODE_SYSTEM; // x1 = time
+        let rdtimeh = (CSR_TIMEH << 20) | (FUNCT3_CSRRS << 12) | (2 << 7) | OPCODE_SYSTEM; // x2 = timeh
+
+        let mut sys = System::new(16, None).unwrap();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&rdtime.to_le_bytes());
+        bytes.extend_from_slice(&rdtimeh.to_le_bytes());
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        // Start a few ticks before the low half wraps from 0xFFFF_FFFF back
+        // to 0, then step across the boundary.
+        sys.clint.set_mtime((1u64 << 32) - 5);
+
+        let mut previous = 0u64;
+        for _ in 0..10 {
+            sys.cpu.pc = DRAM_BASE;
+            sys.step_with_devices().unwrap();
+            sys.step_with_devices().unwrap();
+            let reconstructed = ((sys.cpu.regs[2] as u64) << 32) | sys.cpu.regs[1] as u64;
+            assert!(reconstructed >= previous, "time went backward: {reconstructed} < {previous}");
+            previous = reconstructed;
+            sys.clint.tick(1);
+        }
+        // The loop above did cross the wrap: the low half is small again.
+        assert!(previous as u32 <= 10);
+    }
+
+    #[test]
+    fn test_time_csr_is_gated_by_mcounteren_for_supervisor_mode() {
+        // `CSR_TIME` shares the same mcounteren/scounteren gating as the
+        // HPM counters (bit 1, per the privileged spec) - unlike those,
+        // it's also readable from U-mode when scounteren grants it, which
+        // `counter_accessible` already handles identically to bit 0/2.
+        use crate::cpu::rv32::csr::CSR_TIME;
+        use crate::cpu::PrivilegeLevel;
+
+        const FUNCT3_CSRRS: u32 = 0b010;
+        const OPCODE_SYSTEM: u32 = 0x73;
+        let rdtime = (CSR_TIME << 20) | (FUNCT3_CSRRS << 12) | (1 << 7) | OPCODE_SYSTEM;
+
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.priv_level = PrivilegeLevel::Supervisor;
+        sys.load_binary(&rdtime.to_le_bytes(), DRAM_BASE).unwrap();
+
+        // mcounteren defaults to 0: S-mode can't see `time` yet.
+        let err = sys.step_with_devices().unwrap_err();
+        assert!(matches!(err, crate::cpu::trap::Trap::IllegalInstruction(_)));
+
+        sys.cpu.csr.mcounteren = 1 << 1;
+        sys.cpu.pc = DRAM_BASE;
+        sys.step_with_devices().unwrap();
+    }
+
+    #[test]
+    fn test_clint_read64_of_mtime_never_tears_across_a_tick() {
+        // A guest `ld` on mtime must observe a single atomic snapshot, not
+        // two separately-read 32-bit halves straddling a tick(); composing
+        // from two reads could otherwise briefly go backward (e.g. the low
+        // word wraps to 0 and rolls into the high word between the two
+        // reads). SystemBus::read64/write64 (both rv32 and rv64) now just
+        // forward to this, so exercising it directly here is equivalent to
+        // going through the full bus without needing to hand-assemble one.
+        let mut clint = Clint::new();
+
+        let mut previous = clint.read64(0xBFF8);
+        for _ in 0..10_000 {
+            clint.tick(1);
+            let current = clint.read64(0xBFF8);
+            assert!(
+                current >= previous,
+                "mtime went backward: {} -> {}",
+                previous,
+                current
+            );
+            previous = current;
+        }
+    }
+
+    #[test]
+    fn test_queued_input_all_delivered_without_overrunning_fifo() {
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+
+        let bytes: Vec<u8> = (0..1000u32).map(|i| (i % 256) as u8).collect();
+        sys.queue_input(bytes.clone());
+        assert_eq!(sys.input_pending(), 1000);
+
+        let mut received = Vec::new();
+        let mut rounds = 0;
+        while received.len() < bytes.len() {
+            // Pump more times than the FIFO can hold before draining, to
+            // exercise the backpressure: it must cap at RX_FIFO_DEPTH
+            // instead of silently overflowing the FIFO's own drop-oldest
+            // behavior.
+            for _ in 0..32 {
+                sys.pump_input_queue();
+            }
+            assert!(sys.uarts[0].rx_len() <= 16);
+            while sys.uarts[0].rx_len() > 0 {
+                received.push(sys.uarts[0].read_rbr());
+            }
+            rounds += 1;
+            assert!(rounds < 1000, "input queue never drained");
+        }
+
+        assert_eq!(received, bytes);
+    }
+
+    #[test]
+    fn test_new_with_isa_traps_fp_instruction_when_fd_disabled() {
+        let config = IsaConfig { extensions: "rv32ima".to_string(), ..IsaConfig::default() };
+        let mut sys = System::new_with_isa(16, None, config).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.csr.mtvec = DRAM_BASE + 0x1000; // install a handler so the trap is actually delivered
+
+        // fadd.s f1, f2, f3 - illegal with F/D left out of the ISA string.
+        let insts: [u32; 1] = [0x0031_00d3];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        sys.run(1);
+
+        const ILLEGAL_INSTRUCTION: u32 = 2;
+        assert_eq!(sys.cpu.csr.mcause, ILLEGAL_INSTRUCTION);
+    }
+
+    #[test]
+    fn test_csr_privilege_and_read_only_checks_trap_through_run() {
+        // `Csr::read`/`Csr::write` already centralize both checks (minimum
+        // privilege in address bits [9:8], read-only in bits [11:10]), and
+        // both the plain interpreter and the block cache/JIT funnel every
+        // CSR instruction through `execute_system` to reach them - there is
+        // no separate `run_fast` or a second inline switch with its own CSR
+        // handling in this tree, so these are exercised through `run()`
+        // only.
+        use crate::cpu::PrivilegeLevel;
+        use crate::cpu::rv32::csr::{CSR_CYCLE, CSR_MSTATUS, CSR_SSTATUS};
+
+        const ILLEGAL_INSTRUCTION: u32 = 2;
+        const OPCODE_SYSTEM: u32 = 0x73;
+        const FUNCT3_CSRRW: u32 = 0b001;
+        const FUNCT3_CSRRS: u32 = 0b010;
+
+        // csrrs rd, csr, x0 - a pure read (rs1 = x0 skips the write).
+        let csrrs = |csr_addr: u32, rd: u32| -> u32 {
+            (csr_addr << 20) | (FUNCT3_CSRRS << 12) | (rd << 7) | OPCODE_SYSTEM
+        };
+        // csrrw rd, csr, rs1 - always writes.
+        let csrrw = |csr_addr: u32, rd: u32, rs1: u32| -> u32 {
+            (csr_addr << 20) | (rs1 << 15) | (FUNCT3_CSRRW << 12) | (rd << 7) | OPCODE_SYSTEM
+        };
+
+        // U-mode read of sstatus: sstatus requires at least Supervisor.
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.priv_level = PrivilegeLevel::User;
+        sys.cpu.csr.mtvec = DRAM_BASE + 0x1000;
+        let inst = csrrs(CSR_SSTATUS, 1);
+        sys.load_binary(&inst.to_le_bytes(), DRAM_BASE).unwrap();
+        sys.run(1);
+        assert_eq!(sys.cpu.csr.mcause, ILLEGAL_INSTRUCTION);
+        assert_eq!(sys.cpu.csr.mtval, inst);
+
+        // S-mode read of mstatus: mstatus requires Machine.
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.priv_level = PrivilegeLevel::Supervisor;
+        sys.cpu.csr.mtvec = DRAM_BASE + 0x1000;
+        let inst = csrrs(CSR_MSTATUS, 1);
+        sys.load_binary(&inst.to_le_bytes(), DRAM_BASE).unwrap();
+        sys.run(1);
+        assert_eq!(sys.cpu.csr.mcause, ILLEGAL_INSTRUCTION);
+        assert_eq!(sys.cpu.csr.mtval, inst);
+
+        // S-mode read of sstatus: fine, no trap delivered.
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.priv_level = PrivilegeLevel::Supervisor;
+        let inst = csrrs(CSR_SSTATUS, 1);
+        sys.load_binary(&inst.to_le_bytes(), DRAM_BASE).unwrap();
+        sys.run(1);
+        assert_eq!(sys.cpu.pc, DRAM_BASE + 4);
+        assert_eq!(sys.cpu.csr.mcause, 0);
+
+        // M-mode write to a read-only CSR (cycle): traps despite the
+        // privilege level being high enough.
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.priv_level = PrivilegeLevel::Machine;
+        sys.cpu.csr.mtvec = DRAM_BASE + 0x1000;
+        let inst = csrrw(CSR_CYCLE, 0, 0);
+        sys.load_binary(&inst.to_le_bytes(), DRAM_BASE).unwrap();
+        sys.run(1);
+        assert_eq!(sys.cpu.csr.mcause, ILLEGAL_INSTRUCTION);
+        assert_eq!(sys.cpu.csr.mtval, inst);
+    }
+
+    #[test]
+    fn test_firmware_sbi_mode_delivers_ecall_from_s_to_mtvec() {
+        let mut sys = System::new(16, None).unwrap();
+        sys.set_sbi_mode(SbiMode::Firmware);
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.priv_level = crate::cpu::PrivilegeLevel::Supervisor;
+        sys.cpu.csr.mtvec = DRAM_BASE + 0x1000; // install a handler so the trap is actually delivered
+
+        // ecall
+        let insts: [u32; 1] = [0x0000_0073];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        sys.run(1);
+
+        const ENVIRONMENT_CALL_FROM_S: u32 = 9;
+        assert_eq!(sys.cpu.csr.mcause, ENVIRONMENT_CALL_FROM_S);
+        assert_eq!(sys.cpu.pc, DRAM_BASE + 0x1000);
+    }
+
+    /// Runs a single hand-encoded Zba/Zbb/Zbs instruction with `x6`/`x7`
+    /// preloaded as rs1/rs2 and returns the `x5` (rd) result. Used by the
+    /// B-extension tests below instead of repeating the same load/run
+    /// boilerplate for every instruction.
+    fn run_b_ext_inst(inst: u32, rs1_val: u32, rs2_val: u32) -> u32 {
+        let config = IsaConfig { extensions: "rv32imab".to_string(), ..IsaConfig::default() };
+        let mut sys = System::new_with_isa(16, None, config).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.write_reg(6, rs1_val);
+        sys.cpu.write_reg(7, rs2_val);
+        sys.load_binary(&inst.to_le_bytes(), DRAM_BASE).unwrap();
+        sys.run(1);
+        sys.cpu.read_reg(5)
+    }
+
+    #[test]
+    fn test_zba_shadd_variants() {
+        // sh1add x5, x6, x7 / sh2add x5, x6, x7 / sh3add x5, x6, x7
+        assert_eq!(run_b_ext_inst(0x207322b3, 0x3, 0x5), 0xb);
+        assert_eq!(run_b_ext_inst(0x207342b3, 0x3, 0x5), 0x11);
+        assert_eq!(run_b_ext_inst(0x207362b3, 0x3, 0x5), 0x1d);
+    }
+
+    #[test]
+    fn test_zbb_andn_orn_xnor() {
+        // andn x5, x6, x7 / orn x5, x6, x7 / xnor x5, x6, x7
+        assert_eq!(run_b_ext_inst(0x407372b3, 0xFF00FF00, 0x0F0F0F0F), 0xf000f000);
+        assert_eq!(run_b_ext_inst(0x407362b3, 0xFF00FF00, 0x0F0F0F0F), 0xfff0fff0);
+        assert_eq!(run_b_ext_inst(0x407342b3, 0xFF00FF00, 0x0F0F0F0F), 0x0ff00ff0);
+    }
+
+    #[test]
+    fn test_zbb_min_max() {
+        // min/minu/max/maxu x5, x6, x7, comparing 5 against -5 (0xfffffffb)
+        assert_eq!(run_b_ext_inst(0x0a7342b3, 5, 0xFFFFFFFB), 0xFFFFFFFB); // min (signed)
+        assert_eq!(run_b_ext_inst(0x0a7362b3, 5, 0xFFFFFFFB), 5); // max (signed)
+        assert_eq!(run_b_ext_inst(0x0a7352b3, 5, 0xFFFFFFFB), 5); // minu (unsigned)
+        assert_eq!(run_b_ext_inst(0x0a7372b3, 5, 0xFFFFFFFB), 0xFFFFFFFB); // maxu (unsigned)
+    }
+
+    #[test]
+    fn test_zbb_rol_ror_rori_use_5_bit_shift_amount() {
+        // rol/ror x5, x6, x7 with a shift amount of 4 - RV32 masks the
+        // shift to 5 bits, so this also implicitly covers a register value
+        // above 31 being ignored past that mask.
+        assert_eq!(run_b_ext_inst(0x607312b3, 0x80000001, 4), 0x18);
+        assert_eq!(run_b_ext_inst(0x607352b3, 0x80000001, 4), 0x18000000);
+        // rori x5, x6, 4
+        assert_eq!(run_b_ext_inst(0x60435293, 0x80000001, 0), 0x18000000);
+    }
+
+    #[test]
+    fn test_zbb_clz_ctz_cpop() {
+        // clz x5, x6 / ctz x5, x6
+        assert_eq!(run_b_ext_inst(0x60031293, 0x000000F0, 0), 24);
+        assert_eq!(run_b_ext_inst(0x60131293, 0x000000F0, 0), 4);
+        // cpop x5, x6 on an all-ones input, per the request's callout.
+        assert_eq!(run_b_ext_inst(0x60231293, 0xFFFFFFFF, 0), 32);
+    }
+
+    #[test]
+    fn test_zbb_sext_and_zext() {
+        // sext.b x5, x6 / sext.h x5, x6
+        assert_eq!(run_b_ext_inst(0x60431293, 0x00000080, 0), 0xFFFFFF80);
+        assert_eq!(run_b_ext_inst(0x60531293, 0x00008000, 0), 0xFFFF8000);
+        // zext.h x5, x6 - pack rd, rs1, x0; only valid with rs2 == x0.
+        assert_eq!(run_b_ext_inst(0x080342b3, 0xFFFF8001, 0), 0x8001);
+    }
+
+    #[test]
+    fn test_zbb_zext_h_requires_rs2_to_be_x0() {
+        // Same encoding as zext.h but with rs2 = x7 instead of x0 - this is
+        // an unimplemented plain `pack`, not zext.h, and should stay illegal.
+        let config = IsaConfig { extensions: "rv32imab".to_string(), ..IsaConfig::default() };
+        let mut sys = System::new_with_isa(16, None, config).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.csr.mtvec = DRAM_BASE + 0x1000;
+        let inst: u32 = 0x087342b3;
+        sys.load_binary(&inst.to_le_bytes(), DRAM_BASE).unwrap();
+        sys.run(1);
+        const ILLEGAL_INSTRUCTION: u32 = 2;
+        assert_eq!(sys.cpu.csr.mcause, ILLEGAL_INSTRUCTION);
+    }
+
+    #[test]
+    fn test_zbs_single_bit_register_form() {
+        // bclr/bext/binv/bset x5, x6, x7 clearing/testing/toggling/setting bit 5.
+        assert_eq!(run_b_ext_inst(0x487312b3, 0xFFFFFFFF, 5), 0xFFFFFFDF);
+        assert_eq!(run_b_ext_inst(0x487352b3, 0x00000020, 5), 1);
+        assert_eq!(run_b_ext_inst(0x687312b3, 0x00000000, 5), 0x20);
+        assert_eq!(run_b_ext_inst(0x287312b3, 0x00000000, 5), 0x20);
+    }
+
+    #[test]
+    fn test_zbs_single_bit_immediate_form() {
+        // bclri/bexti/binvi/bseti x5, x6, 5
+        assert_eq!(run_b_ext_inst(0x48531293, 0xFFFFFFFF, 0), 0xFFFFFFDF);
+        assert_eq!(run_b_ext_inst(0x48535293, 0x00000020, 0), 1);
+        assert_eq!(run_b_ext_inst(0x68531293, 0x00000000, 0), 0x20);
+        assert_eq!(run_b_ext_inst(0x28531293, 0x00000000, 0), 0x20);
+    }
+
+    #[test]
+    fn test_b_extension_traps_illegal_when_not_in_isa_string() {
+        // andn x5, x6, x7 - illegal without 'b' in the ISA string (the
+        // default, since default_isa_string() is "rv32imafd").
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.csr.mtvec = DRAM_BASE + 0x1000;
+        let inst: u32 = 0x407372b3;
+        sys.load_binary(&inst.to_le_bytes(), DRAM_BASE).unwrap();
+        sys.run(1);
+        const ILLEGAL_INSTRUCTION: u32 = 2;
+        assert_eq!(sys.cpu.csr.mcause, ILLEGAL_INSTRUCTION);
+    }
+
+    #[test]
+    fn test_new_with_isa_advertises_vendor_arch_impl_ids_via_sbi() {
+        let config = IsaConfig { vendor_id: 0x1, arch_id: 0x2, impl_id: 0x3, ..IsaConfig::default() };
+        let sys = System::new_with_isa(16, None, config).unwrap();
+
+        assert_eq!(sys.cpu.csr.mvendorid, 0x1);
+        assert_eq!(sys.cpu.csr.marchid, 0x2);
+        assert_eq!(sys.cpu.csr.mimpid, 0x3);
+    }
+
+    #[test]
+    fn test_crlf_mode_rewrites_carriage_return_to_newline() {
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.set_input_crlf_mode(InputCrlfMode::CrToLf);
+
+        sys.uart_receive(b'\r');
+
+        assert_eq!(sys.uarts[0].rx_len(), 1);
+        assert_eq!(sys.uarts[0].read_rbr(), b'\n');
+    }
+
+    #[test]
+    fn test_streamed_load_matches_one_shot_load() {
+        let image: Vec<u8> = (0..=255u32).cycle().take(10_000).map(|b| b as u8).collect();
+
+        // Reference: one-shot load.
+        let mut reference = System::new(16, None).unwrap();
+        reference.load_binary(&image, DRAM_BASE).unwrap();
+
+        // Streamed load, chunks arriving out of order.
+        let mut streamed = System::new(16, None).unwrap();
+        streamed.begin_load("kernel", image.len() as u32).unwrap();
+        let chunk_size = 777; // deliberately not a divisor of image.len()
+        let mut chunks: Vec<(u32, &[u8])> = image
+            .chunks(chunk_size)
+            .scan(0u32, |offset, chunk| {
+                let this_offset = *offset;
+                *offset += chunk.len() as u32;
+                Some((this_offset, chunk))
+            })
+            .collect();
+        chunks.reverse();
+        for (offset, chunk) in chunks {
+            streamed.load_chunk(offset, chunk).unwrap();
+        }
+        streamed.finish_load().unwrap();
+
+        assert_eq!(
+            reference.memory.read_slice(DRAM_BASE, image.len()),
+            streamed.memory.read_slice(DRAM_BASE, image.len())
+        );
+    }
+
+    #[test]
+    fn test_load_chunk_rejects_overrun_and_missing_begin() {
+        let mut sys = System::new(16, None).unwrap();
+
+        // No load in progress yet.
+        assert!(sys.load_chunk(0, &[0u8; 4]).is_err());
+
+        sys.begin_load("kernel", 8).unwrap();
+        assert!(sys.load_chunk(4, &[0u8; 8]).is_err()); // extends past total_size
+        assert!(sys.load_chunk(0, &[0u8; 8]).is_ok());
+    }
+
+    #[test]
+    fn test_tohost_write_stops_run_and_decodes_pass() {
+        let mut sys = System::new(16, None).unwrap();
+        let tohost_addr = DRAM_BASE + 0x100;
+        sys.set_tohost_addr(tohost_addr);
+
+        // lui a0, 0x80000 ; addi a0, a0, 0x100 ; addi a1, x0, 1 ; sw a1, 0(a0)
+        let insts: [u32; 4] = [0x8000_0537, 0x1005_0513, 0x0010_0593, 0x00b5_2023];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE;
+
+        let cycles = sys.run(100);
+
+        assert!(cycles < 100); // stopped early, not exhausted the budget
+        assert_eq!(sys.tohost_result(), Some(TohostResult::Pass));
+        assert_eq!(sys.memory.read32(tohost_addr), 1); // write still lands in RAM
+    }
+
+    #[test]
+    fn test_run_chunked_reports_budget_and_callback_stops() {
+        let mut sys = System::new(16, None).unwrap();
+        let dummy_kernel = vec![0x13, 0x00, 0x00, 0x00]; // NOP, spins in the boot ROM
+        sys.setup_linux_boot(&dummy_kernel, "console=ttyS0").unwrap();
+
+        // Runs the full budget when the callback always says yes.
+        let result = sys.run_chunked(1000, 100, || true);
+        assert_eq!(result.cycles, 1000);
+        assert_eq!(result.reason, RunStopReason::Budget);
+
+        // Stops after the first chunk when the callback says no immediately.
+        let result = sys.run_chunked(1000, 100, || false);
+        assert!(result.cycles <= 100);
+        assert_eq!(result.reason, RunStopReason::Callback);
+    }
+
+    #[test]
+    fn test_trace_mmio_records_uart_write() {
+        let mut sys = System::new(16, None).unwrap();
+        sys.trace_mmio_device("uart").unwrap();
+
+        // lui a0, 0x3000 ; addi a1, x0, 65 ; sb a1, 0(a0)
+        let insts: [u32; 3] = [0x0300_0537, 0x0410_0593, 0x00b5_0023];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE;
+
+        sys.run(3);
+
+        let trace = sys.take_mmio_trace();
+        assert_eq!(trace.len(), 1);
+        assert_eq!(trace[0].addr, UART_BASES[0]);
+        assert_eq!(trace[0].size, 1);
+        assert!(trace[0].is_write);
+        assert_eq!(trace[0].value, 65);
+
+        // Draining the trace empties it until the next matching access.
+        assert!(sys.take_mmio_trace().is_empty());
+
+        // Disabling tracing stops further recording.
+        sys.trace_mmio(UART_BASES[0], UART_SIZE, false);
+        sys.cpu.pc = DRAM_BASE;
+        sys.run(3);
+        assert!(sys.take_mmio_trace().is_empty());
+    }
+
+    #[test]
+    fn test_second_uart_is_independently_addressable() {
+        let mut sys = System::new(16, None).unwrap();
+
+        // lui a0, 0x3001 ; addi a1, x0, 66 ; sb a1, 0(a0)  (writes to UART 1's THR)
+        let insts: [u32; 3] = [0x0300_1537, 0x0420_0593, 0x00b5_0023];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE;
+
+        sys.run(3);
+
+        assert_eq!(sys.uart_get_output_on(1), vec![b'B']);
+        assert!(sys.uart_get_output_on(0).is_empty());
+    }
+
+    #[test]
+    fn test_kernel_log_on_dedicated_uart_stays_off_the_interactive_console() {
+        let mut sys = System::new(16, None).unwrap();
+        let dummy_kernel = vec![0x13, 0x00, 0x00, 0x00]; // NOP
+        sys.setup_linux_boot(&dummy_kernel, "console=ttyS1").unwrap();
+
+        // Simulate the kernel's console driver writing its boot log to the
+        // UART named by `console=ttyS1` instead of the interactive shell.
+        sys.uarts[1].write_bytes(b"Linux version 6.1.0\n");
+
+        assert_eq!(sys.uart_get_output_on(1), b"Linux version 6.1.0\n");
+        assert!(sys.uart_get_output_on(0).is_empty());
+    }
+
+    #[test]
+    fn test_commit_log_format_for_addi_lw_and_beq() {
+        let mut sys = System::new(16, None).unwrap();
+
+        // lui x1, 0x80000 ; addi x3, x0, 0x42 ; addi x2, x0, 1 ; sw x0, 0(x1) ;
+        // lw x2, 0(x1) ; beq x2, x0, 8
+        let insts: [u32; 6] = [
+            0x800000B7, 0x04200193, 0x00100113, 0x0000A023, 0x0000A103, 0x00010463,
+        ];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE;
+
+        sys.set_commit_log(true);
+        sys.run(6);
+        let log = sys.take_commit_log();
+
+        assert_eq!(log.len(), 6);
+
+        // addi x3, x0, 0x42: writes x3, no memory access.
+        assert_eq!(
+            log[1],
+            format!("core   0: 0x{:08x} (0x04200193) x3 0x00000042", DRAM_BASE + 4)
+        );
+
+        // lw x2, 0(x1): overwrites the x2 set by the preceding addi, with no
+        // other register in the line, and reports the effective address it
+        // read from (x1 == DRAM_BASE) via `mem`.
+        assert_eq!(
+            log[4],
+            format!(
+                "core   0: 0x{:08x} (0x0000a103) x2 0x00000000 mem 0x{:08x}",
+                DRAM_BASE + 16,
+                DRAM_BASE
+            )
+        );
+
+        // beq x2, x0, 8: taken branch, but branches never write a register.
+        assert_eq!(
+            log[5],
+            format!("core   0: 0x{:08x} (0x00010463)", DRAM_BASE + 20)
+        );
+        assert_eq!(sys.cpu.pc, DRAM_BASE + 20 + 8);
+
+        // Draining empties the log until the next traced instruction.
+        assert!(sys.take_commit_log().is_empty());
+    }
+
+    #[test]
+    fn test_rom_write_faults_and_is_recorded_for_the_debug_api() {
+        use crate::memory::ROM_BASE;
+
+        let mut sys = System::new(16, None).unwrap();
+
+        // lui a0, 0x1 ; addi a1, x0, 1 ; sw a1, 0(a0)
+        let insts: [u32; 3] = [0x0000_1537, 0x0010_0593, 0x00b5_2023];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.csr.mtvec = DRAM_BASE + 0x1000;
+
+        // The three instructions form one straight-line block, so a single
+        // run() cycle carries the whole sequence through to the fault.
+        sys.run(1);
+
+        assert_eq!(sys.cpu.csr.mcause, 7); // StoreAccessFault
+        assert_eq!(sys.cpu.csr.mtval, ROM_BASE);
+
+        let attempts = sys.take_rom_write_attempts();
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].addr, ROM_BASE);
+        assert_eq!(attempts[0].pc, DRAM_BASE + 8);
+
+        // Draining empties the log until the next attempt.
+        assert!(sys.take_rom_write_attempts().is_empty());
+    }
+
+    #[test]
+    fn test_protected_range_blocks_guest_store_with_store_access_fault() {
+        let mut sys = System::new(16, None).unwrap();
+
+        // A RAM range the host wants to keep the guest from writing to,
+        // e.g. a shared-buffer region it owns itself. Kept well away from
+        // both the loaded code and mtvec so only the deliberate store hits it.
+        let guard_base = DRAM_BASE + 0x4000;
+        sys.add_protected_range(guard_base, 0x1000, PROT_READ | PROT_EXEC);
+
+        // lui a0, 0x80004 ; addi a1, x0, 1 ; sw a1, 0(a0)
+        let insts: [u32; 3] = [0x8000_4537, 0x0010_0593, 0x00b5_2023];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.csr.mtvec = DRAM_BASE + 0x1000;
+
+        sys.run(1);
+
+        assert_eq!(sys.cpu.csr.mcause, 7); // StoreAccessFault
+        assert_eq!(sys.cpu.csr.mtval, guard_base);
+    }
+
+    #[test]
+    fn test_strict_memory_faults_on_genuinely_unmapped_load() {
+        // addr just past PLIC's mapped range - not RAM, ROM, or any device.
+        const UNMAPPED_ADDR: u32 = PLIC_BASE + PLIC_SIZE;
+
+        let mut sys = System::new(16, None).unwrap();
+
+        // lui a0, hi(UNMAPPED_ADDR) ; lw a1, lo(UNMAPPED_ADDR)(a0)
+        let hi = (UNMAPPED_ADDR >> 12) & 0xF_FFFF;
+        let lui = (hi << 12) | (10 << 7) | 0x37;
+        let lw = (10 << 15) | (2 << 12) | (11 << 7) | 0x03; // lw a1, 0(a0)
+        let insts: [u32; 2] = [lui, lw];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.csr.mtvec = DRAM_BASE + 0x1000;
+        sys.set_strict_memory(true);
+
+        // Both instructions form one straight-line block.
+        sys.run(1);
+
+        assert_eq!(sys.cpu.csr.mcause, 5); // LoadAccessFault
+        assert_eq!(sys.cpu.csr.mtval, UNMAPPED_ADDR);
+
+        // With strict memory off (the default), the same access is lenient.
+        let mut lenient = System::new(16, None).unwrap();
+        lenient.load_binary(&bytes, DRAM_BASE).unwrap();
+        lenient.cpu.reset();
+        lenient.cpu.pc = DRAM_BASE;
+        lenient.run(1);
+        assert_eq!(lenient.cpu.csr.mcause, 0);
+    }
+
+    #[test]
+    fn test_boot_milestones_track_progress_without_halting() {
+        let mut sys = System::new(16, None).unwrap();
+        sys.set_boot_milestones(true);
+        assert!(sys.get_boot_milestones().iter().all(|m| !m.reached));
+
+        // nop, jal x0, 0 (self-loop, terminates the JIT block)
+        let insts: [u32; 2] = [0x0000_0013, 0x0000_006f];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE;
+
+        sys.uarts[0].write_bytes(b"OpenSBI v1.3\nLinux version 6.1.0\n");
+
+        let (_, reason) = sys.run_with_reason(2);
+        assert_eq!(reason, HaltReason::Completed);
+
+        let milestones = sys.get_boot_milestones();
+        let firmware = milestones.iter().find(|m| m.label == "firmware").unwrap();
+        assert!(firmware.reached);
+        assert!(firmware.instruction_count.is_some());
+
+        let kernel = milestones.iter().find(|m| m.label == "kernel").unwrap();
+        assert!(kernel.reached);
+
+        let init = milestones.iter().find(|m| m.label == "init").unwrap();
+        assert!(!init.reached);
+        assert!(init.instruction_count.is_none());
+
+        // Disabling stops tracking and clears the list.
+        sys.set_boot_milestones(false);
+        assert!(sys.get_boot_milestones().is_empty());
+    }
+
+    #[test]
+    fn test_inject_irq_raises_seip_for_enabled_source() {
+        use crate::cpu::rv32::csr::MIP_SEIP;
+
+        let mut sys = System::new(16, None).unwrap();
+
+        const TEST_IRQ: u32 = 5;
+        sys.plic.write32(TEST_IRQ * 4, 1); // priority
+        sys.plic.write32(0x2080, 1 << TEST_IRQ); // enable, S-mode context
+
+        assert_eq!(sys.cpu.csr.mip & MIP_SEIP, 0);
+
+        sys.inject_irq(TEST_IRQ, true);
+        assert_ne!(sys.cpu.csr.mip & MIP_SEIP, 0);
+
+        sys.inject_irq(TEST_IRQ, false);
+        assert_eq!(sys.cpu.csr.mip & MIP_SEIP, 0);
+    }
+
+    #[test]
+    fn test_profiling_samples_pc_and_aggregates_by_bucket() {
+        let mut sys = System::new(16, None).unwrap();
+
+        // nop, jal x0, 0 (self-loop, terminates the JIT block)
+        let insts: [u32; 2] = [0x0000_0013, 0x0000_006f];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE;
+
+        sys.set_profiling(true, 50);
+        let (_, reason) = sys.run_with_reason(1000);
+        assert_eq!(reason, HaltReason::Completed);
+
+        let samples = sys.take_profile_samples();
+        assert!(!samples.is_empty(), "expected at least one sample over 1000 cycles at interval 50");
+        // The loop settles on jumping to itself, so every sample lands
+        // within the same tiny block regardless of exactly which
+        // instruction it caught mid-flight.
+        assert!(samples.iter().all(|s| s.pc >= DRAM_BASE && s.pc < DRAM_BASE + 8));
+
+        // A second drain returns nothing new until more instructions retire.
+        assert!(sys.take_profile_samples().is_empty());
+
+        let buckets = aggregate_profile_samples(&samples, 64);
+        assert_eq!(buckets.len(), 1, "all samples share the same pc bucket");
+        assert_eq!(buckets[0].count, samples.len() as u64);
+
+        sys.set_profiling(false, 50);
+        assert!(sys.take_profile_samples().is_empty());
+    }
+
+    #[test]
+    fn test_timing_model_penalizes_ram_accesses_into_cycle_counter() {
+        // sw x0, 0(x0); jal x0, 0 (store to RAM, then self-loop)
+        let insts: [u32; 2] = [0x0000_2023, 0x0000_006f];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+
+        let mut baseline = System::new(16, None).unwrap();
+        baseline.load_binary(&bytes, DRAM_BASE).unwrap();
+        baseline.cpu.reset();
+        baseline.cpu.pc = DRAM_BASE;
+        baseline.run_with_reason(200);
+
+        let mut penalized = System::new(16, None).unwrap();
+        penalized.load_binary(&bytes, DRAM_BASE).unwrap();
+        penalized.cpu.reset();
+        penalized.cpu.pc = DRAM_BASE;
+        penalized.set_timing_model(10, 0, 0);
+        penalized.run_with_reason(200);
+
+        assert!(
+            penalized.cpu.csr.cycle > baseline.cpu.csr.cycle,
+            "nonzero ram_cycles should make the cycle counter grow faster: baseline={} penalized={}",
+            baseline.cpu.csr.cycle,
+            penalized.cpu.csr.cycle
+        );
+        assert!(penalized.clint.get_mtime() > baseline.clint.get_mtime());
+
+        // Disabling again (all zeros) drops back to unpenalized accounting.
+        penalized.set_timing_model(0, 0, 0);
+        let before = penalized.cpu.csr.cycle;
+        penalized.run_with_reason(64);
+        let after_baseline = baseline.cpu.csr.cycle;
+        baseline.run_with_reason(64);
+        assert_eq!(penalized.cpu.csr.cycle - before, baseline.cpu.csr.cycle - after_baseline);
+    }
+
+    #[test]
+    fn test_compute_only_loop_never_calls_process_queues() {
+        // addi x1,x1,1 ; jal x0,-4 (self-loop, no virtio activity at all).
+        // `pump_virtio` runs on every batch, but should bail out on
+        // `take_notify_dirty` before ever touching `process_queues` when the
+        // guest never kicks a virtio queue.
+        let insts: [u32; 2] = [0x0010_8093, 0xffdf_f06f];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+
+        let mut sys = System::new(16, None).unwrap();
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.run(100_000);
+
+        assert_eq!(sys.virtio9p.get_process_queues_calls(), 0);
+    }
+
+    #[test]
+    fn test_wfi_wakes_immediately_on_uart_interrupt_not_timer() {
+        use crate::cpu::rv32::csr::MIP_MEIP;
+
+        let mut sys = System::new(16, None).unwrap();
+
+        // Enable the UART's RX-available interrupt and route it through the
+        // PLIC to the M-mode external interrupt line.
+        sys.uarts[0].write8(1, 0x01); // UART_IER = IER_RX_AVAILABLE
+        sys.plic.write32(UART_IRQS[0] * 4, 1); // priority
+        sys.plic.write32(0x2000, 1 << UART_IRQS[0]); // enable, M-mode context
+
+        // Enable MEIE so WFI's (mip & mie) wake check can observe it, and
+        // park the CPU in WFI with the CLINT timer far in the future so a
+        // timer-driven wakeup would never happen within this test.
+        sys.cpu.csr.mie |= MIP_MEIP;
+        sys.cpu.wfi = true;
+
+        sys.uart_receive(b'x');
+
+        assert!(!sys.cpu.wfi, "CPU should wake immediately on the injected UART interrupt");
+    }
+
+    #[test]
+    fn test_run_with_reason_reports_wfi_when_nothing_can_wake_it() {
+        let mut sys = System::new(16, None).unwrap();
+
+        // No timer armed (mtimecmp defaults to u64::MAX) and no interrupt
+        // sources enabled, so this WFI can never wake on its own.
+        sys.cpu.wfi = true;
+
+        let (_, reason) = sys.run_with_reason(1000);
+        assert_eq!(reason, HaltReason::Wfi);
+    }
+
+    #[test]
+    fn test_run_with_reason_reports_pc_zero() {
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = 0;
+
+        let (cycles, reason) = sys.run_with_reason(1000);
+        assert_eq!(reason, HaltReason::PcZero);
+        assert_eq!(cycles, 0);
+    }
+
+    // li a7, 0x53525354 ("SRST") ; addi a0, x0, <reset type> ; ecall
+    // (relies on a0 starting at 0 after `cpu.reset()`, so a plain `addi`
+    // suffices for the small reset-type values used here)
+    fn srst_program(reset_type: u32) -> Vec<u8> {
+        let insts: [u32; 4] = [
+            0x535258b7,                    // lui a7, 0x53525
+            0x35488893,                    // addi a7, a7, 0x354
+            0x00000513 | (reset_type << 20), // addi a0, x0, <reset_type>
+            0x00000073,                    // ecall
+        ];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_sbi_srst_shutdown_reports_powered_off() {
+        let mut sys = System::new(16, None).unwrap();
+        let bytes = srst_program(0); // SBI_SRST_TYPE_SHUTDOWN
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.priv_level = crate::cpu::PrivilegeLevel::Supervisor;
+
+        let (_, reason) = sys.run_with_reason(100);
+        assert_eq!(reason, HaltReason::PoweredOff);
+        assert_eq!(sys.power_state(), SystemPowerState::Shutdown);
+    }
+
+    #[test]
+    fn test_sbi_srst_reboot_without_auto_reboot_reports_reboot_requested() {
+        let mut sys = System::new(16, None).unwrap();
+        let bytes = srst_program(1); // SBI_SRST_TYPE_COLD_REBOOT
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.priv_level = crate::cpu::PrivilegeLevel::Supervisor;
+
+        let (_, reason) = sys.run_with_reason(100);
+        assert_eq!(reason, HaltReason::RebootRequested);
+        assert_eq!(sys.power_state(), SystemPowerState::RebootRequested);
+    }
+
+    #[test]
+    fn test_sbi_legacy_console_getchar_reads_from_input_fifo() {
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.priv_level = crate::cpu::PrivilegeLevel::Supervisor;
+
+        // addi a7, x0, 2 (SBI_EXT_LEGACY_CONSOLE_GETCHAR); ecall
+        let insts: [u32; 2] = [0x0020_0893, 0x0000_0073];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        // No input queued yet - getchar should report -1.
+        sys.run(1);
+        assert_eq!(sys.cpu.read_reg(10) as i32, -1);
+
+        // Queue a byte, rewind, and it should now come back and be consumed.
+        sys.uart_receive(b'x');
+        sys.cpu.pc = DRAM_BASE;
+        sys.run(1);
+        assert_eq!(sys.cpu.read_reg(10), b'x' as u32);
+
+        // The FIFO is now empty again.
+        sys.cpu.pc = DRAM_BASE;
+        sys.run(1);
+        assert_eq!(sys.cpu.read_reg(10) as i32, -1);
+    }
+
+    #[test]
+    fn test_sbi_dbcn_console_read_drains_input_fifo_into_guest_memory() {
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.priv_level = crate::cpu::PrivilegeLevel::Supervisor;
+
+        for &b in b"hi" {
+            sys.uart_receive(b);
+        }
+
+        // lui a1, 0x80000; addi a1, a1, 0x100 (buffer = DRAM_BASE + 0x100)
+        // addi a0, x0, 4 (ask for 4 bytes, only 2 are available)
+        // lui a7, 0x44424; addi a7, a7, 0x34e (a7 = "DBCN")
+        // addi a6, x0, 2 (fid = sbi_debug_console_read)
+        // ecall
+        let insts: [u32; 6] = [
+            0x8000_05b7,
+            0x1005_8593,
+            0x0040_0513,
+            0x4442_48b7,
+            0x34e8_8893,
+            0x0020_0813,
+        ];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        bytes.extend_from_slice(&0x0000_0073u32.to_le_bytes()); // ecall
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        sys.run(insts.len() as u32 + 1);
+
+        const SBI_SUCCESS: u32 = 0;
+        assert_eq!(sys.cpu.read_reg(10), SBI_SUCCESS); // a0 = error
+        assert_eq!(sys.cpu.read_reg(11), 2); // a1 = bytes actually read
+        assert_eq!(sys.read_memory(DRAM_BASE + 0x100, 2), b"hi");
+        assert_eq!(sys.uarts[0].rx_len(), 0);
+    }
+
+    #[test]
+    fn test_sbi_pmu_fw_read_delta_matches_instructions_retired() {
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = DRAM_BASE;
+        sys.cpu.priv_level = crate::cpu::PrivilegeLevel::Supervisor;
+
+        // Drives the SBI PMU extension's ecall sequence end to end:
+        // lui a7, 0x504D5 ; addi a7, a7, -0x2ab (a7 = "PMU" = 0x504d55)
+        // addi a6, x0, 2 ; addi a0, x0, 0 ; addi a1, x0, 2
+        // addi a2, x0, 0 ; addi a3, x0, 2 ; ecall
+        //   (sbi_pmu_counter_config_matching(base=0, mask=0b10, flags=0,
+        //   event_idx=HW_INSTRUCTIONS) - selects the fixed "instructions"
+        //   counter, index 1)
+        // addi a6, x0, 3 ; addi a0, x0, 0 ; addi a1, x0, 2
+        // addi a2, x0, 0 ; addi a3, x0, 0 ; ecall
+        //   (sbi_pmu_counter_start(base=0, mask=0b10, flags=0))
+        // 20x addi x0, x0, 0 (nop)
+        // addi a6, x0, 4 ; addi a0, x0, 0 ; addi a1, x0, 2 ; addi a2, x0, 0 ; ecall
+        //   (sbi_pmu_counter_stop(base=0, mask=0b10, flags=0))
+        // addi a6, x0, 5 ; addi a0, x0, 1 ; ecall
+        //   (sbi_pmu_counter_fw_read(counter_idx=1))
+        let insts: [u32; 42] = [
+            0x0050_58b7, 0xd558_8893,
+            0x0020_0813, 0x0000_0513, 0x0020_0593, 0x0000_0613, 0x0020_0693, 0x0000_0073,
+            0x0030_0813, 0x0000_0513, 0x0020_0593, 0x0000_0613, 0x0000_0693, 0x0000_0073,
+            0x0000_0013, 0x0000_0013, 0x0000_0013, 0x0000_0013, 0x0000_0013,
+            0x0000_0013, 0x0000_0013, 0x0000_0013, 0x0000_0013, 0x0000_0013,
+            0x0000_0013, 0x0000_0013, 0x0000_0013, 0x0000_0013, 0x0000_0013,
+            0x0000_0013, 0x0000_0013, 0x0000_0013, 0x0000_0013, 0x0000_0013,
+            0x0040_0813, 0x0000_0513, 0x0020_0593, 0x0000_0613, 0x0000_0073,
+            0x0050_0813, 0x0010_0513, 0x0000_0073,
+        ];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+
+        const SBI_SUCCESS: u32 = 0;
+        // Instructions retired between counter_start returning and
+        // counter_stop taking effect: the 20 nops, plus the 4 `addi`
+        // setup instructions that load counter_stop's own arguments
+        // (those retire before the ecall that stops the counter does).
+        const RETIRED_BETWEEN_START_AND_STOP: u32 = 24;
+
+        // Each `run(1)` below covers one whole ecall's worth of setup - a
+        // block only ever stops early on an `ecall` trap or a device
+        // access, so every instruction from the end of the previous ecall
+        // up to and including the next one lands in a single step no
+        // matter how many non-branching instructions lead into it.
+        sys.run(1); // counter_config_matching
+        assert_eq!(sys.cpu.read_reg(10), SBI_SUCCESS);
+        assert_eq!(sys.cpu.read_reg(11), 1, "instructions is fixed counter index 1");
+
+        sys.run(1); // counter_start
+        assert_eq!(sys.cpu.read_reg(10), SBI_SUCCESS);
+
+        sys.run(1); // 20 nops, then counter_stop's setup instructions and ecall
+        assert_eq!(sys.cpu.read_reg(10), SBI_SUCCESS);
+
+        sys.run(1); // counter_fw_read
+        assert_eq!(sys.cpu.read_reg(10), SBI_SUCCESS);
+        assert_eq!(sys.cpu.read_reg(11), RETIRED_BETWEEN_START_AND_STOP);
+    }
+
+    #[test]
+    fn test_reboot_restores_boot_images_and_resets_power_state() {
+        let mut sys = System::new(16, None).unwrap();
+        let dummy_kernel = vec![0x13, 0x00, 0x00, 0x00]; // NOP
+        sys.setup_linux_boot(&dummy_kernel, "console=ttyS0").unwrap();
+
+        // Simulate having run for a while and the guest asking to reboot.
+        sys.cpu.pc = 0x8000_1234;
+        sys.power_state = SystemPowerState::RebootRequested;
+
+        sys.reboot().unwrap();
+
+        assert_eq!(sys.cpu.pc, 0x1000); // back in the boot ROM
+        assert_eq!(sys.power_state(), SystemPowerState::Running);
+    }
+
+    #[test]
+    fn test_reboot_without_boot_images_errors() {
+        let mut sys = System::new(16, None).unwrap();
+        assert!(sys.reboot().is_err());
+    }
+
+    #[test]
+    fn test_pc_jump_to_zero_after_boot_faults_instead_of_executing() {
+        let mut sys = System::new(16, None).unwrap();
+        // nop, nop, nop, jal x0, 0 (self-loop, terminates the JIT block so
+        // the run stops after exactly these four instructions)
+        let insts: [u32; 4] = [0x0000_0013, 0x0000_0013, 0x0000_0013, 0x0000_006f];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE;
+
+        // Run the nops so `instruction_count` is nonzero and the one-shot
+        // `PcZero` check at entry no longer applies, then jump off into
+        // unmapped memory the way a corrupted return address or a bad
+        // `mret` target might.
+        let (_, reason) = sys.run_with_reason(4);
+        assert_eq!(reason, HaltReason::Completed);
+        assert!(sys.cpu.instruction_count > 0);
+        sys.cpu.pc = 0;
+
+        // No trap handler is installed (mtvec/stvec both still 0), so the
+        // fetch should raise `InstructionAccessFault` and be reported as an
+        // unhandled trap rather than being fetched and executed as if
+        // address 0 held real instructions.
+        let (_, reason) = sys.run_with_reason(100);
+        assert_eq!(reason, HaltReason::Trap);
+    }
+
+    #[test]
+    fn test_fetch_from_mmio_address_raises_instruction_access_fault() {
+        // 0x0300_0000 is well past RAM and ROM - squarely in unmapped/MMIO
+        // territory - so a jump there must fault rather than let the fetch
+        // path read a device register (with side effects) and execute
+        // whatever came back as if it were a real instruction.
+        let mut sys = System::new(16, None).unwrap();
+        sys.cpu.pc = 0x0300_0000;
+
+        let result = sys.step_with_devices();
+        let err = result.unwrap_err();
+        match err {
+            crate::cpu::trap::Trap::InstructionAccessFault(addr) => assert_eq!(addr, 0x0300_0000),
+            other => panic!("expected InstructionAccessFault, got {:?}", other),
+        }
+
+        sys.cpu.handle_trap(err);
+        assert_eq!(sys.cpu.csr.mcause, 1); // instruction access fault
+        assert_eq!(sys.cpu.csr.mtval, 0x0300_0000);
+    }
+
+    #[test]
+    fn test_from_config_boots_a_full_machine() {
+        let kernel = vec![0x13, 0x00, 0x00, 0x00]; // NOP
+        let config = MachineConfig {
+            ram_mb: 16,
+            kernel: kernel.clone(),
+            cmdline: "console=ttyS0 root=/dev/ram".to_string(),
+            jit_v2: true,
+            rng_seed: Some(42),
+            ..Default::default()
+        };
+
+        let sys = System::from_config(&config).unwrap();
+
+        assert_eq!(sys.cpu.pc, crate::memory::ROM_BASE); // starts in the boot ROM, same as setup_linux_boot*
+        assert!(sys.use_jit_v2);
+        assert!(!sys.get_dtb().is_empty(), "setup_linux_boot_with_initrd should have generated a DTB");
+        assert_eq!(sys.memory.read32(DRAM_BASE), 0x0000_0013); // kernel NOP landed at DRAM_BASE
+    }
+
+    #[test]
+    fn test_from_config_rejects_initrd_without_kernel() {
+        let config = MachineConfig {
+            ram_mb: 16,
+            initrd: Some(vec![1, 2, 3]),
+            ..Default::default()
+        };
+
+        match System::from_config(&config) {
+            Err(SystemError::InvalidMachineConfig { field, .. }) => assert_eq!(field, "initrd"),
+            other => panic!("expected Err(InvalidMachineConfig), got is_ok={}", other.is_ok()),
+        }
+    }
+
+    #[test]
+    fn test_from_config_reports_ram_too_small_for_initrd() {
+        let config = MachineConfig {
+            ram_mb: 1,
+            kernel: vec![0x13, 0x00, 0x00, 0x00],
+            initrd: Some(vec![0xAA; 16]),
+            ..Default::default()
+        };
+
+        match System::from_config(&config) {
+            Err(SystemError::NotEnoughRam { .. }) => {}
+            other => panic!("expected Err(NotEnoughRam), got is_ok={}", other.is_ok()),
+        }
+    }
+
+    #[test]
+    fn test_state_delta_round_trips_via_reloaded_artifacts() {
+        // Big enough that a whole page in the middle stays untouched by the
+        // kernel-load fixup below, so it should be recognized as unchanged
+        // and referenced instead of stored raw.
+        let kernel: Vec<u8> = (0..3 * 4096).map(|i| (i % 251) as u8).collect();
+
+        let mut sys = System::new(16, None).unwrap();
+        sys.setup_linux_boot(&kernel, "console=ttyS0").unwrap();
+
+        // Simulate progress: some CPU state changed, and a heap/stack page
+        // outside the kernel/DTB ranges got written.
+        sys.cpu.write_reg(5, 0xdead_beef);
+        sys.cpu.instruction_count = 42;
+        sys.memory.write8(DRAM_BASE + kernel.len() as u32 + 0x1000, 0xAB);
+
+        let delta = sys.create_state_delta();
+        assert!(!delta.artifact_pages.is_empty());
+        assert!(delta.dirty_pages.values().any(|page| page.contains(&0xAB)));
+
+        // Reload the same kernel into a fresh System, then apply the delta.
+        let mut restored = System::new(16, None).unwrap();
+        restored.setup_linux_boot(&kernel, "console=ttyS0").unwrap();
+        restored.apply_state_delta(&delta).unwrap();
+
+        assert_eq!(restored.cpu.read_reg(5), 0xdead_beef);
+        assert_eq!(restored.cpu.instruction_count, 42);
+        assert_eq!(
+            restored.memory.read8(DRAM_BASE + kernel.len() as u32 + 0x1000),
+            0xAB
+        );
+        assert_eq!(
+            restored.memory.read_slice(DRAM_BASE, kernel.len()),
+            sys.memory.read_slice(DRAM_BASE, kernel.len())
+        );
+    }
+
+    #[test]
+    fn test_state_delta_rejects_mismatched_artifacts() {
+        let kernel_a: Vec<u8> = (0..3 * 4096).map(|i| (i % 251) as u8).collect();
+        let kernel_b: Vec<u8> = (0..3 * 4096).map(|i| (i % 199) as u8).collect();
+
+        let mut sys = System::new(16, None).unwrap();
+        sys.setup_linux_boot(&kernel_a, "console=ttyS0").unwrap();
+        let delta = sys.create_state_delta();
+
+        let mut other = System::new(16, None).unwrap();
+        other.setup_linux_boot(&kernel_b, "console=ttyS0").unwrap();
+        assert!(other.apply_state_delta(&delta).is_err());
+    }
+
+    #[test]
+    fn test_lightweight_snapshot_round_trips() {
+        let kernel = vec![0x13, 0x00, 0x00, 0x00]; // NOP
+        let mut sys = System::new(16, None).unwrap();
+        sys.setup_linux_boot(&kernel, "console=ttyS0").unwrap();
+        sys.cpu.write_reg(5, 0xdead_beef);
+        sys.cpu.instruction_count = 7;
+
+        let snapshot = sys.create_snapshot(kernel.len() as u32, None);
+
+        let mut restored = System::new(16, None).unwrap();
+        restored.setup_linux_boot(&kernel, "console=ttyS0").unwrap();
+        restored.restore_snapshot(&snapshot).unwrap();
+
+        assert_eq!(restored.cpu.read_reg(5), 0xdead_beef);
+        assert_eq!(restored.cpu.instruction_count, 7);
+    }
+
+    #[test]
+    fn test_snapshot_stream_round_trips_in_small_chunks() {
+        let kernel = vec![0x13, 0x00, 0x00, 0x00]; // NOP
+        let mut sys = System::new(16, None).unwrap();
+        sys.setup_linux_boot(&kernel, "console=ttyS0").unwrap();
+        sys.cpu.write_reg(5, 0xdead_beef);
+        sys.cpu.instruction_count = 7;
+        // Touch a few pages past the 1MB-after-kernel boundary `create_snapshot`
+        // treats as guest data, so there's more than one dirty page to stream.
+        let data_start = crate::memory::DRAM_BASE + kernel.len() as u32 + 0x100000;
+        for page in 0..4u32 {
+            sys.memory.write8(data_start + page * crate::snapshot::PAGE_SIZE, 0x42);
+        }
+
+        sys.begin_snapshot_stream(kernel.len() as u32, None);
+
+        let mut restored = System::new(16, None).unwrap();
+        restored.setup_linux_boot(&kernel, "console=ttyS0").unwrap();
+
+        // Drive both sides with a tiny max_bytes so the stream is forced
+        // into many small chunks instead of one big one.
+        let mut chunks_sent = 0;
+        while let Some(chunk) = sys.next_snapshot_chunk(16).unwrap() {
+            restored.feed_snapshot_chunk(&chunk).unwrap();
+            chunks_sent += 1;
+        }
+        assert!(chunks_sent > 4, "expected several small chunks, got {}", chunks_sent);
+
+        restored.finish_snapshot_restore().unwrap();
+
+        assert_eq!(restored.cpu.read_reg(5), 0xdead_beef);
+        assert_eq!(restored.cpu.instruction_count, 7);
+        for page in 0..4u32 {
+            assert_eq!(restored.memory.read8(data_start + page * crate::snapshot::PAGE_SIZE), 0x42);
+        }
+    }
+
+    #[test]
+    fn test_snapshot_stream_is_unaffected_by_guest_writes_after_begin() {
+        // The stream captures everything eagerly at begin_snapshot_stream
+        // time, so pages the guest touches afterward - while chunks are
+        // still being drained - must not leak into the snapshot.
+        let kernel = vec![0x13, 0x00, 0x00, 0x00]; // NOP
+        let mut sys = System::new(16, None).unwrap();
+        sys.setup_linux_boot(&kernel, "console=ttyS0").unwrap();
+        let data_addr = crate::memory::DRAM_BASE + kernel.len() as u32 + 0x100000;
+        sys.memory.write8(data_addr, 0xaa);
+
+        sys.begin_snapshot_stream(kernel.len() as u32, None);
+
+        // Simulate the guest running and dirtying the same page again
+        // between chunk pulls.
+        sys.memory.write8(data_addr, 0xbb);
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = sys.next_snapshot_chunk(4096).unwrap() {
+            chunks.push(chunk);
+        }
+
+        let mut restored = System::new(16, None).unwrap();
+        restored.setup_linux_boot(&kernel, "console=ttyS0").unwrap();
+        for chunk in chunks {
+            restored.feed_snapshot_chunk(&chunk).unwrap();
+        }
+        restored.finish_snapshot_restore().unwrap();
+
+        assert_eq!(restored.memory.read8(data_addr), 0xaa);
+    }
+
+    #[test]
+    fn test_snapshot_restore_rejects_incomplete_stream() {
+        let kernel = vec![0x13, 0x00, 0x00, 0x00]; // NOP
+        let mut sys = System::new(16, None).unwrap();
+        sys.setup_linux_boot(&kernel, "console=ttyS0").unwrap();
+        sys.memory.write8(crate::memory::DRAM_BASE, 0x42);
+
+        sys.begin_snapshot_stream(kernel.len() as u32, None);
+
+        let mut restored = System::new(16, None).unwrap();
+        restored.setup_linux_boot(&kernel, "console=ttyS0").unwrap();
+
+        // Only feed the header, then try to finish without the rest.
+        let header_chunk = sys.next_snapshot_chunk(4096).unwrap().unwrap();
+        restored.feed_snapshot_chunk(&header_chunk).unwrap();
+
+        let err = restored.finish_snapshot_restore().unwrap_err();
+        assert!(err.to_string().contains("snapshot stream error"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_lightweight_snapshot_rejects_wrong_kernel() {
+        let kernel_a = vec![0x13, 0x00, 0x00, 0x00]; // NOP
+        let kernel_b = vec![0x93, 0x00, 0x10, 0x00]; // addi x1, x0, 1
+
+        let mut sys = System::new(16, None).unwrap();
+        sys.setup_linux_boot(&kernel_a, "console=ttyS0").unwrap();
+        let snapshot = sys.create_snapshot(kernel_a.len() as u32, None);
+
+        let mut other = System::new(16, None).unwrap();
+        other.setup_linux_boot(&kernel_b, "console=ttyS0").unwrap();
+        let err = other.restore_snapshot(&snapshot).unwrap_err();
+        assert!(err.to_string().contains("wrong kernel"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_lightweight_snapshot_rejects_mismatched_cmdline() {
+        let kernel = vec![0x13, 0x00, 0x00, 0x00]; // NOP
+
+        let mut sys = System::new(16, None).unwrap();
+        sys.setup_linux_boot(&kernel, "console=ttyS0").unwrap();
+        let snapshot = sys.create_snapshot(kernel.len() as u32, None);
+
+        let mut other = System::new(16, None).unwrap();
+        other.setup_linux_boot(&kernel, "console=ttyS1").unwrap();
+        let err = other.restore_snapshot(&snapshot).unwrap_err();
+        assert!(err.to_string().contains("cmdline"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_lightweight_snapshot_rejects_mismatched_ram_size() {
+        let kernel = vec![0x13, 0x00, 0x00, 0x00]; // NOP
+
+        let mut sys = System::new(16, None).unwrap();
+        sys.setup_linux_boot(&kernel, "console=ttyS0").unwrap();
+        let snapshot = sys.create_snapshot(kernel.len() as u32, None);
+
+        let mut other = System::new(32, None).unwrap();
+        other.setup_linux_boot(&kernel, "console=ttyS0").unwrap();
+        let err = other.restore_snapshot(&snapshot).unwrap_err();
+        assert!(err.to_string().contains("RAM size"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_lightweight_snapshot_rejects_stale_version() {
+        let kernel = vec![0x13, 0x00, 0x00, 0x00]; // NOP
+
+        let mut sys = System::new(16, None).unwrap();
+        sys.setup_linux_boot(&kernel, "console=ttyS0").unwrap();
+        let mut snapshot = sys.create_snapshot(kernel.len() as u32, None);
+        snapshot.version = crate::snapshot::LightweightSnapshot::VERSION - 1;
+
+        let err = sys.restore_snapshot(&snapshot).unwrap_err();
+        assert!(err.to_string().contains("version"), "unexpected error: {}", err);
+    }
+
+    // Sv32 identity-maps DRAM_BASE..+4MB with a single megapage so that plain
+    // instruction fetches in S-mode go through the MMU and populate its TLB,
+    // without needing a real page fault handler or two-level walk.
+    fn identity_map_dram_megapage(sys: &mut System) {
+        const ROOT_PT: u32 = DRAM_BASE + 0x1000;
+        let vpn1 = (DRAM_BASE >> 22) & 0x3FF;
+        let pte1_addr = ROOT_PT + vpn1 * 4;
+        let phys_ppn1 = DRAM_BASE >> 22;
+        let pte1 = (phys_ppn1 << 20) | 0xF; // V | R | W | X, identity megapage
+        sys.load_binary(&pte1.to_le_bytes(), pte1_addr).unwrap();
+
+        sys.cpu.csr.satp = (1 << 31) | (ROOT_PT >> 12); // Sv32, PPN of ROOT_PT
+        sys.cpu.priv_level = crate::cpu::PrivilegeLevel::Supervisor;
+    }
+
+    #[test]
+    fn test_fence_i_invalidates_blocks_but_preserves_tlb() {
+        let mut sys = System::new(16, None).unwrap();
+
+        // A block only ever gets one MMU translation, at its first
+        // instruction, so an always-taken branch is used here to force a
+        // fresh block (and thus a fresh translation) right after fence.i.
+        // nop ; nop ; fence.i ; beq x0, x0, 8 ; <unused> ; nop
+        let insts: [u32; 4] = [0x0000_0013, 0x0000_0013, 0x0000_100F, 0x0000_0463];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        bytes.extend_from_slice(&0x0000_0013u32.to_le_bytes()); // unused fallthrough slot
+        bytes.extend_from_slice(&0x0000_0013u32.to_le_bytes()); // branch target: nop
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE;
+        identity_map_dram_megapage(&mut sys);
+
+        // Block 1: nop, nop, fence.i, beq (branch terminates the block).
+        // The single translation for this block's fetch is a cold miss.
+        sys.run(1);
+        assert_eq!(sys.cpu.tlb_stats(), (0, 1));
+
+        // Block 2: the branch target, in the same megapage. If fence.i had
+        // touched the TLB this would also miss; it doesn't, so it hits.
+        sys.run(1);
+        assert_eq!(sys.cpu.tlb_stats(), (1, 1));
+    }
+
+    #[test]
+    fn test_sfence_vma_flushes_tlb() {
+        let mut sys = System::new(16, None).unwrap();
+
+        // sfence.vma is itself a block terminator (it decodes under
+        // OP_SYSTEM), so the instruction right after it starts a fresh
+        // block/translation without needing an explicit branch.
+        // nop ; sfence.vma x0, x0 ; nop
+        let insts: [u32; 3] = [0x0000_0013, 0x1200_0073, 0x0000_0013];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE;
+        identity_map_dram_megapage(&mut sys);
+
+        // Block 1: nop, sfence.vma. Cold miss, and sfence.vma bumps the TLB
+        // generation before the block ends.
+        sys.run(1);
+        assert_eq!(sys.cpu.tlb_stats(), (0, 1));
+
+        // Block 2: the trailing nop, same megapage - but the generation bump
+        // means this is a fresh walk rather than a TLB hit.
+        sys.run(1);
+        assert_eq!(sys.cpu.tlb_stats(), (0, 2));
+    }
+
+    #[test]
+    fn test_custom_boot_rom_runs_from_its_own_reset_pc() {
+        use crate::memory::ROM_BASE;
+
+        let mut sys = System::new(16, None).unwrap();
+
+        // A tiny custom "firmware": addi a0, x0, 0x42 ; jal x0, 0 (self-loop
+        // so the block ends and `run` reports a clean stop).
+        let insts: [u32; 2] = [0x0420_0513, 0x0000_006f];
+        let mut rom = Vec::new();
+        for inst in insts {
+            rom.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_boot_rom(&rom).unwrap();
+        sys.set_reset_pc(ROM_BASE);
+        sys.reset();
+
+        assert_eq!(sys.cpu.pc, ROM_BASE);
+        sys.run(1);
+        assert_eq!(sys.cpu.read_reg(10), 0x42);
+    }
+
+    #[test]
+    fn test_load_boot_rom_rejects_image_larger_than_rom_window() {
+        let mut sys = System::new(16, None).unwrap();
+        let oversized = vec![0u8; crate::memory::ROM_SIZE as usize + 1];
+        assert!(sys.load_boot_rom(&oversized).is_err());
+    }
+
+    #[test]
+    fn test_state_bytes_round_trip() {
+        let mut sys = System::new(16, None).unwrap();
+        sys.load_binary(&0x0000_0013u32.to_le_bytes(), DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE;
+        sys.run(1);
+
+        let bytes = sys.to_state_bytes().unwrap();
+        let restored = System::from_state_bytes(&bytes).unwrap();
+        assert_eq!(restored.cpu.pc, sys.cpu.pc);
+        assert_eq!(restored.cpu.instruction_count, sys.cpu.instruction_count);
+    }
+
+    #[test]
+    fn test_from_state_bytes_rejects_truncated_blob() {
+        let sys = System::new(16, None).unwrap();
+        let bytes = sys.to_state_bytes().unwrap();
+        assert!(System::from_state_bytes(&bytes[..bytes.len() / 2]).is_err());
+        assert!(System::from_state_bytes(&bytes[..4]).is_err());
+    }
+
+    #[test]
+    fn test_from_state_bytes_rejects_bit_flipped_blob() {
+        let sys = System::new(16, None).unwrap();
+        let mut bytes = sys.to_state_bytes().unwrap();
+        // Corrupt a byte early in the Zstd frame itself (right after our own
+        // 8-byte header), which reliably breaks decompression rather than
+        // risking a flip elsewhere landing on padding/unused bits.
+        bytes[9] ^= 0xFF;
+        assert!(System::from_state_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_from_state_bytes_rejects_bad_magic() {
+        let sys = System::new(16, None).unwrap();
+        let mut bytes = sys.to_state_bytes().unwrap();
+        bytes[0] = b'X';
+        assert!(System::from_state_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_from_state_bytes_rejects_unknown_version() {
+        let sys = System::new(16, None).unwrap();
+        let mut bytes = sys.to_state_bytes().unwrap();
+        bytes[4..8].copy_from_slice(&999u32.to_le_bytes());
+        assert!(System::from_state_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_from_state_bytes_rejects_absurd_length_prefix() {
+        // Hand-craft a bincode payload for a `Vec<u8>` that claims to be
+        // enormous but only actually contains a couple of bytes, then wrap
+        // it in a valid header/zstd frame as if it were a serialized
+        // `System`. The bincode size limit should reject this before it
+        // tries to allocate anything close to the claimed length.
+        let mut fake_payload = Vec::new();
+        fake_payload.extend_from_slice(&(u64::MAX / 2).to_le_bytes());
+        fake_payload.extend_from_slice(&[0u8, 1, 2, 3]);
+
+        let compressed = zstd::stream::encode_all(&fake_payload[..], 0).unwrap();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&STATE_MAGIC);
+        bytes.extend_from_slice(&STATE_FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&compressed);
+
+        assert!(System::from_state_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_reset_preserves_in_memory_fs_by_default_but_wipes_when_requested() {
+        use crate::devices::virtio_9p::filesystem::FileSystem;
+
+        let mut sys = System::new(16, None).unwrap();
+        let root = sys.virtio9p.fs.attach().unwrap();
+        let file = sys.virtio9p.fs.create(&root, "hello.txt", 0o100644, 0).unwrap();
+        sys.virtio9p.fs.write(&file, 0, b"persisted").unwrap();
+
+        // Default: reset() leaves the in-memory overlay untouched.
+        sys.reset();
+        let root = sys.virtio9p.fs.attach().unwrap();
+        let file = sys.virtio9p.fs.walk(&root, "hello.txt").unwrap();
+        assert_eq!(sys.virtio9p.fs.read(&file, 0, 64).unwrap(), b"persisted");
+
+        // Opt in to wiping, and the file is gone after the next reset.
+        sys.set_wipe_fs_on_reset(true);
+        sys.reset();
+        let root = sys.virtio9p.fs.attach().unwrap();
+        assert!(sys.virtio9p.fs.walk(&root, "hello.txt").is_err());
     }
 }
@@ -5,6 +5,7 @@
 use crate::cpu::rv64::{Cpu64, BlockCache, BlockResult, execute_block, mmu::AccessType};
 use crate::cpu::rv64::csr::*;
 use crate::memory::{Memory, Bus, DRAM_BASE};
+use crate::system::{protected_perms, PROT_EXEC, PROT_READ, PROT_WRITE};
 use crate::devices::{Uart, Clint, Plic, Virtio9p};
 use crate::devices::virtio_9p::{Backend, in_memory::InMemoryFileSystem};
 #[cfg(not(target_arch = "wasm32"))]
@@ -12,8 +13,11 @@ use crate::devices::virtio_9p::host::HostFileSystem;
 use serde::{Serialize, Deserialize};
 
 // Device base addresses (matching QEMU virt machine)
-const CLINT_BASE: u32 = 0x0200_0000;
-const CLINT_SIZE: u32 = 0x0001_0000;
+// CLINT's address map is shared with `System` and `dtb::generate_fdt` via
+// `crate::devices::map` rather than redefined per system - see that
+// module's doc comment.
+const CLINT_BASE: u32 = crate::devices::map::CLINT_BASE as u32;
+const CLINT_SIZE: u32 = crate::devices::map::CLINT_SIZE as u32;
 const UART_BASE: u32 = 0x1000_0000; // QEMU virt uses 0x10000000 for UART
 const UART_SIZE: u32 = 0x0000_1000;
 const PLIC_BASE: u32 = 0x0C00_0000; // QEMU virt PLIC at 0x0C000000
@@ -39,6 +43,12 @@ pub struct System64 {
     block_cache: BlockCache,
     #[serde(skip)]
     use_jit_v1: bool,
+    /// Host-imposed physical-address protection overlay, as (base, size,
+    /// perms), set by `add_protected_range`. Empty means no restriction
+    /// beyond whatever RAM/device mapping already applies. See
+    /// `System::add_protected_range` for the RV32 sibling this mirrors.
+    #[serde(skip)]
+    protected_ranges: Vec<(u32, u32, u8)>,
 }
 
 impl System64 {
@@ -49,7 +59,7 @@ impl System64 {
         }
 
         let mut memory = Memory::new(ram_size_mb);
-        memory.init_boot_rom_rv64(); // RV64-specific boot ROM
+        memory.init_boot_rom_rv64(DRAM_BASE); // RV64-specific boot ROM
 
         let fs_backend = if let Some(_path) = fs_path {
             #[cfg(not(target_arch = "wasm32"))]
@@ -73,6 +83,7 @@ impl System64 {
             virtio9p: Virtio9p::new("rootfs", fs_backend),
             block_cache: BlockCache::new(),
             use_jit_v1: false,
+            protected_ranges: Vec::new(),
         })
     }
 
@@ -80,6 +91,21 @@ impl System64 {
         self.use_jit_v1 = enable;
     }
 
+    /// Forbid the guest from accessing the physical range `[base, base+size)`
+    /// beyond what `perms` (`PROT_READ`/`PROT_WRITE`/`PROT_EXEC`, OR'd
+    /// together) allows. See `System::add_protected_range` for the RV32
+    /// sibling - same host-imposed overlay, checked independently of
+    /// whatever the guest's own RISC-V PMP configuration says.
+    pub fn add_protected_range(&mut self, base: u32, size: u32, perms: u8) {
+        self.protected_ranges.push((base, size, perms));
+    }
+
+    /// Remove every protection overlay previously added by
+    /// `add_protected_range`.
+    pub fn clear_protected_ranges(&mut self) {
+        self.protected_ranges.clear();
+    }
+
     /// Load a binary at the specified address
     pub fn load_binary(&mut self, data: &[u8], addr: u32) -> Result<(), String> {
         self.memory.load_binary(data, addr)
@@ -313,6 +339,30 @@ impl System64 {
         cycles
     }
 
+    /// Run in a cooperative, chunked fashion so a caller can interrupt a
+    /// long budget between chunks instead of blocking for the whole thing.
+    /// See `System::run_chunked` for the RV32 sibling.
+    pub fn run_chunked(
+        &mut self,
+        total_cycles: u32,
+        chunk_cycles: u32,
+        mut should_continue: impl FnMut() -> bool,
+    ) -> crate::system::ChunkedRunResult {
+        use crate::system::{ChunkedRunResult, RunStopReason};
+
+        let mut cycles = 0u32;
+        while cycles < total_cycles {
+            let this_chunk = chunk_cycles.min(total_cycles - cycles);
+            cycles += self.run(this_chunk);
+
+            if !should_continue() {
+                return ChunkedRunResult { cycles, reason: RunStopReason::Callback };
+            }
+        }
+
+        ChunkedRunResult { cycles, reason: RunStopReason::Budget }
+    }
+
     fn step(&mut self) -> Result<u32, crate::cpu::rv64::trap::Trap64> {
         let mut bus = SystemBus64::new(
             &mut self.memory,
@@ -320,6 +370,7 @@ impl System64 {
             &mut self.clint,
             &mut self.plic,
             &mut self.virtio9p,
+            &self.protected_ranges,
         );
 
         self.cpu.step(&mut bus)?;
@@ -340,6 +391,7 @@ impl System64 {
             &mut self.clint,
             &mut self.plic,
             &mut self.virtio9p,
+            &self.protected_ranges,
         );
 
         let paddr = match self.cpu.mmu.translate(
@@ -625,6 +677,9 @@ struct SystemBus64<'a> {
     plic: &'a mut Plic,
     virtio9p: &'a mut Virtio9p,
     ram_size: usize,
+    /// Host-imposed access overlay set by `System64::add_protected_range`.
+    /// Empty means no restriction.
+    protected_ranges: &'a [(u32, u32, u8)],
 }
 
 impl<'a> SystemBus64<'a> {
@@ -634,9 +689,10 @@ impl<'a> SystemBus64<'a> {
         clint: &'a mut Clint,
         plic: &'a mut Plic,
         virtio9p: &'a mut Virtio9p,
+        protected_ranges: &'a [(u32, u32, u8)],
     ) -> Self {
         let ram_size = memory.ram_size();
-        SystemBus64 { memory, uart, clint, plic, virtio9p, ram_size }
+        SystemBus64 { memory, uart, clint, plic, virtio9p, ram_size, protected_ranges }
     }
 
     #[inline(always)]
@@ -652,6 +708,18 @@ impl<'a> SystemBus64<'a> {
 }
 
 impl<'a> Bus for SystemBus64<'a> {
+    fn is_executable(&self, addr: u32) -> bool {
+        self.memory.is_executable(addr) && protected_perms(self.protected_ranges, addr) & PROT_EXEC != 0
+    }
+
+    fn is_read_allowed(&self, addr: u32) -> bool {
+        protected_perms(self.protected_ranges, addr) & PROT_READ != 0
+    }
+
+    fn is_write_allowed(&self, addr: u32) -> bool {
+        protected_perms(self.protected_ranges, addr) & PROT_WRITE != 0
+    }
+
     fn read8(&mut self, addr: u32) -> u8 {
         if let Some(offset) = self.ram_offset(addr) {
             return unsafe { self.memory.ram_read8_unchecked(offset) };
@@ -771,9 +839,7 @@ impl<'a> Bus for SystemBus64<'a> {
             }
         }
         if addr >= CLINT_BASE && addr < CLINT_BASE + CLINT_SIZE {
-            let lo = self.clint.read32(addr - CLINT_BASE) as u64;
-            let hi = self.clint.read32(addr - CLINT_BASE + 4) as u64;
-            return lo | (hi << 32);
+            return self.clint.read64(addr - CLINT_BASE);
         }
         let lo = self.read32(addr) as u64;
         let hi = self.read32(addr + 4) as u64;
@@ -788,11 +854,55 @@ impl<'a> Bus for SystemBus64<'a> {
             }
         }
         if addr >= CLINT_BASE && addr < CLINT_BASE + CLINT_SIZE {
-            self.clint.write32(addr - CLINT_BASE, value as u32);
-            self.clint.write32(addr - CLINT_BASE + 4, (value >> 32) as u32);
+            self.clint.write64(addr - CLINT_BASE, value);
             return;
         }
         self.write32(addr, value as u32);
         self.write32(addr + 4, (value >> 32) as u32);
     }
+
+    fn mtime(&self) -> Option<u64> {
+        Some(self.clint.get_mtime())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protected_range_blocks_guest_store_with_store_access_fault() {
+        let mut sys = System64::new(16, None).unwrap();
+
+        // A RAM range the host wants to keep the guest from writing to,
+        // e.g. a shared-buffer region it owns itself. Kept well away from
+        // both the loaded code and mtvec so only the deliberate store hits
+        // it. See `System::add_protected_range`'s RV32 test for the sibling
+        // of this case.
+        let guard_base = DRAM_BASE + 0x4000;
+        sys.add_protected_range(guard_base, 0x1000, PROT_READ | PROT_EXEC);
+
+        // lui a0, 0x80004 ; slli a0, a0, 32 ; srli a0, a0, 32 ; addi a1, x0, 1 ; sw a1, 0(a0)
+        // RV64's `lui` sign-extends its 32-bit immediate into the full
+        // 64-bit register (unlike RV32, where the register width matches),
+        // so an upper immediate with bit 31 set needs the slli/srli pair to
+        // clear it back down to a plain 32-bit physical address.
+        let insts: [u32; 5] = [0x8000_4537, 0x0205_1513, 0x0205_5513, 0x0010_0593, 0x00b5_2023];
+        let mut bytes = Vec::new();
+        for inst in insts {
+            bytes.extend_from_slice(&inst.to_le_bytes());
+        }
+        sys.load_binary(&bytes, DRAM_BASE).unwrap();
+        sys.cpu.reset();
+        sys.cpu.pc = DRAM_BASE as u64;
+        sys.cpu.csr.mtvec = DRAM_BASE as u64 + 0x1000;
+
+        // Unlike `System::run` (RV32), `System64::run`'s plain interpreter
+        // path counts one cycle per instruction rather than per compiled
+        // block, so every instruction needs its own cycle budget.
+        sys.run(insts.len() as u32);
+
+        assert_eq!(sys.cpu.csr.mcause, 7); // StoreAccessFault
+        assert_eq!(sys.cpu.csr.mtval, guard_base as u64);
+    }
 }
@@ -0,0 +1,92 @@
+//! Lock-step comparison harness: runs a small guest program with
+//! `System::set_commit_log` enabled and diffs the resulting Spike-style
+//! trace against a reference log line by line, reporting the first
+//! divergence with surrounding context. Point `LOCKSTEP_REFERENCE` at a
+//! log captured from a real reference simulator (e.g. `spike
+//! --log-commits` or QEMU's `-d in_asm,exec`) to cross-check a change
+//! against it; without the env var this falls back to the checked-in
+//! `tests/guest/lockstep_reference.log`.
+//!
+//! Neither Spike nor QEMU is available in every environment this repo is
+//! built in, so the checked-in reference log is captured from this
+//! emulator itself rather than an independent simulator - it exercises
+//! the diffing logic and guards against accidental commit-log format
+//! regressions, but isn't a substitute for an actual cross-check. Set
+//! `LOCKSTEP_REFERENCE` to a genuine third-party trace for that.
+
+use otoriscv::System;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const DRAM_BASE: u32 = 0x8000_0000;
+const STEPS: u32 = 6;
+
+fn reference_log_path() -> PathBuf {
+    if let Ok(path) = env::var("LOCKSTEP_REFERENCE") {
+        return PathBuf::from(path);
+    }
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests/guest/lockstep_reference.log");
+    path
+}
+
+/// lui x1,0x80000 ; addi x3,x0,0x42 ; addi x2,x0,1 ; sw x0,0(x1) ;
+/// lw x2,0(x1) ; beq x2,x0,8 - the same fixed program the reference log
+/// was captured from, kept in sync with `test_commit_log_format_for_addi_lw_and_beq`
+/// in src/system.rs.
+fn traced_run() -> Vec<String> {
+    let insts: [u32; 6] = [
+        0x800000B7, 0x04200193, 0x00100113, 0x0000A023, 0x0000A103, 0x00010463,
+    ];
+    let mut bytes = Vec::new();
+    for inst in insts {
+        bytes.extend_from_slice(&inst.to_le_bytes());
+    }
+
+    let mut sys = System::new(1, None).unwrap();
+    sys.load_binary(&bytes, DRAM_BASE).unwrap();
+    sys.cpu.pc = DRAM_BASE;
+    sys.set_commit_log(true);
+    sys.run(STEPS);
+    sys.take_commit_log()
+}
+
+#[test]
+fn test_trace_matches_reference_log() {
+    let reference_path = reference_log_path();
+    let reference_text = fs::read_to_string(&reference_path)
+        .unwrap_or_else(|e| panic!("failed to read reference log {:?}: {}", reference_path, e));
+    let reference: Vec<&str> = reference_text.lines().collect();
+
+    let ours = traced_run();
+
+    for (i, pair) in ours.iter().zip(reference.iter()).enumerate() {
+        let (ours_line, ref_line) = pair;
+        if ours_line != ref_line {
+            let start = i.saturating_sub(2);
+            let end = (i + 2).min(ours.len().min(reference.len()) - 1);
+            let mut context = String::new();
+            for j in start..=end {
+                let marker = if j == i { ">>" } else { "  " };
+                context.push_str(&format!(
+                    "{marker} [{j}] ours: {}\n{marker} [{j}] ref:  {}\n",
+                    ours[j], reference[j]
+                ));
+            }
+            panic!(
+                "lockstep trace diverges at line {} against {:?}:\n{}",
+                i, reference_path, context
+            );
+        }
+    }
+
+    assert_eq!(
+        ours.len(),
+        reference.len(),
+        "trace length mismatch against {:?}: ours has {} lines, reference has {}",
+        reference_path,
+        ours.len(),
+        reference.len()
+    );
+}